@@ -5,20 +5,15 @@ use std::sync::RwLock;
 use std::error::Error;
 
 use rustful::{Server, TreeRouter, Context, Response, Log, Handler};
-use rustful::filter::{FilterContext, ResponseFilter, ResponseAction, ContextFilter, ContextAction};
+use rustful::filter::{FilterContext, ResponseFilter, ResponseAction, ContextFilter, ContextAction, Jsonp};
 use rustful::response::Data;
 use rustful::StatusCode;
-use rustful::header::Headers;
+use rustful::header::{Headers, ContentType};
 use rustful::context::Uri;
 
-fn say_hello(mut context: Context, mut response: Response, format: &Format) {
-    //Take the name of the JSONP function from the query variables
-    let mut quote_msg = if let Some(jsonp_name) = context.query.remove("jsonp") {
-        response.filter_storage_mut().insert(JsonpFn(jsonp_name.into()));
-        true
-    } else {
-        false
-    };
+fn say_hello(context: Context, mut response: Response, format: &Format) {
+    //The JSONP filter only wraps JSON, so the message is quoted if there's a callback
+    let mut quote_msg = context.query.get("jsonp").is_some();
 
     //Is the format supposed to be a JSON structure? Then set a variable name
     if let Format::Json = *format {
@@ -32,6 +27,7 @@ fn say_hello(mut context: Context, mut response: Response, format: &Format) {
     };
 
     let message = if quote_msg {
+        response.headers_mut().set(ContentType(content_type!(Application / Json; Charset = Utf8)));
         format!("\"Hello, {}!\"", person)
     } else {
         format!("Hello, {}!", person)
@@ -85,10 +81,11 @@ fn main() {
         context_filters: vec![
             Box::new(RequestLogger::new()),
             Box::new(PathPrefix::new("print")),
-            Box::new(RequestLogger::new())
+            Box::new(RequestLogger::new()),
+            Box::new(Jsonp::new().parameter("jsonp"))
         ],
 
-        response_filters: vec![Box::new(Jsonp), Box::new(Json)],
+        response_filters: vec![Box::new(Jsonp::new().parameter("jsonp")), Box::new(Json)],
 
         ..Server::default()
     }.run();
@@ -176,30 +173,3 @@ impl ResponseFilter for Json {
         ResponseAction::next(output)
     }
 }
-
-struct JsonpFn(String);
-
-struct Jsonp;
-
-impl ResponseFilter for Jsonp {
-    fn begin(&self, ctx: FilterContext, status: StatusCode, _headers: &mut Headers) -> (StatusCode, ResponseAction) {
-        //Check if a JSONP function is defined and write the beginning of the call
-        let output = if let Some(&JsonpFn(ref function)) = ctx.storage.get() {
-            Some(format!("{}(", function))
-        } else {
-            None
-        };
-
-        (status, ResponseAction::next(output))
-    }
-
-    fn write<'a>(&'a self, _ctx: FilterContext, bytes: Option<Data<'a>>) -> ResponseAction {
-        ResponseAction::next(bytes)
-    }
-
-    fn end(&self, ctx: FilterContext) -> ResponseAction {
-        //Check if a JSONP function is defined and write the end of the call
-        let output = ctx.storage.get::<JsonpFn>().map(|_| ");");
-        ResponseAction::next(output)
-    }
-}
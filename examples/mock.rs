@@ -0,0 +1,39 @@
+extern crate rustful;
+
+use std::env;
+use std::error::Error;
+
+use rustful::Server;
+use rustful::handler::mock;
+
+fn main() {
+    //Take the path to the mock routes from the command line.
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            println!("usage: mock <routes.json>");
+            return;
+        }
+    };
+
+    let routes = match mock::load(&path) {
+        Ok(routes) => routes,
+        Err(e) => {
+            println!("could not load {}: {}", path, e);
+            return;
+        }
+    };
+
+    println!("Serving the mock routes from {} on http://localhost:8080.", path);
+
+    let server_result = Server {
+        host: 8080.into(),
+        handlers: routes,
+        ..Server::default()
+    }.run();
+
+    match server_result {
+        Ok(_server) => {},
+        Err(e) => println!("could not start server: {}", e.description())
+    }
+}
@@ -0,0 +1,167 @@
+//A self driving throughput and latency benchmark. It starts a server on the
+//loopback interface and hammers it with keep-alive requests from a number of
+//client threads, before it prints the result as a single line of JSON.
+//
+//Run it in release mode, to get realistic numbers:
+//
+//    cargo run --release --example throughput -- [seconds] [connections] [port]
+//
+//The defaults are 10 seconds, 8 connections and port 8081.
+extern crate rustful;
+extern crate time;
+
+use std::env;
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, TcpStream};
+use std::thread;
+
+use rustful::{Server, Context, Response};
+
+const PATH: &'static str = "/hello";
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let seconds = args.next().and_then(|a| a.parse().ok()).unwrap_or(10u64);
+    let connections = args.next().and_then(|a| a.parse().ok()).unwrap_or(8usize);
+    let port = args.next().and_then(|a| a.parse().ok()).unwrap_or(8081u16);
+
+    let server_result = Server {
+        host: (Ipv4Addr::new(127, 0, 0, 1), port).into(),
+        threads: Some(connections),
+        ..Server::new(|_: Context, response: Response| response.send("Hello, benchmark!"))
+    }.run();
+
+    let mut server = match server_result {
+        Ok(server) => server,
+        Err(e) => {
+            println!("could not start server: {}", e.description());
+            return;
+        }
+    };
+
+    let end = time::precise_time_ns() + seconds * 1_000_000_000;
+    let clients: Vec<_> = (0..connections).map(|_| thread::spawn(move || run_client(port, end))).collect();
+
+    let mut latencies = vec![];
+    let mut errors = 0;
+    for client in clients {
+        match client.join() {
+            Ok(Ok(mut client_latencies)) => latencies.append(&mut client_latencies),
+            _ => errors += 1
+        }
+    }
+
+    let _ = server.close();
+
+    latencies.sort();
+    let requests = latencies.len();
+    let throughput = requests as f64 / seconds as f64;
+
+    println!(
+        "{{\"seconds\":{},\"connections\":{},\"requests\":{},\"failed_connections\":{},\"requests_per_second\":{:.1},\"latency_us\":{{\"p50\":{},\"p90\":{},\"p99\":{},\"max\":{}}}}}",
+        seconds,
+        connections,
+        requests,
+        errors,
+        throughput,
+        percentile(&latencies, 50),
+        percentile(&latencies, 90),
+        percentile(&latencies, 99),
+        latencies.last().map(|&l| l / 1000).unwrap_or(0)
+    );
+}
+
+//Sends requests over a single keep-alive connection until `end`, and
+//returns the latency of each request, in nanoseconds.
+fn run_client(port: u16, end: u64) -> io::Result<Vec<u64>> {
+    let request = format!("GET {} HTTP/1.1\r\nHost: 127.0.0.1:{}\r\n\r\n", PATH, port);
+    let mut stream = BufReader::new(try!(TcpStream::connect((Ipv4Addr::new(127, 0, 0, 1), port))));
+    let mut latencies = vec![];
+    let mut body = vec![];
+
+    loop {
+        let start = time::precise_time_ns();
+        if start >= end {
+            return Ok(latencies);
+        }
+
+        try!(stream.get_mut().write_all(request.as_bytes()));
+        let content_length = try!(read_head(&mut stream));
+
+        body.clear();
+        try!((&mut stream).take(content_length).read_to_end(&mut body));
+        if body.len() as u64 != content_length {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the response body was cut short"));
+        }
+
+        latencies.push(time::precise_time_ns() - start);
+    }
+}
+
+//Reads the status line and the headers, and returns the content length.
+fn read_head<R: BufRead>(stream: &mut R) -> io::Result<u64> {
+    let mut line = String::new();
+    let mut content_length = None;
+
+    try!(stream.read_line(&mut line));
+    if !line.starts_with("HTTP/1.1 200") {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected status line: {:?}", line)));
+    }
+
+    loop {
+        line.clear();
+        if try!(stream.read_line(&mut line)) == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the connection was closed"));
+        }
+
+        let header = line.trim_right();
+        if header.is_empty() {
+            break;
+        }
+
+        let mut parts = header.splitn(2, ':');
+        if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+            if name.to_lowercase() == "content-length" {
+                content_length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    content_length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "the response has no content length"))
+}
+
+//Gets a percentile from sorted latencies, in microseconds.
+fn percentile(latencies: &[u64], percent: usize) -> u64 {
+    if latencies.is_empty() {
+        0
+    } else {
+        latencies[(latencies.len() - 1) * percent / 100] / 1000
+    }
+}
+
+//Run with `cargo test --example throughput`.
+#[cfg(test)]
+mod test {
+    use super::{read_head, percentile};
+
+    #[test]
+    fn read_response_heads() {
+        let mut response: &[u8] = b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\nServer: rustful\r\n\r\nhello";
+        assert_eq!(read_head(&mut response).unwrap(), 5);
+        assert_eq!(response, b"hello");
+
+        assert!(read_head(&mut &b"HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n"[..]).is_err());
+        assert!(read_head(&mut &b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n"[..]).is_err());
+        assert!(read_head(&mut &b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n"[..]).is_err());
+    }
+
+    #[test]
+    fn latency_percentiles() {
+        let latencies: Vec<u64> = (1..101).map(|l| l * 1000).collect();
+        assert_eq!(percentile(&latencies, 50), 50);
+        assert_eq!(percentile(&latencies, 99), 99);
+        assert_eq!(percentile(&latencies, 100), 100);
+        assert_eq!(percentile(&[], 50), 0);
+    }
+}
@@ -3,6 +3,14 @@ FEATURES="
 	rustc_json_body
 	ssl
 	multipart
+	decompression
+	compression
+	brotli
+	dynamic_handlers
+	msgpack
+	cbor
+	jwt
+	log
 "
 
 echo compiling with --no-default-features --features strict
@@ -0,0 +1,378 @@
+//!Circuit breakers for routes that depend on other services.
+//!
+//![`CircuitBreaker`][breaker] is a middleware that keeps track of how many
+//!requests fail. A request fails if its status is `5xx`, which includes the
+//!`503` and `504` responses from timeouts, or if the handler reports it as
+//!failed through a [`BreakerReport`][report]. It also fails if the handler
+//!panics, or if the response hasn't been sent when the handler returns,
+//!such as when it has been moved to another thread. The breaker opens when
+//!too many requests have failed, and all requests are answered with `503
+//!Service Unavailable` and a `Retry-After` header, without calling the
+//!handler, to give the other service some time to recover.
+//!
+//!The breaker becomes half-open when it has been open for a while, and a
+//!few probe requests are let through. It closes again if they succeed, and
+//!opens again if they fail.
+//!
+//!```
+//!extern crate rustful;
+//!extern crate time;
+//!use rustful::{Server, Context, Response};
+//!use rustful::circuit_breaker::{CircuitBreaker, BreakerReport};
+//!
+//!# fn fetch_prices() -> Result<String, ()> { Ok(String::new()) }
+//!fn prices(_context: Context, mut response: Response) {
+//!    match fetch_prices() {
+//!        Ok(prices) => response.send(prices),
+//!        Err(_) => {
+//!            //The upstream service failed, but we can still say something
+//!            if let Some(report) = response.filter_storage().get::<BreakerReport>() {
+//!                report.failure();
+//!            }
+//!            response.send("prices are unavailable at the moment");
+//!        }
+//!    }
+//!}
+//!
+//!# fn main() {
+//!let breaker = CircuitBreaker::new()
+//!    .failure_ratio(0.5)
+//!    .open_for(time::Duration::seconds(30));
+//!
+//!let server = Server {
+//!    middleware: vec![Box::new(breaker)],
+//!    ..Server::new(prices)
+//!};
+//!# }
+//!```
+//!
+//!Each breaker is usually added to a [`Scope`][scope] for the routes that
+//!depend on the same service.
+//!
+//![breaker]: struct.CircuitBreaker.html
+//![report]: struct.BreakerReport.html
+//![scope]: ../handler/scope/struct.Scope.html
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use time::{Duration, SteadyTime};
+
+use StatusCode;
+use context::Context;
+use middleware::{Middleware, Next};
+use response::Response;
+
+///The state of a circuit breaker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BreakerState {
+    ///Requests are let through as usual.
+    Closed,
+
+    ///Requests are rejected.
+    Open,
+
+    ///A few probe requests are let through.
+    HalfOpen
+}
+
+///A handle for reporting a request as failed, even if it doesn't have a
+///`5xx` status. It's put in the filter storage by `CircuitBreaker`.
+#[derive(Clone, Debug, Default)]
+pub struct BreakerReport {
+    failed: Rc<Cell<bool>>
+}
+
+impl BreakerReport {
+    ///Report the request as failed.
+    pub fn failure(&self) {
+        self.failed.set(true);
+    }
+
+    ///Check if the request has been reported as failed.
+    pub fn is_failure(&self) -> bool {
+        self.failed.get()
+    }
+}
+
+#[derive(Clone)]
+struct Settings {
+    failure_ratio: f64,
+    min_requests: u32,
+    window: Duration,
+    open_for: Duration,
+    probes: u32
+}
+
+struct State {
+    state: BreakerState,
+    changed: SteadyTime,
+    window_start: SteadyTime,
+    successes: u32,
+    failures: u32,
+    probing: u32
+}
+
+///A middleware that stops calling the handler when too many requests fail.
+///See the [module documentation][module] for more information.
+///
+///It's cheap to clone, and the clones share the same state.
+///
+///[module]: index.html
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    settings: Arc<Settings>,
+    state: Arc<Mutex<State>>
+}
+
+impl CircuitBreaker {
+    ///Create a closed circuit breaker. It opens when half of at least 20
+    ///requests within 10 seconds have failed, and stays open for 10
+    ///seconds. One probe request at the time is let through when it's
+    ///half-open.
+    pub fn new() -> CircuitBreaker {
+        let now = SteadyTime::now();
+        CircuitBreaker {
+            settings: Arc::new(Settings {
+                failure_ratio: 0.5,
+                min_requests: 20,
+                window: Duration::seconds(10),
+                open_for: Duration::seconds(10),
+                probes: 1
+            }),
+            state: Arc::new(Mutex::new(State {
+                state: BreakerState::Closed,
+                changed: now,
+                window_start: now,
+                successes: 0,
+                failures: 0,
+                probing: 0
+            }))
+        }
+    }
+
+    ///Open when this share of the requests fail, from 0.0 to 1.0.
+    pub fn failure_ratio(mut self, ratio: f64) -> CircuitBreaker {
+        self.settings_mut().failure_ratio = ratio;
+        self
+    }
+
+    ///Don't open before this many requests have been made within the
+    ///window.
+    pub fn min_requests(mut self, requests: u32) -> CircuitBreaker {
+        self.settings_mut().min_requests = requests;
+        self
+    }
+
+    ///Count the failures within windows of this length.
+    pub fn window(mut self, window: Duration) -> CircuitBreaker {
+        self.settings_mut().window = window;
+        self
+    }
+
+    ///Stay open this long before becoming half-open.
+    pub fn open_for(mut self, duration: Duration) -> CircuitBreaker {
+        self.settings_mut().open_for = duration;
+        self
+    }
+
+    ///Let this many probe requests through at the same time when
+    ///half-open. It's at least 1.
+    pub fn probes(mut self, probes: u32) -> CircuitBreaker {
+        self.settings_mut().probes = if probes == 0 { 1 } else { probes };
+        self
+    }
+
+    fn settings_mut(&mut self) -> &mut Settings {
+        Arc::make_mut(&mut self.settings)
+    }
+
+    fn lock(&self) -> MutexGuard<State> {
+        match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner()
+        }
+    }
+
+    ///Get the current state.
+    pub fn state(&self) -> BreakerState {
+        let mut state = self.lock();
+        self.update(&mut state, SteadyTime::now());
+        state.state
+    }
+
+    //Becomes half-open when it has been open for long enough, and starts a
+    //new window when the current one has passed.
+    fn update(&self, state: &mut State, now: SteadyTime) {
+        match state.state {
+            BreakerState::Open if now - state.changed >= self.settings.open_for => {
+                state.state = BreakerState::HalfOpen;
+                state.changed = now;
+                state.probing = 0;
+            },
+            BreakerState::Closed if now - state.window_start >= self.settings.window => {
+                state.window_start = now;
+                state.successes = 0;
+                state.failures = 0;
+            },
+            _ => {}
+        }
+    }
+
+    //Tries to let a request through. It returns whether it's a probe, or
+    //the time until the breaker becomes half-open.
+    fn acquire(&self, now: SteadyTime) -> Result<bool, Duration> {
+        let mut state = self.lock();
+        self.update(&mut state, now);
+
+        match state.state {
+            BreakerState::Closed => Ok(false),
+            BreakerState::Open => Err(self.settings.open_for - (now - state.changed)),
+            BreakerState::HalfOpen if state.probing < self.settings.probes => {
+                state.probing += 1;
+                Ok(true)
+            },
+            BreakerState::HalfOpen => Err(Duration::zero())
+        }
+    }
+
+    //Records the outcome of a request.
+    fn record(&self, probe: bool, failed: bool, now: SteadyTime) {
+        let mut state = self.lock();
+
+        if probe {
+            state.probing = state.probing.saturating_sub(1);
+            if state.state == BreakerState::HalfOpen {
+                state.state = if failed { BreakerState::Open } else { BreakerState::Closed };
+                state.changed = now;
+                state.window_start = now;
+                state.successes = 0;
+                state.failures = 0;
+            }
+            return;
+        }
+
+        if state.state != BreakerState::Closed {
+            return;
+        }
+
+        self.update(&mut state, now);
+        if failed {
+            state.failures += 1;
+        } else {
+            state.successes += 1;
+        }
+
+        let total = state.successes + state.failures;
+        if total >= self.settings.min_requests && state.failures as f64 >= self.settings.failure_ratio * total as f64 {
+            state.state = BreakerState::Open;
+            state.changed = now;
+        }
+    }
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> CircuitBreaker {
+        CircuitBreaker::new()
+    }
+}
+
+impl Middleware for CircuitBreaker {
+    fn around(&self, context: Context, mut response: Response, next: Next) {
+        let probe = match self.acquire(SteadyTime::now()) {
+            Ok(probe) => probe,
+            Err(wait) => {
+                let seconds = (wait.num_milliseconds() + 999) / 1000;
+                let seconds = if seconds < 1 { 1 } else { seconds };
+                response.headers_mut().set_raw("Retry-After", vec![seconds.to_string().into_bytes()]);
+                response.set_status(StatusCode::ServiceUnavailable);
+                return;
+            }
+        };
+
+        let mut outcome = Outcome {
+            breaker: self,
+            probe: probe,
+            failed: None
+        };
+
+        let report = BreakerReport::default();
+        response.filter_storage_mut().insert(report.clone());
+        let outbox = response.outbox();
+
+        next.run(context, response);
+        outcome.failed = Some(match outbox.sent_status() {
+            Some(status) => report.is_failure() || status.to_u16() / 100 == 5,
+            None => true
+        });
+    }
+}
+
+//Records the outcome of a request when it's dropped. It's recorded as a
+//failure if it's not known, such as when the handler panics, so a probe is
+//never left unfinished.
+struct Outcome<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+    failed: Option<bool>
+}
+
+impl<'a> Drop for Outcome<'a> {
+    fn drop(&mut self) {
+        self.breaker.record(self.probe, self.failed.unwrap_or(true), SteadyTime::now());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use time::{Duration, SteadyTime};
+    use super::{CircuitBreaker, BreakerState, Outcome};
+
+    #[test]
+    fn open_and_close() {
+        let breaker = CircuitBreaker::new().min_requests(4).failure_ratio(0.5).open_for(Duration::seconds(10));
+        let now = SteadyTime::now();
+
+        breaker.record(false, false, now);
+        breaker.record(false, true, now);
+        breaker.record(false, false, now);
+        assert_eq!(breaker.acquire(now), Ok(false));
+        breaker.record(false, true, now);
+        assert_eq!(breaker.acquire(now + Duration::seconds(4)), Err(Duration::seconds(6)));
+
+        let later = now + Duration::seconds(10);
+        assert_eq!(breaker.acquire(later), Ok(true));
+        assert_eq!(breaker.acquire(later), Err(Duration::zero()));
+        breaker.record(true, true, later);
+        assert!(breaker.acquire(later).is_err());
+
+        let even_later = later + Duration::seconds(10);
+        assert_eq!(breaker.acquire(even_later), Ok(true));
+        breaker.record(true, false, even_later);
+        assert_eq!(breaker.acquire(even_later), Ok(false));
+        assert_eq!(breaker.lock().state, BreakerState::Closed);
+    }
+
+    #[test]
+    fn finish_unknown_probes() {
+        let breaker = CircuitBreaker::new().min_requests(1).open_for(Duration::seconds(10));
+        let now = SteadyTime::now();
+        breaker.record(false, true, now);
+
+        let later = now + Duration::seconds(10);
+        assert_eq!(breaker.acquire(later), Ok(true));
+        {
+            //Dropped without an outcome, as if the handler panicked.
+            let _outcome = Outcome {
+                breaker: &breaker,
+                probe: true,
+                failed: None
+            };
+        }
+
+        let state = breaker.lock();
+        assert_eq!(state.probing, 0);
+        assert_eq!(state.state, BreakerState::Open);
+    }
+}
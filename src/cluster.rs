@@ -0,0 +1,339 @@
+//!Coordination between server instances.
+//!
+//!Applications that run more than one instance may need to make sure that
+//!a background job only runs in one of them, or that all of them pick up a
+//!configuration change. This module provides a few primitives for that,
+//!built on top of a [`SharedStore`][shared_store] that all instances can
+//!reach:
+//!
+//! * [`Lock`][lock] is a distributed lock, held for a limited time unless
+//!it's renewed.
+//! * [`Election`][election] keeps one of the instances as the leader.
+//! * [`Broadcast`][broadcast] publishes values, such as configuration, to
+//!every instance.
+//!
+//!The store is usually an external service, such as a database, but
+//![`MemoryStore`][memory_store] can be used when all of the instances run in
+//!the same process, or for testing.
+//!
+//!```
+//!extern crate rustful;
+//!extern crate time;
+//!
+//!use std::sync::Arc;
+//!use time::Duration;
+//!use rustful::cluster::{MemoryStore, Election};
+//!
+//!# fn main() {
+//!let store = Arc::new(MemoryStore::new());
+//!let mut first = Election::new(store.clone(), "cleanup", "first", Duration::seconds(30));
+//!let mut second = Election::new(store.clone(), "cleanup", "second", Duration::seconds(30));
+//!
+//!assert!(first.is_leader().unwrap());
+//!assert!(!second.is_leader().unwrap());
+//!# }
+//!```
+//!
+//![shared_store]: trait.SharedStore.html
+//![lock]: struct.Lock.html
+//![election]: struct.Election.html
+//![broadcast]: struct.Broadcast.html
+//![memory_store]: struct.MemoryStore.html
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use time::{Duration, SteadyTime};
+
+///A key-value store that is shared between the instances.
+pub trait SharedStore: Send + Sync {
+    ///Get the value of `key`, unless it has expired.
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+
+    ///Atomically replace the value of `key` with `value`, if the current
+    ///value is `expected`. A `None` means that the key is missing or has
+    ///expired, and setting it to `None` removes it. The new value expires
+    ///after `ttl`, if any. Returns `true` if the value was replaced.
+    fn compare_and_set(&self, key: &str, expected: Option<&[u8]>, value: Option<&[u8]>, ttl: Option<Duration>) -> io::Result<bool>;
+}
+
+impl<S: SharedStore + ?Sized> SharedStore for Arc<S> {
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        (**self).get(key)
+    }
+
+    fn compare_and_set(&self, key: &str, expected: Option<&[u8]>, value: Option<&[u8]>, ttl: Option<Duration>) -> io::Result<bool> {
+        (**self).compare_and_set(key, expected, value, ttl)
+    }
+}
+
+///A `SharedStore` that is kept in memory, within a single process.
+pub struct MemoryStore {
+    entries: Mutex<HashMap<String, (Vec<u8>, Option<SteadyTime>)>>
+}
+
+impl MemoryStore {
+    ///Create an empty store.
+    pub fn new() -> MemoryStore {
+        MemoryStore {
+            entries: Mutex::new(HashMap::new())
+        }
+    }
+}
+
+impl SharedStore for MemoryStore {
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        let entries = try!(self.entries.lock().map_err(|_| poisoned()));
+        Ok(entries.get(key).and_then(|&(ref value, expires)| {
+            if is_expired(expires) {
+                None
+            } else {
+                Some(value.clone())
+            }
+        }))
+    }
+
+    fn compare_and_set(&self, key: &str, expected: Option<&[u8]>, value: Option<&[u8]>, ttl: Option<Duration>) -> io::Result<bool> {
+        let mut entries = try!(self.entries.lock().map_err(|_| poisoned()));
+
+        let matches = {
+            let current = entries.get(key).and_then(|&(ref value, expires)| {
+                if is_expired(expires) {
+                    None
+                } else {
+                    Some(&value[..])
+                }
+            });
+            current == expected
+        };
+
+        if matches {
+            match value {
+                Some(value) => {
+                    let expires = ttl.map(|ttl| SteadyTime::now() + ttl);
+                    entries.insert(key.to_owned(), (value.to_vec(), expires));
+                },
+                None => {
+                    entries.remove(key);
+                }
+            }
+        }
+
+        Ok(matches)
+    }
+}
+
+fn is_expired(expires: Option<SteadyTime>) -> bool {
+    expires.map(|expires| expires <= SteadyTime::now()).unwrap_or(false)
+}
+
+fn poisoned() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "poisoned store lock")
+}
+
+///A distributed lock.
+///
+///The lock is held for a limited time, so it's released even if its owner
+///disappears. The owner has to `renew` it to keep it for longer.
+pub struct Lock<S> {
+    store: S,
+    key: String,
+    owner: String,
+    ttl: Duration
+}
+
+impl<S: SharedStore> Lock<S> {
+    ///Create a lock called `name`, for the owner `owner`. The owner should
+    ///be unique for each instance. The lock is not acquired until `acquire`
+    ///is called.
+    pub fn new<N: Into<String>, O: Into<String>>(store: S, name: N, owner: O, ttl: Duration) -> Lock<S> {
+        Lock {
+            store: store,
+            key: format!("lock:{}", name.into()),
+            owner: owner.into(),
+            ttl: ttl
+        }
+    }
+
+    ///Try to acquire the lock, or renew it if it's already held by this
+    ///owner. Returns `true` if the lock is held by this owner.
+    pub fn acquire(&self) -> io::Result<bool> {
+        if try!(self.renew()) {
+            Ok(true)
+        } else {
+            self.store.compare_and_set(&self.key, None, Some(self.owner.as_bytes()), Some(self.ttl))
+        }
+    }
+
+    ///Extend the time the lock is held. Returns `false` if it's not held by
+    ///this owner.
+    pub fn renew(&self) -> io::Result<bool> {
+        let owner = Some(self.owner.as_bytes());
+        self.store.compare_and_set(&self.key, owner, owner, Some(self.ttl))
+    }
+
+    ///Release the lock, if it's held by this owner.
+    pub fn release(&self) -> io::Result<bool> {
+        self.store.compare_and_set(&self.key, Some(self.owner.as_bytes()), None, None)
+    }
+
+    ///Get the current owner of the lock, if any.
+    pub fn owner(&self) -> io::Result<Option<String>> {
+        self.store.get(&self.key).map(|owner| owner.map(|owner| String::from_utf8_lossy(&owner).into_owned()))
+    }
+}
+
+///Leader election between instances.
+///
+///Each instance creates an `Election` for the same group and calls
+///`is_leader` periodically, more often than `ttl`. The first instance to
+///call it becomes the leader and stays the leader as long as it keeps
+///calling it. Another instance takes over if the leader stops.
+pub struct Election<S> {
+    lock: Lock<S>
+}
+
+impl<S: SharedStore> Election<S> {
+    ///Create an election for the group `group`, where this instance is
+    ///called `node`.
+    pub fn new<G: Into<String>, N: Into<String>>(store: S, group: G, node: N, ttl: Duration) -> Election<S> {
+        Election {
+            lock: Lock::new(store, format!("leader:{}", group.into()), node, ttl)
+        }
+    }
+
+    ///Check if this instance is the leader, and try to become the leader
+    ///if there is none.
+    pub fn is_leader(&mut self) -> io::Result<bool> {
+        self.lock.acquire()
+    }
+
+    ///Get the name of the current leader, if any.
+    pub fn leader(&self) -> io::Result<Option<String>> {
+        self.lock.owner()
+    }
+
+    ///Step down as the leader, so another instance can take over.
+    pub fn resign(&mut self) -> io::Result<bool> {
+        self.lock.release()
+    }
+}
+
+///Publishes values, such as configuration, to every instance.
+///
+///Each instance creates a `Broadcast` for the same topic and calls `poll`
+///periodically to receive values from `publish`.
+pub struct Broadcast<S> {
+    store: S,
+    key: String,
+    last_seen: Option<Vec<u8>>
+}
+
+impl<S: SharedStore> Broadcast<S> {
+    ///Create a broadcast for the topic `topic`.
+    pub fn new<T: Into<String>>(store: S, topic: T) -> Broadcast<S> {
+        Broadcast {
+            store: store,
+            key: format!("broadcast:{}", topic.into()),
+            last_seen: None
+        }
+    }
+
+    ///Publish `value` to all instances, including this one.
+    pub fn publish(&self, value: &[u8]) -> io::Result<()> {
+        loop {
+            let current = try!(self.store.get(&self.key));
+            let version = current.as_ref().map(|current| parse_version(current)).unwrap_or(0) + 1;
+
+            let mut message = format!("{}\n", version).into_bytes();
+            message.extend_from_slice(value);
+
+            let current = current.as_ref().map(|current| &current[..]);
+            if try!(self.store.compare_and_set(&self.key, current, Some(&message), None)) {
+                return Ok(());
+            }
+        }
+    }
+
+    ///Get the latest published value, if it has changed since the last
+    ///call.
+    pub fn poll(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let current = try!(self.store.get(&self.key));
+        if current.is_none() || current == self.last_seen {
+            return Ok(None);
+        }
+
+        self.last_seen = current;
+        Ok(self.last_seen.as_ref().map(|message| {
+            let start = message.iter().position(|&b| b == b'\n').map(|i| i + 1).unwrap_or(0);
+            message[start..].to_vec()
+        }))
+    }
+}
+
+fn parse_version(message: &[u8]) -> u64 {
+    let end = message.iter().position(|&b| b == b'\n').unwrap_or(0);
+    ::std::str::from_utf8(&message[..end]).ok().and_then(|version| version.parse().ok()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use time::Duration;
+    use super::{MemoryStore, Lock, Election, Broadcast};
+
+    #[test]
+    fn exclusive_locks() {
+        let store = Arc::new(MemoryStore::new());
+        let a = Lock::new(store.clone(), "job", "a", Duration::seconds(10));
+        let b = Lock::new(store.clone(), "job", "b", Duration::seconds(10));
+
+        assert!(a.acquire().unwrap());
+        assert!(!b.acquire().unwrap());
+        assert_eq!(b.owner().unwrap(), Some("a".into()));
+
+        assert!(!b.release().unwrap());
+        assert!(a.release().unwrap());
+        assert!(b.acquire().unwrap());
+    }
+
+    #[test]
+    fn expired_locks() {
+        let store = Arc::new(MemoryStore::new());
+        let a = Lock::new(store.clone(), "job", "a", Duration::seconds(-1));
+        let b = Lock::new(store.clone(), "job", "b", Duration::seconds(10));
+
+        assert!(a.acquire().unwrap());
+        assert!(b.acquire().unwrap());
+        assert!(!a.renew().unwrap());
+    }
+
+    #[test]
+    fn elect_leader() {
+        let store = Arc::new(MemoryStore::new());
+        let mut a = Election::new(store.clone(), "group", "a", Duration::seconds(10));
+        let mut b = Election::new(store.clone(), "group", "b", Duration::seconds(10));
+
+        assert!(a.is_leader().unwrap());
+        assert!(!b.is_leader().unwrap());
+        assert!(a.resign().unwrap());
+        assert!(b.is_leader().unwrap());
+        assert_eq!(a.leader().unwrap(), Some("b".into()));
+    }
+
+    #[test]
+    fn broadcast_values() {
+        let store = Arc::new(MemoryStore::new());
+        let publisher = Broadcast::new(store.clone(), "config");
+        let mut subscriber = Broadcast::new(store.clone(), "config");
+
+        assert_eq!(subscriber.poll().unwrap(), None);
+        publisher.publish(b"a").unwrap();
+        assert_eq!(subscriber.poll().unwrap(), Some(b"a".to_vec()));
+        assert_eq!(subscriber.poll().unwrap(), None);
+
+        publisher.publish(b"a").unwrap();
+        assert_eq!(subscriber.poll().unwrap(), Some(b"a".to_vec()));
+    }
+}
@@ -0,0 +1,450 @@
+//!The outcome of completed requests.
+//!
+//!The handler consumes the `Response`, so the final status, the size of the
+//!body, and how long it took, are not known where the request was started.
+//!A [`CompletionHook`][hook] is called with all of that, and a bit about the
+//!request, after the response has been sent. It's called for every request,
+//!including those that were answered by the server itself, such as `404 Not
+//!Found`.
+//!
+//!```
+//!use rustful::{Server, Context, Response};
+//!use rustful::completion::Completion;
+//!
+//!fn log_request(completion: &Completion) {
+//!    println!(
+//!        "{} {} {} {} bytes in {} ms",
+//!        completion.method,
+//!        completion.request_target,
+//!        completion.status,
+//!        completion.bytes,
+//!        completion.duration.num_milliseconds()
+//!    );
+//!}
+//!
+//!let server = Server {
+//!    completion_hook: Some(Box::new(log_request)),
+//!    ..Server::new(|_: Context, response: Response| response.send("hello"))
+//!};
+//!```
+//!
+//!The hook is called from the thread that handled the request, just like
+//!the event sink, so it should not block for long.
+//!
+//!##Access Logs
+//!
+//![`AccessLog`][access_log] is a hook that writes a line per request to a
+//!`Log`, using a format string with the same kind of directives as the
+//!Apache HTTP server. The Common Log Format and the Combined Log Format are
+//!available as `COMMON` and `COMBINED`:
+//!
+//!```
+//!use rustful::{Server, Context, Response};
+//!use rustful::completion::{AccessLog, COMBINED};
+//!use rustful::log::StdOut;
+//!
+//!let server = Server {
+//!    completion_hook: Some(Box::new(AccessLog::new(COMBINED, StdOut))),
+//!    ..Server::new(|_: Context, response: Response| response.send("hello"))
+//!};
+//!```
+//!
+//![hook]: trait.CompletionHook.html
+//![access_log]: struct.AccessLog.html
+
+use std::fmt::Write;
+use std::net::SocketAddr;
+
+use time::{self, Duration, SteadyTime, Tm};
+
+use events::Outbox;
+use header::Headers;
+use log::Log;
+use {Method, StatusCode, HttpVersion};
+
+///The outcome of a completed request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Completion {
+    ///The request method.
+    pub method: Method,
+
+    ///The request target, as it was sent by the client.
+    pub request_target: String,
+
+    ///The HTTP version of the request.
+    pub http_version: HttpVersion,
+
+    ///The `Referer` header of the request.
+    pub referer: Option<String>,
+
+    ///The `User-Agent` header of the request.
+    pub user_agent: Option<String>,
+
+    ///The local time when the request was received.
+    pub time: Tm,
+
+    ///The address of the client. It may be a proxy.
+    pub address: SocketAddr,
+
+    ///The final status of the response.
+    pub status: StatusCode,
+
+    ///The number of body bytes that were written, after compression. It's
+    ///0 for responses without a body, such as responses to `HEAD` requests.
+    pub bytes: u64,
+
+    ///The time from when the request was received, until the response was
+    ///sent.
+    pub duration: Duration
+}
+
+///Receives the outcome of completed requests.
+pub trait CompletionHook: Send + Sync {
+    ///Handle the outcome of a request.
+    fn complete(&self, completion: &Completion);
+}
+
+impl<F: Fn(&Completion) + Send + Sync> CompletionHook for F {
+    fn complete(&self, completion: &Completion) {
+        self(completion);
+    }
+}
+
+#[doc(hidden)]
+///Internal and may change without warning.
+///
+///Calls the hook when it's dropped, which should be after the response.
+pub struct Completing<'a> {
+    hook: &'a CompletionHook,
+    outbox: Outbox,
+    started: SteadyTime,
+    method: Method,
+    request_target: String,
+    http_version: HttpVersion,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    time: Tm,
+    address: SocketAddr
+}
+
+impl<'a> Completing<'a> {
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn new(
+        hook: &'a CompletionHook,
+        outbox: Outbox,
+        method: Method,
+        request_target: String,
+        http_version: HttpVersion,
+        headers: &Headers,
+        address: SocketAddr
+    ) -> Completing<'a> {
+        Completing {
+            hook: hook,
+            outbox: outbox,
+            started: SteadyTime::now(),
+            method: method,
+            request_target: request_target,
+            http_version: http_version,
+            referer: raw_header(headers, "Referer"),
+            user_agent: raw_header(headers, "User-Agent"),
+            time: time::now(),
+            address: address
+        }
+    }
+}
+
+impl<'a> Drop for Completing<'a> {
+    fn drop(&mut self) {
+        let completion = Completion {
+            method: self.method.clone(),
+            request_target: ::std::mem::replace(&mut self.request_target, String::new()),
+            http_version: self.http_version.clone(),
+            referer: self.referer.take(),
+            user_agent: self.user_agent.take(),
+            time: self.time,
+            address: self.address,
+            status: self.outbox.status(),
+            bytes: self.outbox.bytes(),
+            duration: SteadyTime::now() - self.started
+        };
+
+        self.hook.complete(&completion);
+    }
+}
+
+fn raw_header(headers: &Headers, name: &str) -> Option<String> {
+    headers.get_raw(name).and_then(|lines| lines.first()).map(|line| String::from_utf8_lossy(line).into_owned())
+}
+
+///The Common Log Format.
+pub const COMMON: &'static str = "%h %l %u %t \"%r\" %>s %b";
+
+///The Combined Log Format.
+pub const COMBINED: &'static str = "%h %l %u %t \"%r\" %>s %b \"%{Referer}i\" \"%{User-Agent}i\"";
+
+///Writes a line to a `Log` for each request.
+///
+///The format string is made of text and directives, where the directives
+///are replaced with information about the request:
+///
+/// * `%h` - the client address.
+/// * `%l` and `%u` - the remote log name and user, which are always `-`.
+/// * `%t` - the time when the request was received.
+/// * `%r` - the request line, such as `GET /index.html HTTP/1.1`.
+/// * `%m` - the request method.
+/// * `%U` - the request target.
+/// * `%H` - the HTTP version.
+/// * `%s` and `%>s` - the response status code.
+/// * `%b` - the number of body bytes, or `-` if there were none.
+/// * `%B` - the number of body bytes.
+/// * `%D` - the duration in microseconds.
+/// * `%T` - the duration in seconds.
+/// * `%{Referer}i` and `%{User-Agent}i` - the request headers, or `-`.
+/// * `%%` - a `%`.
+///
+///Unknown directives are written as they are. Quotes, backslashes and
+///control characters in the request target and the headers are written as
+///`\xHH`, so they can't end a quoted field or the line.
+pub struct AccessLog<L> {
+    format: Vec<Directive>,
+    log: L
+}
+
+impl<L: Log> AccessLog<L> {
+    ///Create an access log with the format `format` that writes notes to
+    ///`log`.
+    pub fn new(format: &str, log: L) -> AccessLog<L> {
+        AccessLog {
+            format: parse_format(format),
+            log: log
+        }
+    }
+
+    ///Format a log line for `completion`.
+    pub fn format(&self, completion: &Completion) -> String {
+        let mut line = String::new();
+
+        for directive in &self.format {
+            let _ = match *directive {
+                Directive::Text(ref text) => write!(line, "{}", text),
+                Directive::Address => write!(line, "{}", completion.address.ip()),
+                Directive::Unknown => write!(line, "-"),
+                Directive::Time => write!(line, "[{}]", completion.time.strftime("%d/%b/%Y:%H:%M:%S %z").map(|time| time.to_string()).unwrap_or_else(|_| String::new())),
+                Directive::RequestLine => write!(line, "{} {} {}", completion.method, escape(&completion.request_target), completion.http_version),
+                Directive::Method => write!(line, "{}", completion.method),
+                Directive::Target => write!(line, "{}", escape(&completion.request_target)),
+                Directive::Version => write!(line, "{}", completion.http_version),
+                Directive::Status => write!(line, "{}", completion.status.to_u16()),
+                Directive::Bytes(dash) => if dash && completion.bytes == 0 {
+                    write!(line, "-")
+                } else {
+                    write!(line, "{}", completion.bytes)
+                },
+                Directive::Microseconds => write!(line, "{}", completion.duration.num_microseconds().unwrap_or(i64::max_value())),
+                Directive::Seconds => write!(line, "{}", completion.duration.num_seconds()),
+                Directive::Referer => write!(line, "{}", completion.referer.as_ref().map(|r| escape(r)).unwrap_or_else(|| "-".into())),
+                Directive::UserAgent => write!(line, "{}", completion.user_agent.as_ref().map(|r| escape(r)).unwrap_or_else(|| "-".into()))
+            };
+        }
+
+        line
+    }
+}
+
+impl<L: Log> CompletionHook for AccessLog<L> {
+    fn complete(&self, completion: &Completion) {
+        self.log.note(&self.format(completion));
+    }
+}
+
+//Writes `"`, `\` and control characters as `\xHH`.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' | '\\' | '\x00'...'\x1f' | '\x7f' => escaped.push_str(&format!("\\x{:02x}", c as u8)),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+enum Directive {
+    Text(String),
+    Address,
+    Unknown,
+    Time,
+    RequestLine,
+    Method,
+    Target,
+    Version,
+    Status,
+    Bytes(bool),
+    Microseconds,
+    Seconds,
+    Referer,
+    UserAgent
+}
+
+fn parse_format(format: &str) -> Vec<Directive> {
+    let mut directives = vec![];
+    let mut text = String::new();
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            text.push(c);
+            continue;
+        }
+
+        let directive = match chars.next() {
+            Some('%') => {
+                text.push('%');
+                continue;
+            },
+            Some('>') if chars.peek() == Some(&'s') => {
+                chars.next();
+                Directive::Status
+            },
+            Some('{') => {
+                let name: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                match (&*name.to_lowercase(), chars.next()) {
+                    ("referer", Some('i')) => Directive::Referer,
+                    ("user-agent", Some('i')) => Directive::UserAgent,
+                    (_, Some(kind)) => {
+                        text.push_str(&format!("%{{{}}}{}", name, kind));
+                        continue;
+                    },
+                    (_, None) => {
+                        text.push_str(&format!("%{{{}}}", name));
+                        continue;
+                    }
+                }
+            },
+            Some('h') => Directive::Address,
+            Some('l') | Some('u') => Directive::Unknown,
+            Some('t') => Directive::Time,
+            Some('r') => Directive::RequestLine,
+            Some('m') => Directive::Method,
+            Some('U') => Directive::Target,
+            Some('H') => Directive::Version,
+            Some('s') => Directive::Status,
+            Some('b') => Directive::Bytes(true),
+            Some('B') => Directive::Bytes(false),
+            Some('D') => Directive::Microseconds,
+            Some('T') => Directive::Seconds,
+            Some(other) => {
+                text.push('%');
+                text.push(other);
+                continue;
+            },
+            None => {
+                text.push('%');
+                continue;
+            }
+        };
+
+        if !text.is_empty() {
+            directives.push(Directive::Text(::std::mem::replace(&mut text, String::new())));
+        }
+        directives.push(directive);
+    }
+
+    if !text.is_empty() {
+        directives.push(Directive::Text(text));
+    }
+
+    directives
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use time::{self, Duration};
+    use log::Quiet;
+    use server::Dispatcher;
+    use {Server, Context, Response, Method, StatusCode, HttpVersion};
+    use super::{AccessLog, Completion, COMBINED};
+
+    #[test]
+    fn format_access_log() {
+        let completion = Completion {
+            method: Method::Get,
+            request_target: "/index.html?a=b".into(),
+            http_version: HttpVersion::Http11,
+            referer: None,
+            user_agent: Some("curl/7.0".into()),
+            time: time::strptime("10/Oct/2000:13:55:36", "%d/%b/%Y:%H:%M:%S").unwrap(),
+            address: "127.0.0.1:8080".parse().unwrap(),
+            status: StatusCode::NotFound,
+            bytes: 0,
+            duration: Duration::milliseconds(1500)
+        };
+
+        let log = AccessLog::new(COMBINED, Quiet);
+        assert_eq!(log.format(&completion), "127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] \"GET /index.html?a=b HTTP/1.1\" 404 - \"-\" \"curl/7.0\"");
+
+        let log = AccessLog::new("%m %s %B %D %T 100%% %x %{X-Other}i", Quiet);
+        assert_eq!(log.format(&completion), "GET 404 0 1500000 1 100% %x %{X-Other}i");
+    }
+
+    #[test]
+    fn escape_fields() {
+        let completion = Completion {
+            method: Method::Get,
+            request_target: "/a\"b".into(),
+            http_version: HttpVersion::Http11,
+            referer: Some("x\" \"y\\".into()),
+            user_agent: Some("agent\r\n127.0.0.1 - - \x7f".into()),
+            time: time::strptime("10/Oct/2000:13:55:36", "%d/%b/%Y:%H:%M:%S").unwrap(),
+            address: "127.0.0.1:8080".parse().unwrap(),
+            status: StatusCode::Ok,
+            bytes: 0,
+            duration: Duration::zero()
+        };
+
+        let log = AccessLog::new("\"%r\" \"%{Referer}i\" \"%{User-Agent}i\"", Quiet);
+        assert_eq!(log.format(&completion), r#""GET /a\x22b HTTP/1.1" "x\x22 \x22y\x5c" "agent\x0d\x0a127.0.0.1 - - \x7f""#);
+    }
+
+    #[test]
+    fn complete_requests() {
+        fn handler(context: Context, mut response: Response) {
+            if context.uri.as_utf8_path() == Some("/missing") {
+                response.set_status(StatusCode::NotFound);
+            } else {
+                response.send("hello");
+            }
+        }
+
+        let completions = Arc::new(Mutex::new(vec![]));
+        let recorded = completions.clone();
+        let server = Server {
+            completion_hook: Some(Box::new(move |completion: &Completion| recorded.lock().unwrap().push(completion.clone()))),
+            ..Server::new(handler as fn(Context, Response))
+        };
+        let (instance, _scheme) = server.build();
+        let address = "127.0.0.1:8080".parse().unwrap();
+
+        instance.dispatch(b"GET /a?b=c HTTP/1.1\r\nHost: localhost\r\nUser-Agent: test\r\n\r\n", address);
+        instance.dispatch(b"HEAD /a HTTP/1.1\r\nHost: localhost\r\n\r\n", address);
+        instance.dispatch(b"GET /missing HTTP/1.1\r\nHost: localhost\r\n\r\n", address);
+
+        let completions = completions.lock().unwrap();
+        assert_eq!(completions.len(), 3);
+
+        assert_eq!(completions[0].method, Method::Get);
+        assert_eq!(completions[0].request_target, "/a?b=c");
+        assert_eq!(completions[0].user_agent, Some("test".into()));
+        assert_eq!(completions[0].address, address);
+        assert_eq!(completions[0].status, StatusCode::Ok);
+        assert_eq!(completions[0].bytes, 5);
+
+        assert_eq!(completions[1].method, Method::Head);
+        assert_eq!(completions[1].status, StatusCode::Ok);
+        assert_eq!(completions[1].bytes, 0);
+
+        assert_eq!(completions[2].status, StatusCode::NotFound);
+    }
+}
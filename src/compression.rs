@@ -0,0 +1,358 @@
+//!Response compression.
+//!
+//!Response bodies can be compressed with gzip, deflate or, with the `brotli`
+//!feature, brotli, depending on what the client accepts in its
+//!`Accept-Encoding` header. Brotli is only chosen if the client gives it a
+//!higher quality than the others. It's enabled by
+//!setting the server's `compression` field:
+//!
+//!```
+//!use rustful::{Server, Context, Response};
+//!use rustful::compression::Compression;
+//!
+//!let server = Server {
+//!    compression: Some(Compression {
+//!        min_size: 512,
+//!        ..Compression::default()
+//!    }),
+//!    ..Server::new(|_: Context, response: Response| response.send("a long text"))
+//!};
+//!```
+//!
+//!Sized responses, from `Response::send`, are only compressed if they are
+//!at least `min_size` bytes, and they get the `Content-Length` of the
+//!compressed body. The size of `Chunked` responses is unknown, so they are
+//!always compressed if the content type allows it. `Raw` responses are never
+//!compressed, since their length is set in advance.
+//!
+//!This module is only available with the `compression` feature.
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use flate2;
+use flate2::write::{GzEncoder, ZlibEncoder};
+#[cfg(feature = "brotli")]
+use brotli2::write::BrotliEncoder;
+
+use header::{Headers, AcceptEncoding, ContentEncoding, ContentType, Encoding};
+use StatusCode;
+
+///Settings for response compression.
+#[derive(Clone, Debug)]
+pub struct Compression {
+    ///The smallest sized response body, in bytes, that is compressed.
+    ///Smaller bodies are not worth the effort. Default is 1024.
+    pub min_size: usize,
+
+    ///Media types that are never compressed, because they are usually
+    ///compressed already. An entry that ends with `/`, such as `image/`,
+    ///matches all subtypes. Default is `image/`, `audio/`, `video/`,
+    ///`application/zip`, `application/gzip` and `font/woff2`.
+    pub skip: Vec<String>
+}
+
+impl Compression {
+    ///Find the best content coding for a request with the headers
+    ///`headers`, if the client accepts any of them. A `*` means `gzip`,
+    ///unless `gzip` is listed on its own.
+    pub fn negotiate(&self, headers: &Headers) -> Option<Coding> {
+        let accepted = match headers.get::<AcceptEncoding>() {
+            Some(accepted) => accepted,
+            None => return None
+        };
+
+        let mut codings = vec![];
+        let mut wildcard = None;
+        for item in accepted.iter() {
+            let coding = match item.item {
+                Encoding::Gzip => Coding::Gzip,
+                Encoding::Deflate => Coding::Deflate,
+                Encoding::EncodingExt(ref coding) if coding == "*" => {
+                    wildcard = Some(item.quality.0);
+                    continue;
+                },
+                #[cfg(feature = "brotli")]
+                Encoding::EncodingExt(ref coding) if coding == "br" => Coding::Brotli,
+                _ => continue
+            };
+
+            codings.push((coding, item.quality.0));
+        }
+
+        //An explicit `gzip;q=0` beats a `*`.
+        if let Some(quality) = wildcard {
+            if !codings.iter().any(|&(coding, _)| coding == Coding::Gzip) {
+                codings.push((Coding::Gzip, quality));
+            }
+        }
+
+        let mut best: Option<(Coding, u16)> = None;
+        for (coding, quality) in codings {
+            let better = match best {
+                Some((best_coding, best_quality)) => quality > best_quality || (quality == best_quality && coding < best_coding),
+                None => quality > 0
+            };
+
+            if better {
+                best = Some((coding, quality));
+            }
+        }
+
+        best.map(|(coding, _)| coding)
+    }
+
+    ///Check if a response with the status `status` and the headers
+    ///`headers` can be compressed. It's not if it has no body, if it's
+    ///encoded already, or if its content type is in the skip list.
+    pub fn applies_to(&self, status: StatusCode, headers: &Headers) -> bool {
+        match status.to_u16() {
+            100...199 | 204 | 304 => return false,
+            _ => {}
+        }
+
+        if headers.has::<ContentEncoding>() {
+            return false;
+        }
+
+        match headers.get::<ContentType>() {
+            Some(&ContentType(ref mime)) => {
+                let media_type = format!("{}/{}", mime.0, mime.1).to_lowercase();
+                !self.skip.iter().any(|skip| {
+                    if skip.ends_with('/') {
+                        media_type.starts_with(&**skip)
+                    } else {
+                        media_type == *skip
+                    }
+                })
+            },
+            None => true
+        }
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Compression {
+        Compression {
+            min_size: 1024,
+            skip: vec![
+                "image/".into(),
+                "audio/".into(),
+                "video/".into(),
+                "application/zip".into(),
+                "application/gzip".into(),
+                "font/woff2".into()
+            ]
+        }
+    }
+}
+
+///A supported content coding, in order of preference.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Coding {
+    ///The gzip format.
+    Gzip,
+    ///The zlib format, which is what `deflate` means in HTTP.
+    Deflate,
+    ///The brotli format, which is called `br` in HTTP. It's only available
+    ///with the `brotli` feature.
+    #[cfg(feature = "brotli")]
+    Brotli
+}
+
+impl Coding {
+    ///Get the corresponding `Content-Encoding` value.
+    pub fn encoding(&self) -> Encoding {
+        match *self {
+            Coding::Gzip => Encoding::Gzip,
+            Coding::Deflate => Encoding::Deflate,
+            #[cfg(feature = "brotli")]
+            Coding::Brotli => Encoding::EncodingExt("br".into())
+        }
+    }
+}
+
+///Compress `data` in one go.
+pub fn compress(coding: Coding, data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = Encoder::new(coding);
+    let mut compressed = try!(encoder.write(data));
+    compressed.extend(try!(encoder.finish()));
+    Ok(compressed)
+}
+
+//A moderate quality, since the highest ones are too slow for responses
+//that are compressed on the fly.
+#[cfg(feature = "brotli")]
+const BROTLI_LEVEL: u32 = 5;
+
+#[doc(hidden)]
+///Internal and may change without warning.
+pub struct Encoder {
+    encoder: Inner,
+    output: Rc<RefCell<Vec<u8>>>
+}
+
+enum Inner {
+    Gzip(GzEncoder<Output>),
+    Deflate(ZlibEncoder<Output>),
+    #[cfg(feature = "brotli")]
+    Brotli(BrotliEncoder<Output>)
+}
+
+impl Encoder {
+    pub fn new(coding: Coding) -> Encoder {
+        let output = Rc::new(RefCell::new(vec![]));
+        let sink = Output(output.clone());
+
+        Encoder {
+            encoder: match coding {
+                Coding::Gzip => Inner::Gzip(GzEncoder::new(sink, flate2::Compression::Default)),
+                Coding::Deflate => Inner::Deflate(ZlibEncoder::new(sink, flate2::Compression::Default)),
+                #[cfg(feature = "brotli")]
+                Coding::Brotli => Inner::Brotli(BrotliEncoder::new(sink, BROTLI_LEVEL))
+            },
+            output: output
+        }
+    }
+
+    //Compresses `data` and returns the compressed data that is ready so far.
+    pub fn write(&mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+        try!(match self.encoder {
+            Inner::Gzip(ref mut encoder) => encoder.write_all(data),
+            Inner::Deflate(ref mut encoder) => encoder.write_all(data),
+            #[cfg(feature = "brotli")]
+            Inner::Brotli(ref mut encoder) => encoder.write_all(data)
+        });
+        Ok(self.take_output())
+    }
+
+    //Returns everything that has been written so far, in compressed form.
+    pub fn flush(&mut self) -> io::Result<Vec<u8>> {
+        try!(match self.encoder {
+            Inner::Gzip(ref mut encoder) => encoder.flush(),
+            Inner::Deflate(ref mut encoder) => encoder.flush(),
+            #[cfg(feature = "brotli")]
+            Inner::Brotli(ref mut encoder) => encoder.flush()
+        });
+        Ok(self.take_output())
+    }
+
+    //Returns the rest of the compressed data.
+    pub fn finish(self) -> io::Result<Vec<u8>> {
+        try!(match self.encoder {
+            Inner::Gzip(encoder) => encoder.finish().map(|_| ()),
+            Inner::Deflate(encoder) => encoder.finish().map(|_| ()),
+            #[cfg(feature = "brotli")]
+            Inner::Brotli(encoder) => encoder.finish().map(|_| ())
+        });
+        let output = self.output.borrow().clone();
+        Ok(output)
+    }
+
+    fn take_output(&mut self) -> Vec<u8> {
+        ::std::mem::replace(&mut *self.output.borrow_mut(), vec![])
+    }
+}
+
+//Collects the compressed data, so it can be taken out between writes.
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+    use flate2::read::GzDecoder;
+    use header::{Headers, ContentType, ContentEncoding, Encoding};
+    use mime::{Mime, TopLevel, SubLevel};
+    use StatusCode;
+    use super::{Compression, Coding, Encoder, compress};
+
+    #[test]
+    fn negotiate_coding() {
+        let compression = Compression::default();
+        let mut headers = Headers::new();
+        assert_eq!(compression.negotiate(&headers), None);
+
+        headers.set_raw("Accept-Encoding", vec![b"deflate, gzip".to_vec()]);
+        assert_eq!(compression.negotiate(&headers), Some(Coding::Gzip));
+
+        headers.set_raw("Accept-Encoding", vec![b"gzip;q=0.5, deflate".to_vec()]);
+        assert_eq!(compression.negotiate(&headers), Some(Coding::Deflate));
+
+        headers.set_raw("Accept-Encoding", vec![b"gzip;q=0, identity".to_vec()]);
+        assert_eq!(compression.negotiate(&headers), None);
+
+        headers.set_raw("Accept-Encoding", vec![b"*".to_vec()]);
+        assert_eq!(compression.negotiate(&headers), Some(Coding::Gzip));
+
+        headers.set_raw("Accept-Encoding", vec![b"gzip;q=0, *".to_vec()]);
+        assert_eq!(compression.negotiate(&headers), None);
+
+        headers.set_raw("Accept-Encoding", vec![b"*, gzip;q=0, deflate;q=0.5".to_vec()]);
+        assert_eq!(compression.negotiate(&headers), Some(Coding::Deflate));
+    }
+
+    #[test]
+    #[cfg(feature = "brotli")]
+    fn negotiate_brotli() {
+        let compression = Compression::default();
+        let mut headers = Headers::new();
+
+        headers.set_raw("Accept-Encoding", vec![b"gzip, br".to_vec()]);
+        assert_eq!(compression.negotiate(&headers), Some(Coding::Gzip));
+
+        headers.set_raw("Accept-Encoding", vec![b"gzip;q=0.8, br".to_vec()]);
+        assert_eq!(compression.negotiate(&headers), Some(Coding::Brotli));
+
+        let compressed = compress(Coding::Brotli, b"hello world").unwrap();
+        let mut decompressed = String::new();
+        ::brotli2::read::BrotliDecoder::new(&compressed[..]).read_to_string(&mut decompressed).unwrap();
+        assert_eq!(decompressed, "hello world");
+    }
+
+    #[test]
+    fn skip_responses() {
+        let compression = Compression::default();
+        let mut headers = Headers::new();
+        assert!(compression.applies_to(StatusCode::Ok, &headers));
+        assert!(!compression.applies_to(StatusCode::NotModified, &headers));
+
+        headers.set(ContentType(Mime(TopLevel::Image, SubLevel::Png, vec![])));
+        assert!(!compression.applies_to(StatusCode::Ok, &headers));
+
+        headers.set(ContentType(Mime(TopLevel::Text, SubLevel::Html, vec![])));
+        assert!(compression.applies_to(StatusCode::Ok, &headers));
+
+        headers.set(ContentEncoding(vec![Encoding::Gzip]));
+        assert!(!compression.applies_to(StatusCode::Ok, &headers));
+    }
+
+    #[test]
+    fn compress_stream() {
+        let mut encoder = Encoder::new(Coding::Gzip);
+        let mut compressed = encoder.write(b"hello ").unwrap();
+        compressed.extend(encoder.flush().unwrap());
+        compressed.extend(encoder.write(b"world").unwrap());
+        compressed.extend(encoder.finish().unwrap());
+
+        let mut decompressed = String::new();
+        GzDecoder::new(&compressed[..]).unwrap().read_to_string(&mut decompressed).unwrap();
+        assert_eq!(decompressed, "hello world");
+
+        let compressed = compress(Coding::Gzip, b"hello world").unwrap();
+        let mut decompressed = String::new();
+        GzDecoder::new(&compressed[..]).unwrap().read_to_string(&mut decompressed).unwrap();
+        assert_eq!(decompressed, "hello world");
+    }
+}
@@ -4,27 +4,229 @@
 use rustc_serialize::json;
 #[cfg(feature = "rustc_json_body")]
 use rustc_serialize::Decodable;
+#[cfg(feature = "rustc_json_body")]
+use std::collections::{HashMap, BTreeMap};
 
 #[cfg(feature = "multipart")]
 use multipart::server::{HttpRequest, Multipart};
 
-use std::io::{self, Read};
+#[cfg(feature = "decompression")]
+use flate2::read::{GzDecoder, ZlibDecoder};
+#[cfg(feature = "decompression")]
+use std::sync::Arc;
+#[cfg(feature = "decompression")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use std::io::{self, Read, BufRead, Cursor};
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::str::from_utf8;
+#[cfg(feature = "rustc_json_body")]
+use std::rc::Rc;
+#[cfg(feature = "rustc_json_body")]
+use std::cell::RefCell;
 
 use hyper::buffer::BufReader;
 use hyper::http::h1::HttpReader;
 use hyper::net::NetworkStream;
 
 use context::Parameters;
-use header::Headers;
+use header::{Headers, ContentLength};
+
+type RequestReader<'a, 'b> = HttpReader<&'a mut BufReader<&'b mut NetworkStream>>;
+
+//The maximum total size of the trailer headers.
+const MAX_TRAILERS_SIZE: u64 = 8192;
+
+//The size of the read-ahead buffer, used by the `BufRead` methods.
+const READ_AHEAD_SIZE: usize = 8192;
 
 ///A reader for a request body.
+///
+///It implements `BufRead`, so line based formats can be read with
+///`read_line` or `lines`:
+///
+///```
+///use std::io::BufRead;
+///use rustful::{Context, Response};
+///
+///fn import(context: Context, response: Response) {
+///    let mut count = 0;
+///    for line in context.body.lines() {
+///        match line {
+///            Ok(ref line) if line.is_empty() => continue,
+///            Ok(_) => count += 1,
+///            Err(_) => break
+///        }
+///    }
+///
+///    response.send(format!("imported {} records", count));
+///}
+///```
 pub struct BodyReader<'a, 'b: 'a> {
-    reader: HttpReader<&'a mut BufReader<&'b mut NetworkStream>>,
+    reader: Decoder<RequestReader<'a, 'b>>,
+    content_length: Option<u64>,
+    limit: Option<u64>,
+    trailers: Option<Headers>,
+    buffer: Option<Cursor<Vec<u8>>>,
+    read_ahead: Cursor<Vec<u8>>,
+
+    #[cfg(feature = "rustc_json_body")]
+    media_type: Option<String>,
+    #[cfg(feature = "rustc_json_body")]
+    decoders: Option<&'a BodyDecoders>,
+
+    #[cfg(feature = "decompression")]
+    guard: Option<BombGuard>,
 
     #[cfg(feature = "multipart")]
     multipart_boundary: Option<String>
 }
 
+impl<'a, 'b> BodyReader<'a, 'b> {
+    ///Get the size limit for the convenience readers, such as
+    ///`read_query_body`, if any.
+    pub fn limit(&self) -> Option<u64> {
+        self.limit
+    }
+
+    ///Set the size limit for the convenience readers, such as
+    ///`read_query_body`. The default is taken from the `max_body_size`
+    ///setting of the server.
+    pub fn set_limit(&mut self, limit: Option<u64>) {
+        self.limit = limit;
+    }
+
+    ///Read the whole body into `buf`, but fail with a [`TooLarge`][too_large]
+    ///error if it's longer than `max` bytes. The error is returned before
+    ///anything is read if the `Content-Length` header already tells that the
+    ///body is too large.
+    ///
+    ///```
+    ///use std::io;
+    ///use rustful::{Context, Response};
+    ///use rustful::StatusCode::{PayloadTooLarge, BadRequest};
+    ///use rustful::context::body::TooLarge;
+    ///
+    ///fn my_handler(mut context: Context, mut response: Response) {
+    ///    let mut body = vec![];
+    ///    match context.body.read_to_end_limited(&mut body, 1024) {
+    ///        Ok(_) => response.send(format!("got {} bytes", body.len())),
+    ///        Err(ref e) if TooLarge::is_cause_of(e) => response.set_status(PayloadTooLarge),
+    ///        Err(_) => response.set_status(BadRequest)
+    ///    }
+    ///}
+    ///```
+    ///
+    ///[too_large]: struct.TooLarge.html
+    pub fn read_to_end_limited(&mut self, buf: &mut Vec<u8>, max: u64) -> io::Result<usize> {
+        if let Some(length) = self.content_length {
+            if length > max {
+                return Err(TooLarge { limit: max }.into());
+            }
+        }
+
+        //Read one byte more than allowed, to see if there is more.
+        let length = try!(self.by_ref().take(max.saturating_add(1)).read_to_end(buf));
+        if length as u64 > max {
+            Err(TooLarge { limit: max }.into())
+        } else {
+            Ok(length)
+        }
+    }
+
+    ///Get the trailer headers that were sent after a chunked request body.
+    ///They are only available after the whole body has been read, and
+    ///`None` is returned if the body is not chunked.
+    ///
+    ///```
+    ///use std::io::Read;
+    ///use rustful::{Context, Response};
+    ///
+    ///fn my_handler(mut context: Context, response: Response) {
+    ///    let mut body = vec![];
+    ///    context.body.read_to_end(&mut body).unwrap();
+    ///
+    ///    let checksum = context.body.trailers()
+    ///        .and_then(|trailers| trailers.get_raw("Checksum"))
+    ///        .and_then(|values| values.first())
+    ///        .map(|value| String::from_utf8_lossy(value).into_owned());
+    ///
+    ///    response.send(format!("got {} bytes with the checksum {:?}", body.len(), checksum));
+    ///}
+    ///```
+    pub fn trailers(&self) -> Option<&Headers> {
+        self.trailers.as_ref()
+    }
+
+    ///Read the rest of the body into memory, but fail with a
+    ///[`TooLarge`][too_large] error if it's longer than `max` bytes. The
+    ///buffered bytes are returned, and the body can still be read as usual
+    ///afterwards. This makes it possible for a filter to check the raw body,
+    ///while the handler parses it.
+    ///
+    ///The body is only buffered once, so later calls will return the same
+    ///bytes, regardless of `max`. Anything that was read before the first
+    ///call is not included.
+    ///
+    ///```
+    ///use rustful::{Context, StatusCode};
+    ///use rustful::header::Headers;
+    ///use rustful::filter::{ContextFilter, ContextAction, FilterContext};
+    ///
+    ///# fn has_valid_signature(headers: &Headers, body: &[u8]) -> bool { true }
+    ///struct VerifySignature;
+    ///
+    ///impl ContextFilter for VerifySignature {
+    ///    fn modify(&self, _: FilterContext, context: &mut Context) -> ContextAction {
+    ///        let valid = match context.body.buffer(64 * 1024) {
+    ///            Ok(body) => has_valid_signature(&context.headers, body),
+    ///            Err(_) => false
+    ///        };
+    ///
+    ///        if valid {
+    ///            ContextAction::next()
+    ///        } else {
+    ///            ContextAction::abort(StatusCode::Unauthorized)
+    ///        }
+    ///    }
+    ///}
+    ///```
+    ///
+    ///[too_large]: struct.TooLarge.html
+    pub fn buffer(&mut self, max: u64) -> io::Result<&[u8]> {
+        if self.buffer.is_none() {
+            let mut buf = vec![];
+            try!(self.read_to_end_limited(&mut buf, max));
+            self.buffer = Some(Cursor::new(buf));
+        }
+
+        Ok(self.buffer.as_ref().map(|buffer| &buffer.get_ref()[..]).unwrap_or(&[]))
+    }
+
+    ///Get the buffered body, if `buffer` has been called.
+    pub fn buffered(&self) -> Option<&[u8]> {
+        self.buffer.as_ref().map(|buffer| &buffer.get_ref()[..])
+    }
+
+    ///Start reading a buffered body from the beginning again. It does
+    ///nothing if the body is not buffered.
+    pub fn rewind(&mut self) {
+        if let Some(ref mut buffer) = self.buffer {
+            buffer.set_position(0);
+        }
+    }
+
+    //Reads the whole body, respecting the limit, if any.
+    fn read_to_end_checked(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        match self.limit {
+            Some(limit) => self.read_to_end_limited(buf, limit),
+            None => self.read_to_end(buf)
+        }
+    }
+}
+
 #[cfg(feature = "multipart")]
 impl<'a, 'b> BodyReader<'a, 'b> {
     ///Try to create a `multipart/form-data` reader from the request body.
@@ -66,11 +268,10 @@ impl<'a, 'b> BodyReader<'a, 'b> {
     ///# fn main() {}
     ///```
     pub fn as_multipart<'r>(&'r mut self) -> Option<Multipart<MultipartRequest<'r, 'a, 'b>>> {
-        let reader = &mut self.reader;
-        self.multipart_boundary.as_ref().and_then(move |boundary|
+        self.multipart_boundary.clone().and_then(move |boundary|
             Multipart::from_request(MultipartRequest {
                 boundary: boundary,
-                reader: reader
+                reader: self
             }).ok()
         )
     }
@@ -95,7 +296,18 @@ impl<'a, 'b> BodyReader<'a, 'b> {
         };
 
         BodyReader {
-            reader: reader,
+            reader: Decoder::Identity(reader),
+            content_length: headers.get().map(|&ContentLength(length)| length),
+            limit: None,
+            trailers: None,
+            buffer: None,
+            read_ahead: Cursor::new(vec![]),
+            #[cfg(feature = "rustc_json_body")]
+            media_type: media_type(headers),
+            #[cfg(feature = "rustc_json_body")]
+            decoders: None,
+            #[cfg(feature = "decompression")]
+            guard: None,
             multipart_boundary: boundary
         }
     }
@@ -105,17 +317,556 @@ impl<'a, 'b> BodyReader<'a, 'b> {
 impl<'a, 'b> BodyReader<'a, 'b> {
     #[doc(hidden)]
     ///Internal and may change without warning.
-    pub fn from_reader(reader: HttpReader<&'a mut BufReader<&'b mut NetworkStream>>, _headers: &Headers) -> BodyReader<'a, 'b> {
+    pub fn from_reader(reader: HttpReader<&'a mut BufReader<&'b mut NetworkStream>>, headers: &Headers) -> BodyReader<'a, 'b> {
         BodyReader {
-            reader: reader
+            reader: Decoder::Identity(reader),
+            content_length: headers.get().map(|&ContentLength(length)| length),
+            limit: None,
+            trailers: None,
+            buffer: None,
+            read_ahead: Cursor::new(vec![]),
+            #[cfg(feature = "rustc_json_body")]
+            media_type: media_type(headers),
+            #[cfg(feature = "rustc_json_body")]
+            decoders: None,
+            #[cfg(feature = "decompression")]
+            guard: None
+        }
+    }
+}
+
+#[cfg(feature = "rustc_json_body")]
+impl<'a, 'b> BodyReader<'a, 'b> {
+    ///Read and decode the request body as a type `T`, using the decoder for
+    ///the media type in the `Content-Type` header. The decoders are
+    ///registered in the server's `body_decoders`, and JSON and URL encoded
+    ///forms are supported by default. The size limit of the `BodyReader` is
+    ///respected, if any.
+    ///
+    ///```
+    ///extern crate rustful;
+    ///extern crate rustc_serialize;
+    ///
+    ///use rustful::{Context, Response};
+    ///use rustful::StatusCode::{BadRequest, UnsupportedMediaType};
+    ///use rustful::context::body::DecodeError;
+    ///
+    ///#[derive(RustcDecodable)]
+    ///struct Comment {
+    ///    author: String,
+    ///    text: String
+    ///}
+    ///
+    ///fn add_comment(mut context: Context, mut response: Response) {
+    ///    match context.body.decode::<Comment>() {
+    ///        Ok(comment) => response.send(format!("{} said {}", comment.author, comment.text)),
+    ///        Err(DecodeError::UnsupportedMediaType(_)) => response.set_status(UnsupportedMediaType),
+    ///        Err(_) => response.set_status(BadRequest)
+    ///    }
+    ///}
+    ///# fn main() {}
+    ///```
+    pub fn decode<T: Decodable>(&mut self) -> Result<T, DecodeError> {
+        let decoder = match (self.decoders, self.media_type.as_ref()) {
+            (Some(decoders), Some(media_type)) => decoders.get(media_type),
+            _ => None
+        };
+
+        let decoder = match decoder {
+            Some(decoder) => decoder,
+            None => return Err(DecodeError::UnsupportedMediaType(self.media_type.clone()))
+        };
+
+        let mut body = Vec::new();
+        try!(self.read_to_end_checked(&mut body));
+        let value = try!(decoder.decode(&body));
+        T::decode(&mut json::Decoder::new(value)).map_err(DecodeError::Decode)
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn set_decoders(&mut self, decoders: &'a BodyDecoders) {
+        self.decoders = Some(decoders);
+    }
+}
+
+//Gets the lower case `type/subtype` from the `Content-Type` header.
+#[cfg(feature = "rustc_json_body")]
+fn media_type(headers: &Headers) -> Option<String> {
+    headers.get::<::header::ContentType>().map(|&::header::ContentType(::mime::Mime(ref top, ref sub, _))| {
+        format!("{}/{}", top, sub).to_lowercase()
+    })
+}
+
+///Decodes request bodies of a certain media type. See
+///`BodyReader::decode`.
+///
+///The body is decoded into a generic JSON structure, which is then decoded
+///into the target type, so a decoder for any format that maps to JSON values
+///can be added. It's implemented for functions and closures with the same
+///signature as `decode`.
+#[cfg(feature = "rustc_json_body")]
+pub trait BodyDecoder: Send + Sync {
+    ///Decode the whole body.
+    fn decode(&self, body: &[u8]) -> Result<json::Json, DecodeError>;
+}
+
+#[cfg(feature = "rustc_json_body")]
+impl<F: Fn(&[u8]) -> Result<json::Json, DecodeError> + Send + Sync> BodyDecoder for F {
+    fn decode(&self, body: &[u8]) -> Result<json::Json, DecodeError> {
+        self(body)
+    }
+}
+
+///A collection of body decoders, keyed by media type.
+///
+///```
+///extern crate rustful;
+///extern crate rustc_serialize;
+///
+///use rustc_serialize::json::Json;
+///use rustful::Server;
+///use rustful::context::body::DecodeError;
+///
+/////Each line is a string.
+///fn decode_lines(body: &[u8]) -> Result<Json, DecodeError> {
+///    let text = try!(String::from_utf8(body.to_vec()).map_err(|_| DecodeError::Syntax("not UTF-8".into())));
+///    Ok(Json::Array(text.lines().map(|line| Json::String(line.into())).collect()))
+///}
+///
+///# fn main() {
+///# let handler = |_: rustful::Context, _: rustful::Response| {};
+///let mut server = Server::new(handler);
+///server.body_decoders.insert("text/plain", decode_lines);
+///# }
+///```
+#[cfg(feature = "rustc_json_body")]
+pub struct BodyDecoders {
+    decoders: HashMap<String, Box<BodyDecoder>>
+}
+
+#[cfg(feature = "rustc_json_body")]
+impl BodyDecoders {
+    ///Create an empty collection, without the default decoders.
+    pub fn new() -> BodyDecoders {
+        BodyDecoders {
+            decoders: HashMap::new()
+        }
+    }
+
+    ///Add or replace the decoder for `media_type`, such as
+    ///`application/msgpack`.
+    pub fn insert<D: BodyDecoder + 'static>(&mut self, media_type: &str, decoder: D) {
+        self.decoders.insert(media_type.to_lowercase(), Box::new(decoder));
+    }
+
+    ///Remove the decoder for `media_type`.
+    pub fn remove(&mut self, media_type: &str) {
+        self.decoders.remove(&media_type.to_lowercase());
+    }
+
+    ///Get the decoder for `media_type`. Types with a `+json` suffix, such as
+    ///`application/hal+json`, use the `application/json` decoder if they
+    ///don't have one of their own.
+    pub fn get(&self, media_type: &str) -> Option<&BodyDecoder> {
+        let media_type = media_type.to_lowercase();
+        self.decoders.get(&media_type)
+            .or_else(|| if media_type.ends_with("+json") {
+                self.decoders.get("application/json")
+            } else {
+                None
+            })
+            .map(|decoder| &**decoder)
+    }
+}
+
+#[cfg(feature = "rustc_json_body")]
+impl Default for BodyDecoders {
+    ///Create a collection with decoders for `application/json` and
+    ///`application/x-www-form-urlencoded`. The form values are decoded as
+    ///strings.
+    fn default() -> BodyDecoders {
+        let mut decoders = BodyDecoders::new();
+        decoders.insert("application/json", decode_json);
+        decoders.insert("application/x-www-form-urlencoded", decode_form);
+        decoders
+    }
+}
+
+#[cfg(feature = "rustc_json_body")]
+fn decode_json(body: &[u8]) -> Result<json::Json, DecodeError> {
+    json::Json::from_reader(&mut &body[..]).map_err(|e| DecodeError::Syntax(e.to_string()))
+}
+
+#[cfg(feature = "rustc_json_body")]
+fn decode_form(body: &[u8]) -> Result<json::Json, DecodeError> {
+    let fields = ::utils::parse_parameters(body).into_iter().map(|(key, value)| {
+        (key.as_utf8_lossy().into_owned(), json::Json::String(value.as_utf8_lossy().into_owned()))
+    }).collect::<BTreeMap<_, _>>();
+
+    Ok(json::Json::Object(fields))
+}
+
+///The error that is produced when a request body can't be decoded.
+#[cfg(feature = "rustc_json_body")]
+#[derive(Debug)]
+pub enum DecodeError {
+    ///There is no decoder for the media type of the body. The media type is
+    ///included if the `Content-Type` header is set. It should usually be
+    ///answered with `415 Unsupported Media Type`.
+    UnsupportedMediaType(Option<String>),
+
+    ///The body could not be read.
+    Io(io::Error),
+
+    ///The body is not valid for its media type.
+    Syntax(String),
+
+    ///The decoded body doesn't match the target type.
+    Decode(json::DecoderError)
+}
+
+#[cfg(feature = "rustc_json_body")]
+impl From<io::Error> for DecodeError {
+    fn from(error: io::Error) -> DecodeError {
+        DecodeError::Io(error)
+    }
+}
+
+#[cfg(feature = "rustc_json_body")]
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::UnsupportedMediaType(Some(ref media_type)) => write!(f, "unsupported media type: {}", media_type),
+            DecodeError::UnsupportedMediaType(None) => write!(f, "the media type is missing"),
+            DecodeError::Io(ref e) => write!(f, "could not read the body: {}", e),
+            DecodeError::Syntax(ref e) => write!(f, "invalid body: {}", e),
+            DecodeError::Decode(ref e) => write!(f, "could not decode the body: {}", e)
+        }
+    }
+}
+
+#[cfg(feature = "rustc_json_body")]
+impl Error for DecodeError {
+    fn description(&self) -> &str {
+        match *self {
+            DecodeError::UnsupportedMediaType(_) => "unsupported media type",
+            DecodeError::Io(ref e) => e.description(),
+            DecodeError::Syntax(_) => "invalid body",
+            DecodeError::Decode(ref e) => e.description()
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            DecodeError::Io(ref e) => Some(e),
+            DecodeError::Decode(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+#[cfg(feature = "decompression")]
+impl<'a, 'b> BodyReader<'a, 'b> {
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn decompress(mut self, headers: &mut Headers, limits: &DecompressionLimits) -> io::Result<BodyReader<'a, 'b>> {
+        use header::{ContentEncoding, Encoding};
+
+        let encoding = match headers.get::<ContentEncoding>() {
+            Some(&ContentEncoding(ref encodings)) if encodings.len() == 1 => encodings[0].clone(),
+            _ => return Ok(self)
+        };
+
+        let compressed = Arc::new(AtomicUsize::new(0));
+
+        self.reader = match (self.reader, encoding) {
+            (Decoder::Identity(reader), Encoding::Gzip) => {
+                Decoder::Gzip(try!(GzDecoder::new(Counted::new(reader, compressed.clone()))))
+            },
+            (Decoder::Identity(reader), Encoding::Deflate) => {
+                Decoder::Deflate(ZlibDecoder::new(Counted::new(reader, compressed.clone())))
+            },
+            (reader, _) => {
+                self.reader = reader;
+                return Ok(self);
+            }
+        };
+
+        self.guard = Some(BombGuard {
+            limits: limits.clone(),
+            compressed: compressed,
+            decompressed: 0,
+            head: None
+        });
+
+        //The body is now presented as if it was never encoded, and the
+        //length of the decompressed body is unknown.
+        self.content_length = None;
+        headers.remove::<ContentEncoding>();
+        headers.remove::<ContentLength>();
+
+        Ok(self)
+    }
+}
+
+///Limits for decompressed request bodies, to protect the server from
+///decompression bombs. A body that exceeds them can't be read any further,
+///and the read fails with a [`DecompressionBomb`][bomb] error.
+///
+///It's only available with the `decompression` feature.
+///
+///[bomb]: struct.DecompressionBomb.html
+#[cfg(feature = "decompression")]
+#[derive(Clone, Debug)]
+pub struct DecompressionLimits {
+    ///The highest allowed ratio between the decompressed and the compressed
+    ///size. Default is 100.
+    pub max_ratio: u64,
+
+    ///The decompressed size, in bytes, below which the ratio is not checked,
+    ///since small bodies may compress very well. Default is 1 MiB.
+    pub min_size: u64,
+
+    ///Allow the decompressed body to be another compressed archive, such as
+    ///gzip or zip. Bodies that are compressed in several layers are often
+    ///used to multiply the effect of a bomb. Default is `false`.
+    pub allow_nested: bool,
+
+    triggered: Arc<AtomicUsize>
+}
+
+#[cfg(feature = "decompression")]
+impl DecompressionLimits {
+    ///The number of bodies that have been stopped by these limits, or by any
+    ///of their clones.
+    pub fn triggered(&self) -> usize {
+        self.triggered.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(feature = "decompression")]
+impl Default for DecompressionLimits {
+    fn default() -> DecompressionLimits {
+        DecompressionLimits {
+            max_ratio: 100,
+            min_size: 1024 * 1024,
+            allow_nested: false,
+            triggered: Arc::new(AtomicUsize::new(0))
+        }
+    }
+}
+
+///The error that is produced when a decompressed request body exceeds the
+///`DecompressionLimits`.
+///
+///It's wrapped in an `io::Error`, so `DecompressionBomb::is_cause_of` can be
+///used to check if an IO error was caused by it. It should usually be
+///answered with `413 Payload Too Large`.
+#[cfg(feature = "decompression")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecompressionBomb {
+    ///The compression ratio went above the limit.
+    Ratio(u64),
+
+    ///The decompressed body is another compressed archive.
+    Nested
+}
+
+#[cfg(feature = "decompression")]
+impl DecompressionBomb {
+    ///Check if `error` was caused by a decompression bomb.
+    pub fn is_cause_of(error: &io::Error) -> bool {
+        error.get_ref().map(|e| e.is::<DecompressionBomb>()).unwrap_or(false)
+    }
+}
+
+#[cfg(feature = "decompression")]
+impl fmt::Display for DecompressionBomb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecompressionBomb::Ratio(limit) => write!(f, "the request body is compressed more than {} times", limit),
+            DecompressionBomb::Nested => write!(f, "the request body contains a nested archive")
+        }
+    }
+}
+
+#[cfg(feature = "decompression")]
+impl Error for DecompressionBomb {
+    fn description(&self) -> &str {
+        "the request body is a decompression bomb"
+    }
+}
+
+#[cfg(feature = "decompression")]
+impl Into<io::Error> for DecompressionBomb {
+    fn into(self) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, self)
+    }
+}
+
+//Keeps track of the compressed and decompressed size of a body. The first
+//few decompressed bytes are held back until it's known if they are the
+//start of another archive.
+#[cfg(feature = "decompression")]
+struct BombGuard {
+    limits: DecompressionLimits,
+    compressed: Arc<AtomicUsize>,
+    decompressed: u64,
+    head: Option<Cursor<Vec<u8>>>
+}
+
+#[cfg(feature = "decompression")]
+impl BombGuard {
+    fn read<R: Read>(&mut self, reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+        if self.head.is_none() {
+            //A short read may split the magic number, so the whole magic
+            //number is read before it's checked.
+            let mut head = vec![0; MAGIC_LENGTH];
+            let mut length = 0;
+            while length < head.len() {
+                match reader.read(&mut head[length..]) {
+                    Ok(0) => break,
+                    Ok(read) => length += read,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                    Err(e) => return Err(e)
+                }
+            }
+            head.truncate(length);
+            try!(self.check(&head));
+            self.head = Some(Cursor::new(head));
+        }
+
+        if let Some(ref mut head) = self.head {
+            if (head.position() as usize) < head.get_ref().len() {
+                return head.read(buf);
+            }
+        }
+
+        let length = try!(reader.read(buf));
+        try!(self.check(&buf[..length]));
+        Ok(length)
+    }
+
+    fn check(&mut self, data: &[u8]) -> io::Result<()> {
+        let result = if !self.limits.allow_nested && self.decompressed == 0 && is_archive(data) {
+            Err(DecompressionBomb::Nested)
+        } else {
+            self.decompressed += data.len() as u64;
+            let compressed = self.compressed.load(Ordering::Relaxed) as u64;
+            let max_size = compressed.saturating_mul(self.limits.max_ratio);
+            if self.decompressed > self.limits.min_size && self.decompressed > max_size {
+                Err(DecompressionBomb::Ratio(self.limits.max_ratio))
+            } else {
+                Ok(())
+            }
+        };
+
+        result.map_err(|bomb| {
+            self.limits.triggered.fetch_add(1, Ordering::Relaxed);
+            bomb.into()
+        })
+    }
+}
+
+//The length of the longest magic number in `is_archive`.
+#[cfg(feature = "decompression")]
+const MAGIC_LENGTH: usize = 4;
+
+//Checks for the magic numbers of gzip and zip.
+#[cfg(feature = "decompression")]
+fn is_archive(data: &[u8]) -> bool {
+    data.starts_with(&[0x1f, 0x8b]) || data.starts_with(b"PK\x03\x04")
+}
+
+//Counts the bytes that are read from the inner reader.
+#[cfg(feature = "decompression")]
+struct Counted<R> {
+    reader: R,
+    count: Arc<AtomicUsize>
+}
+
+#[cfg(feature = "decompression")]
+impl<R> Counted<R> {
+    fn new(reader: R, count: Arc<AtomicUsize>) -> Counted<R> {
+        Counted {
+            reader: reader,
+            count: count
+        }
+    }
+}
+
+#[cfg(feature = "decompression")]
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let length = try!(self.reader.read(buf));
+        self.count.fetch_add(length, Ordering::Relaxed);
+        Ok(length)
+    }
+}
+
+//Decodes the `Content-Encoding` of the request body.
+enum Decoder<R: Read> {
+    Identity(R),
+    #[cfg(feature = "decompression")]
+    Gzip(GzDecoder<Counted<R>>),
+    #[cfg(feature = "decompression")]
+    Deflate(ZlibDecoder<Counted<R>>)
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Decoder::Identity(ref mut reader) => reader.read(buf),
+            #[cfg(feature = "decompression")]
+            Decoder::Gzip(ref mut reader) => reader.read(buf),
+            #[cfg(feature = "decompression")]
+            Decoder::Deflate(ref mut reader) => reader.read(buf)
         }
     }
 }
 
+///The error that is produced when the request body is larger than allowed.
+///
+///It's wrapped in an `io::Error`, so `TooLarge::is_cause_of` can be used to
+///check if an IO error was caused by a too large body. It should usually be
+///answered with `413 Payload Too Large`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TooLarge {
+    ///The size limit, in bytes.
+    pub limit: u64
+}
+
+impl TooLarge {
+    ///Check if `error` was caused by a too large request body.
+    pub fn is_cause_of(error: &io::Error) -> bool {
+        error.get_ref().map(|e| e.is::<TooLarge>()).unwrap_or(false)
+    }
+}
+
+impl fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the request body is larger than {} bytes", self.limit)
+    }
+}
+
+impl Error for TooLarge {
+    fn description(&self) -> &str {
+        "the request body is too large"
+    }
+}
+
+impl Into<io::Error> for TooLarge {
+    fn into(self) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, self)
+    }
+}
+
 ///`BodyReader` extension for reading and parsing a query string.
 pub trait ExtQueryBody {
     ///Read and parse the request body as a query string. The body will be
     ///decoded as UTF-8 and plain '+' characters will be replaced with spaces.
+    ///The size limit of the `BodyReader` is respected, if any.
     ///
     ///A simplified example of how to parse `a=number&b=number`:
     ///
@@ -141,8 +892,8 @@ impl<'a, 'b> ExtQueryBody for BodyReader<'a, 'b> {
     #[inline]
     fn read_query_body(&mut self) -> io::Result<Parameters> {
         let mut buf = Vec::new();
-        try!(self.read_to_end(&mut buf));
-        Ok(::utils::parse_parameters(&buf)    )
+        try!(self.read_to_end_checked(&mut buf));
+        Ok(::utils::parse_parameters(&buf))
     }
 }
 
@@ -153,7 +904,8 @@ impl<'a, 'b> ExtQueryBody for BodyReader<'a, 'b> {
 #[cfg(feature = "rustc_json_body")]
 pub trait ExtJsonBody {
     ///Read the request body into a generic JSON structure. This structure can
-    ///then be navigated and parsed freely.
+    ///then be navigated and parsed freely. The size limit of the `BodyReader`
+    ///is respected, if any.
     ///
     ///A simplified example of how to parse `{ "a": number, "b": number }`:
     ///
@@ -175,7 +927,8 @@ pub trait ExtJsonBody {
     fn read_json_body(&mut self) -> Result<json::Json, json::BuilderError>;
 
     ///Read and decode a request body as a type `T`. The target type must
-    ///implement `rustc_serialize::Decodable`.
+    ///implement `rustc_serialize::Decodable`. The size limit of the
+    ///`BodyReader` is respected, if any.
     ///
     ///A simplified example of how to parse `{ "a": number, "b": number }`:
     ///
@@ -201,42 +954,329 @@ pub trait ExtJsonBody {
     ///# fn main() {}
     ///```
     fn decode_json_body<T: Decodable>(&mut self) -> json::DecodeResult<T>;
+
+    ///Read the request body as a stream of JSON parser events, instead of
+    ///building the whole structure in memory. This makes it possible to
+    ///process large documents, such as bulk imports, one element at the
+    ///time. The size limit of the `BodyReader` is respected, if any, and IO
+    ///errors are reported as `ParserError::IoError` events.
+    ///
+    ///A simplified example of how to count the objects in a huge array:
+    ///
+    ///```
+    ///extern crate rustful;
+    ///extern crate rustc_serialize;
+    ///
+    ///use rustful::{Context, Response};
+    ///use rustful::StatusCode::BadRequest;
+    ///use rustful::context::body::ExtJsonBody;
+    ///use rustc_serialize::json::JsonEvent;
+    ///
+    ///fn my_handler(mut context: Context, mut response: Response) {
+    ///    let mut events = context.body.read_json_events();
+    ///    let mut objects = 0;
+    ///
+    ///    while let Some(event) = events.next() {
+    ///        match event {
+    ///            //An object that is directly inside the root array
+    ///            JsonEvent::ObjectStart if events.stack().len() == 1 => objects += 1,
+    ///            JsonEvent::Error(_) => {
+    ///                response.set_status(BadRequest);
+    ///                return;
+    ///            },
+    ///            _ => {}
+    ///        }
+    ///    }
+    ///
+    ///    response.send(format!("found {} objects", objects));
+    ///}
+    ///# fn main() {}
+    ///```
+    fn read_json_events(&mut self) -> JsonEvents;
 }
 
 #[cfg(feature = "rustc_json_body")]
 impl<'a, 'b> ExtJsonBody for BodyReader<'a, 'b> {
     fn read_json_body(&mut self) -> Result<json::Json, json::BuilderError> {
-        json::Json::from_reader(self)
+        if self.limit.is_some() {
+            let mut buf = Vec::new();
+            try!(self.read_to_end_checked(&mut buf).map_err(|e| json::ParserError::IoError(e)));
+            json::Json::from_reader(&mut &buf[..])
+        } else {
+            json::Json::from_reader(self)
+        }
     }
 
     fn decode_json_body<T: Decodable>(&mut self) -> json::DecodeResult<T> {
-        let mut buf = String::new();
-        try!(self.read_to_string(&mut buf).map_err(|e| {
+        let mut buf = Vec::new();
+        try!(self.read_to_end_checked(&mut buf).map_err(|e| {
             let parse_err = json::ParserError::IoError(e);
             json::DecoderError::ParseError(parse_err)
         }));
+        let buf = try!(String::from_utf8(buf).map_err(|_| {
+            let parse_err = json::ParserError::SyntaxError(json::ErrorCode::NotUtf8, 0, 0);
+            json::DecoderError::ParseError(parse_err)
+        }));
         json::decode(&buf)
     }
+
+    fn read_json_events(&mut self) -> JsonEvents {
+        let limit = self.limit;
+        JsonEvents::new(Box::new(self), limit)
+    }
+}
+
+///A stream of JSON parser events from a request body.
+///
+///It's created by `ExtJsonBody::read_json_events`.
+#[cfg(feature = "rustc_json_body")]
+pub struct JsonEvents<'r> {
+    parser: json::Parser<JsonChars<'r>>,
+    error: Rc<RefCell<Option<io::Error>>>
+}
+
+#[cfg(feature = "rustc_json_body")]
+impl<'r> JsonEvents<'r> {
+    fn new(reader: Box<Read + 'r>, limit: Option<u64>) -> JsonEvents<'r> {
+        let error = Rc::new(RefCell::new(None));
+
+        JsonEvents {
+            parser: json::Parser::new(JsonChars {
+                bytes: io::BufReader::new(reader).bytes(),
+                read: 0,
+                limit: limit,
+                error: error.clone()
+            }),
+            error: error
+        }
+    }
+
+    ///The path to the current position in the document, such as the index
+    ///of the current array element.
+    pub fn stack(&self) -> &json::Stack {
+        self.parser.stack()
+    }
+}
+
+#[cfg(feature = "rustc_json_body")]
+impl<'r> Iterator for JsonEvents<'r> {
+    type Item = json::JsonEvent;
+
+    fn next(&mut self) -> Option<json::JsonEvent> {
+        //The parser will usually complain about an unexpected end when the
+        //reader fails, so the underlying cause is reported instead.
+        match self.parser.next() {
+            Some(json::JsonEvent::Error(e)) => {
+                let e = self.error.borrow_mut().take().map(json::ParserError::IoError).unwrap_or(e);
+                Some(json::JsonEvent::Error(e))
+            },
+            None => self.error.borrow_mut().take().map(|e| json::JsonEvent::Error(json::ParserError::IoError(e))),
+            event => event
+        }
+    }
+}
+
+//Decodes UTF-8 characters from a reader, while keeping track of the size.
+#[cfg(feature = "rustc_json_body")]
+struct JsonChars<'r> {
+    bytes: io::Bytes<io::BufReader<Box<Read + 'r>>>,
+    read: u64,
+    limit: Option<u64>,
+    error: Rc<RefCell<Option<io::Error>>>
+}
+
+#[cfg(feature = "rustc_json_body")]
+impl<'r> JsonChars<'r> {
+    fn next_byte(&mut self) -> Option<u8> {
+        match self.bytes.next() {
+            Some(Ok(byte)) => {
+                self.read += 1;
+                if let Some(limit) = self.limit {
+                    if self.read > limit {
+                        self.fail(TooLarge { limit: limit }.into());
+                        return None;
+                    }
+                }
+
+                Some(byte)
+            },
+            Some(Err(e)) => {
+                self.fail(e);
+                None
+            },
+            None => None
+        }
+    }
+
+    fn fail(&mut self, error: io::Error) {
+        let mut current = self.error.borrow_mut();
+        if current.is_none() {
+            *current = Some(error);
+        }
+    }
+
+    fn invalid(&mut self) -> Option<char> {
+        self.fail(io::Error::new(io::ErrorKind::InvalidData, "the request body is not valid UTF-8"));
+        None
+    }
+}
+
+#[cfg(feature = "rustc_json_body")]
+impl<'r> Iterator for JsonChars<'r> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let first = match self.next_byte() {
+            Some(byte) => byte,
+            None => return None
+        };
+
+        let width = match first {
+            0x00...0x7f => return Some(first as char),
+            0xc2...0xdf => 2,
+            0xe0...0xef => 3,
+            0xf0...0xf4 => 4,
+            _ => return self.invalid()
+        };
+
+        let mut buf = [first, 0, 0, 0];
+        for i in 1..width {
+            match self.next_byte() {
+                Some(byte) => buf[i] = byte,
+                None => return self.invalid()
+            }
+        }
+
+        match from_utf8(&buf[..width]).ok().and_then(|s| s.chars().next()) {
+            Some(c) => Some(c),
+            None => self.invalid()
+        }
+    }
+}
+
+impl<'a, 'b> BodyReader<'a, 'b> {
+    //Reads directly from the connection.
+    fn read_stream(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let length = try!(self.read_decoded(buf));
+
+        //The trailers follows directly after the last chunk, and they are
+        //not read by Hyper. The remaining size stays at `Some(0)` after
+        //the terminating chunk.
+        if length == 0 && !buf.is_empty() && self.trailers.is_none() {
+            if let Decoder::Identity(HttpReader::ChunkedReader(ref mut reader, Some(0))) = self.reader {
+                self.trailers = Some(try!(read_trailers(reader)));
+            }
+        }
+
+        Ok(length)
+    }
+
+    #[cfg(feature = "decompression")]
+    fn read_decoded(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.guard {
+            Some(ref mut guard) => guard.read(&mut self.reader, buf),
+            None => self.reader.read(buf)
+        }
+    }
+
+    #[cfg(not(feature = "decompression"))]
+    fn read_decoded(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+
+    fn has_read_ahead(&self) -> bool {
+        (self.read_ahead.position() as usize) < self.read_ahead.get_ref().len()
+    }
 }
 
 impl<'a, 'b> Read for BodyReader<'a, 'b> {
     ///Read the request body.
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.reader.read(buf)
+        if let Some(ref mut buffer) = self.buffer {
+            return buffer.read(buf);
+        }
+
+        if self.has_read_ahead() {
+            self.read_ahead.read(buf)
+        } else {
+            self.read_stream(buf)
+        }
+    }
+}
+
+impl<'a, 'b> BufRead for BodyReader<'a, 'b> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.buffer.is_some() {
+            self.buffer.as_mut().expect("the body should be buffered").fill_buf()
+        } else {
+            if !self.has_read_ahead() {
+                let mut data = mem::replace(self.read_ahead.get_mut(), vec![]);
+                data.resize(READ_AHEAD_SIZE, 0);
+                let length = try!(self.read_stream(&mut data));
+                data.truncate(length);
+                self.read_ahead = Cursor::new(data);
+            }
+
+            self.read_ahead.fill_buf()
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self.buffer {
+            Some(ref mut buffer) => buffer.consume(amt),
+            None => self.read_ahead.consume(amt)
+        }
     }
 }
 
+//Reads the trailer section of a chunked body, including the final empty line.
+fn read_trailers<R: BufRead>(reader: R) -> io::Result<Headers> {
+    let mut reader = reader.take(MAX_TRAILERS_SIZE);
+    let mut headers = Headers::new();
+    let mut line = vec![];
+
+    loop {
+        line.clear();
+        if try!(reader.read_until(b'\n', &mut line)) == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the trailers are incomplete or too large"));
+        }
+
+        let line = trim(&line);
+        if line.is_empty() {
+            return Ok(headers);
+        }
+
+        let colon = try!(line.iter().position(|&b| b == b':').ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "invalid trailer header")
+        }));
+        let name = try!(from_utf8(trim(&line[..colon])).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, "invalid trailer header name")
+        }));
+
+        let mut values = headers.get_raw(name).map(|values| values.to_vec()).unwrap_or_else(Vec::new);
+        values.push(trim(&line[colon + 1..]).to_vec());
+        headers.set_raw(name.to_owned(), values);
+    }
+}
+
+fn trim(bytes: &[u8]) -> &[u8] {
+    let is_space = |b: &u8| *b == b' ' || *b == b'\t' || *b == b'\r' || *b == b'\n';
+    let start = bytes.iter().position(|b| !is_space(b)).unwrap_or(bytes.len());
+    let end = bytes.iter().rposition(|b| !is_space(b)).map(|i| i + 1).unwrap_or(start);
+    &bytes[start..end]
+}
+
 ///A specialized request representation for the multipart interface.
 #[cfg(feature = "multipart")]
 pub struct MultipartRequest<'r, 'a: 'r, 'b: 'a> {
-    boundary: &'r str,
-    reader: &'r mut HttpReader<&'a mut BufReader<&'b mut NetworkStream>>
+    boundary: String,
+    reader: &'r mut BodyReader<'a, 'b>
 }
 
 #[cfg(feature = "multipart")]
 impl<'r, 'a, 'b> HttpRequest for MultipartRequest<'r, 'a, 'b> {
     fn multipart_boundary(&self) -> Option<&str> {
-        Some(self.boundary)
+        Some(&self.boundary)
     }
 }
 
@@ -246,4 +1286,278 @@ impl<'r, 'a, 'b> Read for MultipartRequest<'r, 'a, 'b> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         self.reader.read(buf)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "rustc_json_body")]
+    use rustc_serialize::json::{JsonEvent, ParserError};
+    #[cfg(feature = "rustc_json_body")]
+    use super::JsonEvents;
+    use std::io::{self, BufRead, Read, Write, Cursor};
+    use std::net::SocketAddr;
+    use hyper::buffer::BufReader;
+    use hyper::http::h1::HttpReader;
+    use hyper::net::NetworkStream;
+    use header::{Headers, ContentLength};
+    use context::body::ExtQueryBody;
+    use super::{BodyReader, TooLarge, read_trailers};
+    #[cfg(feature = "decompression")]
+    use std::sync::Arc;
+    #[cfg(feature = "decompression")]
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    #[cfg(feature = "decompression")]
+    use super::{BombGuard, DecompressionLimits, DecompressionBomb};
+    #[cfg(feature = "rustc_json_body")]
+    use super::BodyDecoders;
+
+    #[test]
+    fn parse_trailers() {
+        let mut body: &[u8] = b"Checksum: abc\r\nX-Thing:  a \r\nx-thing: b\r\n\r\nnext request";
+        let trailers = read_trailers(&mut body).unwrap();
+
+        assert_eq!(trailers.get_raw("Checksum"), Some(&[b"abc".to_vec()][..]));
+        assert_eq!(trailers.get_raw("X-Thing"), Some(&[b"a".to_vec(), b"b".to_vec()][..]));
+        assert_eq!(body, b"next request");
+    }
+
+    struct MockStream(Cursor<Vec<u8>>);
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl NetworkStream for MockStream {
+        fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+            Ok("127.0.0.1:8080".parse().unwrap())
+        }
+    }
+
+    #[test]
+    fn chunked_body_trailers() {
+        let mut stream = MockStream(Cursor::new(b"3\r\nabc\r\n2\r\nde\r\n0\r\nChecksum: xyz\r\n\r\n".to_vec()));
+        let mut buffer = BufReader::new(&mut stream as &mut NetworkStream);
+        let mut body = BodyReader::from_reader(HttpReader::ChunkedReader(&mut buffer, None), &Headers::new());
+
+        let mut content = String::new();
+        body.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "abcde");
+
+        let trailers = body.trailers().expect("the trailers should be read");
+        assert_eq!(trailers.get_raw("Checksum"), Some(&[b"xyz".to_vec()][..]));
+    }
+
+    #[test]
+    fn limit_body_size() {
+        let mut headers = Headers::new();
+        headers.set(ContentLength(10));
+        let mut stream = MockStream(Cursor::new(b"0123456789".to_vec()));
+        let mut buffer = BufReader::new(&mut stream as &mut NetworkStream);
+        let mut body = BodyReader::from_reader(HttpReader::SizedReader(&mut buffer, 10), &headers);
+
+        let mut content = vec![];
+        let error = body.read_to_end_limited(&mut content, 5).unwrap_err();
+        assert!(TooLarge::is_cause_of(&error));
+        assert!(content.is_empty(), "the body was read before the Content-Length was checked");
+
+        let mut stream = MockStream(Cursor::new(b"3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n".to_vec()));
+        let mut buffer = BufReader::new(&mut stream as &mut NetworkStream);
+        let mut body = BodyReader::from_reader(HttpReader::ChunkedReader(&mut buffer, None), &Headers::new());
+        let error = body.read_to_end_limited(&mut vec![], 4).unwrap_err();
+        assert!(TooLarge::is_cause_of(&error));
+
+        let mut stream = MockStream(Cursor::new(b"3\r\na=b\r\n2\r\n&c\r\n0\r\n\r\n".to_vec()));
+        let mut buffer = BufReader::new(&mut stream as &mut NetworkStream);
+        let mut body = BodyReader::from_reader(HttpReader::ChunkedReader(&mut buffer, None), &Headers::new());
+        body.set_limit(Some(4));
+        assert!(body.read_query_body().err().map_or(false, |e| TooLarge::is_cause_of(&e)));
+
+        let mut stream = MockStream(Cursor::new(b"3\r\na=b\r\n2\r\n&c\r\n0\r\n\r\n".to_vec()));
+        let mut buffer = BufReader::new(&mut stream as &mut NetworkStream);
+        let mut body = BodyReader::from_reader(HttpReader::ChunkedReader(&mut buffer, None), &Headers::new());
+        body.set_limit(Some(5));
+        assert_eq!(body.read_query_body().unwrap().get("a").map(|a| a.into_owned()), Some("b".to_owned()));
+    }
+
+    #[test]
+    fn reread_buffered_body() {
+        let mut stream = MockStream(Cursor::new(b"3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n".to_vec()));
+        let mut buffer = BufReader::new(&mut stream as &mut NetworkStream);
+        let mut body = BodyReader::from_reader(HttpReader::ChunkedReader(&mut buffer, None), &Headers::new());
+
+        assert!(body.buffered().is_none());
+        assert_eq!(body.buffer(5).unwrap(), b"abcde");
+        assert_eq!(body.buffer(1).unwrap(), b"abcde");
+
+        let mut content = String::new();
+        body.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "abcde");
+
+        body.rewind();
+        let mut content = String::new();
+        body.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "abcde");
+        assert_eq!(body.buffered(), Some(&b"abcde"[..]));
+    }
+
+    #[test]
+    fn read_lines() {
+        let mut stream = MockStream(Cursor::new(b"3\r\na\nb\r\n4\r\nc\nde\r\n0\r\n\r\n".to_vec()));
+        let mut buffer = BufReader::new(&mut stream as &mut NetworkStream);
+        let mut body = BodyReader::from_reader(HttpReader::ChunkedReader(&mut buffer, None), &Headers::new());
+
+        let mut line = String::new();
+        body.read_line(&mut line).unwrap();
+        assert_eq!(line, "a\n");
+
+        //Plain reads continue where the line ended.
+        let mut byte = [0];
+        body.read_exact(&mut byte).unwrap();
+        assert_eq!(&byte, b"b");
+
+        let lines: Vec<_> = body.lines().map(|line| line.unwrap()).collect();
+        assert_eq!(lines, vec!["c", "de"]);
+    }
+
+    #[test]
+    fn parse_incomplete_trailers() {
+        assert!(read_trailers(&b"Checksum: abc\r\n"[..]).is_err());
+        assert!(read_trailers(&b"Checksum\r\n\r\n"[..]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "rustc_json_body")]
+    fn json_events() {
+        let body: &[u8] = b"[{\"name\": \"\xc3\xa5\"}, 2]";
+        let events: Vec<_> = JsonEvents::new(Box::new(body), None).collect();
+
+        assert_eq!(events, vec![
+            JsonEvent::ArrayStart,
+            JsonEvent::ObjectStart,
+            JsonEvent::StringValue("\u{e5}".into()),
+            JsonEvent::ObjectEnd,
+            JsonEvent::U64Value(2),
+            JsonEvent::ArrayEnd
+        ]);
+    }
+
+    #[test]
+    #[cfg(feature = "rustc_json_body")]
+    fn json_events_too_large() {
+        let body: &[u8] = b"[1, 2, 3, 4]";
+        let last = JsonEvents::new(Box::new(body), Some(5)).last();
+
+        match last {
+            Some(JsonEvent::Error(ParserError::IoError(ref e))) => assert!(TooLarge::is_cause_of(e)),
+            other => panic!("expected a size error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "rustc_json_body")]
+    fn find_decoders() {
+        let decoders = BodyDecoders::default();
+        assert!(decoders.get("application/json").is_some());
+        assert!(decoders.get("Application/HAL+JSON").is_some());
+        assert!(decoders.get("text/plain").is_none());
+
+        let form = decoders.get("application/x-www-form-urlencoded").unwrap().decode(b"a=1&b=x+y").unwrap();
+        assert_eq!(form.find("a").and_then(|a| a.as_string()), Some("1"));
+        assert_eq!(form.find("b").and_then(|b| b.as_string()), Some("x y"));
+    }
+
+    #[test]
+    #[cfg(feature = "decompression")]
+    fn decompression_bombs() {
+        let limits = DecompressionLimits {
+            min_size: 100,
+            ..DecompressionLimits::default()
+        };
+        let compressed = Arc::new(AtomicUsize::new(1));
+        let mut guard = BombGuard {
+            limits: limits.clone(),
+            compressed: compressed.clone(),
+            decompressed: 0,
+            head: None
+        };
+
+        assert!(guard.check(&[0; 100]).is_ok());
+        let error = guard.check(&[0; 1]).unwrap_err();
+        assert!(DecompressionBomb::is_cause_of(&error));
+
+        compressed.store(10, Ordering::Relaxed);
+        assert!(guard.check(&[0; 800]).is_ok());
+
+        let mut guard = BombGuard {
+            limits: limits.clone(),
+            compressed: compressed,
+            decompressed: 0,
+            head: None
+        };
+        assert!(guard.check(&[0x1f, 0x8b, 8, 0]).is_err());
+        assert_eq!(limits.triggered(), 2);
+    }
+
+    //Hands out one byte at the time.
+    #[cfg(feature = "decompression")]
+    struct Trickle<'a>(&'a [u8]);
+
+    #[cfg(feature = "decompression")]
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.split_first() {
+                Some((&first, rest)) if !buf.is_empty() => {
+                    buf[0] = first;
+                    self.0 = rest;
+                    Ok(1)
+                },
+                _ => Ok(0)
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "decompression")]
+    fn split_magic_numbers() {
+        let limits = DecompressionLimits::default();
+        let mut guard = BombGuard {
+            limits: limits.clone(),
+            compressed: Arc::new(AtomicUsize::new(100)),
+            decompressed: 0,
+            head: None
+        };
+        let mut reader = Trickle(b"PK\x03\x04rest");
+        let mut buf = [0; 16];
+        let error = guard.read(&mut reader, &mut buf).unwrap_err();
+        assert!(DecompressionBomb::is_cause_of(&error));
+
+        let mut guard = BombGuard {
+            limits: limits.clone(),
+            compressed: Arc::new(AtomicUsize::new(100)),
+            decompressed: 0,
+            head: None
+        };
+        let mut reader = Trickle(b"plain");
+        let mut body = vec![];
+        loop {
+            match guard.read(&mut reader, &mut buf).unwrap() {
+                0 => break,
+                length => body.extend_from_slice(&buf[..length])
+            }
+        }
+        assert_eq!(body, b"plain");
+        assert_eq!(limits.triggered(), 1);
+    }
+}
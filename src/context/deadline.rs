@@ -0,0 +1,103 @@
+use time::{Duration, SteadyTime};
+
+///The point in time when the server no longer wants to spend time on a
+///request.
+///
+///It's derived from `Server::request_timeout` and can be used to limit the
+///time spent on calls to other services, or to give up before doing work
+///that the client will never see.
+///
+///```
+///extern crate rustful;
+///extern crate time;
+///use rustful::{Context, Response};
+///use rustful::StatusCode::ServiceUnavailable;
+///use time::Duration;
+///
+///fn my_handler(context: Context, mut response: Response) {
+///    let timeout = match context.deadline() {
+///        Some(deadline) if deadline.has_expired() => {
+///            response.set_status(ServiceUnavailable);
+///            return;
+///        },
+///        Some(deadline) => deadline.limit(Duration::seconds(5)),
+///        None => Duration::seconds(5)
+///    };
+///
+///    response.send(format!("waiting at most {} ms for the database", timeout.num_milliseconds()));
+///}
+///# fn main() {}
+///```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Deadline {
+    expires: SteadyTime
+}
+
+impl Deadline {
+    ///Create a deadline that expires after `timeout`, from now.
+    pub fn from_now(timeout: Duration) -> Deadline {
+        Deadline::new(SteadyTime::now() + timeout)
+    }
+
+    ///Create a deadline that expires at `expires`.
+    pub fn new(expires: SteadyTime) -> Deadline {
+        Deadline {
+            expires: expires
+        }
+    }
+
+    ///The point in time when the deadline expires.
+    pub fn expires(&self) -> SteadyTime {
+        self.expires
+    }
+
+    ///The remaining time until the deadline expires. It's zero if it has
+    ///already expired.
+    pub fn remaining(&self) -> Duration {
+        let remaining = self.expires - SteadyTime::now();
+        if remaining > Duration::zero() {
+            remaining
+        } else {
+            Duration::zero()
+        }
+    }
+
+    ///Check if the deadline has expired.
+    pub fn has_expired(&self) -> bool {
+        SteadyTime::now() >= self.expires
+    }
+
+    ///Limit `timeout` to the remaining time. This is useful for making
+    ///calls to other services with a timeout that doesn't exceed the
+    ///deadline.
+    pub fn limit(&self, timeout: Duration) -> Duration {
+        let remaining = self.remaining();
+        if timeout < remaining {
+            timeout
+        } else {
+            remaining
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use time::Duration;
+    use super::Deadline;
+
+    #[test]
+    fn remaining_time() {
+        let deadline = Deadline::from_now(Duration::hours(1));
+        assert!(!deadline.has_expired());
+        assert!(deadline.remaining() > Duration::minutes(59));
+        assert_eq!(deadline.limit(Duration::seconds(1)), Duration::seconds(1));
+    }
+
+    #[test]
+    fn expired_deadline() {
+        let deadline = Deadline::from_now(Duration::seconds(-1));
+        assert!(deadline.has_expired());
+        assert_eq!(deadline.remaining(), Duration::zero());
+        assert_eq!(deadline.limit(Duration::seconds(1)), Duration::zero());
+    }
+}
@@ -129,15 +129,20 @@
 //![log]: ../log/index.html
 //![body_reader]: body/struct.BodyReader.html
 
-use std::net::SocketAddr;
+use std::net::{SocketAddr, IpAddr};
 use std::fmt;
 use std::borrow::Cow;
 
 use HttpVersion;
 use Method;
-use header::Headers;
+use header::{Headers, Accept};
+use mime::{Mime, TopLevel, SubLevel};
+use headers::{Link, LinkValue, Prefer, Preference, Priority, ClientHints};
+use utils;
+use utils::header_list;
 use log::Log;
 use Global;
+use server::Dispatcher;
 
 use self::body::BodyReader;
 use self::hypermedia::Hypermedia;
@@ -151,6 +156,15 @@ pub use self::maybe_utf8::{MaybeUtf8, MaybeUtf8Owned, MaybeUtf8Slice};
 mod parameters;
 pub use self::parameters::Parameters;
 
+mod query;
+pub use self::query::Query;
+
+mod deadline;
+pub use self::deadline::Deadline;
+
+mod variables;
+pub use self::variables::{RouteVariables, VariableIter};
+
 ///A container for handler input, like request data and utilities.
 pub struct Context<'a, 'b: 'a, 's> {
     ///Headers from the HTTP request.
@@ -168,14 +182,27 @@ pub struct Context<'a, 'b: 'a, 's> {
     ///The requested URI.
     pub uri: Uri,
 
+    ///The request-target, exactly as it was sent by the client. It's not
+    ///decoded in any way, which makes it suitable for things like signature
+    ///verification. Use `query.raw()` to get only the raw query string.
+    ///
+    ///The exception is an absolute-form target, such as
+    ///`http://example.com/a`, which is only sent to proxies. It's parsed
+    ///before it reaches the server, so it's the parsed URL, serialized
+    ///again. It may differ from what was sent, such as in the letter case of
+    ///the host, and signatures should not be verified against it.
+    pub request_target: String,
+
     ///Hypermedia from the current endpoint.
     pub hypermedia: Hypermedia<'s>,
 
-    ///Route variables.
-    pub variables: Parameters,
+    ///Route variables. They borrow their values from a copy of the request
+    ///path, so use `variables.to_parameters()` to get a mutable map.
+    pub variables: RouteVariables<'s>,
 
-    ///Query variables from the path.
-    pub query: Parameters,
+    ///Query variables from the path. They are parsed when they are accessed
+    ///for the first time.
+    pub query: Query,
 
     ///The fragment part of the URL (after #), if provided.
     pub fragment: Option<MaybeUtf8Owned>,
@@ -188,6 +215,339 @@ pub struct Context<'a, 'b: 'a, 's> {
 
     ///A reader for the request body.
     pub body: BodyReader<'a, 'b>,
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub trusted_proxies: &'s [IpAddr],
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub deadline: Option<Deadline>,
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub dispatcher: Option<&'s Dispatcher>,
+}
+
+impl<'a, 'b, 's> Context<'a, 'b, 's> {
+    ///Get the links from the `Link` header, if any.
+    ///
+    ///```
+    ///use rustful::{Context, Response};
+    ///
+    ///fn my_handler(context: Context, response: Response) {
+    ///    if let Some(next) = context.links().iter().find(|link| link.has_rel("next")) {
+    ///        response.send(format!("the next page is {}", next.uri));
+    ///    } else {
+    ///        response.send("this is the last page");
+    ///    }
+    ///}
+    ///```
+    pub fn links(&self) -> &[LinkValue] {
+        self.headers.get::<Link>().map(|links| &links.0[..]).unwrap_or(&[])
+    }
+
+    ///Get the preferences from the `Prefer` header, if any.
+    pub fn preferences(&self) -> &[Preference] {
+        self.headers.get::<Prefer>().map(|prefer| &prefer.0[..]).unwrap_or(&[])
+    }
+
+    ///Check if the client has expressed a preference with the same name and
+    ///value as `preference`. Remember to mark it as applied in the response,
+    ///using `Response::apply_preference`, if it's honored.
+    ///
+    ///```
+    ///use rustful::{Context, Response};
+    ///use rustful::headers::Preference;
+    ///
+    ///fn my_handler(context: Context, mut response: Response) {
+    ///    //...store something...
+    ///
+    ///    if context.prefers(&Preference::return_minimal()) {
+    ///        response.apply_preference(Preference::return_minimal());
+    ///    } else {
+    ///        response.send("the stored thing");
+    ///    }
+    ///}
+    ///```
+    pub fn prefers(&self, preference: &Preference) -> bool {
+        self.headers.get::<Prefer>().map(|prefer| prefer.contains(preference)).unwrap_or(false)
+    }
+
+    ///Get the priority of the request, from the `Priority` header. The
+    ///default priority is returned if the header is missing. It's not used
+    ///by the server itself, and doesn't change the order in which requests
+    ///are handled.
+    pub fn priority(&self) -> Priority {
+        self.headers.get::<Priority>().cloned().unwrap_or_else(Priority::default)
+    }
+
+    ///Get the deadline for the request, if `Server::request_timeout` is set.
+    ///See [`Deadline`][deadline] for an example.
+    ///
+    ///[deadline]: struct.Deadline.html
+    pub fn deadline(&self) -> Option<Deadline> {
+        self.deadline
+    }
+
+    ///Parse the HTTP Client Hints from the request headers.
+    pub fn client_hints(&self) -> ClientHints {
+        ClientHints::from_headers(&self.headers)
+    }
+
+    ///Get the username and password from a `Basic` `Authorization` header.
+    ///The password is empty if it's missing, and `None` is returned if the
+    ///header is missing or malformed. The username ends at the first `:`, so
+    ///the password may contain more of them.
+    ///
+    ///```
+    ///use rustful::{Context, Response};
+    ///use rustful::StatusCode::Unauthorized;
+    ///
+    ///fn my_handler(context: Context, mut response: Response) {
+    ///    match context.basic_auth() {
+    ///        Some((ref user, ref password)) if user == "admin" && password == "hunter2" => response.send("welcome back!"),
+    ///        _ => {
+    ///            response.set_status(Unauthorized);
+    ///            response.headers_mut().set_raw("WWW-Authenticate", vec![b"Basic realm=\"admin\"".to_vec()]);
+    ///        }
+    ///    }
+    ///}
+    ///```
+    pub fn basic_auth(&self) -> Option<(String, String)> {
+        let value = match self.headers.get_raw("Authorization") {
+            Some(lines) if lines.len() == 1 => String::from_utf8_lossy(&lines[0]),
+            _ => return None
+        };
+
+        let credentials = match value.find(' ') {
+            Some(space) if value[..space].to_lowercase() == "basic" => value[space + 1..].trim(),
+            _ => return None
+        };
+
+        let credentials = match ::utils::base64_decode(credentials.as_bytes()).and_then(|c| String::from_utf8(c).ok()) {
+            Some(credentials) => credentials,
+            None => return None
+        };
+
+        Some(match credentials.find(':') {
+            Some(colon) => (credentials[..colon].to_owned(), credentials[colon + 1..].to_owned()),
+            None => (credentials, String::new())
+        })
+    }
+
+    ///Find the response format that the client prefers, if it has told.
+    ///
+    ///The extension of the requested file, such as `.json` in
+    ///`/things.json`, is checked first, followed by `X-Requested-With:
+    ///XMLHttpRequest`, which is taken as a request for JSON, and finally the
+    ///media type with the highest quality in the `Accept` header. Wildcards
+    ///in `Accept` are ignored.
+    ///
+    ///```
+    ///use rustful::{Context, Response};
+    ///use rustful::context::Format;
+    ///
+    ///fn my_handler(context: Context, response: Response) {
+    ///    match context.preferred_format() {
+    ///        Some(Format::Json) => response.send(r#"{"message": "hello"}"#),
+    ///        Some(Format::Html) | None => response.send("<p>hello</p>"),
+    ///        Some(_) => response.send("hello")
+    ///    }
+    ///}
+    ///```
+    pub fn preferred_format(&self) -> Option<Format> {
+        self.uri.as_utf8_path().and_then(format_from_extension)
+            .or_else(|| if is_xhr(&self.headers) { Some(Format::Json) } else { None })
+            .or_else(|| format_from_accept(&self.headers))
+    }
+
+    ///Check if the client prefers JSON responses. See `preferred_format`.
+    ///
+    ///```
+    ///use rustful::{Context, Response};
+    ///use rustful::StatusCode::NotFound;
+    ///
+    ///fn not_found(context: Context, mut response: Response) {
+    ///    response.set_status(NotFound);
+    ///    if context.wants_json() {
+    ///        response.send(r#"{"error": "not found"}"#);
+    ///    } else {
+    ///        response.send("<h1>Not Found</h1>");
+    ///    }
+    ///}
+    ///```
+    pub fn wants_json(&self) -> bool {
+        self.preferred_format() == Some(Format::Json)
+    }
+
+    ///Check if the connection is kept alive after this request. HTTP/1.1
+    ///connections are kept alive unless the client sends `Connection:
+    ///close`, while HTTP/1.0 connections are closed unless it sends
+    ///`Connection: keep-alive`.
+    pub fn keep_alive(&self) -> bool {
+        keep_alive(&self.http_version, &self.headers)
+    }
+
+    ///Check if the response body can be sent with chunked transfer encoding,
+    ///which requires HTTP/1.1. Older clients need a `Content-Length`, or a
+    ///body that ends when the connection is closed.
+    ///
+    ///```
+    ///use rustful::{Context, Response};
+    ///
+    ///fn my_handler(context: Context, response: Response) {
+    ///    if context.supports_chunked() {
+    ///        let mut chunks = response.into_chunked();
+    ///        chunks.send("streamed ");
+    ///        chunks.send("in chunks");
+    ///    } else {
+    ///        response.send("sent in one piece");
+    ///    }
+    ///}
+    ///```
+    pub fn supports_chunked(&self) -> bool {
+        match self.http_version {
+            HttpVersion::Http09 | HttpVersion::Http10 => false,
+            _ => true
+        }
+    }
+
+    ///Check if the client asks to switch to another protocol, using
+    ///`Connection: Upgrade` and an `Upgrade` header.
+    pub fn is_upgrade(&self) -> bool {
+        has_token(&self.headers, "Connection", "upgrade") && !header_list(&self.headers, "Upgrade").is_empty()
+    }
+
+    ///Get the protocols from the `Upgrade` header, in the client's order of
+    ///preference, if this is an upgrade request.
+    pub fn upgrade_protocols(&self) -> Vec<&str> {
+        if self.is_upgrade() {
+            header_list(&self.headers, "Upgrade")
+        } else {
+            vec![]
+        }
+    }
+
+    ///Check if this is a request to upgrade to the WebSocket protocol.
+    pub fn is_websocket_upgrade(&self) -> bool {
+        self.method == Method::Get && self.upgrade_protocols().iter().any(|protocol| {
+            let name = protocol.split('/').next().unwrap_or(protocol);
+            name.to_lowercase() == "websocket"
+        })
+    }
+
+    ///Get the WebSocket handshake headers, if this is a WebSocket upgrade
+    ///request with a `Sec-WebSocket-Key`.
+    ///
+    ///```
+    ///use rustful::{Context, Response};
+    ///use rustful::StatusCode::{BadRequest, UpgradeRequired};
+    ///
+    ///fn my_handler(context: Context, mut response: Response) {
+    ///    match context.websocket() {
+    ///        Some(ref handshake) if handshake.version != "13" => response.set_status(BadRequest),
+    ///        Some(handshake) => response.send(format!("key: {}", handshake.key)),
+    ///        None => response.set_status(UpgradeRequired)
+    ///    }
+    ///}
+    ///```
+    pub fn websocket(&self) -> Option<WebSocketHandshake> {
+        if !self.is_websocket_upgrade() {
+            return None;
+        }
+
+        header_list(&self.headers, "Sec-WebSocket-Key").first().map(|&key| WebSocketHandshake {
+            key: key,
+            version: header_list(&self.headers, "Sec-WebSocket-Version").first().cloned().unwrap_or(""),
+            protocols: header_list(&self.headers, "Sec-WebSocket-Protocol"),
+            extensions: header_list(&self.headers, "Sec-WebSocket-Extensions")
+        })
+    }
+
+    ///Get the IP address of the client.
+    ///
+    ///This is the same as the peer address in `address`, unless the request
+    ///came through one of the server's trusted proxies. The address is then
+    ///taken from the `Forwarded` header, or `X-Forwarded-For` if `Forwarded`
+    ///is missing, by walking the chain of forwarding nodes backwards until an
+    ///untrusted node is found.
+    ///
+    ///```
+    ///use rustful::{Context, Response};
+    ///
+    ///fn my_handler(context: Context, response: Response) {
+    ///    response.send(format!("your IP address is {}", context.client_ip()));
+    ///}
+    ///```
+    pub fn client_ip(&self) -> IpAddr {
+        client_ip(self.address.ip(), &self.headers, self.trusted_proxies)
+    }
+}
+
+fn client_ip(peer: IpAddr, headers: &Headers, trusted_proxies: &[IpAddr]) -> IpAddr {
+    if !trusted_proxies.contains(&peer) {
+        return peer;
+    }
+
+    let mut client = peer;
+
+    for node in forwarded_for(headers).into_iter().rev() {
+        match node {
+            Some(address) => {
+                client = address;
+                if !trusted_proxies.contains(&address) {
+                    break;
+                }
+            },
+            //Unknown or obfuscated node. The last known one has to do.
+            None => break
+        }
+    }
+
+    client
+}
+
+//Collects the forwarding chain from `Forwarded` or `X-Forwarded-For`.
+fn forwarded_for(headers: &Headers) -> Vec<Option<IpAddr>> {
+    let mut nodes = vec![];
+
+    if let Some(lines) = headers.get_raw("forwarded") {
+        for line in lines {
+            let line = String::from_utf8_lossy(line);
+            for element in utils::split_outside(&line, ',') {
+                let node = utils::split_outside(element, ';').into_iter()
+                    .filter_map(utils::parse_parameter)
+                    .find(|&(ref name, _)| name == "for")
+                    .map(|(_, value)| value.as_ref().and_then(|v| parse_node(v)));
+
+                if let Some(node) = node {
+                    nodes.push(node);
+                }
+            }
+        }
+    } else if let Some(lines) = headers.get_raw("x-forwarded-for") {
+        for line in lines {
+            let line = String::from_utf8_lossy(line);
+            nodes.extend(line.split(',').map(|node| parse_node(node.trim())));
+        }
+    }
+
+    nodes
+}
+
+//Parses addresses like `192.0.2.60`, `192.0.2.60:80`, `[2001:db8::1]` and
+//`[2001:db8::1]:4711`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse() {
+        Some(ip)
+    } else if let Ok(address) = node.parse::<SocketAddr>() {
+        Some(address.ip())
+    } else if node.starts_with('[') && node.ends_with(']') {
+        node[1..node.len() - 1].parse().ok()
+    } else {
+        None
+    }
 }
 
 ///A URI that can be a path or an asterisk (`*`).
@@ -250,3 +610,212 @@ impl fmt::Display for Uri {
         self.as_utf8_path_lossy().unwrap_or_else(|| "*".into()).fmt(f)
     }
 }
+
+///A common response format. See `Context::preferred_format`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    ///JSON, from `application/json`, any `+json` type or `.json`.
+    Json,
+    ///HTML, from `text/html`, `application/xhtml+xml`, `.html` or `.htm`.
+    Html,
+    ///XML, from `application/xml`, `text/xml`, any other `+xml` type or
+    ///`.xml`.
+    Xml,
+    ///Plain text, from `text/plain` or `.txt`.
+    Text
+}
+
+///The WebSocket headers from an upgrade request. See
+///`Context::websocket`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WebSocketHandshake<'a> {
+    ///The `Sec-WebSocket-Key`, which is used to compute the
+    ///`Sec-WebSocket-Accept` response header.
+    pub key: &'a str,
+    ///The `Sec-WebSocket-Version`, or an empty string if it's missing.
+    pub version: &'a str,
+    ///The subprotocols from `Sec-WebSocket-Protocol`, in the client's order
+    ///of preference.
+    pub protocols: Vec<&'a str>,
+    ///The extensions from `Sec-WebSocket-Extensions`, including their
+    ///parameters.
+    pub extensions: Vec<&'a str>
+}
+
+fn format_from_extension(path: &str) -> Option<Format> {
+    let file = path.rsplit('/').next().unwrap_or(path);
+    let extension = match file.rfind('.') {
+        Some(index) => file[index + 1..].to_lowercase(),
+        None => return None
+    };
+
+    match &*extension {
+        "json" => Some(Format::Json),
+        "html" | "htm" => Some(Format::Html),
+        "xml" => Some(Format::Xml),
+        "txt" => Some(Format::Text),
+        _ => None
+    }
+}
+
+fn is_xhr(headers: &Headers) -> bool {
+    headers.get_raw("X-Requested-With")
+        .and_then(|values| values.first())
+        .map(|value| String::from_utf8_lossy(value).to_lowercase() == "xmlhttprequest")
+        .unwrap_or(false)
+}
+
+fn keep_alive(version: &HttpVersion, headers: &Headers) -> bool {
+    match *version {
+        HttpVersion::Http09 => false,
+        HttpVersion::Http10 => has_token(headers, "Connection", "keep-alive"),
+        _ => !has_token(headers, "Connection", "close")
+    }
+}
+
+//Collects the comma separated values from every `name` header.
+fn has_token(headers: &Headers, name: &str, token: &str) -> bool {
+    header_list(headers, name).iter().any(|value| value.to_lowercase() == token)
+}
+
+fn format_from_accept(headers: &Headers) -> Option<Format> {
+    let accept = match headers.get::<Accept>() {
+        Some(accept) => accept,
+        None => return None
+    };
+
+    let mut best: Option<(Format, u16)> = None;
+    for item in accept.iter() {
+        let format = match format_from_mime(&item.item) {
+            Some(format) => format,
+            None => continue
+        };
+
+        //The first one wins if the quality is the same.
+        if best.map(|(_, quality)| item.quality.0 > quality).unwrap_or(item.quality.0 > 0) {
+            best = Some((format, item.quality.0));
+        }
+    }
+
+    best.map(|(format, _)| format)
+}
+
+fn format_from_mime(mime: &Mime) -> Option<Format> {
+    match *mime {
+        Mime(TopLevel::Application, SubLevel::Json, _) => Some(Format::Json),
+        Mime(TopLevel::Text, SubLevel::Html, _) => Some(Format::Html),
+        Mime(TopLevel::Application, SubLevel::Ext(ref sub), _) if sub == "xhtml+xml" => Some(Format::Html),
+        Mime(TopLevel::Application, SubLevel::Xml, _) | Mime(TopLevel::Text, SubLevel::Xml, _) => Some(Format::Xml),
+        Mime(TopLevel::Text, SubLevel::Plain, _) => Some(Format::Text),
+        Mime(_, SubLevel::Ext(ref sub), _) if sub.ends_with("+json") => Some(Format::Json),
+        Mime(_, SubLevel::Ext(ref sub), _) if sub.ends_with("+xml") => Some(Format::Xml),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+    use header::Headers;
+    use super::{Context, client_ip, format_from_extension, format_from_accept, Format, has_token, keep_alive};
+    use utils::header_list;
+    use server::Dispatcher;
+    use {Server, Response, HttpVersion};
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn untrusted_peer() {
+        let mut headers = Headers::new();
+        headers.set_raw("X-Forwarded-For", vec![b"10.0.0.1".to_vec()]);
+        assert_eq!(client_ip(ip("192.0.2.1"), &headers, &[]), ip("192.0.2.1"));
+    }
+
+    #[test]
+    fn x_forwarded_for_chain() {
+        let trusted = [ip("10.0.0.1"), ip("10.0.0.2")];
+        let mut headers = Headers::new();
+        headers.set_raw("X-Forwarded-For", vec![b"203.0.113.7, 198.51.100.3, 10.0.0.2".to_vec()]);
+        assert_eq!(client_ip(ip("10.0.0.1"), &headers, &trusted), ip("198.51.100.3"));
+    }
+
+    #[test]
+    fn forwarded_chain() {
+        let trusted = [ip("10.0.0.1")];
+        let mut headers = Headers::new();
+        headers.set_raw("Forwarded", vec![b"for=\"[2001:db8::1]:4711\";proto=https, for=10.0.0.1".to_vec()]);
+        assert_eq!(client_ip(ip("10.0.0.1"), &headers, &trusted), ip("2001:db8::1"));
+
+        headers.set_raw("Forwarded", vec![b"for=unknown, for=10.0.0.1".to_vec()]);
+        assert_eq!(client_ip(ip("10.0.0.1"), &headers, &trusted), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn format_extensions() {
+        assert_eq!(format_from_extension("/things.json"), Some(Format::Json));
+        assert_eq!(format_from_extension("/a.b/page.HTML"), Some(Format::Html));
+        assert_eq!(format_from_extension("/a.json/things"), None);
+    }
+
+    #[test]
+    fn format_accept() {
+        let mut headers = Headers::new();
+        headers.set_raw("Accept", vec![b"text/html;q=0.8, application/hal+json, */*".to_vec()]);
+        assert_eq!(format_from_accept(&headers), Some(Format::Json));
+
+        headers.set_raw("Accept", vec![b"text/plain;q=0.5, application/xml;q=0.5".to_vec()]);
+        assert_eq!(format_from_accept(&headers), Some(Format::Text));
+
+        headers.set_raw("Accept", vec![b"*/*".to_vec()]);
+        assert_eq!(format_from_accept(&headers), None);
+    }
+
+    #[test]
+    fn header_lists() {
+        let mut headers = Headers::new();
+        headers.set_raw("Connection", vec![b"keep-alive, Upgrade".to_vec()]);
+        headers.set_raw("Sec-WebSocket-Protocol", vec![b"chat, ".to_vec(), b"superchat".to_vec()]);
+
+        assert!(has_token(&headers, "Connection", "upgrade"));
+        assert!(!has_token(&headers, "Connection", "close"));
+        assert_eq!(header_list(&headers, "Sec-WebSocket-Protocol"), vec!["chat", "superchat"]);
+        assert_eq!(header_list(&headers, "Upgrade"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn connection_reuse() {
+        let mut headers = Headers::new();
+        assert!(keep_alive(&HttpVersion::Http11, &headers));
+        assert!(!keep_alive(&HttpVersion::Http10, &headers));
+
+        headers.set_raw("Connection", vec![b"Keep-Alive".to_vec()]);
+        assert!(keep_alive(&HttpVersion::Http10, &headers));
+
+        headers.set_raw("Connection", vec![b"close".to_vec()]);
+        assert!(!keep_alive(&HttpVersion::Http11, &headers));
+    }
+
+    #[test]
+    fn basic_credentials() {
+        fn handler(context: Context, response: Response) {
+            response.send(format!("{:?}", context.basic_auth()));
+        }
+
+        let (instance, _scheme) = Server::new(handler as fn(Context, Response)).build();
+        let address = "127.0.0.1:8080".parse().unwrap();
+        let authorize = |authorization: &str| {
+            let request = format!("GET / HTTP/1.1\r\nHost: localhost\r\nAuthorization: {}\r\n\r\n", authorization);
+            String::from_utf8(instance.dispatch(request.as_bytes(), address)).unwrap()
+        };
+
+        //"admin:hunter2", "admin" and "admin:a:b"
+        assert!(authorize("Basic YWRtaW46aHVudGVyMg==").ends_with(r#"Some(("admin", "hunter2"))"#));
+        assert!(authorize("Basic YWRtaW4=").ends_with(r#"Some(("admin", ""))"#));
+        assert!(authorize("Basic YWRtaW46YTpi").ends_with(r#"Some(("admin", "a:b"))"#));
+        assert!(authorize("Basic !!!").ends_with("None"));
+        assert!(authorize("basic YWRtaW46aHVudGVyMg==").ends_with(r#"Some(("admin", "hunter2"))"#));
+        assert!(authorize("Bearer YWRtaW46aHVudGVyMg==").ends_with("None"));
+    }
+}
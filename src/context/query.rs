@@ -0,0 +1,184 @@
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+use context::Parameters;
+use utils;
+
+///Lazily parsed query parameters.
+///
+///The query string is stored as it is and it's not parsed into `Parameters`
+///until it's accessed for the first time. `Query` dereferences to
+///`Parameters`, so it can be used in the same way.
+///
+///```
+///use rustful::context::Query;
+///
+///let query = Query::from_raw("a=1&b=2");
+///assert!(!query.is_parsed());
+///
+///assert_eq!(query.get("a"), Some("1".into()));
+///assert!(query.is_parsed());
+///```
+pub struct Query {
+    raw: Vec<u8>,
+    parsed: UnsafeCell<Option<Parameters>>
+}
+
+impl Query {
+    ///Create an empty `Query`.
+    pub fn new() -> Query {
+        Query::from(Parameters::new())
+    }
+
+    ///Create a `Query` from a raw, undecoded, query string.
+    pub fn from_raw<V: Into<Vec<u8>>>(raw: V) -> Query {
+        Query {
+            raw: raw.into(),
+            parsed: UnsafeCell::new(None)
+        }
+    }
+
+    ///Get the query string as it was sent by the client, without any
+    ///decoding. This is empty if the `Query` was created from `Parameters`.
+    ///
+    ///```
+    ///use rustful::context::Query;
+    ///
+    ///let query = Query::from_raw("a=%C3%A5+b");
+    ///assert_eq!(query.raw(), b"a=%C3%A5+b");
+    ///assert_eq!(query.get("a"), Some("\u{e5} b".into()));
+    ///```
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    ///Check if the query string has been parsed.
+    pub fn is_parsed(&self) -> bool {
+        unsafe { (*self.parsed.get()).is_some() }
+    }
+
+    fn parameters(&self) -> &Parameters {
+        if let Some(parameters) = unsafe { (*self.parsed.get()).as_ref() } {
+            return parameters;
+        }
+
+        let parameters = utils::parse_parameters(&self.raw);
+
+        //`parsed` works like a cell that can only be set once. It's only
+        //written to while it's `None`, and no reference to its content
+        //can exist before that, so the shared references that are handed
+        //out are never invalidated. `UnsafeCell` also makes sure that
+        //`Query` is not `Sync`.
+        unsafe {
+            let parsed = self.parsed.get();
+            *parsed = Some(parameters);
+            (*parsed).as_ref().expect("query was not parsed")
+        }
+    }
+
+    fn parameters_mut(&mut self) -> &mut Parameters {
+        //`&mut self` guarantees that no other references exist.
+        let parsed = unsafe { &mut *self.parsed.get() };
+        if parsed.is_none() {
+            *parsed = Some(utils::parse_parameters(&self.raw));
+        }
+
+        parsed.as_mut().expect("query was not parsed")
+    }
+}
+
+impl Deref for Query {
+    type Target = Parameters;
+
+    fn deref(&self) -> &Parameters {
+        self.parameters()
+    }
+}
+
+impl DerefMut for Query {
+    fn deref_mut(&mut self) -> &mut Parameters {
+        self.parameters_mut()
+    }
+}
+
+impl From<Parameters> for Query {
+    fn from(parameters: Parameters) -> Query {
+        Query {
+            raw: vec![],
+            parsed: UnsafeCell::new(Some(parameters))
+        }
+    }
+}
+
+impl Into<Parameters> for Query {
+    fn into(self) -> Parameters {
+        match unsafe { self.parsed.into_inner() } {
+            Some(parameters) => parameters,
+            None => utils::parse_parameters(&self.raw)
+        }
+    }
+}
+
+impl Clone for Query {
+    fn clone(&self) -> Query {
+        Query {
+            raw: self.raw.clone(),
+            parsed: UnsafeCell::new(unsafe { (*self.parsed.get()).clone() })
+        }
+    }
+}
+
+impl PartialEq for Query {
+    fn eq(&self, other: &Query) -> bool {
+        self.parameters() == other.parameters()
+    }
+}
+
+impl Eq for Query {}
+
+impl fmt::Debug for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.parameters(), f)
+    }
+}
+
+impl Default for Query {
+    fn default() -> Query {
+        Query::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Query;
+
+    #[test]
+    fn parse_on_access() {
+        let mut query = Query::from_raw("a=1&b=x+y");
+        assert!(!query.is_parsed());
+
+        assert_eq!(query.get("b"), Some("x y".into()));
+        assert!(query.is_parsed());
+
+        query.insert("c", "3".to_owned());
+        assert_eq!(query.len(), 3);
+        assert_eq!(query.raw(), b"a=1&b=x+y");
+    }
+
+    #[test]
+    fn parse_mutable_access() {
+        let mut query = Query::from_raw("a=1");
+        assert_eq!(query.remove("a"), Some("1".to_owned().into()));
+        assert!(query.is_empty());
+    }
+
+    #[test]
+    fn keep_parsed_references() {
+        let query = Query::from_raw("a=1&b=2");
+        let a = query.get_raw("a").map(|a| a.as_bytes());
+        let b = query.get_raw("b").map(|b| b.as_bytes());
+        assert_eq!(a, Some(&b"1"[..]));
+        assert_eq!(b, Some(&b"2"[..]));
+    }
+}
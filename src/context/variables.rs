@@ -0,0 +1,252 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use context::{Parameters, MaybeUtf8, MaybeUtf8Owned, MaybeUtf8Slice};
+
+///Route variables, such as `id` in `/products/:id`.
+///
+///The names are borrowed from the router and the values are ranges of a
+///single copy of the request path, so looking up or comparing variables
+///does not allocate anything. Use `to_parameters` to get an owned and
+///mutable copy.
+///
+///```
+///use rustful::context::RouteVariables;
+///
+///let path = "/products/42";
+///let variables = RouteVariables::new(path.into(), vec![("id".into(), 10..12)]);
+///assert_eq!(variables.get("id"), Some("42".into()));
+///assert_eq!(variables.parse("id"), Ok(42));
+///```
+#[derive(Clone)]
+pub struct RouteVariables<'s> {
+    path: MaybeUtf8Owned,
+    variables: Vec<(MaybeUtf8Slice<'s>, Range<usize>)>
+}
+
+impl<'s> RouteVariables<'s> {
+    ///Create an empty set of variables.
+    pub fn empty() -> RouteVariables<'s> {
+        RouteVariables {
+            path: MaybeUtf8::Utf8(String::new()),
+            variables: vec![]
+        }
+    }
+
+    ///Create a set of variables, where each value is a byte range of `path`.
+    ///The path is only copied if there are any variables, and ranges that
+    ///are out of bounds are ignored.
+    pub fn new(path: MaybeUtf8Slice, variables: Vec<(MaybeUtf8Slice<'s>, Range<usize>)>) -> RouteVariables<'s> {
+        if variables.is_empty() {
+            return RouteVariables::empty();
+        }
+
+        let length = path.len();
+        let path = match path {
+            MaybeUtf8::Utf8(s) => MaybeUtf8::Utf8(s.to_owned()),
+            MaybeUtf8::NotUtf8(v) => MaybeUtf8::NotUtf8(v.to_owned())
+        };
+
+        RouteVariables {
+            path: path,
+            variables: variables.into_iter().filter(|&(_, ref range)| range.start <= range.end && range.end <= length).collect()
+        }
+    }
+
+    ///Get a variable as a UTF-8 string. A lossy conversion will be performed
+    ///if it's not encoded as UTF-8. Use `get_raw` to get the original data.
+    pub fn get<'a, K: ?Sized + AsRef<[u8]>>(&'a self, key: &K) -> Option<Cow<'a, str>> {
+        self.get_raw(key).map(|value| match value {
+            MaybeUtf8::Utf8(s) => Cow::Borrowed(s),
+            MaybeUtf8::NotUtf8(v) => String::from_utf8_lossy(v)
+        })
+    }
+
+    ///Get a variable that may or may not be a UTF-8 string.
+    pub fn get_raw<'a, K: ?Sized + AsRef<[u8]>>(&'a self, key: &K) -> Option<MaybeUtf8Slice<'a>> {
+        let key = key.as_ref();
+        self.variables.iter()
+            .find(|&&(ref name, _)| name.as_bytes() == key)
+            .map(|&(_, ref range)| self.slice(range))
+    }
+
+    ///Returns true if a variable with the given key exists.
+    pub fn contains_key<K: ?Sized + AsRef<[u8]>>(&self, key: &K) -> bool {
+        let key = key.as_ref();
+        self.variables.iter().any(|&(ref name, _)| name.as_bytes() == key)
+    }
+
+    ///Get the number of variables.
+    pub fn len(&self) -> usize {
+        self.variables.len()
+    }
+
+    ///Check if there are no variables.
+    pub fn is_empty(&self) -> bool {
+        self.variables.is_empty()
+    }
+
+    ///Iterate over the names and values of the variables.
+    pub fn iter<'a>(&'a self) -> VariableIter<'a, 's> {
+        VariableIter {
+            variables: self,
+            index: 0
+        }
+    }
+
+    ///Try to parse a variable as `T`, if it exists. The error will be `None`
+    ///if the variable does not exist, and `Some` if it does exists, but the
+    ///parsing failed.
+    ///
+    ///```
+    ///# use rustful::{Context, Response};
+    ///fn my_handler(context: Context, response: Response) {
+    ///    let age: Result<u8, _> = context.variables.parse("age");
+    ///    match age {
+    ///        Ok(age) => response.send(format!("age: {}", age)),
+    ///        Err(Some(_)) => response.send("age must be a positive number"),
+    ///        Err(None) => response.send("no age provided")
+    ///    }
+    ///}
+    ///```
+    pub fn parse<K: ?Sized + AsRef<[u8]>, T: FromStr>(&self, key: &K) -> Result<T, Option<T::Err>> {
+        if let Some(value) = self.get(key) {
+            value.parse().map_err(|e| Some(e))
+        } else {
+            Err(None)
+        }
+    }
+
+    ///Try to parse a variable as `T`, if it exists, or return the default in
+    ///`or`.
+    pub fn parse_or<K: ?Sized + AsRef<[u8]>, T: FromStr>(&self, key: &K, or: T) -> T {
+        self.parse(key).unwrap_or(or)
+    }
+
+    ///Try to parse a variable as `T`, if it exists, or create a new one using
+    ///`or_else`. The `or_else` function will receive the parsing error if the
+    ///value existed, but was impossible to parse.
+    pub fn parse_or_else<K: ?Sized, T, F>(&self, key: &K, or_else: F) -> T where
+        K: AsRef<[u8]>,
+        T: FromStr,
+        F: FnOnce(Option<T::Err>) -> T
+    {
+        self.parse(key).unwrap_or_else(or_else)
+    }
+
+    ///Copy the variables into an owned `Parameters` map.
+    pub fn to_parameters(&self) -> Parameters {
+        self.iter().map(|(name, value)| (to_owned(name), to_owned(value))).collect()
+    }
+
+    fn slice<'a>(&'a self, range: &Range<usize>) -> MaybeUtf8Slice<'a> {
+        match self.path {
+            MaybeUtf8::Utf8(ref s) if s.is_char_boundary(range.start) && s.is_char_boundary(range.end) => {
+                MaybeUtf8::Utf8(&s[range.start..range.end])
+            },
+            ref path => MaybeUtf8::NotUtf8(&path.as_bytes()[range.start..range.end])
+        }
+    }
+}
+
+impl<'s> Default for RouteVariables<'s> {
+    fn default() -> RouteVariables<'s> {
+        RouteVariables::empty()
+    }
+}
+
+impl<'s> PartialEq for RouteVariables<'s> {
+    fn eq(&self, other: &RouteVariables) -> bool {
+        self.len() == other.len() && self.iter().all(|(name, value)| other.get_raw(&name) == Some(value))
+    }
+}
+
+impl<'s> Eq for RouteVariables<'s> {}
+
+impl<'s> fmt::Debug for RouteVariables<'s> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'s> From<RouteVariables<'s>> for Parameters {
+    fn from(variables: RouteVariables<'s>) -> Parameters {
+        variables.to_parameters()
+    }
+}
+
+impl<'a, 's> IntoIterator for &'a RouteVariables<'s> {
+    type IntoIter = VariableIter<'a, 's>;
+    type Item = (MaybeUtf8Slice<'s>, MaybeUtf8Slice<'a>);
+
+    fn into_iter(self) -> VariableIter<'a, 's> {
+        self.iter()
+    }
+}
+
+///An iterator over the names and values of route variables.
+pub struct VariableIter<'a, 's: 'a> {
+    variables: &'a RouteVariables<'s>,
+    index: usize
+}
+
+impl<'a, 's> Iterator for VariableIter<'a, 's> {
+    type Item = (MaybeUtf8Slice<'s>, MaybeUtf8Slice<'a>);
+
+    fn next(&mut self) -> Option<(MaybeUtf8Slice<'s>, MaybeUtf8Slice<'a>)> {
+        let variables = self.variables;
+        let next = variables.variables.get(self.index).map(|&(ref name, ref range)| {
+            (name.clone(), variables.slice(range))
+        });
+
+        if next.is_some() {
+            self.index += 1;
+        }
+
+        next
+    }
+}
+
+fn to_owned(value: MaybeUtf8Slice) -> MaybeUtf8Owned {
+    match value {
+        MaybeUtf8::Utf8(s) => MaybeUtf8::Utf8(s.to_owned()),
+        MaybeUtf8::NotUtf8(v) => MaybeUtf8::NotUtf8(v.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use context::MaybeUtf8;
+    use super::RouteVariables;
+
+    #[test]
+    fn borrow_values() {
+        let variables = RouteVariables::new("/a/b/c".into(), vec![("x".into(), 1..2), ("y".into(), 5..6)]);
+        assert_eq!(variables.get("x"), Some("a".into()));
+        assert_eq!(variables.get("y"), Some("c".into()));
+        assert_eq!(variables.get("z"), None);
+        assert_eq!(variables.len(), 2);
+    }
+
+    #[test]
+    fn ignore_invalid_ranges() {
+        let variables = RouteVariables::new("/a".into(), vec![("x".into(), 1..5)]);
+        assert!(variables.is_empty());
+    }
+
+    #[test]
+    fn non_utf8_values() {
+        let path = MaybeUtf8::NotUtf8(&b"/\xff"[..]);
+        let variables = RouteVariables::new(path, vec![("x".into(), 1..2)]);
+        assert_eq!(variables.get_raw("x"), Some(MaybeUtf8::NotUtf8(&b"\xff"[..])));
+    }
+
+    #[test]
+    fn convert_to_parameters() {
+        let variables = RouteVariables::new("/a/b".into(), vec![("x".into(), 1..2)]);
+        let parameters = variables.to_parameters();
+        assert_eq!(parameters.get("x"), Some("a".into()));
+    }
+}
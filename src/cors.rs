@@ -0,0 +1,342 @@
+//!Cross-origin resource sharing (CORS).
+//!
+//!A [`CorsPolicy`][policy] describes which origins may access a resource,
+//!and how. It's applied to a response with `Response::apply_cors`, which
+//!sets the CORS headers for both simple and preflighted requests:
+//!
+//!```
+//!use rustful::{Context, Response, StatusCode};
+//!use rustful::cors::{CorsPolicy, CorsRequest};
+//!
+//!fn my_handler(context: Context, mut response: Response) {
+//!    let policy = CorsPolicy::new()
+//!        .allow_origin("https://example.com")
+//!        .allow_header("Content-Type")
+//!        .allow_credentials();
+//!
+//!    match response.apply_cors(&policy, &context.method, &context.headers) {
+//!        //The status is already set to 204 No Content.
+//!        CorsRequest::Preflight => {},
+//!        CorsRequest::Rejected => response.set_status(StatusCode::Forbidden),
+//!        CorsRequest::Actual | CorsRequest::SameOrigin => response.send("the resource")
+//!    }
+//!}
+//!```
+//!
+//!The origin is echoed in `Access-Control-Allow-Origin`, together with
+//!`Vary: Origin`, unless any origin is allowed, in which case it's `*`.
+//!Credentials can only be allowed for a list of origins. A policy that
+//!allows any origin together with credentials would let every site make
+//!credentialed requests, so it rejects all cross-origin requests.
+//!
+//![policy]: struct.CorsPolicy.html
+
+use header::Headers;
+use utils::{header_list, add_vary};
+use {Method, StatusCode};
+
+///The origins that are allowed by a `CorsPolicy`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AllowedOrigins {
+    ///Allow any origin.
+    Any,
+
+    ///Allow only these origins, such as `https://example.com`. They are
+    ///compared without regard to case.
+    List(Vec<String>)
+}
+
+///A CORS policy.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CorsPolicy {
+    ///The allowed origins. Default is an empty list.
+    pub origins: AllowedOrigins,
+
+    ///The allowed methods, in addition to `GET`, `HEAD` and `POST`. Default
+    ///is an empty list.
+    pub methods: Vec<Method>,
+
+    ///The request headers that are allowed, in addition to the simple
+    ///headers. Default is an empty list.
+    pub headers: Vec<String>,
+
+    ///The response headers that are exposed to the client, in addition to
+    ///the simple headers. Default is an empty list.
+    pub expose_headers: Vec<String>,
+
+    ///Allow requests with credentials, such as cookies. It requires a list
+    ///of origins, and all cross-origin requests are rejected if it's
+    ///combined with `AllowedOrigins::Any`. Default is `false`.
+    pub credentials: bool,
+
+    ///How long, in seconds, the client may cache a preflight response.
+    ///Default is `None`.
+    pub max_age: Option<u32>
+}
+
+impl CorsPolicy {
+    ///Create a policy that doesn't allow any origins.
+    pub fn new() -> CorsPolicy {
+        CorsPolicy {
+            origins: AllowedOrigins::List(vec![]),
+            methods: vec![],
+            headers: vec![],
+            expose_headers: vec![],
+            credentials: false,
+            max_age: None
+        }
+    }
+
+    ///Allow any origin.
+    pub fn allow_any_origin(mut self) -> CorsPolicy {
+        self.origins = AllowedOrigins::Any;
+        self
+    }
+
+    ///Allow `origin`. It has no effect if any origin is allowed.
+    pub fn allow_origin<O: Into<String>>(mut self, origin: O) -> CorsPolicy {
+        if let AllowedOrigins::List(ref mut origins) = self.origins {
+            origins.push(origin.into());
+        }
+        self
+    }
+
+    ///Allow `method`.
+    pub fn allow_method(mut self, method: Method) -> CorsPolicy {
+        self.methods.push(method);
+        self
+    }
+
+    ///Allow the request header `header`.
+    pub fn allow_header<H: Into<String>>(mut self, header: H) -> CorsPolicy {
+        self.headers.push(header.into());
+        self
+    }
+
+    ///Expose the response header `header`.
+    pub fn expose_header<H: Into<String>>(mut self, header: H) -> CorsPolicy {
+        self.expose_headers.push(header.into());
+        self
+    }
+
+    ///Allow requests with credentials. The allowed origins have to be listed
+    ///explicitly, since all cross-origin requests are rejected if any origin
+    ///is allowed.
+    pub fn allow_credentials(mut self) -> CorsPolicy {
+        self.credentials = true;
+        self
+    }
+
+    ///Let the client cache preflight responses for `seconds`.
+    pub fn max_age(mut self, seconds: u32) -> CorsPolicy {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    ///Check if `origin` is allowed.
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        match self.origins {
+            AllowedOrigins::Any => true,
+            AllowedOrigins::List(ref origins) => {
+                let origin = origin.to_lowercase();
+                origins.iter().any(|allowed| allowed.to_lowercase() == origin)
+            }
+        }
+    }
+
+    ///Check if `method` is allowed.
+    pub fn allows_method(&self, method: &Method) -> bool {
+        match *method {
+            Method::Get | Method::Head | Method::Post => true,
+            ref method => self.methods.contains(method)
+        }
+    }
+
+    ///Check if the request header `header` is allowed.
+    pub fn allows_header(&self, header: &str) -> bool {
+        let header = header.to_lowercase();
+        match &*header {
+            "accept" | "accept-language" | "content-language" | "content-type" => true,
+            header => self.headers.iter().any(|allowed| allowed.to_lowercase() == header)
+        }
+    }
+
+    ///Set the CORS headers in `response_headers`, for a request with the
+    ///method `method` and the headers `request_headers`. The status of a
+    ///preflight response is returned, and it should be used.
+    ///
+    ///This is what `Response::apply_cors` uses, and it can be used where
+    ///there is no `Response`.
+    pub fn apply(&self, method: &Method, request_headers: &Headers, response_headers: &mut Headers) -> (CorsRequest, Option<StatusCode>) {
+        let origin = match request_headers.get_raw("Origin").and_then(|lines| lines.first()) {
+            Some(origin) => String::from_utf8_lossy(origin).into_owned(),
+            None => return (CorsRequest::SameOrigin, None)
+        };
+
+        let wildcard = self.origins == AllowedOrigins::Any;
+        if wildcard && self.credentials {
+            return (CorsRequest::Rejected, None);
+        }
+
+        if !wildcard {
+            add_vary(response_headers, "Origin");
+        }
+
+        if !self.allows_origin(&origin) {
+            return (CorsRequest::Rejected, None);
+        }
+
+        let requested_method = request_headers.get_raw("Access-Control-Request-Method")
+            .and_then(|lines| lines.first())
+            .and_then(|method| ::std::str::from_utf8(method).ok())
+            .and_then(|method| method.trim().parse::<Method>().ok());
+
+        let preflight = match (method, requested_method) {
+            (&Method::Options, Some(requested_method)) => {
+                let requested_headers = header_list(request_headers, "Access-Control-Request-Headers");
+                if !self.allows_method(&requested_method) || !requested_headers.iter().all(|header| self.allows_header(header)) {
+                    return (CorsRequest::Rejected, None);
+                }
+
+                let methods: Vec<String> = self.methods.iter().map(|method| method.to_string()).collect();
+                if !methods.is_empty() {
+                    response_headers.set_raw("Access-Control-Allow-Methods", vec![methods.join(", ").into_bytes()]);
+                }
+
+                if !requested_headers.is_empty() {
+                    response_headers.set_raw("Access-Control-Allow-Headers", vec![requested_headers.join(", ").into_bytes()]);
+                }
+
+                if let Some(max_age) = self.max_age {
+                    response_headers.set_raw("Access-Control-Max-Age", vec![max_age.to_string().into_bytes()]);
+                }
+
+                true
+            },
+            _ => {
+                if !self.expose_headers.is_empty() {
+                    response_headers.set_raw("Access-Control-Expose-Headers", vec![self.expose_headers.join(", ").into_bytes()]);
+                }
+
+                false
+            }
+        };
+
+        let allowed_origin = if wildcard { "*".to_owned() } else { origin };
+        response_headers.set_raw("Access-Control-Allow-Origin", vec![allowed_origin.into_bytes()]);
+
+        if self.credentials {
+            response_headers.set_raw("Access-Control-Allow-Credentials", vec![b"true".to_vec()]);
+        }
+
+        if preflight {
+            (CorsRequest::Preflight, Some(StatusCode::NoContent))
+        } else {
+            (CorsRequest::Actual, None)
+        }
+    }
+}
+
+impl Default for CorsPolicy {
+    fn default() -> CorsPolicy {
+        CorsPolicy::new()
+    }
+}
+
+///The kind of request that a CORS policy was applied to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CorsRequest {
+    ///The request has no `Origin` header, so no CORS headers were set.
+    SameOrigin,
+
+    ///A simple or actual cross-origin request, which may be handled as
+    ///usual.
+    Actual,
+
+    ///A preflight request, which was answered by the CORS headers. It
+    ///shouldn't be handled any further.
+    Preflight,
+
+    ///The origin, method or headers are not allowed, or the policy allows
+    ///credentials for any origin, so no CORS headers were set, and the
+    ///client will block the response.
+    Rejected
+}
+
+#[cfg(test)]
+mod test {
+    use header::Headers;
+    use {Method, StatusCode};
+    use super::{CorsPolicy, CorsRequest};
+
+    fn raw(headers: &Headers, name: &str) -> Option<String> {
+        headers.get_raw(name).map(|lines| String::from_utf8_lossy(&lines[0]).into_owned())
+    }
+
+    #[test]
+    fn simple_requests() {
+        let policy = CorsPolicy::new().allow_origin("https://example.com").expose_header("X-Total");
+        let mut request = Headers::new();
+        let mut response = Headers::new();
+
+        assert_eq!(policy.apply(&Method::Get, &request, &mut response).0, CorsRequest::SameOrigin);
+
+        request.set_raw("Origin", vec![b"https://other.com".to_vec()]);
+        assert_eq!(policy.apply(&Method::Get, &request, &mut response).0, CorsRequest::Rejected);
+        assert_eq!(raw(&response, "Access-Control-Allow-Origin"), None);
+
+        request.set_raw("Origin", vec![b"https://EXAMPLE.com".to_vec()]);
+        let mut response = Headers::new();
+        assert_eq!(policy.apply(&Method::Get, &request, &mut response), (CorsRequest::Actual, None));
+        assert_eq!(raw(&response, "Access-Control-Allow-Origin"), Some("https://EXAMPLE.com".to_owned()));
+        assert_eq!(raw(&response, "Access-Control-Expose-Headers"), Some("X-Total".to_owned()));
+        assert_eq!(raw(&response, "Vary"), Some("Origin".to_owned()));
+
+        let mut response = Headers::new();
+        CorsPolicy::new().allow_any_origin().apply(&Method::Get, &request, &mut response);
+        assert_eq!(raw(&response, "Access-Control-Allow-Origin"), Some("*".to_owned()));
+        assert_eq!(raw(&response, "Vary"), None);
+    }
+
+    #[test]
+    fn preflight_requests() {
+        let policy = CorsPolicy::new()
+            .allow_origin("https://example.com")
+            .allow_method(Method::Put)
+            .allow_header("X-Token")
+            .allow_credentials()
+            .max_age(600);
+
+        let mut request = Headers::new();
+        request.set_raw("Origin", vec![b"https://example.com".to_vec()]);
+        request.set_raw("Access-Control-Request-Method", vec![b"PUT".to_vec()]);
+        request.set_raw("Access-Control-Request-Headers", vec![b"x-token, content-type".to_vec()]);
+
+        let mut response = Headers::new();
+        assert_eq!(policy.apply(&Method::Options, &request, &mut response), (CorsRequest::Preflight, Some(StatusCode::NoContent)));
+        assert_eq!(raw(&response, "Access-Control-Allow-Origin"), Some("https://example.com".to_owned()));
+        assert_eq!(raw(&response, "Access-Control-Allow-Methods"), Some("PUT".to_owned()));
+        assert_eq!(raw(&response, "Access-Control-Allow-Headers"), Some("x-token, content-type".to_owned()));
+        assert_eq!(raw(&response, "Access-Control-Allow-Credentials"), Some("true".to_owned()));
+        assert_eq!(raw(&response, "Access-Control-Max-Age"), Some("600".to_owned()));
+
+        request.set_raw("Access-Control-Request-Method", vec![b"DELETE".to_vec()]);
+        assert_eq!(policy.apply(&Method::Options, &request, &mut Headers::new()).0, CorsRequest::Rejected);
+    }
+
+    #[test]
+    fn reject_credentials_for_any_origin() {
+        let policy = CorsPolicy::new().allow_any_origin().allow_credentials();
+
+        let mut request = Headers::new();
+        request.set_raw("Origin", vec![b"https://example.com".to_vec()]);
+
+        let mut response = Headers::new();
+        assert_eq!(policy.apply(&Method::Get, &request, &mut response), (CorsRequest::Rejected, None));
+        assert_eq!(raw(&response, "Access-Control-Allow-Origin"), None);
+        assert_eq!(raw(&response, "Access-Control-Allow-Credentials"), None);
+
+        request.set_raw("Access-Control-Request-Method", vec![b"GET".to_vec()]);
+        assert_eq!(policy.apply(&Method::Options, &request, &mut Headers::new()).0, CorsRequest::Rejected);
+    }
+}
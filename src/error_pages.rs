@@ -0,0 +1,138 @@
+//!Custom error pages.
+//!
+//!Responses with an error status and no body, such as `404 Not Found` when
+//!no handler matches, a `413 Payload Too Large` from a handler, or the
+//!status from an aborting context filter, get their body from the error
+//!pages of the server. A page is rendered when the response is sent without
+//!a body, which is what happens when it's dropped after `set_status`. It's
+//!the same for statuses that are set by handlers and by the server itself.
+//!
+//!```
+//!#[macro_use]
+//!extern crate rustful;
+//!use rustful::{Server, Context, Response, StatusCode};
+//!use rustful::header::ContentType;
+//!use rustful::error_pages::ErrorPages;
+//!
+//!fn not_found(_status: StatusCode, response: &mut Response) -> String {
+//!    response.headers_mut().set(ContentType(content_type!(Text / Html; Charset = Utf8)));
+//!    "<h1>Nothing here</h1>".to_owned()
+//!}
+//!
+//!fn other_error(status: StatusCode, _response: &mut Response) -> String {
+//!    format!("Oops! {}", status)
+//!}
+//!
+//!# fn main() {
+//!let mut error_pages = ErrorPages::new();
+//!error_pages.insert(StatusCode::NotFound, not_found);
+//!error_pages.set_default(other_error);
+//!
+//!let server = Server {
+//!    error_pages: error_pages,
+//!    ..Server::new(|_: Context, mut response: Response| response.set_status(StatusCode::NotFound))
+//!};
+//!# }
+//!```
+
+use std::collections::HashMap;
+
+use response::Response;
+use StatusCode;
+
+///Renders an error page.
+pub trait ErrorPage: Send + Sync + 'static {
+    ///Set the headers of `response` for an error page for `status`, and
+    ///return its body.
+    fn render(&self, status: StatusCode, response: &mut Response) -> String;
+}
+
+impl<F> ErrorPage for F where F: Fn(StatusCode, &mut Response) -> String + Send + Sync + 'static {
+    fn render(&self, status: StatusCode, response: &mut Response) -> String {
+        self(status, response)
+    }
+}
+
+///A set of error pages, by status code.
+pub struct ErrorPages {
+    pages: HashMap<u16, Box<ErrorPage>>,
+    default: Option<Box<ErrorPage>>
+}
+
+impl ErrorPages {
+    ///Create an empty set, where no pages are rendered.
+    pub fn new() -> ErrorPages {
+        ErrorPages {
+            pages: HashMap::new(),
+            default: None
+        }
+    }
+
+    ///Set the page for `status`.
+    pub fn insert<P: ErrorPage>(&mut self, status: StatusCode, page: P) {
+        self.pages.insert(status.to_u16(), Box::new(page));
+    }
+
+    ///Remove the page for `status`.
+    pub fn remove(&mut self, status: StatusCode) {
+        self.pages.remove(&status.to_u16());
+    }
+
+    ///Set the page for all `4xx` and `5xx` statuses that don't have a page
+    ///of their own.
+    pub fn set_default<P: ErrorPage>(&mut self, page: P) {
+        self.default = Some(Box::new(page));
+    }
+
+    ///Find the page for `status`, if it's an error status.
+    pub fn get(&self, status: StatusCode) -> Option<&ErrorPage> {
+        let code = status.to_u16();
+        if code < 400 || code >= 600 {
+            return None;
+        }
+
+        self.pages.get(&code).or(self.default.as_ref()).map(|page| &**page)
+    }
+
+    ///Check if there are no pages.
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty() && self.default.is_none()
+    }
+}
+
+impl Default for ErrorPages {
+    fn default() -> ErrorPages {
+        ErrorPages::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use response::Response;
+    use StatusCode;
+    use super::ErrorPages;
+
+    fn not_found(_status: StatusCode, _response: &mut Response) -> String {
+        "not found".into()
+    }
+
+    fn other(_status: StatusCode, _response: &mut Response) -> String {
+        "other".into()
+    }
+
+    #[test]
+    fn find_pages() {
+        let mut pages = ErrorPages::new();
+        assert!(pages.is_empty());
+        assert!(pages.get(StatusCode::NotFound).is_none());
+
+        pages.insert(StatusCode::NotFound, not_found);
+        assert!(pages.get(StatusCode::NotFound).is_some());
+        assert!(pages.get(StatusCode::InternalServerError).is_none());
+
+        pages.set_default(other);
+        assert!(pages.get(StatusCode::InternalServerError).is_some());
+        assert!(pages.get(StatusCode::Ok).is_none());
+        assert!(pages.get(StatusCode::MovedPermanently).is_none());
+    }
+}
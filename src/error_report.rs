@@ -0,0 +1,284 @@
+//!Reports about failed requests.
+//!
+//!An [`ErrorReporter`][reporter] is called when a request fails, which is
+//!when the handler panics, or when the response has a `5xx` status. It gets
+//!an [`ErrorReport`][report] with what is known about the request, which
+//!can be sent on to an error tracking service:
+//!
+//!```
+//!use rustful::{Server, Context, Response};
+//!use rustful::error_report::ErrorReport;
+//!
+//!fn report(report: &ErrorReport) {
+//!    match report.panic {
+//!        Some(ref message) => println!("{} {} panicked: {}", report.method, report.request_target, message),
+//!        None => println!("{} {} failed with {}", report.method, report.request_target, report.status)
+//!    }
+//!}
+//!
+//!let server = Server {
+//!    error_reporter: Some(Box::new(report)),
+//!    ..Server::new(|_: Context, response: Response| response.send("hello"))
+//!};
+//!```
+//!
+//!The reporter is called from the thread that handled the request, after
+//!the response has been sent, so it should hand the report over to another
+//!thread if it's slow. A panic is passed on after it has been reported.
+//!
+//!Headers that carry credentials are left out of the reports. These are
+//!`Authorization`, `Proxy-Authorization`, `Cookie` and `X-Api-Key`, as well
+//!as the headers that are added using [`Redact`][redact]:
+//!
+//!```
+//!use rustful::{Server, Context, Response};
+//!use rustful::error_report::{ErrorReport, Redact};
+//!
+//!let reporter = Redact::new(|report: &ErrorReport| println!("{:?}", report), vec!["X-Secret-Token".into()]);
+//!
+//!let server = Server {
+//!    error_reporter: Some(Box::new(reporter)),
+//!    ..Server::new(|_: Context, response: Response| response.send("hello"))
+//!};
+//!```
+//!
+//![reporter]: trait.ErrorReporter.html
+//![report]: struct.ErrorReport.html
+//![redact]: struct.Redact.html
+
+use std::any::Any;
+use std::net::SocketAddr;
+
+use {Method, StatusCode};
+use events::Outbox;
+use header::Headers;
+
+//Headers that are not included in reports, since they carry credentials.
+const SECRET_HEADERS: &'static [&'static str] = &["Authorization", "Proxy-Authorization", "Cookie", "X-Api-Key"];
+
+///What is known about a failed request.
+#[derive(Clone, Debug)]
+pub struct ErrorReport {
+    ///The request method.
+    pub method: Method,
+
+    ///The request target, as it was sent by the client.
+    pub request_target: String,
+
+    ///The address of the client. It may be a proxy.
+    pub address: SocketAddr,
+
+    ///The request headers, except for the ones that carry credentials.
+    pub headers: Headers,
+
+    ///The ID of the request, if it was given one by
+    ///[`RequestIds`][request_ids].
+    ///
+    ///[request_ids]: ../request_id/struct.RequestIds.html
+    pub request_id: Option<String>,
+
+    ///The status of the response. It's `500 Internal Server Error` if the
+    ///handler panicked.
+    pub status: StatusCode,
+
+    ///The panic message, if the handler panicked.
+    pub panic: Option<String>
+}
+
+///Receives reports about failed requests.
+pub trait ErrorReporter: Send + Sync {
+    ///Handle a report about a failed request.
+    fn report(&self, report: &ErrorReport);
+
+    ///Check if the header `name` should be left out of the reports, in
+    ///addition to the default ones. The default is to not leave out any
+    ///other headers.
+    fn is_secret_header(&self, _name: &str) -> bool {
+        false
+    }
+}
+
+impl<F: Fn(&ErrorReport) + Send + Sync> ErrorReporter for F {
+    fn report(&self, report: &ErrorReport) {
+        self(report);
+    }
+}
+
+///An `ErrorReporter` that leaves out more headers from the reports.
+pub struct Redact<R> {
+    reporter: R,
+    headers: Vec<String>
+}
+
+impl<R: ErrorReporter> Redact<R> {
+    ///Leave out `headers`, in addition to the ones that are left out by
+    ///`reporter`.
+    pub fn new(reporter: R, headers: Vec<String>) -> Redact<R> {
+        Redact {
+            reporter: reporter,
+            headers: headers
+        }
+    }
+}
+
+impl<R: ErrorReporter> ErrorReporter for Redact<R> {
+    fn report(&self, report: &ErrorReport) {
+        self.reporter.report(report);
+    }
+
+    fn is_secret_header(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        self.headers.iter().any(|header| header.to_lowercase() == name) || self.reporter.is_secret_header(&name)
+    }
+}
+
+#[doc(hidden)]
+///Internal and may change without warning.
+///
+///Collects information about a request, in case it fails.
+pub struct Reporting<'a> {
+    reporter: &'a ErrorReporter,
+    outbox: Outbox,
+    method: Method,
+    request_target: String,
+    address: SocketAddr,
+    headers: Vec<(String, Vec<Vec<u8>>)>
+}
+
+impl<'a> Reporting<'a> {
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn new(
+        reporter: &'a ErrorReporter,
+        outbox: Outbox,
+        method: Method,
+        request_target: String,
+        address: SocketAddr,
+        headers: &Headers
+    ) -> Reporting<'a> {
+        //Only the raw lines of the headers that may be reported are copied.
+        //The `Headers` in the report are built if the request fails.
+        let headers = headers.iter()
+            .map(|header| header.name())
+            .filter(|name| !SECRET_HEADERS.iter().any(|secret| secret.to_lowercase() == name.to_lowercase()))
+            .filter(|name| !reporter.is_secret_header(name))
+            .filter_map(|name| headers.get_raw(name).map(|lines| (name.to_owned(), lines.to_vec())))
+            .collect();
+
+        Reporting {
+            reporter: reporter,
+            outbox: outbox,
+            method: method,
+            request_target: request_target,
+            address: address,
+            headers: headers
+        }
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    ///
+    ///Reports the request if it failed.
+    pub fn finish(self, panic: Option<&Any>) {
+        let panic = panic.map(panic_message);
+        let status = if panic.is_some() {
+            StatusCode::InternalServerError
+        } else {
+            self.outbox.status()
+        };
+
+        if panic.is_none() && status.to_u16() / 100 != 5 {
+            return;
+        }
+
+        let mut headers = Headers::new();
+        for (name, lines) in self.headers {
+            headers.set_raw(name, lines);
+        }
+
+        self.reporter.report(&ErrorReport {
+            method: self.method,
+            request_target: self.request_target,
+            address: self.address,
+            headers: headers,
+            request_id: self.outbox.request_id(),
+            status: status,
+            panic: panic
+        });
+    }
+}
+
+//Gets the message from a panic payload.
+fn panic_message(payload: &Any) -> String {
+    if let Some(message) = payload.downcast_ref::<&'static str>() {
+        (*message).to_owned()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::any::Any;
+    use std::sync::Mutex;
+    use {Method, StatusCode};
+    use events::Outbox;
+    use header::{Headers, Authorization, UserAgent};
+    use super::{ErrorReport, Reporting, Redact};
+
+    #[test]
+    fn report_failures() {
+        let reports = Mutex::new(vec![]);
+        let reporter = |report: &ErrorReport| reports.lock().unwrap().push(report.clone());
+
+        let mut headers = Headers::new();
+        headers.set(Authorization("secret".to_owned()));
+        headers.set(UserAgent("curl/7.0".to_owned()));
+        headers.set_raw("X-Api-Key", vec![b"secret".to_vec()]);
+        headers.set_raw("X-Token", vec![b"secret".to_vec()]);
+
+        let report = |status: StatusCode, panic: Option<&'static str>| {
+            let outbox = Outbox::new();
+            outbox.set_status(status);
+            outbox.set_request_id("abc".to_owned());
+            let reporting = Reporting::new(&reporter, outbox, Method::Get, "/a?b".to_owned(), "127.0.0.1:8080".parse().unwrap(), &headers);
+            reporting.finish(panic.as_ref().map(|p| p as &Any));
+        };
+
+        report(StatusCode::NotFound, None);
+        report(StatusCode::BadGateway, None);
+        report(StatusCode::Ok, Some("oops"));
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].status, StatusCode::BadGateway);
+        assert_eq!(reports[0].panic, None);
+        assert_eq!(reports[0].request_id, Some("abc".to_owned()));
+        assert!(reports[0].headers.get::<Authorization<String>>().is_none());
+        assert!(reports[0].headers.get_raw("X-Api-Key").is_none());
+        assert_eq!(reports[0].headers.get_raw("X-Token"), Some(&[b"secret".to_vec()][..]));
+        assert!(reports[0].headers.get::<UserAgent>().is_some());
+        assert_eq!(reports[1].status, StatusCode::InternalServerError);
+        assert_eq!(reports[1].panic, Some("oops".to_owned()));
+    }
+
+    #[test]
+    fn redact_headers() {
+        let reports = Mutex::new(vec![]);
+        let reporter = Redact::new(|report: &ErrorReport| reports.lock().unwrap().push(report.clone()), vec!["x-token".to_owned()]);
+
+        let mut headers = Headers::new();
+        headers.set_raw("X-Token", vec![b"secret".to_vec()]);
+        headers.set_raw("X-Other", vec![b"value".to_vec()]);
+
+        let outbox = Outbox::new();
+        outbox.set_status(StatusCode::InternalServerError);
+        Reporting::new(&reporter, outbox, Method::Get, "/".to_owned(), "127.0.0.1:8080".parse().unwrap(), &headers).finish(None);
+
+        let reports = reports.lock().unwrap();
+        assert!(reports[0].headers.get_raw("X-Token").is_none());
+        assert_eq!(reports[0].headers.get_raw("X-Other"), Some(&[b"value".to_vec()][..]));
+    }
+}
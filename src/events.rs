@@ -0,0 +1,213 @@
+//!Domain events that are delivered after the response has been sent.
+//!
+//!Handlers can emit events, such as "an order was created", using
+//!`Response::emit`. The events are collected during the request and they
+//!are delivered to the server's [`EventSink`][sink], together with the final
+//!response status, when the response has been sent. Events from requests
+//!that ended in an error can then be dropped or handled differently.
+//!
+//!The sink is called from the thread that handled the request, so it should
+//!not block for long. Slow deliveries, such as sending webhooks, are better
+//!done in a separate thread, for example by passing the events through a
+//!channel:
+//!
+//!```no_run
+//!use std::sync::Mutex;
+//!use std::sync::mpsc::channel;
+//!use std::thread;
+//!use rustful::{Server, Context, Response};
+//!use rustful::events::{Event, Delivery};
+//!
+//!fn create_order(context: Context, mut response: Response) {
+//!    //...create the order...
+//!    response.emit(Event::new("order.created", r#"{"id": 42}"#));
+//!    response.send("order 42 was created");
+//!}
+//!
+//!let (sender, receiver) = channel::<Delivery>();
+//!
+//!thread::spawn(move || {
+//!    for delivery in receiver {
+//!        if delivery.status.is_success() {
+//!            for event in delivery.events {
+//!                println!("{}: {}", event.name, event.payload);
+//!            }
+//!        }
+//!    }
+//!});
+//!
+//!let server = Server {
+//!    event_sink: Some(Box::new(Mutex::new(sender))),
+//!    ..Server::new(create_order)
+//!};
+//!```
+//!
+//![sink]: trait.EventSink.html
+
+use std::cell::RefCell;
+use std::mem;
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::sync::mpsc::Sender;
+
+use StatusCode;
+
+///A domain event, emitted by a handler.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    ///The name of the event, such as `"order.created"`.
+    pub name: String,
+
+    ///The event payload, in any format the sink understands.
+    pub payload: String
+}
+
+impl Event {
+    ///Create a new event.
+    pub fn new<N: Into<String>, P: Into<String>>(name: N, payload: P) -> Event {
+        Event {
+            name: name.into(),
+            payload: payload.into()
+        }
+    }
+}
+
+///The events from a request, together with its outcome.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Delivery {
+    ///The final status of the response.
+    pub status: StatusCode,
+
+    ///The emitted events, in order.
+    pub events: Vec<Event>
+}
+
+///A receiver of emitted events.
+pub trait EventSink: Send + Sync {
+    ///Deliver the events from a request. It's only called if at least one
+    ///event was emitted.
+    fn deliver(&self, delivery: Delivery);
+}
+
+impl<F: Fn(Delivery) + Send + Sync> EventSink for F {
+    fn deliver(&self, delivery: Delivery) {
+        self(delivery);
+    }
+}
+
+impl EventSink for Mutex<Sender<Delivery>> {
+    fn deliver(&self, delivery: Delivery) {
+        if let Ok(sender) = self.lock() {
+            //The receiver is gone, so there is no one to tell.
+            let _ = sender.send(delivery);
+        }
+    }
+}
+
+#[doc(hidden)]
+///Internal and may change without warning.
+#[derive(Clone)]
+pub struct Outbox(Rc<RefCell<(Delivery, u64, Option<String>, bool)>>);
+
+impl Outbox {
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn new() -> Outbox {
+        Outbox(Rc::new(RefCell::new((Delivery {
+            status: StatusCode::Ok,
+            events: vec![]
+        }, 0, None, false))))
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn push(&self, event: Event) {
+        (self.0.borrow_mut().0).events.push(event);
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn set_status(&self, status: StatusCode) {
+        let mut state = self.0.borrow_mut();
+        state.0.status = status;
+        state.3 = true;
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn status(&self) -> StatusCode {
+        (self.0.borrow().0).status
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn sent_status(&self) -> Option<StatusCode> {
+        let state = self.0.borrow();
+        if state.3 {
+            Some(state.0.status)
+        } else {
+            None
+        }
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn add_bytes(&self, bytes: u64) {
+        self.0.borrow_mut().1 += bytes;
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn bytes(&self) -> u64 {
+        self.0.borrow().1
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn set_request_id(&self, id: String) {
+        self.0.borrow_mut().2 = Some(id);
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn request_id(&self) -> Option<String> {
+        self.0.borrow().2.clone()
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn take(&self) -> Option<Delivery> {
+        let mut state = self.0.borrow_mut();
+        let delivery = &mut state.0;
+        if delivery.events.is_empty() {
+            None
+        } else {
+            Some(Delivery {
+                status: delivery.status,
+                events: mem::replace(&mut delivery.events, vec![])
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use StatusCode;
+    use super::{Outbox, Event};
+
+    #[test]
+    fn take_delivery() {
+        let outbox = Outbox::new();
+        assert_eq!(outbox.take(), None);
+
+        outbox.push(Event::new("a", "1"));
+        assert_eq!(outbox.sent_status(), None);
+        outbox.set_status(StatusCode::Created);
+        assert_eq!(outbox.sent_status(), Some(StatusCode::Created));
+
+        let delivery = outbox.take().unwrap();
+        assert_eq!(delivery.status, StatusCode::Created);
+        assert_eq!(delivery.events, vec![Event::new("a", "1")]);
+        assert_eq!(outbox.take(), None);
+    }
+}
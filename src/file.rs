@@ -1,6 +1,21 @@
 //!File related utilities.
 
-use mime::{Mime, TopLevel, SubLevel};
+use std::cmp::{self, Ordering};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use mime::{Mime, TopLevel, SubLevel, Attr, Value};
+use time::{self, Tm, Timespec};
+use url::percent_encoding::percent_decode;
+
+use header::{Headers, Accept, ContentType, CacheControl, CacheDirective};
+use context::Context;
+use handler::Handler;
+use response::Response;
+use {Method, StatusCode};
+use utils::encode_path_segment;
 
 include!(concat!(env!("OUT_DIR"), "/mime.rs"));
 
@@ -26,6 +41,636 @@ pub fn ext_to_mime(ext: &str) -> Option<Mime> {
     })
 }
 
+///The part of a file that is requested by a `Range` header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileRange {
+    ///The whole file. This is also the result of a missing or malformed
+    ///`Range` header, or one with more than one range, since it's allowed to
+    ///ignore it.
+    Full,
+
+    ///The bytes from the first to the second position, including both.
+    Partial(u64, u64),
+
+    ///The range is outside the file, which should result in `416 Range Not
+    ///Satisfiable`.
+    Unsatisfiable
+}
+
+///Find the requested part of a file of `length` bytes, from the `Range`
+///header in `headers`. Only single byte ranges are supported.
+///
+///```
+///use rustful::header::Headers;
+///use rustful::file::{FileRange, requested_range};
+///
+///let mut headers = Headers::new();
+///headers.set_raw("Range", vec![b"bytes=100-".to_vec()]);
+///assert_eq!(requested_range(&headers, 1000), FileRange::Partial(100, 999));
+///```
+pub fn requested_range(headers: &Headers, length: u64) -> FileRange {
+    let range = match headers.get_raw("Range").and_then(|lines| lines.first()) {
+        Some(range) => String::from_utf8_lossy(range).into_owned(),
+        None => return FileRange::Full
+    };
+
+    let mut parts = range.trim().splitn(2, '=');
+    let spec = match (parts.next(), parts.next()) {
+        (Some(unit), Some(spec)) if unit.trim().to_lowercase() == "bytes" && !spec.contains(',') => spec.trim(),
+        _ => return FileRange::Full
+    };
+
+    let (start, end) = match spec.find('-') {
+        Some(dash) => (&spec[..dash], &spec[dash + 1..]),
+        None => return FileRange::Full
+    };
+
+    match (start.parse::<u64>(), end.parse::<u64>()) {
+        //bytes=first-last
+        (Ok(first), Ok(last)) if first <= last => if first < length {
+            FileRange::Partial(first, ::std::cmp::min(last, length - 1))
+        } else {
+            FileRange::Unsatisfiable
+        },
+
+        //bytes=first-
+        (Ok(first), Err(_)) if end.is_empty() => if first < length {
+            FileRange::Partial(first, length - 1)
+        } else {
+            FileRange::Unsatisfiable
+        },
+
+        //bytes=-suffix_length
+        (Err(_), Ok(suffix)) if start.is_empty() => if suffix > 0 && length > 0 {
+            FileRange::Partial(length.saturating_sub(suffix), length - 1)
+        } else {
+            FileRange::Unsatisfiable
+        },
+
+        _ => FileRange::Full
+    }
+}
+
+///How `DirectoryHandler` treats symbolic links.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Symlinks {
+    ///Don't serve anything through a symbolic link.
+    Deny,
+
+    ///Follow symbolic links, as long as the file they lead to is inside the
+    ///root directory. This is the default.
+    InsideRoot,
+
+    ///Follow every symbolic link, even if it leads outside the root
+    ///directory.
+    Follow
+}
+
+///A handler that serves the files in a directory tree.
+///
+///The part of the request path that comes after `prefix` is looked up in
+///the root directory. Paths that would go outside the root, because of `..`,
+///encoded `..`, absolute paths, NUL characters or symbolic links, are
+///answered with `404 Not Found`, just like missing files. See
+///[`Symlinks`](enum.Symlinks.html) for how symbolic links are treated.
+///The MIME type is guessed with [`ext_to_mime`](fn.ext_to_mime.html) and
+///the files are streamed to the client with support for ranges and
+///conditional requests, as described for
+///[`Response::send_file_ranged`](../response/struct.Response.html#method.send_file_ranged).
+///
+///A directory is served as its first index file that exists, which is
+///`index.html` or `index.htm` by default. A request for a
+///directory that doesn't end with `/` is redirected to the same path with a
+///`/`, so relative links in the index file will work. Directories without
+///an index file can optionally be listed, as HTML or, if the client prefers
+///it in its `Accept` header, as JSON. The JSON listing is an array of
+///objects with a `name`, a `directory` flag, the `size` in bytes and the
+///`modified` time in RFC 3339 format. Only `GET` and `HEAD` requests are
+///allowed.
+///
+///The `Cache-Control` header can be set for files with some extensions, or
+///with paths that match a pattern, using `cache_extension` and
+///`cache_pattern`. The rules are tried in the order they were added, and
+///the first one that matches is used.
+///
+///```no_run
+///use rustful::Server;
+///use rustful::file::DirectoryHandler;
+///
+/////Serves `path/to/files/style.css` as `/static/style.css`
+///let server = Server {
+///    host: 8080.into(),
+///    ..Server::new(DirectoryHandler::new("path/to/files", "/static"))
+///};
+///```
+///
+///Hashed assets can be cached for a long time, while HTML files have to be
+///revalidated:
+///
+///```no_run
+///use rustful::Server;
+///use rustful::file::DirectoryHandler;
+///use rustful::header::CacheDirective;
+///
+///let files = DirectoryHandler::new("path/to/files", "/")
+///    .cache_pattern("assets/**", vec![
+///        CacheDirective::Public,
+///        CacheDirective::MaxAge(31536000),
+///        CacheDirective::Extension("immutable".into(), None)
+///    ])
+///    .cache_extension("html", vec![CacheDirective::NoCache]);
+///
+///let server = Server {
+///    host: 8080.into(),
+///    ..Server::new(files)
+///};
+///```
+pub struct DirectoryHandler {
+    root: PathBuf,
+    prefix: String,
+    index_files: Vec<String>,
+    listing: bool,
+    show_hidden: bool,
+    symlinks: Symlinks,
+    cache_rules: Vec<(FileMatch, Vec<CacheDirective>)>
+}
+
+//What a cache rule applies to.
+enum FileMatch {
+    Extension(String),
+    Pattern(String)
+}
+
+impl DirectoryHandler {
+    ///Serve the files in `root` for request paths that start with `prefix`.
+    pub fn new<P: Into<PathBuf>, S: Into<String>>(root: P, prefix: S) -> DirectoryHandler {
+        DirectoryHandler {
+            root: root.into(),
+            prefix: prefix.into().trim_right_matches('/').to_owned(),
+            index_files: vec!["index.html".into(), "index.htm".into()],
+            listing: false,
+            show_hidden: false,
+            symlinks: Symlinks::InsideRoot,
+            cache_rules: vec![]
+        }
+    }
+
+    ///Set the names of the files that are served for a directory, in order
+    ///of preference. An empty list means that directories don't have index
+    ///files. Default is `index.html` and `index.htm`.
+    pub fn index_files<I, S>(mut self, index_files: I) -> DirectoryHandler where
+        I: IntoIterator<Item=S>,
+        S: Into<String>
+    {
+        self.index_files = index_files.into_iter().map(Into::into).collect();
+        self
+    }
+
+    ///Find the index file in `directory`, if it has one.
+    pub fn find_index(&self, directory: &Path) -> Option<PathBuf> {
+        self.index_files.iter()
+            .map(|name| directory.join(name))
+            .find(|index| index.is_file())
+    }
+
+    ///List the content of directories that don't have an index file,
+    ///instead of responding with `404 Not Found`. Default is `false`.
+    pub fn listing(mut self, listing: bool) -> DirectoryHandler {
+        self.listing = listing;
+        self
+    }
+
+    ///Include hidden files, whose names start with `.`, in directory
+    ///listings. Default is `false`.
+    pub fn show_hidden(mut self, show_hidden: bool) -> DirectoryHandler {
+        self.show_hidden = show_hidden;
+        self
+    }
+
+    ///Set `Cache-Control` to `directives` for files with the extension
+    ///`extension`, such as `"html"`. The extension is not case sensitive.
+    pub fn cache_extension<S: Into<String>>(mut self, extension: S, directives: Vec<CacheDirective>) -> DirectoryHandler {
+        let extension = extension.into().trim_left_matches('.').to_lowercase();
+        self.cache_rules.push((FileMatch::Extension(extension), directives));
+        self
+    }
+
+    ///Set `Cache-Control` to `directives` for files with paths, relative to
+    ///the root directory, that match `pattern`. A `*` matches anything
+    ///within a path segment, and `**` matches any number of segments, so
+    ///`"assets/**"` matches everything in `assets` and `"**/*.min.js"`
+    ///matches minified scripts in any directory.
+    pub fn cache_pattern<S: Into<String>>(mut self, pattern: S, directives: Vec<CacheDirective>) -> DirectoryHandler {
+        let pattern = pattern.into().trim_left_matches('/').to_owned();
+        self.cache_rules.push((FileMatch::Pattern(pattern), directives));
+        self
+    }
+
+    ///Find the `Cache-Control` directives for a file.
+    pub fn cache_directives(&self, path: &Path) -> Option<&[CacheDirective]> {
+        let relative = match path.strip_prefix(&self.root) {
+            Ok(relative) => relative.components()
+                .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .join("/"),
+            Err(_) => return None
+        };
+        let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase());
+
+        self.cache_rules.iter().find(|&&(ref rule, _)| match *rule {
+            FileMatch::Extension(ref expected) => extension.as_ref() == Some(expected),
+            FileMatch::Pattern(ref pattern) => glob_matches(pattern.as_bytes(), relative.as_bytes())
+        }).map(|&(_, ref directives)| &**directives)
+    }
+
+    ///Set how symbolic links are treated. Default is
+    ///`Symlinks::InsideRoot`.
+    pub fn symlinks(mut self, symlinks: Symlinks) -> DirectoryHandler {
+        self.symlinks = symlinks;
+        self
+    }
+
+    ///Find the file or directory for a request path, if it's inside the
+    ///root directory.
+    pub fn resolve(&self, path: &str) -> Option<PathBuf> {
+        if !path.starts_with(&self.prefix) {
+            return None;
+        }
+
+        let rest = &path[self.prefix.len()..];
+        if !rest.is_empty() && !rest.starts_with('/') {
+            return None;
+        }
+
+        let resolved = match resolve_path(&self.root, rest) {
+            Some(resolved) => resolved,
+            None => return None
+        };
+
+        let allowed = match self.symlinks {
+            Symlinks::Deny => !has_symlinks(&self.root, &resolved),
+            Symlinks::InsideRoot => is_inside(&self.root, &resolved),
+            Symlinks::Follow => true
+        };
+
+        if allowed {
+            Some(resolved)
+        } else {
+            None
+        }
+    }
+}
+
+impl Handler for DirectoryHandler {
+    fn handle_request(&self, context: Context, mut response: Response) {
+        match context.method {
+            Method::Get | Method::Head => {},
+            _ => {
+                response.set_status(StatusCode::MethodNotAllowed);
+                response.headers_mut().set_raw("Allow", vec![b"GET, HEAD".to_vec()]);
+                return;
+            }
+        }
+
+        let request_path = match context.uri.as_utf8_path() {
+            Some(path) => path.to_owned(),
+            None => return response.set_status(StatusCode::NotFound)
+        };
+
+        let mut path = match self.resolve(&request_path) {
+            Some(path) => path,
+            None => return response.set_status(StatusCode::NotFound)
+        };
+
+        if fs::metadata(&path).map(|metadata| metadata.is_dir()).unwrap_or(false) {
+            if !request_path.ends_with('/') {
+                let location = with_trailing_slash(&context.request_target);
+                response.set_status(StatusCode::MovedPermanently);
+                response.headers_mut().set_raw("Location", vec![location.into_bytes()]);
+                return;
+            }
+
+            match self.find_index(&path) {
+                Some(index) => path = index,
+                None if self.listing => return self.send_listing(&path, &request_path, context, response),
+                None => return response.set_status(StatusCode::NotFound)
+            }
+        }
+
+        if path.is_file() {
+            if let Some(directives) = self.cache_directives(&path) {
+                response.headers_mut().set(CacheControl(directives.to_vec()));
+            }
+        }
+
+        let result = if let Method::Head = context.method {
+            response.send_file(&path)
+        } else {
+            response.send_file_ranged(&path, &context.headers)
+        };
+
+        let result = result
+            .or_else(|e| e.send_not_found("the file was not found"))
+            .or_else(|e| e.ignore_send_error());
+
+        if let Err((e, mut response)) = result {
+            context.log.error(&format!("failed to open '{}': {}", path.display(), e));
+            response.set_status(StatusCode::InternalServerError);
+        }
+    }
+}
+
+impl DirectoryHandler {
+    fn send_listing(&self, directory: &Path, request_path: &str, context: Context, mut response: Response) {
+        let entries = match read_listing(directory, self.show_hidden) {
+            Ok(entries) => entries,
+            Err(e) => {
+                context.log.error(&format!("failed to list '{}': {}", directory.display(), e));
+                return response.set_status(StatusCode::InternalServerError);
+            }
+        };
+
+        let (body, sub_level) = if prefers_json(&context.headers) {
+            (listing_json(&entries), SubLevel::Json)
+        } else {
+            let is_root = request_path.trim_right_matches('/') == self.prefix;
+            (listing_html(request_path, &entries, !is_root), SubLevel::Html)
+        };
+
+        let top_level = match sub_level {
+            SubLevel::Json => TopLevel::Application,
+            _ => TopLevel::Text
+        };
+
+        response.headers_mut().set(ContentType(Mime(top_level, sub_level, vec![(Attr::Charset, Value::Utf8)])));
+        response.send(body);
+    }
+}
+
+//A file or directory in a directory listing.
+struct ListingEntry {
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: Option<Tm>
+}
+
+//Reads the entries in `directory`, with the directories first and sorted
+//by name.
+fn read_listing(directory: &Path, show_hidden: bool) -> io::Result<Vec<ListingEntry>> {
+    let mut entries = vec![];
+
+    for entry in try!(fs::read_dir(directory)) {
+        let entry = try!(entry);
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue
+        };
+
+        if !show_hidden && name.starts_with('.') {
+            continue;
+        }
+
+        let metadata = try!(entry.metadata());
+        let modified = metadata.modified().ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|modified| time::at_utc(Timespec::new(modified.as_secs() as i64, 0)));
+
+        entries.push(ListingEntry {
+            name: name,
+            is_dir: metadata.is_dir(),
+            size: if metadata.is_dir() { 0 } else { metadata.len() },
+            modified: modified
+        });
+    }
+
+    entries.sort_by(|a, b| match b.is_dir.cmp(&a.is_dir) {
+        Ordering::Equal => a.name.cmp(&b.name),
+        ordering => ordering
+    });
+    Ok(entries)
+}
+
+//Checks if JSON is more accepted than HTML. HTML is preferred if they are
+//equally accepted.
+fn prefers_json(headers: &Headers) -> bool {
+    let accepted = match headers.get::<Accept>() {
+        Some(accepted) => accepted,
+        None => return false
+    };
+
+    let mut html = 0;
+    let mut json = 0;
+    for item in accepted.iter() {
+        let quality = item.quality.0;
+        match item.item {
+            Mime(TopLevel::Application, SubLevel::Json, _) => json = cmp::max(json, quality),
+            Mime(TopLevel::Text, SubLevel::Html, _) |
+            Mime(TopLevel::Text, SubLevel::Star, _) |
+            Mime(TopLevel::Star, _, _) => html = cmp::max(html, quality),
+            _ => {}
+        }
+    }
+
+    json > html
+}
+
+fn listing_html(request_path: &str, entries: &[ListingEntry], parent_link: bool) -> String {
+    let title = escape_html(&format!("Index of {}", request_path));
+    let mut html = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n</head>\n<body>\n<h1>{0}</h1>\n<table>\n<tr><th>Name</th><th>Size</th><th>Modified</th></tr>\n", title);
+
+    if parent_link {
+        html.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+    }
+
+    for entry in entries {
+        let (href, name, size) = if entry.is_dir {
+            (format!("{}/", encode_path_segment(&entry.name)), format!("{}/", entry.name), String::new())
+        } else {
+            (encode_path_segment(&entry.name), entry.name.clone(), entry.size.to_string())
+        };
+        let modified = entry.modified.as_ref().and_then(|modified| modified.strftime("%Y-%m-%d %H:%M").ok())
+            .map(|modified| modified.to_string())
+            .unwrap_or_else(String::new);
+
+        html.push_str(&format!(
+            "<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td></tr>\n",
+            escape_html(&href),
+            escape_html(&name),
+            size,
+            modified
+        ));
+    }
+
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+fn listing_json(entries: &[ListingEntry]) -> String {
+    let mut json = String::from("[");
+
+    for (index, entry) in entries.iter().enumerate() {
+        if index > 0 {
+            json.push(',');
+        }
+
+        json.push_str("{\"name\":");
+        push_json_string(&mut json, &entry.name);
+        json.push_str(&format!(",\"directory\":{},\"size\":{},\"modified\":", entry.is_dir, entry.size));
+        match entry.modified {
+            Some(ref modified) => push_json_string(&mut json, &modified.rfc3339().to_string()),
+            None => json.push_str("null")
+        }
+        json.push('}');
+    }
+
+    json.push(']');
+    json
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+fn push_json_string(output: &mut String, value: &str) {
+    output.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if (c as u32) < 0x20 => output.push_str(&format!("\\u{:04x}", c as u32)),
+            c => output.push(c)
+        }
+    }
+    output.push('"');
+}
+
+//Joins the segments of `path` to `root`. Returns `None` if any of them
+//could be used to get outside `root`.
+fn resolve_path(root: &Path, path: &str) -> Option<PathBuf> {
+    let mut resolved = root.to_path_buf();
+
+    for segment in path.split('/') {
+        if !is_safe_segment(segment.as_bytes()) {
+            return None;
+        }
+
+        //Segments that were encoded twice are decoded by some file systems
+        //and proxies, so they are checked once more.
+        if segment.contains('%') && !is_safe_segment(&percent_decode(segment.as_bytes())) {
+            return None;
+        }
+
+        match segment {
+            "" | "." => {},
+            segment => resolved.push(segment)
+        }
+    }
+
+    Some(resolved)
+}
+
+//Matches a path against a pattern, where `*` matches anything except `/`
+//and `**` matches anything, including `/`. A `**/` may match nothing.
+fn glob_matches(pattern: &[u8], path: &[u8]) -> bool {
+    if pattern.starts_with(b"**") {
+        let rest = &pattern[2..];
+        if rest.starts_with(b"/") && glob_matches(&rest[1..], path) {
+            return true;
+        }
+
+        return (0..path.len() + 1).any(|skip| glob_matches(rest, &path[skip..]));
+    }
+
+    match pattern.first() {
+        Some(&b'*') => {
+            let rest = &pattern[1..];
+            let segment_end = path.iter().position(|&byte| byte == b'/').unwrap_or(path.len());
+            (0..segment_end + 1).any(|skip| glob_matches(rest, &path[skip..]))
+        },
+        Some(&expected) => path.first() == Some(&expected) && glob_matches(&pattern[1..], &path[1..]),
+        None => path.is_empty()
+    }
+}
+
+//Checks that a path segment can't be used to leave its directory.
+fn is_safe_segment(segment: &[u8]) -> bool {
+    segment != b".." && !segment.iter().any(|&byte| byte == b'/' || byte == b'\\' || byte == b':' || byte == 0)
+}
+
+//Checks if any part of `path`, below `root`, is a symbolic link.
+fn has_symlinks(root: &Path, path: &Path) -> bool {
+    let relative = match path.strip_prefix(root) {
+        Ok(relative) => relative,
+        Err(_) => return true
+    };
+
+    let mut current = root.to_path_buf();
+    for component in relative.components() {
+        current.push(component.as_os_str());
+        match fs::symlink_metadata(&current) {
+            Ok(metadata) => if metadata.file_type().is_symlink() {
+                return true;
+            },
+            //Missing files are not found anyway.
+            Err(_) => return false
+        }
+    }
+
+    false
+}
+
+//Checks if `path` is inside `root`, after resolving symbolic links.
+fn is_inside(root: &Path, path: &Path) -> bool {
+    let root = match fs::canonicalize(root) {
+        Ok(root) => root,
+        //Missing files are not found anyway.
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return true,
+        Err(_) => return false
+    };
+
+    match fs::canonicalize(path) {
+        Ok(path) => path.starts_with(root),
+        //Missing files are not found anyway.
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => true,
+        Err(_) => false
+    }
+}
+
+//Adds a `/` to the path part of a request target.
+//Adds a slash to the path of `request_target`. Only the path and the query
+//are kept, and leading slashes are collapsed, since a location like
+//`//docs/` would point to another host.
+fn with_trailing_slash(request_target: &str) -> String {
+    let target = match request_target.find("://") {
+        Some(index) if !request_target.starts_with('/') => {
+            let rest = &request_target[index + 3..];
+            rest.find('/').map(|index| &rest[index..]).unwrap_or("/")
+        },
+        _ => request_target
+    };
+    let target = format!("/{}", target.trim_left_matches(|c: char| c == '/' || c == '\\'));
+
+    match target.find('?') {
+        Some(index) => format!("{}/{}", &target[..index], &target[index..]),
+        None => format!("{}/", target)
+    }
+}
+
 enum MaybeKnown<T> {
     Known(T),
     Unknown(&'static str)
@@ -48,3 +693,182 @@ impl<'a> Into<SubLevel> for &'a MaybeKnown<SubLevel> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::Path;
+    use tempdir;
+    use header::Headers;
+    use time;
+    use header::CacheDirective;
+    use super::{FileRange, requested_range, DirectoryHandler, Symlinks, with_trailing_slash, glob_matches};
+    use super::{ListingEntry, listing_html, listing_json, prefers_json};
+
+    fn range(value: &str, length: u64) -> FileRange {
+        let mut headers = Headers::new();
+        headers.set_raw("Range", vec![value.as_bytes().to_vec()]);
+        requested_range(&headers, length)
+    }
+
+    #[test]
+    fn byte_ranges() {
+        assert_eq!(requested_range(&Headers::new(), 10), FileRange::Full);
+        assert_eq!(range("bytes=0-4", 10), FileRange::Partial(0, 4));
+        assert_eq!(range("bytes=5-100", 10), FileRange::Partial(5, 9));
+        assert_eq!(range("bytes=5-", 10), FileRange::Partial(5, 9));
+        assert_eq!(range("bytes=-3", 10), FileRange::Partial(7, 9));
+        assert_eq!(range("bytes=-30", 10), FileRange::Partial(0, 9));
+        assert_eq!(range("bytes=10-", 10), FileRange::Unsatisfiable);
+        assert_eq!(range("bytes=-0", 10), FileRange::Unsatisfiable);
+        assert_eq!(range("bytes=0-1,3-4", 10), FileRange::Full);
+        assert_eq!(range("bytes=4-2", 10), FileRange::Full);
+        assert_eq!(range("items=0-1", 10), FileRange::Full);
+    }
+
+    #[test]
+    fn resolve_paths() {
+        let handler = DirectoryHandler::new("files", "/static/");
+        assert_eq!(handler.resolve("/static"), Some(Path::new("files").to_path_buf()));
+        assert_eq!(handler.resolve("/static/a/./b.txt"), Some(Path::new("files/a/b.txt").to_path_buf()));
+        assert_eq!(handler.resolve("/static//a/"), Some(Path::new("files/a").to_path_buf()));
+        assert_eq!(handler.resolve("/static/../secret"), None);
+        assert_eq!(handler.resolve("/static/a/../../secret"), None);
+        assert_eq!(handler.resolve("/static/a\\..\\..\\secret"), None);
+        assert_eq!(handler.resolve("/static/c:/secret"), None);
+        assert_eq!(handler.resolve("/staticfiles/a"), None);
+        assert_eq!(handler.resolve("/other/a"), None);
+        assert_eq!(handler.resolve("/static/%2e%2e/secret"), None);
+        assert_eq!(handler.resolve("/static/%2E%2E%2Fsecret"), None);
+        assert_eq!(handler.resolve("/static/a%5c..%5csecret"), None);
+        assert_eq!(handler.resolve("/static/a\0.txt"), None);
+        assert_eq!(handler.resolve("/static//etc/passwd"), Some(Path::new("files/etc/passwd").to_path_buf()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempdir::TempDir::new("resolve_symlinks").unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir(&root).unwrap();
+        fs::File::create(dir.path().join("secret.txt")).unwrap();
+        fs::File::create(root.join("public.txt")).unwrap();
+        symlink(dir.path().join("secret.txt"), root.join("outside.txt")).unwrap();
+        symlink(root.join("public.txt"), root.join("inside.txt")).unwrap();
+
+        let handler = DirectoryHandler::new(&root, "/");
+        assert_eq!(handler.resolve("/public.txt"), Some(root.join("public.txt")));
+        assert_eq!(handler.resolve("/inside.txt"), Some(root.join("inside.txt")));
+        assert_eq!(handler.resolve("/outside.txt"), None);
+        assert_eq!(handler.resolve("/missing.txt"), Some(root.join("missing.txt")));
+
+        let handler = handler.symlinks(Symlinks::Deny);
+        assert_eq!(handler.resolve("/public.txt"), Some(root.join("public.txt")));
+        assert_eq!(handler.resolve("/inside.txt"), None);
+        assert_eq!(handler.resolve("/outside.txt"), None);
+
+        let handler = handler.symlinks(Symlinks::Follow);
+        assert_eq!(handler.resolve("/outside.txt"), Some(root.join("outside.txt")));
+    }
+
+    #[test]
+    fn directory_redirects() {
+        assert_eq!(with_trailing_slash("/static/a"), "/static/a/");
+        assert_eq!(with_trailing_slash("/static/a?b=c"), "/static/a/?b=c");
+        assert_eq!(with_trailing_slash("//docs"), "/docs/");
+        assert_eq!(with_trailing_slash("/\\docs?a"), "/docs/?a");
+        assert_eq!(with_trailing_slash("http://example.com//docs"), "/docs/");
+    }
+
+    #[test]
+    fn index_files() {
+        let dir = tempdir::TempDir::new("index_files").unwrap();
+        fs::File::create(dir.path().join("index.htm")).unwrap();
+        fs::File::create(dir.path().join("home.html")).unwrap();
+        fs::create_dir(dir.path().join("index.html")).unwrap();
+
+        let handler = DirectoryHandler::new(dir.path(), "/");
+        assert_eq!(handler.find_index(dir.path()), Some(dir.path().join("index.htm")));
+
+        let handler = handler.index_files(vec!["home.html", "index.htm"]);
+        assert_eq!(handler.find_index(dir.path()), Some(dir.path().join("home.html")));
+
+        let handler = handler.index_files(Vec::<String>::new());
+        assert_eq!(handler.find_index(dir.path()), None);
+    }
+
+    #[test]
+    fn directory_listings() {
+        let entries = vec![
+            ListingEntry {
+                name: "a b".into(),
+                is_dir: true,
+                size: 0,
+                modified: None
+            },
+            ListingEntry {
+                name: "<c>.txt".into(),
+                is_dir: false,
+                size: 12,
+                modified: Some(time::at_utc(time::Timespec::new(971186136, 0)))
+            }
+        ];
+
+        let html = listing_html("/static/", &entries, false);
+        assert!(html.contains("<title>Index of /static/</title>"));
+        assert!(!html.contains("../"));
+        assert!(html.contains("<tr><td><a href=\"a%20b/\">a b/</a></td><td></td><td></td></tr>"));
+        assert!(html.contains("<tr><td><a href=\"%3Cc%3E.txt\">&lt;c&gt;.txt</a></td><td>12</td><td>2000-10-10 13:55</td></tr>"));
+        assert!(listing_html("/static/a/", &entries, true).contains("<a href=\"../\">"));
+
+        assert_eq!(
+            listing_json(&entries),
+            "[{\"name\":\"a b\",\"directory\":true,\"size\":0,\"modified\":null},\
+            {\"name\":\"<c>.txt\",\"directory\":false,\"size\":12,\"modified\":\"2000-10-10T13:55:36Z\"}]"
+        );
+    }
+
+    #[test]
+    fn listing_formats() {
+        let accept = |value: &str| {
+            let mut headers = Headers::new();
+            headers.set_raw("Accept", vec![value.as_bytes().to_vec()]);
+            prefers_json(&headers)
+        };
+
+        assert!(!prefers_json(&Headers::new()));
+        assert!(accept("application/json"));
+        assert!(!accept("text/html, application/json"));
+        assert!(accept("text/html;q=0.5, application/json"));
+        assert!(!accept("*/*"));
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_matches(b"assets/**", b"assets/a.js"));
+        assert!(glob_matches(b"assets/**", b"assets/a/b.js"));
+        assert!(!glob_matches(b"assets/**", b"other/a.js"));
+        assert!(glob_matches(b"**/*.min.js", b"a.min.js"));
+        assert!(glob_matches(b"**/*.min.js", b"a/b/c.min.js"));
+        assert!(!glob_matches(b"**/*.min.js", b"a/b/c.js"));
+        assert!(glob_matches(b"*.css", b"style.css"));
+        assert!(!glob_matches(b"*.css", b"a/style.css"));
+        assert!(glob_matches(b"app.*.js", b"app.3f2a1c.js"));
+    }
+
+    #[test]
+    fn cache_rules() {
+        let handler = DirectoryHandler::new("files", "/")
+            .cache_pattern("/assets/**", vec![CacheDirective::MaxAge(31536000)])
+            .cache_extension(".HTML", vec![CacheDirective::NoCache]);
+
+        let directives = |path: &str| handler.cache_directives(Path::new(path)).map(|directives| directives.to_vec());
+        assert_eq!(directives("files/assets/app.js"), Some(vec![CacheDirective::MaxAge(31536000)]));
+        assert_eq!(directives("files/assets/index.html"), Some(vec![CacheDirective::MaxAge(31536000)]));
+        assert_eq!(directives("files/index.Html"), Some(vec![CacheDirective::NoCache]));
+        assert_eq!(directives("files/app.js"), None);
+        assert_eq!(directives("other/assets/app.js"), None);
+    }
+}
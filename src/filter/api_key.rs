@@ -0,0 +1,218 @@
+use std::collections::{BTreeMap, HashMap};
+
+use StatusCode;
+use context::Context;
+use filter::{FilterContext, ContextFilter, ContextAction};
+use store::Store;
+
+///The client that an API key belongs to.
+///
+///It's put in the filter storage by [`ApiKeyAuth`][api_key_auth].
+///
+///[api_key_auth]: struct.ApiKeyAuth.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct Principal {
+    ///The identity of the client.
+    pub id: String,
+
+    ///Extra information about the key, such as its rate limit class.
+    pub metadata: BTreeMap<String, String>
+}
+
+impl Principal {
+    ///Create a principal without any metadata.
+    pub fn new<S: Into<String>>(id: S) -> Principal {
+        Principal {
+            id: id.into(),
+            metadata: BTreeMap::new()
+        }
+    }
+
+    ///Add a metadata value.
+    pub fn with<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Principal {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    ///Get a metadata value.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(|value| &**value)
+    }
+}
+
+///Looks up the owners of API keys.
+pub trait KeyValidator: Send + Sync {
+    ///Find the owner of `key`, or `None` if the key is invalid.
+    fn validate(&self, key: &str) -> Option<Principal>;
+}
+
+impl<F: Fn(&str) -> Option<Principal> + Send + Sync> KeyValidator for F {
+    fn validate(&self, key: &str) -> Option<Principal> {
+        self(key)
+    }
+}
+
+///A fixed set of keys.
+impl KeyValidator for HashMap<String, Principal> {
+    fn validate(&self, key: &str) -> Option<Principal> {
+        self.get(key).cloned()
+    }
+}
+
+///Keys are stored with the principal's identity as value. Values that are
+///not valid UTF-8 are treated as invalid keys.
+impl KeyValidator for Store {
+    fn validate(&self, key: &str) -> Option<Principal> {
+        self.get(key).and_then(|id| String::from_utf8(id).ok()).map(Principal::new)
+    }
+}
+
+///A context filter that authenticates clients using API keys.
+///
+///The key is taken from the `X-Api-Key` header by default, and it can also
+///be taken from a query parameter. The [`Principal`][principal] of a valid
+///key is put in the filter storage, and requests with missing or invalid
+///keys are answered with `401 Unauthorized`.
+///
+///```
+///use std::collections::HashMap;
+///use rustful::{Server, Context, Response};
+///use rustful::filter::{ApiKeyAuth, Principal};
+///
+///fn whoami(_context: Context, response: Response) {
+///    let (id, class) = match response.filter_storage().get::<Principal>() {
+///        Some(principal) => (principal.id.clone(), principal.get("class").unwrap_or("basic").to_owned()),
+///        None => ("nobody".into(), "none".into())
+///    };
+///    response.send(format!("{} ({})", id, class));
+///}
+///
+///let mut keys = HashMap::new();
+///keys.insert("abc123".to_owned(), Principal::new("alice").with("class", "premium"));
+///
+///let server = Server {
+///    context_filters: vec![Box::new(ApiKeyAuth::new(keys).query("api_key"))],
+///    ..Server::new(whoami)
+///};
+///```
+///
+///[principal]: struct.Principal.html
+pub struct ApiKeyAuth<V> {
+    validator: V,
+    header: Option<String>,
+    query: Option<String>,
+    optional: bool
+}
+
+impl<V: KeyValidator> ApiKeyAuth<V> {
+    ///Create a filter that checks the keys with `validator`.
+    pub fn new(validator: V) -> ApiKeyAuth<V> {
+        ApiKeyAuth {
+            validator: validator,
+            header: Some("X-Api-Key".into()),
+            query: None,
+            optional: false
+        }
+    }
+
+    ///Take the key from the header `name`, or from no header if it's
+    ///`None`. Default is `X-Api-Key`.
+    pub fn header<S: Into<String>>(mut self, name: Option<S>) -> ApiKeyAuth<V> {
+        self.header = name.map(Into::into);
+        self
+    }
+
+    ///Also take the key from the query parameter `name`. The header is
+    ///checked first.
+    pub fn query<S: Into<String>>(mut self, name: S) -> ApiKeyAuth<V> {
+        self.query = Some(name.into());
+        self
+    }
+
+    ///Let requests without a key through, without a principal. Invalid keys
+    ///are still rejected.
+    pub fn optional(mut self) -> ApiKeyAuth<V> {
+        self.optional = true;
+        self
+    }
+
+    fn find_key(&self, context: &Context) -> Option<String> {
+        let from_header = self.header.as_ref().and_then(|name| context.headers.get_raw(name))
+            .and_then(|lines| lines.first())
+            .map(|line| String::from_utf8_lossy(line).trim().to_owned());
+
+        from_header.or_else(|| self.query.as_ref().and_then(|name| context.query.get(name)).map(|key| key.into_owned()))
+    }
+}
+
+impl<V: KeyValidator> ContextFilter for ApiKeyAuth<V> {
+    fn modify(&self, context: FilterContext, request_context: &mut Context) -> ContextAction {
+        match self.find_key(request_context) {
+            Some(key) => match self.validator.validate(&key) {
+                Some(principal) => {
+                    context.storage.insert(principal);
+                    ContextAction::next()
+                },
+                None => ContextAction::abort(StatusCode::Unauthorized)
+            },
+            None if self.optional => ContextAction::next(),
+            None => ContextAction::abort(StatusCode::Unauthorized)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use {Server, Context, Response};
+    use server::Dispatcher;
+    use super::{ApiKeyAuth, Principal};
+
+    fn whoami(_context: Context, response: Response) {
+        let id = response.filter_storage().get::<Principal>().map(|principal| principal.id.clone());
+        response.send(id.unwrap_or_else(|| "nobody".into()));
+    }
+
+    fn keys() -> HashMap<String, Principal> {
+        let mut keys = HashMap::new();
+        keys.insert("abc123".to_owned(), Principal::new("alice"));
+        keys
+    }
+
+    #[test]
+    fn authenticate_keys() {
+        let server = Server {
+            context_filters: vec![Box::new(ApiKeyAuth::new(keys()).query("api_key"))],
+            ..Server::new(whoami as fn(Context, Response))
+        };
+        let (instance, _scheme) = server.build();
+        let address = "127.0.0.1:8080".parse().unwrap();
+        let request = |target: &str, key: Option<&str>| {
+            let key = key.map(|key| format!("X-Api-Key: {}\r\n", key)).unwrap_or_default();
+            let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n{}\r\n", target, key);
+            String::from_utf8(instance.dispatch(request.as_bytes(), address)).unwrap()
+        };
+
+        assert!(request("/", Some("abc123")).ends_with("alice"));
+        assert!(request("/?api_key=abc123", None).ends_with("alice"));
+        assert!(request("/", None).starts_with("HTTP/1.1 401"));
+        assert!(request("/", Some("wrong")).starts_with("HTTP/1.1 401"));
+        assert!(request("/?api_key=abc123", Some("wrong")).starts_with("HTTP/1.1 401"));
+    }
+
+    #[test]
+    fn optional_keys() {
+        let server = Server {
+            context_filters: vec![Box::new(ApiKeyAuth::new(keys()).optional())],
+            ..Server::new(whoami as fn(Context, Response))
+        };
+        let (instance, _scheme) = server.build();
+        let address = "127.0.0.1:8080".parse().unwrap();
+
+        let output = String::from_utf8(instance.dispatch(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n", address)).unwrap();
+        assert!(output.ends_with("nobody"), "{}", output);
+
+        let output = String::from_utf8(instance.dispatch(b"GET / HTTP/1.1\r\nHost: localhost\r\nX-Api-Key: wrong\r\n\r\n", address)).unwrap();
+        assert!(output.starts_with("HTTP/1.1 401"), "{}", output);
+    }
+}
@@ -0,0 +1,136 @@
+use StatusCode;
+use header::ContentLength;
+use context::Context;
+use context::body::TooLarge;
+use filter::{FilterContext, ContextFilter, ContextAction};
+
+///A context filter that rejects request bodies that are larger than a
+///limit with `413 Payload Too Large`.
+///
+///A body with a `Content-Length` header is rejected right away if it's too
+///large, without reading it. A body without one, such as a chunked or
+///decompressed body, is buffered up to the limit with `BodyReader::buffer`,
+///and rejected if there is more. The handler reads the buffered body as
+///usual. The size limit of the `BodyReader` is also lowered to the limit,
+///if it's higher.
+///
+///This makes it possible to have a lower limit for some routes than the
+///server's `max_body_size`, using a [`Scope`][scope]:
+///
+///```
+///#[macro_use]
+///extern crate rustful;
+///use rustful::{Server, Context, Response, TreeRouter};
+///use rustful::filter::BodyLimit;
+///use rustful::handler::scope::Scope;
+///
+///fn upload(_context: Context, response: Response) {
+///    response.send("uploaded");
+///}
+///
+///fn comment(_context: Context, response: Response) {
+///    response.send("commented");
+///}
+///
+///# fn main() {
+///let uploads = insert_routes! {
+///    TreeRouter::new() => {
+///        "upload" => Post: upload as fn(Context, Response)
+///    }
+///};
+///
+///let comments = insert_routes! {
+///    TreeRouter::new() => {
+///        "new" => Post: comment as fn(Context, Response)
+///    }
+///};
+///
+///let mut router = Scope::new().wrap_router(uploads);
+///router.insert_router("comments", Scope::new().context_filter(BodyLimit::new(16 * 1024)).wrap_router(comments));
+///
+///let server = Server {
+///    handlers: router,
+///    max_body_size: Some(10 * 1024 * 1024),
+///    ..Server::default()
+///};
+///# }
+///```
+///
+///[scope]: ../handler/scope/struct.Scope.html
+#[derive(Clone, Copy, Debug)]
+pub struct BodyLimit {
+    max: u64
+}
+
+impl BodyLimit {
+    ///Reject bodies that are larger than `max` bytes.
+    pub fn new(max: u64) -> BodyLimit {
+        BodyLimit {
+            max: max
+        }
+    }
+}
+
+impl ContextFilter for BodyLimit {
+    fn modify(&self, context: FilterContext, request_context: &mut Context) -> ContextAction {
+        let limit = match request_context.body.limit() {
+            Some(limit) if limit < self.max => limit,
+            _ => self.max
+        };
+        request_context.body.set_limit(Some(limit));
+
+        match request_context.headers.get::<ContentLength>() {
+            Some(&ContentLength(length)) if length > self.max => return ContextAction::abort(StatusCode::PayloadTooLarge),
+            Some(_) => return ContextAction::next(),
+            None => {}
+        }
+
+        match request_context.body.buffer(self.max) {
+            Ok(_) => ContextAction::next(),
+            Err(ref e) if TooLarge::is_cause_of(e) => ContextAction::abort(StatusCode::PayloadTooLarge),
+            Err(e) => {
+                context.log.note(&format!("failed to read the request body: {}", e));
+                ContextAction::abort(StatusCode::BadRequest)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+    use {Server, Context, Response};
+    use server::Dispatcher;
+    use super::BodyLimit;
+
+    fn echo(mut context: Context, response: Response) {
+        let mut body = String::new();
+        match context.body.read_to_string(&mut body) {
+            Ok(_) => response.send(body),
+            Err(_) => response.send("failed to read the body")
+        }
+    }
+
+    #[test]
+    fn limit_bodies() {
+        let server = Server {
+            context_filters: vec![Box::new(BodyLimit::new(8))],
+            ..Server::new(echo as fn(Context, Response))
+        };
+        let (instance, _scheme) = server.build();
+        let address = "127.0.0.1:8080".parse().unwrap();
+        let request = |request: &[u8]| String::from_utf8(instance.dispatch(request, address)).unwrap();
+
+        let output = request(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nhello");
+        assert!(output.starts_with("HTTP/1.1 200") && output.ends_with("hello"), "{}", output);
+
+        let output = request(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 11\r\n\r\nhello world");
+        assert!(output.starts_with("HTTP/1.1 413"), "{}", output);
+
+        let output = request(b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n");
+        assert!(output.starts_with("HTTP/1.1 200") && output.ends_with("abcde"), "{}", output);
+
+        let output = request(b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n");
+        assert!(output.starts_with("HTTP/1.1 413"), "{}", output);
+    }
+}
@@ -0,0 +1,59 @@
+use StatusCode;
+use header::Headers;
+use utils::add_vary;
+
+use filter::{FilterContext, ResponseFilter, ResponseAction};
+use response::Data;
+
+///A response filter that asks clients to send HTTP Client Hints.
+///
+///The hints are advertised in the `Accept-CH` header and added to `Vary`, to
+///make sure that caches keep the different variants apart. The parsed hints
+///can then be found using `Context::client_hints`.
+///
+///```
+///use rustful::{Server, Context, Response};
+///use rustful::filter::AcceptClientHints;
+///
+///let server = Server {
+///    response_filters: vec![
+///        Box::new(AcceptClientHints::new(&["Sec-CH-DPR", "Sec-CH-Width", "Save-Data"]))
+///    ],
+///    ..Server::new(|_: Context, _: Response| {})
+///};
+///```
+pub struct AcceptClientHints {
+    hints: String
+}
+
+impl AcceptClientHints {
+    ///Create a filter that asks for the hints in `hints`, such as `Sec-CH-DPR`
+    ///or `Save-Data`.
+    pub fn new(hints: &[&str]) -> AcceptClientHints {
+        AcceptClientHints {
+            hints: hints.join(", ")
+        }
+    }
+}
+
+impl ResponseFilter for AcceptClientHints {
+    fn begin(&self, _ctx: FilterContext, status: StatusCode, headers: &mut Headers) -> (StatusCode, ResponseAction) {
+        if !self.hints.is_empty() {
+            headers.set_raw("Accept-CH", vec![self.hints.as_bytes().to_vec()]);
+
+            for hint in self.hints.split(", ") {
+                add_vary(headers, hint);
+            }
+        }
+
+        (status, ResponseAction::next(None::<Data>))
+    }
+
+    fn write<'a>(&'a self, _ctx: FilterContext, content: Option<Data<'a>>) -> ResponseAction {
+        ResponseAction::next(content)
+    }
+
+    fn end(&self, _ctx: FilterContext) -> ResponseAction {
+        ResponseAction::next(None::<Data>)
+    }
+}
@@ -0,0 +1,156 @@
+use StatusCode;
+use Method;
+use header::{Headers, IfNoneMatch, ETag, EntityTag};
+
+use context::Context;
+use filter::{FilterContext, ContextFilter, ContextAction, ResponseFilter, ResponseAction};
+use response::Data;
+use utils;
+
+///A filter that gives responses an `ETag`, and answers conditional `GET`
+///requests with `304 Not Modified`.
+///
+///It's both a context filter, that remembers the `If-None-Match` header of
+///`GET` and `HEAD` requests, and a response filter, that hashes `200 OK`
+///response bodies that are sent in one piece. The tag is weak, since it's
+///calculated before the body is compressed. Responses that already have an
+///`ETag`, and `Chunked` and `Raw` responses, are left as they are.
+///
+///```
+///use rustful::{Server, Context, Response};
+///use rustful::filter::ConditionalGet;
+///
+///let server = Server {
+///    context_filters: vec![Box::new(ConditionalGet)],
+///    response_filters: vec![Box::new(ConditionalGet)],
+///    ..Server::new(|_: Context, response: Response| response.send("hello"))
+///};
+///```
+///
+///The server's `auto_etag` option does the same thing, but with a strong
+///tag that is calculated after the response filters and the compression.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ConditionalGet;
+
+//The state of a request, in the filter storage.
+struct Conditional {
+    if_none_match: Option<IfNoneMatch>,
+    body: Vec<u8>,
+    eligible: bool,
+    ended: bool
+}
+
+impl ContextFilter for ConditionalGet {
+    fn modify(&self, context: FilterContext, request_context: &mut Context) -> ContextAction {
+        if request_context.method == Method::Get || request_context.method == Method::Head {
+            context.storage.insert(Conditional {
+                if_none_match: request_context.headers.get::<IfNoneMatch>().cloned(),
+                body: vec![],
+                eligible: false,
+                ended: false
+            });
+        }
+
+        ContextAction::next()
+    }
+}
+
+impl ResponseFilter for ConditionalGet {
+    fn begin(&self, context: FilterContext, status: StatusCode, headers: &mut Headers) -> (StatusCode, ResponseAction) {
+        if let Some(state) = context.storage.get_mut::<Conditional>() {
+            state.eligible = status == StatusCode::Ok && !headers.has::<ETag>();
+        }
+
+        (status, ResponseAction::next(None::<Data>))
+    }
+
+    fn write<'a>(&'a self, context: FilterContext, content: Option<Data<'a>>) -> ResponseAction {
+        if let (Some(state), Some(content)) = (context.storage.get_mut::<Conditional>(), content.as_ref()) {
+            if state.eligible {
+                state.body.extend_from_slice(content.as_bytes());
+            }
+        }
+
+        ResponseAction::next(content)
+    }
+
+    fn end(&self, context: FilterContext) -> ResponseAction {
+        if let Some(state) = context.storage.get_mut::<Conditional>() {
+            state.ended = true;
+        }
+
+        ResponseAction::next(None::<Data>)
+    }
+
+    fn finish_headers(&self, context: FilterContext, status: StatusCode, headers: &mut Headers) -> StatusCode {
+        let state = match context.storage.remove::<Conditional>() {
+            //Chunked responses are finished before their body is written.
+            Some(state) => if state.eligible && state.ended && status == StatusCode::Ok && !headers.has::<ETag>() {
+                state
+            } else {
+                return status;
+            },
+            None => return status
+        };
+
+        let tag = utils::to_hex(&utils::sha256(&state.body)[..16]);
+        let not_modified = match state.if_none_match {
+            Some(IfNoneMatch::Any) => true,
+            Some(IfNoneMatch::Items(ref tags)) => tags.iter().any(|t| t.tag() == tag),
+            None => false
+        };
+
+        headers.set(ETag(EntityTag::new(true, tag)));
+        if not_modified {
+            StatusCode::NotModified
+        } else {
+            status
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {Server, Context, Response};
+    use server::Dispatcher;
+    use super::ConditionalGet;
+
+    fn handler(_context: Context, response: Response) {
+        response.send("hello");
+    }
+
+    #[test]
+    fn answer_not_modified() {
+        let server = Server {
+            context_filters: vec![Box::new(ConditionalGet)],
+            response_filters: vec![Box::new(ConditionalGet)],
+            ..Server::new(handler as fn(Context, Response))
+        };
+        let (instance, _scheme) = server.build();
+        let address = "127.0.0.1:8080".parse().unwrap();
+        let request = |method: &str, if_none_match: Option<&str>| {
+            let condition = if_none_match.map(|tag| format!("If-None-Match: {}\r\n", tag)).unwrap_or_default();
+            let request = format!("{} / HTTP/1.1\r\nHost: localhost\r\n{}\r\n", method, condition);
+            String::from_utf8(instance.dispatch(request.as_bytes(), address)).unwrap()
+        };
+
+        let output = request("GET", None);
+        assert!(output.starts_with("HTTP/1.1 200"), "{}", output);
+        let tag = output.lines()
+            .find(|line| line.to_lowercase().starts_with("etag:"))
+            .map(|line| line[5..].trim().to_owned())
+            .expect("missing ETag");
+        assert!(tag.starts_with("W/\""), "{}", tag);
+
+        let output = request("GET", Some(&tag));
+        assert!(output.starts_with("HTTP/1.1 304"), "{}", output);
+        let output = request("GET", Some("*"));
+        assert!(output.starts_with("HTTP/1.1 304"), "{}", output);
+        let output = request("GET", Some("W/\"something-else\""));
+        assert!(output.starts_with("HTTP/1.1 200"), "{}", output);
+
+        let output = request("POST", Some(&tag));
+        assert!(output.starts_with("HTTP/1.1 200"), "{}", output);
+        assert!(!output.to_lowercase().contains("etag:"), "{}", output);
+    }
+}
@@ -0,0 +1,203 @@
+use {StatusCode, Method};
+use header::{Headers, ContentType, ETag};
+use mime::{Mime, TopLevel, SubLevel, Attr, Value};
+
+use context::Context;
+use filter::{FilterContext, ContextFilter, ContextAction, ResponseFilter, ResponseAction};
+use response::Data;
+use utils::remove_content_length;
+
+///The longest callback name that is accepted.
+const MAX_CALLBACK_LENGTH: usize = 128;
+
+///A filter that wraps JSON responses in JSONP callbacks.
+///
+///It's both a context filter, that takes the name of the callback from a
+///query parameter, and a response filter, that wraps the body in a call to
+///it. The callback has to be a JavaScript identifier, or a path of them,
+///such as `callbacks.users`, and requests with invalid callbacks are
+///rejected with `400 Bad Request`. Only `GET` and `HEAD` requests are
+///wrapped, and only if the response is JSON. Everything else passes through
+///as it is.
+///
+///Wrapped responses get the `application/javascript` content type, and
+///`X-Content-Type-Options: nosniff`. The call is prefixed with an empty
+///comment, as a protection against content sniffing attacks.
+///
+///```
+///use rustful::{Server, Context, Response};
+///use rustful::header::ContentType;
+///use rustful::mime::{Mime, TopLevel, SubLevel};
+///use rustful::filter::Jsonp;
+///
+/////GET /?callback=show responds with `/**/show({"hello":"world"});`
+///let server = Server {
+///    context_filters: vec![Box::new(Jsonp::new())],
+///    response_filters: vec![Box::new(Jsonp::new())],
+///    ..Server::new(|_: Context, mut response: Response| {
+///        response.headers_mut().set(ContentType(Mime(TopLevel::Application, SubLevel::Json, vec![])));
+///        response.send(r#"{"hello":"world"}"#)
+///    })
+///};
+///```
+#[derive(Clone, Debug)]
+pub struct Jsonp {
+    parameter: String
+}
+
+//The state of a request, in the filter storage.
+struct JsonpCallback {
+    name: String,
+    wrapping: bool
+}
+
+impl Jsonp {
+    ///Create a filter that takes the callback from the `callback` query
+    ///parameter.
+    pub fn new() -> Jsonp {
+        Jsonp {
+            parameter: "callback".into()
+        }
+    }
+
+    ///Set the name of the query parameter with the callback. Default is
+    ///`callback`.
+    pub fn parameter<S: Into<String>>(mut self, parameter: S) -> Jsonp {
+        self.parameter = parameter.into();
+        self
+    }
+}
+
+impl Default for Jsonp {
+    fn default() -> Jsonp {
+        Jsonp::new()
+    }
+}
+
+impl ContextFilter for Jsonp {
+    fn modify(&self, context: FilterContext, request_context: &mut Context) -> ContextAction {
+        if request_context.method != Method::Get && request_context.method != Method::Head {
+            return ContextAction::next();
+        }
+
+        let callback = match request_context.query.get(&self.parameter) {
+            Some(callback) => callback.into_owned(),
+            None => return ContextAction::next()
+        };
+
+        if is_valid_callback(&callback) {
+            context.storage.insert(JsonpCallback {
+                name: callback,
+                wrapping: false
+            });
+            ContextAction::next()
+        } else {
+            context.log.note(&format!("rejected an invalid JSONP callback: {:?}", callback));
+            ContextAction::abort(StatusCode::BadRequest)
+        }
+    }
+}
+
+impl ResponseFilter for Jsonp {
+    fn begin(&self, context: FilterContext, status: StatusCode, headers: &mut Headers) -> (StatusCode, ResponseAction) {
+        let callback = match context.storage.get_mut::<JsonpCallback>() {
+            Some(callback) => callback,
+            None => return (status, ResponseAction::next(None::<Data>))
+        };
+
+        let is_json = match headers.get::<ContentType>() {
+            Some(&ContentType(Mime(TopLevel::Application, SubLevel::Json, _))) => true,
+            Some(&ContentType(Mime(TopLevel::Application, SubLevel::Ext(ref sub), _))) => sub.ends_with("+json"),
+            _ => false
+        };
+
+        if !is_json || status == StatusCode::NoContent || status == StatusCode::NotModified {
+            return (status, ResponseAction::next(None::<Data>));
+        }
+
+        callback.wrapping = true;
+        headers.set(ContentType(Mime(
+            TopLevel::Application,
+            SubLevel::Javascript,
+            vec![(Attr::Charset, Value::Utf8)]
+        )));
+        //The body changes, so its length and tag no longer apply.
+        remove_content_length(headers);
+        headers.remove::<ETag>();
+        headers.set_raw("X-Content-Type-Options", vec![b"nosniff".to_vec()]);
+
+        (status, ResponseAction::next(Some(format!("/**/{}(", callback.name))))
+    }
+
+    fn write<'a>(&'a self, _context: FilterContext, content: Option<Data<'a>>) -> ResponseAction {
+        ResponseAction::next(content)
+    }
+
+    fn end(&self, context: FilterContext) -> ResponseAction {
+        match context.storage.get::<JsonpCallback>() {
+            Some(callback) if callback.wrapping => ResponseAction::next(Some(");")),
+            _ => ResponseAction::next(None::<Data>)
+        }
+    }
+}
+
+//Checks that the callback is one or more dot separated identifiers.
+fn is_valid_callback(callback: &str) -> bool {
+    callback.len() <= MAX_CALLBACK_LENGTH && callback.split('.').all(|identifier| {
+        let mut chars = identifier.chars();
+        match chars.next() {
+            Some('a'...'z') | Some('A'...'Z') | Some('_') | Some('$') => chars.all(|c| match c {
+                'a'...'z' | 'A'...'Z' | '0'...'9' | '_' | '$' => true,
+                _ => false
+            }),
+            _ => false
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use {Server, Context, Response};
+    use header::{ContentType, ETag, EntityTag};
+    use mime::{Mime, TopLevel, SubLevel};
+    use server::Dispatcher;
+    use super::{Jsonp, is_valid_callback};
+
+    #[test]
+    fn validate_callbacks() {
+        assert!(is_valid_callback("show"));
+        assert!(is_valid_callback("$.callbacks._users2"));
+        assert!(!is_valid_callback(""));
+        assert!(!is_valid_callback("a..b"));
+        assert!(!is_valid_callback("2fast"));
+        assert!(!is_valid_callback("alert(1);x"));
+        assert!(!is_valid_callback(&::std::iter::repeat('a').take(129).collect::<String>()));
+    }
+
+    #[test]
+    fn wrap_json() {
+        fn handler(_context: Context, mut response: Response) {
+            response.headers_mut().set(ContentType(Mime(TopLevel::Application, SubLevel::Json, vec![])));
+            response.headers_mut().set(ETag(EntityTag::new(false, "abc".into())));
+            response.send("{}");
+        }
+
+        let server = Server {
+            context_filters: vec![Box::new(Jsonp::new())],
+            response_filters: vec![Box::new(Jsonp::new())],
+            ..Server::new(handler as fn(Context, Response))
+        };
+        let (instance, _scheme) = server.build();
+        let address = "127.0.0.1:8080".parse().unwrap();
+
+        let output = instance.dispatch(b"GET /?callback=show HTTP/1.1\r\nHost: localhost\r\n\r\n", address);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("/**/show("), "{}", output);
+        assert!(output.contains(");"), "{}", output);
+        assert!(!output.contains("ETag"), "{}", output);
+
+        let output = instance.dispatch(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n", address);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("ETag"), "{}", output);
+    }
+}
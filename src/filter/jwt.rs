@@ -0,0 +1,356 @@
+//!JSON Web Token authentication.
+//!
+//!See [`JwtAuth`][jwt_auth] for more information.
+//!
+//![jwt_auth]: struct.JwtAuth.html
+
+use std::sync::{Arc, RwLock};
+
+use rustc_serialize::json::{self, Json};
+use time;
+
+use StatusCode;
+use context::Context;
+use filter::{FilterContext, ContextFilter, ContextAction, FilterResponse};
+use utils::{hmac_sha256, base64_decode, constant_time_eq};
+
+///The claims of a validated JSON Web Token.
+///
+///They are put in the filter storage by [`JwtAuth`][jwt_auth].
+///
+///[jwt_auth]: struct.JwtAuth.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct Claims(pub json::Object);
+
+impl Claims {
+    ///Get the claim `name`.
+    pub fn get(&self, name: &str) -> Option<&Json> {
+        self.0.get(name)
+    }
+
+    ///Get the subject of the token, from the `sub` claim.
+    pub fn subject(&self) -> Option<&str> {
+        self.get("sub").and_then(Json::as_string)
+    }
+}
+
+#[derive(Clone)]
+struct Settings {
+    issuer: Option<String>,
+    audience: Option<String>,
+    leeway: i64,
+    optional: bool,
+    require_expiration: bool
+}
+
+///A context filter that validates JSON Web Tokens from the `Authorization:
+///Bearer` header.
+///
+///Tokens are signed with HMAC-SHA256 (`HS256`), and their signature,
+///expiration time (`exp`) and not-before time (`nbf`) are checked, as well as
+///the issuer (`iss`) and audience (`aud`), if they are expected. Tokens
+///without an expiration time are rejected, unless `allow_no_expiration` is
+///used. The claims of a valid token are put in the filter storage as
+///[`Claims`][claims], and requests with missing or invalid tokens are
+///answered with `401 Unauthorized` and a `WWW-Authenticate: Bearer`
+///challenge, as described in RFC 6750.
+///
+///The keys can be replaced at any time using `set_keys`, and all clones of
+///the filter will use the new keys. A token is valid if it's signed with any
+///of the keys, which allows the keys to be rotated.
+///
+///```
+///use rustful::{Server, Context, Response};
+///use rustful::filter::{JwtAuth, Claims};
+///
+///fn whoami(_context: Context, response: Response) {
+///    let user = response.filter_storage().get::<Claims>()
+///        .and_then(|claims| claims.subject())
+///        .unwrap_or("nobody")
+///        .to_owned();
+///    response.send(user);
+///}
+///
+///let auth = JwtAuth::new(vec![b"a secret key".to_vec()])
+///    .issuer("https://auth.example.com")
+///    .audience("my-api");
+///
+///let server = Server {
+///    context_filters: vec![Box::new(auth.clone())],
+///    ..Server::new(whoami)
+///};
+///
+/////Later, when the keys have been rotated:
+///auth.set_keys(vec![b"a new secret key".to_vec(), b"a secret key".to_vec()]);
+///```
+///
+///This is only available with the `jwt` feature.
+///
+///[claims]: struct.Claims.html
+#[derive(Clone)]
+pub struct JwtAuth {
+    keys: Arc<RwLock<Vec<Vec<u8>>>>,
+    settings: Arc<Settings>
+}
+
+impl JwtAuth {
+    ///Create a filter that accepts tokens that are signed with any of
+    ///`keys`.
+    pub fn new(keys: Vec<Vec<u8>>) -> JwtAuth {
+        JwtAuth {
+            keys: Arc::new(RwLock::new(keys)),
+            settings: Arc::new(Settings {
+                issuer: None,
+                audience: None,
+                leeway: 0,
+                optional: false,
+                require_expiration: true
+            })
+        }
+    }
+
+    ///Require the `iss` claim to be `issuer`.
+    pub fn issuer<S: Into<String>>(mut self, issuer: S) -> JwtAuth {
+        self.settings_mut().issuer = Some(issuer.into());
+        self
+    }
+
+    ///Require the `aud` claim to be, or contain, `audience`.
+    pub fn audience<S: Into<String>>(mut self, audience: S) -> JwtAuth {
+        self.settings_mut().audience = Some(audience.into());
+        self
+    }
+
+    ///Allow the clocks to differ by `seconds` seconds when checking `exp`
+    ///and `nbf`. Default is 0.
+    pub fn leeway(mut self, seconds: i64) -> JwtAuth {
+        self.settings_mut().leeway = seconds;
+        self
+    }
+
+    ///Accept tokens without an expiration time (`exp`). They are valid
+    ///until the key is replaced, so this should be used with care.
+    pub fn allow_no_expiration(mut self) -> JwtAuth {
+        self.settings_mut().require_expiration = false;
+        self
+    }
+
+    ///Let requests without an `Authorization` header through, without any
+    ///claims. Invalid tokens are still rejected.
+    pub fn optional(mut self) -> JwtAuth {
+        self.settings_mut().optional = true;
+        self
+    }
+
+    fn settings_mut(&mut self) -> &mut Settings {
+        Arc::make_mut(&mut self.settings)
+    }
+
+    ///Replace the keys. The new keys are used by all clones of the filter.
+    pub fn set_keys(&self, keys: Vec<Vec<u8>>) {
+        match self.keys.write() {
+            Ok(mut current) => *current = keys,
+            Err(poisoned) => *poisoned.into_inner() = keys
+        }
+    }
+
+    ///Validate `token` at the time `now`, in seconds since the Unix epoch.
+    pub fn validate(&self, token: &str, now: i64) -> Option<Claims> {
+        let mut parts = token.split('.');
+        let (header, payload, signature) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(header), Some(payload), Some(signature), None) => (header, payload, signature),
+            _ => return None
+        };
+        let signed = &token[..header.len() + payload.len() + 1];
+
+        let header = match decode_json(header) {
+            Some(Json::Object(header)) => header,
+            _ => return None
+        };
+        if header.get("alg").and_then(Json::as_string) != Some("HS256") {
+            return None;
+        }
+
+        let signature = match base64url_decode(signature) {
+            Some(signature) => signature,
+            None => return None
+        };
+        let valid = {
+            let keys = match self.keys.read() {
+                Ok(keys) => keys,
+                Err(poisoned) => poisoned.into_inner()
+            };
+            keys.iter().any(|key| constant_time_eq(&hmac_sha256(key, signed.as_bytes()), &signature))
+        };
+        if !valid {
+            return None;
+        }
+
+        let claims = match decode_json(payload) {
+            Some(Json::Object(claims)) => claims,
+            _ => return None
+        };
+
+        if self.check_claims(&claims, now) {
+            Some(Claims(claims))
+        } else {
+            None
+        }
+    }
+
+    fn check_claims(&self, claims: &json::Object, now: i64) -> bool {
+        let settings = &self.settings;
+        let time_claim = |name: &str| claims.get(name).map(|value| value.as_i64().or_else(|| value.as_f64().map(|v| v as i64)));
+
+        match time_claim("exp") {
+            Some(Some(expires)) if expires + settings.leeway > now => {},
+            Some(_) => return false,
+            None if settings.require_expiration => return false,
+            None => {}
+        }
+
+        match time_claim("nbf") {
+            Some(Some(not_before)) if not_before - settings.leeway <= now => {},
+            Some(_) => return false,
+            None => {}
+        }
+
+        if let Some(ref issuer) = settings.issuer {
+            if claims.get("iss").and_then(Json::as_string) != Some(&**issuer) {
+                return false;
+            }
+        }
+
+        if let Some(ref audience) = settings.audience {
+            return match claims.get("aud") {
+                Some(&Json::String(ref aud)) => aud == audience,
+                Some(&Json::Array(ref auds)) => auds.iter().any(|aud| aud.as_string() == Some(&**audience)),
+                _ => false
+            };
+        }
+
+        true
+    }
+}
+
+impl ContextFilter for JwtAuth {
+    fn modify(&self, context: FilterContext, request_context: &mut Context) -> ContextAction {
+        let token = request_context.headers.get_raw("Authorization")
+            .and_then(|lines| lines.first())
+            .and_then(|line| ::std::str::from_utf8(line).ok())
+            .map(|line| line.trim());
+
+        let token = match token {
+            Some(line) if line.len() > 7 && line.is_char_boundary(7) && line[..7].to_lowercase() == "bearer " => line[7..].trim(),
+            Some(_) => return unauthorized(None),
+            None => return if self.settings.optional {
+                ContextAction::next()
+            } else {
+                unauthorized(None)
+            }
+        };
+
+        match self.validate(token, time::get_time().sec) {
+            Some(claims) => {
+                context.storage.insert(claims);
+                ContextAction::next()
+            },
+            None => unauthorized(Some("invalid_token"))
+        }
+    }
+}
+
+//Answers with `401 Unauthorized` and a Bearer challenge, with the error
+//code `error`, if any.
+fn unauthorized(error: Option<&str>) -> ContextAction {
+    let challenge = match error {
+        Some(error) => format!("Bearer error=\"{}\"", error),
+        None => "Bearer".to_owned()
+    };
+
+    let mut response = FilterResponse::new(StatusCode::Unauthorized);
+    response.headers.set_raw("WWW-Authenticate", vec![challenge.into_bytes()]);
+    ContextAction::respond(response)
+}
+
+fn base64url_decode(encoded: &str) -> Option<Vec<u8>> {
+    let standard: Vec<u8> = encoded.bytes().map(|b| match b {
+        b'-' => b'+',
+        b'_' => b'/',
+        b'+' | b'/' => b'!',
+        b => b
+    }).collect();
+    base64_decode(&standard)
+}
+
+fn decode_json(encoded: &str) -> Option<Json> {
+    base64url_decode(encoded)
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .and_then(|source| Json::from_str(&source).ok())
+}
+
+#[cfg(test)]
+mod test {
+    use filter::ContextAction;
+    use StatusCode;
+    use utils::{hmac_sha256, base64_encode};
+    use super::{JwtAuth, unauthorized};
+
+    fn base64url(bytes: &[u8]) -> String {
+        base64_encode(bytes).trim_right_matches('=').chars().map(|c| match c {
+            '+' => '-',
+            '/' => '_',
+            c => c
+        }).collect()
+    }
+
+    //Signs `claims` with "secret".
+    fn token(claims: &str) -> String {
+        let signed = format!("{}.{}", base64url(br#"{"alg":"HS256","typ":"JWT"}"#), base64url(claims.as_bytes()));
+        let signature = base64url(&hmac_sha256(b"secret", signed.as_bytes()));
+        format!("{}.{}", signed, signature)
+    }
+
+    #[test]
+    fn validate_tokens() {
+        let token = token(r#"{"sub":"alice","iss":"me","aud":["you","them"],"exp":2000}"#);
+        let auth = JwtAuth::new(vec![b"secret".to_vec()]).issuer("me").audience("them");
+
+        let claims = auth.validate(&token, 1000).expect("the token was not valid");
+        assert_eq!(claims.subject(), Some("alice"));
+
+        assert!(auth.validate(&token, 2000).is_none());
+        assert!(JwtAuth::new(vec![b"secret".to_vec()]).issuer("other").validate(&token, 1000).is_none());
+        assert!(JwtAuth::new(vec![b"secret".to_vec()]).audience("other").validate(&token, 1000).is_none());
+
+        auth.set_keys(vec![b"other".to_vec()]);
+        assert!(auth.validate(&token, 1000).is_none());
+    }
+
+    #[test]
+    fn require_expiration() {
+        let token = token(r#"{"sub":"alice"}"#);
+        assert!(JwtAuth::new(vec![b"secret".to_vec()]).validate(&token, 1000).is_none());
+
+        let claims = JwtAuth::new(vec![b"secret".to_vec()]).allow_no_expiration().validate(&token, 1000);
+        assert_eq!(claims.as_ref().and_then(|claims| claims.subject()), Some("alice"));
+    }
+
+    #[test]
+    fn bearer_challenges() {
+        match unauthorized(Some("invalid_token")) {
+            ContextAction::Respond(response) => {
+                assert_eq!(response.status, StatusCode::Unauthorized);
+                assert_eq!(response.headers.get_raw("WWW-Authenticate"), Some(&[b"Bearer error=\"invalid_token\"".to_vec()][..]));
+            },
+            _ => panic!("expected a response")
+        }
+
+        match unauthorized(None) {
+            ContextAction::Respond(response) => {
+                assert_eq!(response.headers.get_raw("WWW-Authenticate"), Some(&[b"Bearer".to_vec()][..]));
+            },
+            _ => panic!("expected a response")
+        }
+    }
+}
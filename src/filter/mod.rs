@@ -0,0 +1,453 @@
+//!Request and context filters.
+
+use std::net::SocketAddr;
+
+use anymap::AnyMap;
+
+use StatusCode;
+use header::{Headers, Header, HeaderFormat};
+
+use context::Context;
+use log::Log;
+
+use response::Data;
+
+use Global;
+
+pub use self::client_hints::AcceptClientHints;
+pub use self::api_key::{ApiKeyAuth, KeyValidator, Principal};
+pub use self::conditional::ConditionalGet;
+pub use self::timeout::Timeout;
+#[doc(hidden)]
+pub use self::timeout::EnforcedDeadline;
+pub use self::body_limit::BodyLimit;
+pub use self::origin_check::OriginCheck;
+pub use self::on_path::OnPath;
+pub use self::rewrite::{RewriteBody, BodyRewriter};
+pub use self::jsonp::Jsonp;
+#[doc(hidden)]
+pub use self::on_path::RequestPath;
+#[cfg(feature = "jwt")]
+pub use self::jwt::{JwtAuth, Claims};
+
+mod client_hints;
+mod api_key;
+mod conditional;
+mod timeout;
+mod body_limit;
+mod origin_check;
+mod on_path;
+mod rewrite;
+mod jsonp;
+#[cfg(feature = "jwt")]
+mod jwt;
+
+///Contextual tools for filters.
+pub struct FilterContext<'a> {
+    ///Shared storage for filters. It is local to the current request and
+    ///accessible from the handler and all of the filters. It can be used to
+    ///send data between these units.
+    pub storage: &'a mut AnyMap,
+
+    ///Log for notes, errors and warnings.
+    pub log: &'a Log,
+
+    ///Globally accessible data.
+    pub global: &'a Global,
+}
+
+///Contextual tools for setting up filters.
+pub struct GlobalContext<'a> {
+    ///Log for notes, errors and warnings.
+    pub log: &'a Log,
+
+    ///Globally accessible data.
+    pub global: &'a Global,
+}
+
+///A trait for context filters.
+///
+///They are able to modify and react to a `Context` before it's sent to the handler.
+pub trait ContextFilter: Send + Sync {
+    ///Try to modify the handler `Context`.
+    fn modify(&self, context: FilterContext, request_context: &mut Context) -> ContextAction;
+
+    ///The name of the filter, which is used to find it in the server's
+    ///filter stack. See `Server::insert_context_filter_before` and
+    ///[`Named`][named].
+    ///
+    ///The default implementation has no name.
+    ///
+    ///[named]: struct.Named.html
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    ///Prepare the filter when the server is built, before any requests are
+    ///handled. It's where connections can be opened, and maintenance threads
+    ///can be started. A filter is set up once for each of the server's
+    ///filter stacks it's in, but the filters in a [`Scope`][scope] are not
+    ///set up.
+    ///
+    ///The default implementation does nothing.
+    ///
+    ///[scope]: ../handler/scope/struct.Scope.html
+    #[allow(unused_variables)]
+    fn setup(&self, context: &GlobalContext) {}
+
+    ///Release the resources from `setup` when the server instance is
+    ///dropped, which is after it has stopped handling requests.
+    ///
+    ///The default implementation does nothing.
+    fn teardown(&self) {}
+}
+
+///The result from a context filter.
+#[derive(Clone)]
+pub enum ContextAction {
+    ///Continue to the next filter in the stack.
+    Next,
+
+    ///Abort and set HTTP status. The body is the error page for the
+    ///status, if the server has one.
+    Abort(StatusCode),
+
+    ///Abort and send a complete response.
+    Respond(FilterResponse)
+}
+
+impl<'a> ContextAction {
+    ///Continue to the next filter in the stack.
+    pub fn next() -> ContextAction {
+        ContextAction::Next
+    }
+
+    ///Abort and set HTTP status. The body is the error page for the
+    ///status, if the server has one.
+    pub fn abort(status: StatusCode) -> ContextAction {
+        ContextAction::Abort(status)
+    }
+
+    ///Abort and send a complete response.
+    ///
+    ///```
+    ///#[macro_use]
+    ///extern crate rustful;
+    ///use rustful::{Context, StatusCode};
+    ///use rustful::header::ContentType;
+    ///use rustful::filter::{ContextFilter, ContextAction, FilterContext, FilterResponse};
+    ///
+    ///struct RequireAuth;
+    ///
+    ///impl ContextFilter for RequireAuth {
+    ///    fn modify(&self, _: FilterContext, context: &mut Context) -> ContextAction {
+    ///        if context.headers.get_raw("Authorization").is_some() {
+    ///            ContextAction::next()
+    ///        } else {
+    ///            ContextAction::respond(
+    ///                FilterResponse::new(StatusCode::Unauthorized)
+    ///                    .header(ContentType(content_type!(Application / Json; Charset = Utf8)))
+    ///                    .body(r#"{"error":"missing credentials"}"#)
+    ///            )
+    ///        }
+    ///    }
+    ///}
+    ///# fn main() {}
+    ///```
+    pub fn respond(response: FilterResponse) -> ContextAction {
+        ContextAction::Respond(response)
+    }
+}
+
+///A complete response from a filter, that is sent instead of the handler's
+///response.
+///
+///It's used by context filters through `ContextAction::respond`. A response
+///filter can put one in the filter storage before returning
+///`ResponseAction::Abort`, to have it sent instead of the default `500
+///Internal Server Error`, as long as nothing has been written to the client.
+///
+///The headers are added to the headers of the response. The body is the
+///error page for the status, if the server has one, unless it's set.
+#[derive(Clone, Debug)]
+pub struct FilterResponse {
+    ///The status code.
+    pub status: StatusCode,
+
+    ///Headers to add to the response.
+    pub headers: Headers,
+
+    ///The response body, or `None` to use the error page.
+    pub body: Option<Vec<u8>>
+}
+
+impl FilterResponse {
+    ///Create a response with the status `status`.
+    pub fn new(status: StatusCode) -> FilterResponse {
+        FilterResponse {
+            status: status,
+            headers: Headers::new(),
+            body: None
+        }
+    }
+
+    ///Set a header.
+    pub fn header<H: Header + HeaderFormat>(mut self, header: H) -> FilterResponse {
+        self.headers.set(header);
+        self
+    }
+
+    ///Set the body.
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> FilterResponse {
+        self.body = Some(body.into());
+        self
+    }
+}
+
+///A trait for admission filters.
+///
+///They run before anything else is done with a request, and they only get
+///to know the client address and how busy the server is. This makes them
+///cheap enough to turn away unwanted clients, such as banned addresses or
+///clients that connect too often, before any time is spent on the request.
+///
+///Hyper 0.6 doesn't have a hook for new connections, so they are called for
+///each request, once Hyper has read the request head. Nothing else is done
+///with the request before that, and a rejected request is not seen by any
+///other filters, hooks or handlers.
+///
+///A rejected request gets the status code, or the response from
+///`ContextAction::respond`, with `Connection: close`. The response is sent
+///as it is, without the server's default headers, response filters or error
+///pages, so its body is empty unless it's set.
+///
+///```
+///use std::net::IpAddr;
+///use rustful::StatusCode;
+///use rustful::filter::{AdmissionFilter, ContextAction, Peer};
+///
+///struct Ban(Vec<IpAddr>);
+///
+///impl AdmissionFilter for Ban {
+///    fn admit(&self, peer: &Peer) -> ContextAction {
+///        if self.0.contains(&peer.address.ip()) {
+///            ContextAction::abort(StatusCode::Forbidden)
+///        } else {
+///            ContextAction::next()
+///        }
+///    }
+///}
+///```
+pub trait AdmissionFilter: Send + Sync {
+    ///Decide if the request from `peer` should be handled.
+    fn admit(&self, peer: &Peer) -> ContextAction;
+
+    ///Prepare the filter when the server is built, before any requests are
+    ///handled. See `ContextFilter::setup`.
+    ///
+    ///The default implementation does nothing.
+    #[allow(unused_variables)]
+    fn setup(&self, context: &GlobalContext) {}
+
+    ///Release the resources from `setup` when the server instance is
+    ///dropped. See `ContextFilter::teardown`.
+    ///
+    ///The default implementation does nothing.
+    fn teardown(&self) {}
+}
+
+///What an `AdmissionFilter` gets to know about a request.
+#[derive(Clone, Copy, Debug)]
+pub struct Peer {
+    ///The address of the peer. It may be a proxy.
+    pub address: SocketAddr,
+
+    ///The number of requests that the server is currently handling,
+    ///including this one.
+    pub in_flight: usize
+}
+
+///A trait for response filters.
+///
+///They are able to modify headers and data before it gets written in the response.
+pub trait ResponseFilter: Send + Sync {
+    ///Set or modify headers before they are sent to the client and maybe initiate the body.
+    fn begin(&self, context: FilterContext, status: StatusCode, headers: &mut Headers) ->
+        (StatusCode, ResponseAction);
+
+    ///Handle content before writing it to the body.
+    fn write<'a>(&'a self, context: FilterContext, content: Option<Data<'a>>) -> ResponseAction;
+
+    ///End of body writing. Last chance to add content.
+    fn end(&self, context: FilterContext) -> ResponseAction;
+
+    ///Inspect and modify the final status and headers, right before they
+    ///are written. This happens after `begin`, and after the server has
+    ///added its own headers, such as `Content-Length`, `Content-Encoding`
+    ///and `ETag`, for responses that are sent in one piece. It's useful for
+    ///adding security headers, or removing internal headers.
+    ///
+    ///It's also called for `Raw` responses, and files, where the other
+    ///methods are bypassed. The `Content-Length` header shouldn't be changed
+    ///in that case.
+    ///
+    ///The default implementation keeps everything as it is.
+    #[allow(unused_variables)]
+    fn finish_headers(&self, context: FilterContext, status: StatusCode, headers: &mut Headers) -> StatusCode {
+        status
+    }
+
+    ///The name of the filter, which is used to find it in the server's
+    ///filter stack. See `Server::insert_response_filter_before` and
+    ///[`Named`][named].
+    ///
+    ///The default implementation has no name.
+    ///
+    ///[named]: struct.Named.html
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    ///Prepare the filter when the server is built, before any requests are
+    ///handled. See `ContextFilter::setup`.
+    ///
+    ///The default implementation does nothing.
+    #[allow(unused_variables)]
+    fn setup(&self, context: &GlobalContext) {}
+
+    ///Release the resources from `setup` when the server instance is
+    ///dropped. See `ContextFilter::teardown`.
+    ///
+    ///The default implementation does nothing.
+    fn teardown(&self) {}
+}
+
+///Gives a name to a context or response filter.
+///
+///Filters are applied in the order they are in the server's filter stacks.
+///A named filter can be found by its name, which makes it possible to put
+///other filters before or after it, or to replace or remove it, without
+///knowing where it is. This is useful when the filters come from different
+///places, such as libraries that set up a part of a server.
+///
+///```
+///use rustful::{Server, Context, Response};
+///use rustful::filter::{Named, ConditionalGet, AcceptClientHints};
+///
+///let mut server = Server {
+///    context_filters: vec![Box::new(Named::new("conditional", ConditionalGet))],
+///    response_filters: vec![Box::new(Named::new("conditional", ConditionalGet))],
+///    ..Server::new(|_: Context, response: Response| response.send("hello"))
+///};
+///
+///server.insert_response_filter_before("conditional", Box::new(AcceptClientHints::new(&["DPR", "Width"]))).ok();
+///server.remove_context_filter("conditional");
+///```
+#[derive(Clone, Debug)]
+pub struct Named<F> {
+    name: String,
+    filter: F
+}
+
+impl<F> Named<F> {
+    ///Give `filter` the name `name`.
+    pub fn new<N: Into<String>>(name: N, filter: F) -> Named<F> {
+        Named {
+            name: name.into(),
+            filter: filter
+        }
+    }
+
+    ///Get a reference to the filter.
+    pub fn filter(&self) -> &F {
+        &self.filter
+    }
+
+    ///Get the filter, without the name.
+    pub fn into_filter(self) -> F {
+        self.filter
+    }
+}
+
+impl<F: ContextFilter> ContextFilter for Named<F> {
+    fn modify(&self, context: FilterContext, request_context: &mut Context) -> ContextAction {
+        self.filter.modify(context, request_context)
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+
+    fn setup(&self, context: &GlobalContext) {
+        self.filter.setup(context);
+    }
+
+    fn teardown(&self) {
+        self.filter.teardown();
+    }
+}
+
+impl<F: ResponseFilter> ResponseFilter for Named<F> {
+    fn begin(&self, context: FilterContext, status: StatusCode, headers: &mut Headers) -> (StatusCode, ResponseAction) {
+        self.filter.begin(context, status, headers)
+    }
+
+    fn write<'a>(&'a self, context: FilterContext, content: Option<Data<'a>>) -> ResponseAction {
+        self.filter.write(context, content)
+    }
+
+    fn end(&self, context: FilterContext) -> ResponseAction {
+        self.filter.end(context)
+    }
+
+    fn finish_headers(&self, context: FilterContext, status: StatusCode, headers: &mut Headers) -> StatusCode {
+        self.filter.finish_headers(context, status, headers)
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+
+    fn setup(&self, context: &GlobalContext) {
+        self.filter.setup(context);
+    }
+
+    fn teardown(&self) {
+        self.filter.teardown();
+    }
+}
+
+///The result from a response filter.
+#[derive(Clone)]
+pub enum ResponseAction<'a> {
+    ///Continue to the next filter and maybe write data.
+    Next(Option<Data<'a>>),
+
+    ///Do not continue to the next filter.
+    SilentAbort,
+
+    ///Abort with an error. The response is replaced with an empty `500
+    ///Internal Server Error`, or the error page for it, if nothing has been
+    ///written to the client yet. A [`FilterResponse`][filter_response] in
+    ///the filter storage is sent instead, if there is one.
+    ///
+    ///[filter_response]: struct.FilterResponse.html
+    Abort(String)
+}
+
+impl<'a> ResponseAction<'a> {
+    ///Continue to the next filter and maybe write data.
+    pub fn next<T: Into<Data<'a>>>(data: Option<T>) -> ResponseAction<'a> {
+        ResponseAction::Next(data.map(|d| d.into()))
+    }
+
+    ///Do not continue to the next filter.
+    pub fn silent_abort() -> ResponseAction<'a> {
+        ResponseAction::SilentAbort
+    }
+
+    ///Abort with an error.
+    pub fn abort(message: String) -> ResponseAction<'a> {
+        ResponseAction::Abort(message)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,226 @@
+use StatusCode;
+use header::Headers;
+use context::Context;
+use response::Data;
+use filter::{FilterContext, GlobalContext, ContextFilter, ContextAction, ResponseFilter, ResponseAction};
+
+#[doc(hidden)]
+///Internal and may change without warning.
+///
+///The decoded request path, in the filter storage.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestPath(pub String);
+
+#[derive(Clone, Debug, PartialEq)]
+enum PathMatch {
+    Prefix(Vec<String>),
+    Pattern(Vec<String>)
+}
+
+impl PathMatch {
+    fn matches(&self, path: &str) -> bool {
+        let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+
+        match *self {
+            PathMatch::Prefix(ref prefix) => prefix.iter().all(|expected| segments.next() == Some(expected)),
+            PathMatch::Pattern(ref pattern) => {
+                for (index, expected) in pattern.iter().enumerate() {
+                    if expected == "**" && index + 1 == pattern.len() {
+                        return true;
+                    }
+
+                    match segments.next() {
+                        Some(segment) => if expected != "*" && expected != segment {
+                            return false;
+                        },
+                        None => return false
+                    }
+                }
+
+                segments.next().is_none()
+            }
+        }
+    }
+}
+
+fn split_segments(path: &str) -> Vec<String> {
+    path.split('/').filter(|segment| !segment.is_empty()).map(ToOwned::to_owned).collect()
+}
+
+///A wrapper that makes a context or response filter apply only to some
+///request paths.
+///
+///The paths are either prefixes, which match whole path segments, or
+///patterns, where `*` matches any one segment, and a `**` at the end
+///matches the rest of the path. The path is compared after it has been
+///percent decoded and, depending on `Server::path_normalization`,
+///normalized by the server. Requests without a path,
+///such as `OPTIONS *`, never match.
+///
+///```
+///use rustful::{Server, Context, Response};
+///use rustful::filter::{OnPath, OriginCheck, AcceptClientHints};
+///
+///let server = Server {
+///    context_filters: vec![
+///        //Only for /api and everything below it
+///        Box::new(OnPath::prefix("/api", OriginCheck::new().allow("https://example.com")))
+///    ],
+///    response_filters: vec![
+///        Box::new(OnPath::pattern("/images/*/large", AcceptClientHints::new(&["DPR", "Width"])))
+///    ],
+///    ..Server::new(|_: Context, response: Response| response.send("hello"))
+///};
+///```
+///
+///A response filter that doesn't apply lets the response through as it is.
+///The path is only known to response filters in the server's
+///`response_filters`, for requests that have been handled, and not for
+///example when the request itself was malformed.
+#[derive(Clone, Debug)]
+pub struct OnPath<F> {
+    path: PathMatch,
+    filter: F
+}
+
+impl<F> OnPath<F> {
+    ///Apply `filter` to `prefix` and every path below it. `/api` matches
+    ///`/api` and `/api/users`, but not `/apis`.
+    pub fn prefix(prefix: &str, filter: F) -> OnPath<F> {
+        OnPath {
+            path: PathMatch::Prefix(split_segments(prefix)),
+            filter: filter
+        }
+    }
+
+    ///Apply `filter` to the paths that match `pattern`. `/users/*/avatar`
+    ///matches `/users/alice/avatar`, and `/static/**` matches `/static`
+    ///and every path below it.
+    pub fn pattern(pattern: &str, filter: F) -> OnPath<F> {
+        OnPath {
+            path: PathMatch::Pattern(split_segments(pattern)),
+            filter: filter
+        }
+    }
+
+    ///Check if the filter applies to `path`.
+    pub fn applies_to(&self, path: &str) -> bool {
+        self.path.matches(path)
+    }
+
+    ///Get a reference to the filter.
+    pub fn filter(&self) -> &F {
+        &self.filter
+    }
+
+    ///Get the filter, without the path.
+    pub fn into_filter(self) -> F {
+        self.filter
+    }
+
+    fn applies(&self, context: &FilterContext) -> bool {
+        match context.storage.get::<RequestPath>() {
+            Some(&RequestPath(ref path)) => self.path.matches(path),
+            None => false
+        }
+    }
+}
+
+impl<F: ContextFilter> ContextFilter for OnPath<F> {
+    fn modify(&self, context: FilterContext, request_context: &mut Context) -> ContextAction {
+        let applies = match request_context.uri.as_path() {
+            Some(path) => self.path.matches(&path.as_utf8_lossy()),
+            None => false
+        };
+
+        if applies {
+            self.filter.modify(context, request_context)
+        } else {
+            ContextAction::next()
+        }
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.filter.name()
+    }
+
+    fn setup(&self, context: &GlobalContext) {
+        self.filter.setup(context);
+    }
+
+    fn teardown(&self) {
+        self.filter.teardown();
+    }
+}
+
+impl<F: ResponseFilter> ResponseFilter for OnPath<F> {
+    fn begin(&self, context: FilterContext, status: StatusCode, headers: &mut Headers) -> (StatusCode, ResponseAction) {
+        if self.applies(&context) {
+            self.filter.begin(context, status, headers)
+        } else {
+            (status, ResponseAction::next(None::<Data>))
+        }
+    }
+
+    fn write<'a>(&'a self, context: FilterContext, content: Option<Data<'a>>) -> ResponseAction {
+        if self.applies(&context) {
+            self.filter.write(context, content)
+        } else {
+            ResponseAction::next(content)
+        }
+    }
+
+    fn end(&self, context: FilterContext) -> ResponseAction {
+        if self.applies(&context) {
+            self.filter.end(context)
+        } else {
+            ResponseAction::next(None::<Data>)
+        }
+    }
+
+    fn finish_headers(&self, context: FilterContext, status: StatusCode, headers: &mut Headers) -> StatusCode {
+        if self.applies(&context) {
+            self.filter.finish_headers(context, status, headers)
+        } else {
+            status
+        }
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.filter.name()
+    }
+
+    fn setup(&self, context: &GlobalContext) {
+        self.filter.setup(context);
+    }
+
+    fn teardown(&self) {
+        self.filter.teardown();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PathMatch;
+    use super::split_segments;
+
+    #[test]
+    fn match_paths() {
+        let prefix = PathMatch::Prefix(split_segments("/api/"));
+        assert!(prefix.matches("/api"));
+        assert!(prefix.matches("/api/users/5"));
+        assert!(!prefix.matches("/apis"));
+        assert!(!prefix.matches("/"));
+        assert!(PathMatch::Prefix(split_segments("/")).matches("/anything"));
+
+        let pattern = PathMatch::Pattern(split_segments("/users/*/avatar"));
+        assert!(pattern.matches("/users/alice/avatar"));
+        assert!(!pattern.matches("/users/alice"));
+        assert!(!pattern.matches("/users/alice/avatar/large"));
+
+        let rest = PathMatch::Pattern(split_segments("/static/**"));
+        assert!(rest.matches("/static"));
+        assert!(rest.matches("/static/css/main.css"));
+        assert!(!rest.matches("/images/a.png"));
+    }
+}
@@ -0,0 +1,148 @@
+use {Method, StatusCode};
+use header::Headers;
+use context::Context;
+use filter::{FilterContext, ContextFilter, ContextAction};
+
+///A context filter that rejects state changing requests from other
+///origins with `403 Forbidden`.
+///
+///Requests with other methods than `GET`, `HEAD`, `OPTIONS` and `TRACE` are
+///checked against a list of allowed origins, using the `Origin` header, or
+///the origin of the `Referer` header if there is no `Origin`. This is a
+///lightweight protection against cross-site request forgery, for APIs that
+///can't use tokens. Requests without either header are rejected, unless
+///`allow_missing` is used, and `Origin: null` is always rejected.
+///
+///```
+///use rustful::{Server, Context, Response};
+///use rustful::filter::OriginCheck;
+///
+///let check = OriginCheck::new()
+///    .allow("https://example.com")
+///    .allow("https://admin.example.com");
+///
+///let server = Server {
+///    context_filters: vec![Box::new(check)],
+///    ..Server::new(|_: Context, response: Response| response.send("hello"))
+///};
+///```
+#[derive(Clone, Debug, PartialEq)]
+pub struct OriginCheck {
+    origins: Vec<String>,
+    allow_missing: bool
+}
+
+impl OriginCheck {
+    ///Create a filter that doesn't allow any origins.
+    pub fn new() -> OriginCheck {
+        OriginCheck {
+            origins: vec![],
+            allow_missing: false
+        }
+    }
+
+    ///Allow `origin`, such as `https://example.com`. Origins are compared
+    ///without regard to case.
+    pub fn allow<O: Into<String>>(mut self, origin: O) -> OriginCheck {
+        let origin: String = origin.into();
+        self.origins.push(origin.trim_right_matches('/').to_lowercase());
+        self
+    }
+
+    ///Let requests without an `Origin` or a `Referer` header through. They
+    ///usually come from other clients than browsers.
+    pub fn allow_missing(mut self) -> OriginCheck {
+        self.allow_missing = true;
+        self
+    }
+
+    ///Check if a request with the method `method` and the headers
+    ///`headers` is allowed.
+    pub fn allows(&self, method: &Method, headers: &Headers) -> bool {
+        match *method {
+            Method::Get | Method::Head | Method::Options | Method::Trace => return true,
+            _ => {}
+        }
+
+        let origin = first_header(headers, "Origin").or_else(|| {
+            first_header(headers, "Referer").and_then(|referer| origin_of(&referer))
+        });
+
+        match origin {
+            Some(ref origin) if origin == "null" => false,
+            Some(origin) => {
+                let origin = origin.to_lowercase();
+                self.origins.iter().any(|allowed| *allowed == origin)
+            },
+            None => self.allow_missing
+        }
+    }
+}
+
+impl Default for OriginCheck {
+    fn default() -> OriginCheck {
+        OriginCheck::new()
+    }
+}
+
+impl ContextFilter for OriginCheck {
+    fn modify(&self, context: FilterContext, request_context: &mut Context) -> ContextAction {
+        if self.allows(&request_context.method, &request_context.headers) {
+            ContextAction::next()
+        } else {
+            context.log.note(&format!("rejected a {} request from another origin", request_context.method));
+            ContextAction::abort(StatusCode::Forbidden)
+        }
+    }
+}
+
+fn first_header(headers: &Headers, name: &str) -> Option<String> {
+    headers.get_raw(name)
+        .and_then(|lines| lines.first())
+        .and_then(|line| ::std::str::from_utf8(line).ok())
+        .map(|line| line.trim().to_owned())
+        .and_then(|line| if line.is_empty() { None } else { Some(line) })
+}
+
+//Gets the `scheme://host[:port]` part of a URL.
+fn origin_of(url: &str) -> Option<String> {
+    let scheme_end = match url.find("://") {
+        Some(index) => index,
+        None => return None
+    };
+
+    let rest = &url[scheme_end + 3..];
+    let host_end = rest.find(|c| c == '/' || c == '?' || c == '#').unwrap_or(rest.len());
+    if host_end == 0 {
+        None
+    } else {
+        Some(format!("{}://{}", &url[..scheme_end], &rest[..host_end]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use Method;
+    use header::Headers;
+    use super::OriginCheck;
+
+    fn headers(name: &str, value: &str) -> Headers {
+        let mut headers = Headers::new();
+        headers.set_raw(name.to_owned(), vec![value.as_bytes().to_vec()]);
+        headers
+    }
+
+    #[test]
+    fn check_origins() {
+        let check = OriginCheck::new().allow("https://Example.com/");
+
+        assert!(check.allows(&Method::Get, &headers("Origin", "https://evil.com")));
+        assert!(check.allows(&Method::Post, &headers("Origin", "https://example.com")));
+        assert!(!check.allows(&Method::Post, &headers("Origin", "https://evil.com")));
+        assert!(!check.allows(&Method::Delete, &headers("Origin", "null")));
+        assert!(check.allows(&Method::Put, &headers("Referer", "https://EXAMPLE.com/page?x=1")));
+        assert!(!check.allows(&Method::Put, &headers("Referer", "https://example.com.evil.com/")));
+        assert!(!check.allows(&Method::Post, &Headers::new()));
+        assert!(check.clone().allow_missing().allows(&Method::Post, &Headers::new()));
+    }
+}
@@ -0,0 +1,211 @@
+use std::marker::PhantomData;
+
+use StatusCode;
+use header::{Headers, ContentType, ContentEncoding, ETag};
+use mime::{Mime, TopLevel, Attr, Value};
+use response::Data;
+use filter::{FilterContext, ResponseFilter, ResponseAction};
+use utils::remove_content_length;
+
+///Rewrites whole response bodies for `RewriteBody`.
+///
+///It's implemented for closures that take the body as a string slice and
+///return the new body.
+pub trait BodyRewriter: Send + Sync {
+    ///Check if a response with `status` and `headers` should be rewritten.
+    ///The headers may be changed, for example to remove or add cache
+    ///validators.
+    ///
+    ///The default implementation rewrites successful responses with a
+    ///`text/*` content type.
+    fn applies(&self, status: StatusCode, headers: &mut Headers) -> bool {
+        status.to_u16() / 100 == 2 && match headers.get::<ContentType>() {
+            Some(&ContentType(Mime(TopLevel::Text, _, _))) => true,
+            _ => false
+        }
+    }
+
+    ///Rewrite the whole body.
+    fn rewrite(&self, body: &str) -> String;
+}
+
+impl<F: Fn(&str) -> String + Send + Sync> BodyRewriter for F {
+    fn rewrite(&self, body: &str) -> String {
+        self(body)
+    }
+}
+
+//The body that is being collected, in the filter storage. The type
+//parameter keeps the buffers of different rewriters apart.
+struct Buffer<R> {
+    body: Vec<u8>,
+    _rewriter: PhantomData<R>
+}
+
+///A response filter that collects the response body and rewrites it as a
+///whole, using a [`BodyRewriter`][rewriter].
+///
+///Rewriting a body is mostly about the headers. The rewritten body is
+///`UTF-8` encoded, so only bodies with a `UTF-8` or `US-ASCII` charset, or
+///without a charset, are rewritten, and the charset is set to `UTF-8`. The
+///`Content-Length` and `ETag` headers are removed, since they are for the
+///original body, and bodies with a `Content-Encoding` are left alone,
+///since they are already compressed. The server compresses the new body
+///and sets its length, if it's sent in one piece. Bodies that are not
+///valid `UTF-8` are sent as they are.
+///
+///The body is collected in memory, so bodies that are larger than the
+///maximum size are sent unchanged, starting with the part that has been
+///collected. Streamed bodies are sent as one chunk at the end, unless they
+///are too large.
+///
+///```
+///use rustful::{Server, Context, Response};
+///use rustful::filter::RewriteBody;
+///
+///let live_reload = RewriteBody::new(|body: &str| {
+///    body.replace("</body>", "<script src=\"/live-reload.js\"></script></body>")
+///});
+///
+///let server = Server {
+///    response_filters: vec![Box::new(live_reload)],
+///    ..Server::new(|_: Context, response: Response| response.send("<html><body>hello</body></html>"))
+///};
+///```
+///
+///[rewriter]: trait.BodyRewriter.html
+pub struct RewriteBody<R> {
+    rewriter: R,
+    max_size: usize
+}
+
+impl<R: BodyRewriter> RewriteBody<R> {
+    ///Rewrite bodies using `rewriter`.
+    pub fn new(rewriter: R) -> RewriteBody<R> {
+        RewriteBody {
+            rewriter: rewriter,
+            max_size: 1024 * 1024
+        }
+    }
+
+    ///Set the maximum size of the bodies that are rewritten, in bytes.
+    ///Default is 1 MiB.
+    pub fn max_size(mut self, max_size: usize) -> RewriteBody<R> {
+        self.max_size = max_size;
+        self
+    }
+}
+
+impl<R: BodyRewriter + 'static> ResponseFilter for RewriteBody<R> {
+    fn begin(&self, context: FilterContext, status: StatusCode, headers: &mut Headers) -> (StatusCode, ResponseAction) {
+        if status == StatusCode::NoContent || status == StatusCode::NotModified || headers.has::<ContentEncoding>() {
+            return (status, ResponseAction::next(None::<Data>));
+        }
+
+        if !is_utf8_compatible(headers) || !self.rewriter.applies(status, headers) {
+            return (status, ResponseAction::next(None::<Data>));
+        }
+
+        remove_content_length(headers);
+        headers.remove::<ETag>();
+
+        let content_type = headers.get::<ContentType>().cloned();
+        if let Some(ContentType(Mime(top, sub, mut params))) = content_type {
+            params.retain(|&(ref attr, _)| *attr != Attr::Charset);
+            params.push((Attr::Charset, Value::Utf8));
+            headers.set(ContentType(Mime(top, sub, params)));
+        }
+
+        context.storage.insert(Buffer::<R> {
+            body: vec![],
+            _rewriter: PhantomData
+        });
+
+        (status, ResponseAction::next(None::<Data>))
+    }
+
+    fn write<'a>(&'a self, context: FilterContext, content: Option<Data<'a>>) -> ResponseAction {
+        let too_large = match (context.storage.get_mut::<Buffer<R>>(), content.as_ref()) {
+            (Some(buffer), Some(content)) => {
+                if buffer.body.len() + content.as_bytes().len() > self.max_size {
+                    true
+                } else {
+                    buffer.body.extend_from_slice(content.as_bytes());
+                    return ResponseAction::next(None::<Data>);
+                }
+            },
+            (Some(_), None) => return ResponseAction::next(None::<Data>),
+            (None, _) => false
+        };
+
+        if too_large {
+            context.log.note(&format!("the response body is larger than {} bytes, so it was not rewritten", self.max_size));
+            if let Some(buffer) = context.storage.remove::<Buffer<R>>() {
+                let mut body = buffer.body;
+                if let Some(content) = content {
+                    body.extend_from_slice(content.as_bytes());
+                }
+                return ResponseAction::next(Some(body));
+            }
+        }
+
+        ResponseAction::next(content)
+    }
+
+    fn end(&self, context: FilterContext) -> ResponseAction {
+        match context.storage.remove::<Buffer<R>>() {
+            Some(buffer) => match String::from_utf8(buffer.body) {
+                Ok(body) => ResponseAction::next(Some(self.rewriter.rewrite(&body))),
+                Err(e) => {
+                    context.log.warning("the response body is not valid UTF-8, so it was not rewritten");
+                    ResponseAction::next(Some(e.into_bytes()))
+                }
+            },
+            None => ResponseAction::next(None::<Data>)
+        }
+    }
+}
+
+//Checks if the body can be decoded as UTF-8, according to its charset.
+fn is_utf8_compatible(headers: &Headers) -> bool {
+    let params = match headers.get::<ContentType>() {
+        Some(&ContentType(Mime(_, _, ref params))) => params,
+        None => return true
+    };
+
+    params.iter().all(|&(ref attr, ref value)| {
+        if *attr != Attr::Charset {
+            return true;
+        }
+
+        match *value {
+            Value::Utf8 => true,
+            Value::Ext(ref charset) => {
+                let charset = charset.to_lowercase();
+                charset == "utf-8" || charset == "utf8" || charset == "us-ascii"
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use header::{Headers, ContentType};
+    use mime::{Mime, TopLevel, SubLevel, Attr, Value};
+    use super::is_utf8_compatible;
+
+    #[test]
+    fn utf8_charsets() {
+        let mut headers = Headers::new();
+        assert!(is_utf8_compatible(&headers));
+
+        headers.set(ContentType(Mime(TopLevel::Text, SubLevel::Html, vec![(Attr::Charset, Value::Utf8)])));
+        assert!(is_utf8_compatible(&headers));
+
+        headers.set(ContentType(Mime(TopLevel::Text, SubLevel::Html, vec![(Attr::Charset, Value::Ext("US-ASCII".into()))])));
+        assert!(is_utf8_compatible(&headers));
+
+        headers.set(ContentType(Mime(TopLevel::Text, SubLevel::Html, vec![(Attr::Charset, Value::Ext("ISO-8859-1".into()))])));
+        assert!(!is_utf8_compatible(&headers));
+    }
+}
@@ -0,0 +1,118 @@
+use time::Duration;
+
+use StatusCode;
+use context::{Context, Deadline};
+use filter::{FilterContext, ContextFilter, ContextAction};
+
+#[doc(hidden)]
+///Internal and may change without warning.
+///
+///The deadline that the response enforces, in the filter storage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EnforcedDeadline {
+    pub deadline: Deadline,
+    pub status: StatusCode
+}
+
+///A context filter that replaces responses that are started too late.
+///
+///It sets the request's [`Deadline`][deadline], just like
+///`Server::request_timeout`, and checks it when the handler starts to send
+///its response. A late response is replaced with an empty `503 Service
+///Unavailable` (or the status from `status`), and everything the handler
+///writes after that is discarded.
+///
+///This is a check for late responses, and not a way to stop slow handlers.
+///The handler runs in the same thread as the response and can't be
+///interrupted, so it keeps the connection busy until it returns, no matter
+///how late it is. A chunked response that was started before the deadline
+///is not affected either. Handlers that do a lot of work should check
+///`Context::deadline` and give up early.
+///
+///It's usually added to the routes that need it, using a
+///[`Scope`][scope]. The earliest deadline is used if more than one timeout
+///applies to a request.
+///
+///```
+///#[macro_use]
+///extern crate rustful;
+///extern crate time;
+///use rustful::{Server, Context, Response, TreeRouter};
+///use rustful::filter::Timeout;
+///use rustful::handler::scope::Scope;
+///
+///fn search(context: Context, response: Response) {
+///    let mut results = vec![];
+///    while results.len() < 100 {
+///        if context.deadline().map(|d| d.has_expired()).unwrap_or(false) {
+///            break;
+///        }
+///        results.push(results.len().to_string());
+///    }
+///    response.send(results.join(", "));
+///}
+///
+///# fn main() {
+///let scope = Scope::new().context_filter(Timeout::new(time::Duration::seconds(2)));
+///
+///let server = Server {
+///    handlers: scope.wrap_router(insert_routes!{
+///        TreeRouter::new() => {
+///            "search" => Get: search as fn(Context, Response)
+///        }
+///    }),
+///    ..Server::default()
+///};
+///# }
+///```
+///
+///[deadline]: ../context/struct.Deadline.html
+///[scope]: ../handler/scope/struct.Scope.html
+#[derive(Clone, Copy, Debug)]
+pub struct Timeout {
+    budget: Duration,
+    status: StatusCode
+}
+
+impl Timeout {
+    ///Give the handler `budget` to produce a response.
+    pub fn new(budget: Duration) -> Timeout {
+        Timeout {
+            budget: budget,
+            status: StatusCode::ServiceUnavailable
+        }
+    }
+
+    ///Set the status of late responses. Default is `503 Service
+    ///Unavailable`, and `504 Gateway Timeout` is an alternative for handlers
+    ///that are waiting for other servers.
+    pub fn status(mut self, status: StatusCode) -> Timeout {
+        self.status = status;
+        self
+    }
+}
+
+impl ContextFilter for Timeout {
+    fn modify(&self, context: FilterContext, request_context: &mut Context) -> ContextAction {
+        let deadline = Deadline::from_now(self.budget);
+
+        let earlier = match context.storage.get::<EnforcedDeadline>() {
+            Some(current) => current.deadline <= deadline,
+            None => false
+        };
+
+        if !earlier {
+            context.storage.insert(EnforcedDeadline {
+                deadline: deadline,
+                status: self.status
+            });
+        }
+
+        request_context.deadline = match request_context.deadline {
+            Some(current) if current < deadline => Some(current),
+            _ => Some(deadline)
+        };
+
+        ContextAction::next()
+    }
+}
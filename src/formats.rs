@@ -0,0 +1,344 @@
+//!Serialization formats for response bodies.
+//!
+//!Values are always serialized as JSON, and they can also be serialized as
+//![MessagePack][msgpack] with the `msgpack` feature, and as [CBOR][cbor]
+//!with the `cbor` feature. `Response::send_negotiated` picks the format
+//!from the `Accept` header of the request:
+//!
+//!```
+//!extern crate rustful;
+//!extern crate rustc_serialize;
+//!use rustful::{Context, Response};
+//!
+//!#[derive(RustcEncodable)]
+//!struct Reading {
+//!    sensor: String,
+//!    value: f64
+//!}
+//!
+//!fn my_handler(context: Context, response: Response) {
+//!    let reading = Reading {
+//!        sensor: "temperature".into(),
+//!        value: 21.5
+//!    };
+//!
+//!    response.send_negotiated(&reading, &context.headers);
+//!}
+//!# fn main() {}
+//!```
+//!
+//!The binary formats are produced from the same JSON structure, so any
+//!`Encodable` value can be sent in all of the formats.
+//!
+//!This module is only available with the `rustc_json_body` feature.
+//!
+//![msgpack]: http://msgpack.org/
+//![cbor]: https://tools.ietf.org/html/rfc7049
+
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+use std::mem::transmute;
+
+use rustc_serialize::json::Json;
+
+use header::{Headers, Accept};
+use mime::{Mime, TopLevel, SubLevel, Attr, Value};
+
+///A serialization format.
+///
+///The formats are ordered by preference, for when the client accepts more
+///than one of them equally.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Format {
+    ///JSON.
+    Json,
+
+    ///MessagePack. It requires the `msgpack` feature.
+    #[cfg(feature = "msgpack")]
+    MsgPack,
+
+    ///CBOR. It requires the `cbor` feature.
+    #[cfg(feature = "cbor")]
+    Cbor
+}
+
+impl Format {
+    ///Get the content type of the format.
+    pub fn content_type(&self) -> Mime {
+        match *self {
+            Format::Json => Mime(TopLevel::Application, SubLevel::Json, vec![(Attr::Charset, Value::Utf8)]),
+            #[cfg(feature = "msgpack")]
+            Format::MsgPack => Mime(TopLevel::Application, SubLevel::Ext("msgpack".into()), vec![]),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => Mime(TopLevel::Application, SubLevel::Ext("cbor".into()), vec![])
+        }
+    }
+
+    ///Serialize `value` in the format.
+    pub fn encode(&self, value: &Json) -> Vec<u8> {
+        match *self {
+            Format::Json => value.to_string().into_bytes(),
+            #[cfg(feature = "msgpack")]
+            Format::MsgPack => to_msgpack(value),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => to_cbor(value)
+        }
+    }
+
+    fn from_mime(mime: &Mime) -> Option<Format> {
+        match *mime {
+            Mime(TopLevel::Star, _, _) | Mime(TopLevel::Application, SubLevel::Star, _) => Some(Format::Json),
+            Mime(TopLevel::Application, SubLevel::Json, _) => Some(Format::Json),
+            #[cfg(feature = "msgpack")]
+            Mime(TopLevel::Application, SubLevel::Ext(ref sub), _) if sub == "msgpack" || sub == "x-msgpack" => Some(Format::MsgPack),
+            #[cfg(feature = "cbor")]
+            Mime(TopLevel::Application, SubLevel::Ext(ref sub), _) if sub == "cbor" => Some(Format::Cbor),
+            _ => None
+        }
+    }
+}
+
+///Find the best format for a request with the headers `headers`. It's JSON
+///if there is no `Accept` header, and `None` if none of the available
+///formats are accepted.
+pub fn negotiate(headers: &Headers) -> Option<Format> {
+    let accepted = match headers.get::<Accept>() {
+        Some(accepted) => accepted,
+        None => return Some(Format::Json)
+    };
+
+    let mut best: Option<(Format, u16)> = None;
+    for item in accepted.iter() {
+        let format = match Format::from_mime(&item.item) {
+            Some(format) => format,
+            None => continue
+        };
+
+        let quality = item.quality.0;
+        let better = match best {
+            Some((best_format, best_quality)) => quality > best_quality || (quality == best_quality && format < best_format),
+            None => quality > 0
+        };
+
+        if better {
+            best = Some((format, quality));
+        }
+    }
+
+    best.map(|(format, _)| format)
+}
+
+///Serialize `value` as MessagePack. It requires the `msgpack` feature.
+#[cfg(feature = "msgpack")]
+pub fn to_msgpack(value: &Json) -> Vec<u8> {
+    let mut buffer = vec![];
+    write_msgpack(&mut buffer, value);
+    buffer
+}
+
+#[cfg(feature = "msgpack")]
+fn write_msgpack(buffer: &mut Vec<u8>, value: &Json) {
+    match *value {
+        Json::Null => buffer.push(0xc0),
+        Json::Boolean(false) => buffer.push(0xc2),
+        Json::Boolean(true) => buffer.push(0xc3),
+        Json::U64(n) => write_msgpack_uint(buffer, n),
+        Json::I64(n) if n >= 0 => write_msgpack_uint(buffer, n as u64),
+        Json::I64(n) => if n >= -32 {
+            buffer.push(n as u8);
+        } else if n >= -0x80 {
+            buffer.push(0xd0);
+            push_be(buffer, n as u64, 1);
+        } else if n >= -0x8000 {
+            buffer.push(0xd1);
+            push_be(buffer, n as u64, 2);
+        } else if n >= -0x8000_0000 {
+            buffer.push(0xd2);
+            push_be(buffer, n as u64, 4);
+        } else {
+            buffer.push(0xd3);
+            push_be(buffer, n as u64, 8);
+        },
+        Json::F64(n) => {
+            buffer.push(0xcb);
+            push_be(buffer, unsafe { transmute::<f64, u64>(n) }, 8);
+        },
+        Json::String(ref s) => {
+            let length = s.len() as u64;
+            if length < 32 {
+                buffer.push(0xa0 | length as u8);
+            } else {
+                write_msgpack_length(buffer, length, [0xd9, 0xda, 0xdb]);
+            }
+            buffer.extend_from_slice(s.as_bytes());
+        },
+        Json::Array(ref items) => {
+            let length = items.len() as u64;
+            if length < 16 {
+                buffer.push(0x90 | length as u8);
+            } else {
+                write_msgpack_length(buffer, length, [0xdc, 0xdc, 0xdd]);
+            }
+            for item in items {
+                write_msgpack(buffer, item);
+            }
+        },
+        Json::Object(ref fields) => {
+            let length = fields.len() as u64;
+            if length < 16 {
+                buffer.push(0x80 | length as u8);
+            } else {
+                write_msgpack_length(buffer, length, [0xde, 0xde, 0xdf]);
+            }
+            for (key, value) in fields {
+                write_msgpack(buffer, &Json::String(key.clone()));
+                write_msgpack(buffer, value);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "msgpack")]
+fn write_msgpack_uint(buffer: &mut Vec<u8>, n: u64) {
+    if n < 0x80 {
+        buffer.push(n as u8);
+    } else if n <= 0xff {
+        buffer.push(0xcc);
+        push_be(buffer, n, 1);
+    } else if n <= 0xffff {
+        buffer.push(0xcd);
+        push_be(buffer, n, 2);
+    } else if n <= 0xffff_ffff {
+        buffer.push(0xce);
+        push_be(buffer, n, 4);
+    } else {
+        buffer.push(0xcf);
+        push_be(buffer, n, 8);
+    }
+}
+
+//Writes a length with the marker for 8, 16 or 32 bits. Arrays and maps
+//don't have an 8 bit variant, so they use the 16 bit marker twice.
+#[cfg(feature = "msgpack")]
+fn write_msgpack_length(buffer: &mut Vec<u8>, length: u64, markers: [u8; 3]) {
+    if length <= 0xff && markers[0] != markers[1] {
+        buffer.push(markers[0]);
+        push_be(buffer, length, 1);
+    } else if length <= 0xffff {
+        buffer.push(markers[1]);
+        push_be(buffer, length, 2);
+    } else {
+        buffer.push(markers[2]);
+        push_be(buffer, length, 4);
+    }
+}
+
+///Serialize `value` as CBOR. It requires the `cbor` feature.
+#[cfg(feature = "cbor")]
+pub fn to_cbor(value: &Json) -> Vec<u8> {
+    let mut buffer = vec![];
+    write_cbor(&mut buffer, value);
+    buffer
+}
+
+#[cfg(feature = "cbor")]
+fn write_cbor(buffer: &mut Vec<u8>, value: &Json) {
+    match *value {
+        Json::Null => buffer.push(0xf6),
+        Json::Boolean(false) => buffer.push(0xf4),
+        Json::Boolean(true) => buffer.push(0xf5),
+        Json::U64(n) => write_cbor_head(buffer, 0, n),
+        Json::I64(n) if n >= 0 => write_cbor_head(buffer, 0, n as u64),
+        Json::I64(n) => write_cbor_head(buffer, 1, !n as u64),
+        Json::F64(n) => {
+            buffer.push(0xfb);
+            push_be(buffer, unsafe { transmute::<f64, u64>(n) }, 8);
+        },
+        Json::String(ref s) => {
+            write_cbor_head(buffer, 3, s.len() as u64);
+            buffer.extend_from_slice(s.as_bytes());
+        },
+        Json::Array(ref items) => {
+            write_cbor_head(buffer, 4, items.len() as u64);
+            for item in items {
+                write_cbor(buffer, item);
+            }
+        },
+        Json::Object(ref fields) => {
+            write_cbor_head(buffer, 5, fields.len() as u64);
+            for (key, value) in fields {
+                write_cbor_head(buffer, 3, key.len() as u64);
+                buffer.extend_from_slice(key.as_bytes());
+                write_cbor(buffer, value);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "cbor")]
+fn write_cbor_head(buffer: &mut Vec<u8>, major: u8, n: u64) {
+    let major = major << 5;
+    if n < 24 {
+        buffer.push(major | n as u8);
+    } else if n <= 0xff {
+        buffer.push(major | 24);
+        push_be(buffer, n, 1);
+    } else if n <= 0xffff {
+        buffer.push(major | 25);
+        push_be(buffer, n, 2);
+    } else if n <= 0xffff_ffff {
+        buffer.push(major | 26);
+        push_be(buffer, n, 4);
+    } else {
+        buffer.push(major | 27);
+        push_be(buffer, n, 8);
+    }
+}
+
+//Appends the lowest `bytes` bytes of `n`, in big endian order.
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+fn push_be(buffer: &mut Vec<u8>, n: u64, bytes: usize) {
+    for i in (0..bytes).rev() {
+        buffer.push((n >> (i * 8)) as u8);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rustc_serialize::json::Json;
+    use header::Headers;
+    use super::{Format, negotiate};
+
+    #[test]
+    fn negotiate_formats() {
+        let mut headers = Headers::new();
+        assert_eq!(negotiate(&headers), Some(Format::Json));
+
+        headers.set_raw("Accept", vec![b"text/html".to_vec()]);
+        assert_eq!(negotiate(&headers), None);
+
+        headers.set_raw("Accept", vec![b"text/html, */*;q=0.1".to_vec()]);
+        assert_eq!(negotiate(&headers), Some(Format::Json));
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn encode_msgpack() {
+        let value = Json::from_str(r#"{"a": [1, -1, -200, 300, true, null], "b": "c"}"#).unwrap();
+        assert_eq!(Format::MsgPack.encode(&value), vec![
+            0x82,
+            0xa1, b'a', 0x96, 0x01, 0xff, 0xd1, 0xff, 0x38, 0xcd, 0x01, 0x2c, 0xc3, 0xc0,
+            0xa1, b'b', 0xa1, b'c'
+        ]);
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn encode_cbor() {
+        let value = Json::from_str(r#"{"a": [1, -1, -200, 300, true, null], "b": "c"}"#).unwrap();
+        assert_eq!(Format::Cbor.encode(&value), vec![
+            0xa2,
+            0x61, b'a', 0x86, 0x01, 0x20, 0x38, 0xc7, 0x19, 0x01, 0x2c, 0xf5, 0xf6,
+            0x61, b'b', 0x61, b'c'
+        ]);
+    }
+}
@@ -0,0 +1,315 @@
+//!Adaptive concurrency limits.
+//!
+//!A fixed limit on the number of concurrent requests to an upstream service
+//!is either too low to use its capacity, or too high to protect it when it
+//!slows down. [`AdaptiveLimit`][limit] finds the limit as it goes, using
+//!additive increase and multiplicative decrease (AIMD) on the latency:
+//!
+//! * The limit grows by about one for each full round of requests, as long as
+//!the latency stays within `tolerance` times the lowest observed latency.
+//! * The limit is multiplied by `backoff` when the latency goes above that,
+//!or when a request is marked as dropped.
+//!
+//!Requests above the limit are rejected right away, so the excess load is
+//!shed locally instead of queuing up in the upstream service. The limit can
+//!be used directly around outbound calls, or through the
+//![`AdaptiveConcurrency`][handler] handler, which responds with `503 Service
+//!Unavailable` when the limit is reached.
+//!
+//!```
+//!#[macro_use]
+//!extern crate rustful;
+//!use rustful::{TreeRouter, Context, Response};
+//!use rustful::handler::concurrency::{AdaptiveLimit, AdaptiveConcurrency};
+//!
+//!fn search(context: Context, response: Response) {
+//!    //Ask the search service...
+//!    response.send("the results");
+//!}
+//!
+//!# fn main() {
+//!let limit = AdaptiveLimit::new(20).max_limit(200);
+//!
+//!let router = insert_routes! {
+//!    TreeRouter::new() => {
+//!        "search" => Get: AdaptiveConcurrency::new(search, limit.clone())
+//!    }
+//!};
+//!
+//!println!("the current limit is {}", limit.limit());
+//!# }
+//!```
+//!
+//![limit]: struct.AdaptiveLimit.html
+//![handler]: struct.AdaptiveConcurrency.html
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use time::{Duration, SteadyTime};
+
+use context::Context;
+use filter::GlobalContext;
+use handler::Handler;
+use response::Response;
+use StatusCode;
+
+///An adaptive limit on the number of concurrent requests. It's cheap to
+///clone, and the clones share the same limit.
+#[derive(Clone)]
+pub struct AdaptiveLimit {
+    state: Arc<Mutex<State>>,
+    rejected: Arc<AtomicUsize>
+}
+
+struct State {
+    limit: f64,
+    min_limit: f64,
+    max_limit: f64,
+    tolerance: f64,
+    backoff: f64,
+    in_flight: usize,
+    baseline: Option<Duration>
+}
+
+impl AdaptiveLimit {
+    ///Create a limit that starts at `initial` concurrent requests. The
+    ///limit stays between 1 and 1000 by default, and `tolerance` and
+    ///`backoff` are 2.0 and 0.9.
+    pub fn new(initial: usize) -> AdaptiveLimit {
+        AdaptiveLimit {
+            state: Arc::new(Mutex::new(State {
+                limit: initial as f64,
+                min_limit: 1.0,
+                max_limit: 1000.0,
+                tolerance: 2.0,
+                backoff: 0.9,
+                in_flight: 0,
+                baseline: None
+            })),
+            rejected: Arc::new(AtomicUsize::new(0))
+        }
+    }
+
+    ///Set the lowest possible limit.
+    pub fn min_limit(self, min_limit: usize) -> AdaptiveLimit {
+        self.with_state(|state| state.min_limit = min_limit as f64)
+    }
+
+    ///Set the highest possible limit.
+    pub fn max_limit(self, max_limit: usize) -> AdaptiveLimit {
+        self.with_state(|state| state.max_limit = max_limit as f64)
+    }
+
+    ///Set how many times slower than the lowest observed latency a request
+    ///can be before the limit is decreased.
+    pub fn tolerance(self, tolerance: f64) -> AdaptiveLimit {
+        self.with_state(|state| state.tolerance = tolerance)
+    }
+
+    ///Set the factor the limit is multiplied by when it's decreased.
+    pub fn backoff(self, backoff: f64) -> AdaptiveLimit {
+        self.with_state(|state| state.backoff = backoff)
+    }
+
+    ///Try to start a request. The returned permit records the latency of
+    ///the request when it's dropped. `None` is returned if the limit is
+    ///reached, and the request should then be rejected.
+    pub fn acquire(&self) -> Option<Permit> {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return None
+        };
+
+        if state.in_flight as f64 >= state.limit.floor() {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        state.in_flight += 1;
+        Some(Permit {
+            limit: self.clone(),
+            start: SteadyTime::now(),
+            dropped: false
+        })
+    }
+
+    ///Get the current limit.
+    pub fn limit(&self) -> usize {
+        self.state.lock().map(|state| state.limit as usize).unwrap_or(0)
+    }
+
+    ///Get the number of requests that are currently running.
+    pub fn in_flight(&self) -> usize {
+        self.state.lock().map(|state| state.in_flight).unwrap_or(0)
+    }
+
+    ///Get the number of requests that have been rejected.
+    pub fn rejected(&self) -> usize {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    fn with_state<F: FnOnce(&mut State)>(self, f: F) -> AdaptiveLimit {
+        if let Ok(mut state) = self.state.lock() {
+            f(&mut *state);
+            state.limit = clamp(state.limit, state.min_limit, state.max_limit);
+        }
+        self
+    }
+
+    fn record(&self, latency: Duration, dropped: bool) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return
+        };
+
+        let busy = state.in_flight as f64 >= state.limit.floor();
+        state.in_flight -= 1;
+
+        let baseline = match state.baseline {
+            Some(baseline) if baseline <= latency => {
+                //Let the baseline drift upwards, in case the upstream
+                //service has become permanently slower.
+                baseline + (latency - baseline) / 100
+            },
+            _ => latency
+        };
+        state.baseline = Some(baseline);
+
+        let too_slow = latency.num_microseconds().unwrap_or(i64::max_value()) as f64 >
+            baseline.num_microseconds().unwrap_or(i64::max_value()) as f64 * state.tolerance;
+
+        let limit = if dropped || too_slow {
+            state.limit * state.backoff
+        } else if busy {
+            //Probe for more capacity, but only if the limit is in use.
+            state.limit + 1.0 / state.limit
+        } else {
+            state.limit
+        };
+
+        state.limit = clamp(limit, state.min_limit, state.max_limit);
+    }
+}
+
+fn clamp(value: f64, min: f64, max: f64) -> f64 {
+    value.max(min).min(max)
+}
+
+///A running request, counted by an `AdaptiveLimit`. The request is
+///considered finished when the permit is dropped.
+pub struct Permit {
+    limit: AdaptiveLimit,
+    start: SteadyTime,
+    dropped: bool
+}
+
+impl Permit {
+    ///Mark the request as dropped by the upstream service, for example
+    ///because of a timeout or an overload response. This decreases the
+    ///limit, regardless of the latency.
+    pub fn dropped(&mut self) {
+        self.dropped = true;
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.limit.record(SteadyTime::now() - self.start, self.dropped);
+    }
+}
+
+///Limits the number of concurrent requests to a handler, using an
+///`AdaptiveLimit`. Requests above the limit get `503 Service Unavailable`.
+pub struct AdaptiveConcurrency<H> {
+    handler: H,
+    limit: AdaptiveLimit
+}
+
+impl<H: Handler> AdaptiveConcurrency<H> {
+    ///Limit the concurrent requests to `handler` with `limit`.
+    pub fn new(handler: H, limit: AdaptiveLimit) -> AdaptiveConcurrency<H> {
+        AdaptiveConcurrency {
+            handler: handler,
+            limit: limit
+        }
+    }
+
+    ///Get the limit.
+    pub fn limit(&self) -> &AdaptiveLimit {
+        &self.limit
+    }
+}
+
+impl<H: Handler> Handler for AdaptiveConcurrency<H> {
+    fn handle_request(&self, context: Context, mut response: Response) {
+        if let Some(_permit) = self.limit.acquire() {
+            self.handler.handle_request(context, response);
+        } else {
+            response.set_status(StatusCode::ServiceUnavailable);
+            response.headers_mut().set_raw("Retry-After", vec![b"1".to_vec()]);
+        }
+    }
+
+    fn setup(&self, context: &GlobalContext) {
+        self.handler.setup(context);
+    }
+
+    fn teardown(&self) {
+        self.handler.teardown();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use time::Duration;
+    use super::AdaptiveLimit;
+
+    #[test]
+    fn reject_above_limit() {
+        let limit = AdaptiveLimit::new(2);
+        let a = limit.acquire();
+        let b = limit.acquire();
+        assert!(a.is_some() && b.is_some());
+        assert!(limit.acquire().is_none());
+        assert_eq!(limit.rejected(), 1);
+
+        drop(a);
+        assert_eq!(limit.in_flight(), 1);
+        assert!(limit.acquire().is_some());
+    }
+
+    #[test]
+    fn increase_when_busy() {
+        let limit = AdaptiveLimit::new(2);
+        for _ in 0..10 {
+            limit.state.lock().unwrap().in_flight = 2;
+            limit.record(Duration::milliseconds(10), false);
+        }
+        assert!(limit.limit() > 2);
+
+        let limit = AdaptiveLimit::new(2);
+        for _ in 0..10 {
+            limit.state.lock().unwrap().in_flight = 1;
+            limit.record(Duration::milliseconds(10), false);
+        }
+        assert_eq!(limit.limit(), 2);
+    }
+
+    #[test]
+    fn decrease_when_slow() {
+        let limit = AdaptiveLimit::new(10).min_limit(5);
+        limit.state.lock().unwrap().in_flight = 1;
+        limit.record(Duration::milliseconds(10), false);
+
+        limit.state.lock().unwrap().in_flight = 1;
+        limit.record(Duration::milliseconds(50), false);
+        assert_eq!(limit.limit(), 9);
+
+        for _ in 0..20 {
+            limit.state.lock().unwrap().in_flight = 1;
+            limit.record(Duration::milliseconds(10), true);
+        }
+        assert_eq!(limit.limit(), 5);
+    }
+}
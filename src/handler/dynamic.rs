@@ -0,0 +1,427 @@
+//!Handlers loaded from dynamic libraries. *Experimental.*
+//!
+//![`DynamicHandler`][dynamic_handler] loads a handler from a shared library,
+//!and replaces it when the file changes, without restarting the server.
+//!Requests that are already running keep using the old library until they
+//!are done. The library may be written in any language that can export a C
+//!function with this signature:
+//!
+//!```c
+//!int rustful_handler_v1(const RawRequest *request, const RawResponse *response);
+//!```
+//!
+//!The request and response types are described by
+//![`RawRequest`][raw_request] and [`RawResponse`][raw_response]. The whole
+//!request body is read before the function is called, and the status,
+//!headers and body that are given to the response callbacks are sent when
+//!it returns. A return value other than 0 results in `500 Internal Server
+//!Error`. None of the pointers are valid after the function has returned.
+//!
+//!```no_run
+//!extern crate rustful;
+//!extern crate time;
+//!use rustful::Server;
+//!use rustful::handler::dynamic::DynamicHandler;
+//!
+//!# fn main() {
+//!let handler = DynamicHandler::open("libendpoint.so").unwrap().reload_interval(time::Duration::seconds(2));
+//!
+//!let server = Server {
+//!    host: 8080.into(),
+//!    ..Server::new(handler)
+//!};
+//!# }
+//!```
+//!
+//!The library is copied to a new, private, directory in the temporary
+//!directory before it's loaded, since most dynamic loaders would otherwise
+//!return the library that is already loaded from the same path. The
+//!directory is only accessible by the current user, so the copy can't be
+//!replaced by someone else before it's loaded. The library should be replaced
+//!atomically, by moving the new file into place, to avoid loading a half
+//!written file.
+//!
+//!This module is only available on Unix platforms, with the
+//!`dynamic_handlers` feature.
+//!
+//![dynamic_handler]: struct.DynamicHandler.html
+//![raw_request]: struct.RawRequest.html
+//![raw_response]: struct.RawResponse.html
+
+use std::env;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::io::{self, Read};
+use std::os::raw::{c_char, c_int, c_void};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::sync::{Arc, RwLock};
+
+use time::Duration;
+
+use context::Context;
+use handler::{Handler, WatchedFile, read_body};
+use response::Response;
+use StatusCode;
+use utils::to_hex;
+
+///The name of the function that is called in the library.
+pub const SYMBOL: &'static str = "rustful_handler_v1";
+
+const RTLD_NOW: c_int = 2;
+
+#[cfg_attr(target_os = "linux", link(name = "dl"))]
+extern "C" {
+    fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn dlclose(handle: *mut c_void) -> c_int;
+    fn dlerror() -> *mut c_char;
+}
+
+///A borrowed sequence of bytes. It's not null terminated.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RawSlice {
+    ///The first byte.
+    pub data: *const u8,
+    ///The number of bytes.
+    pub len: usize
+}
+
+impl RawSlice {
+    fn new(bytes: &[u8]) -> RawSlice {
+        RawSlice {
+            data: bytes.as_ptr(),
+            len: bytes.len()
+        }
+    }
+
+    unsafe fn as_bytes<'a>(&self) -> &'a [u8] {
+        if self.data.is_null() || self.len == 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(self.data, self.len)
+        }
+    }
+}
+
+///A request header.
+#[repr(C)]
+pub struct RawHeader {
+    ///The header name.
+    pub name: RawSlice,
+    ///The header value.
+    pub value: RawSlice
+}
+
+///The request, as it's passed to the library.
+#[repr(C)]
+pub struct RawRequest {
+    ///The request method.
+    pub method: RawSlice,
+    ///The requested path, without the query string.
+    pub path: RawSlice,
+    ///The raw query string.
+    pub query: RawSlice,
+    ///The request headers.
+    pub headers: *const RawHeader,
+    ///The number of request headers.
+    pub header_count: usize,
+    ///The whole request body.
+    pub body: RawSlice
+}
+
+///The response callbacks. Each of them takes `context` as their first
+///argument.
+#[repr(C)]
+pub struct RawResponse {
+    ///The response state.
+    pub context: *mut c_void,
+    ///Set the status code.
+    pub set_status: extern "C" fn(context: *mut c_void, status: u16),
+    ///Set a header, replacing any previous value.
+    pub set_header: extern "C" fn(context: *mut c_void, name: RawSlice, value: RawSlice),
+    ///Append bytes to the response body.
+    pub write: extern "C" fn(context: *mut c_void, data: RawSlice)
+}
+
+type HandlerFn = unsafe extern "C" fn(*const RawRequest, *const RawResponse) -> c_int;
+
+//A loaded library, which is closed when the last request is done with it.
+struct Library {
+    handle: *mut c_void,
+    handler: HandlerFn
+}
+
+//The library handle is only used to close it, and the handler function is
+//required to be thread safe.
+unsafe impl Send for Library {}
+unsafe impl Sync for Library {}
+
+impl Library {
+    fn load(path: &Path) -> io::Result<Library> {
+        let directory = try!(private_temp_dir());
+        let copy = directory.join(path.file_name().unwrap_or("handler".as_ref()));
+        let library = fs::copy(path, &copy).and_then(|_| unsafe { Library::open(&copy) });
+
+        //The loaded library stays mapped, so the copy isn't needed anymore.
+        let _ = fs::remove_file(&copy);
+        let _ = fs::remove_dir(&directory);
+        library
+    }
+
+    unsafe fn open(path: &Path) -> io::Result<Library> {
+        let filename = try!(CString::new(path.as_os_str().as_bytes()).map_err(|_| invalid_path()));
+        let symbol = CString::new(SYMBOL).unwrap();
+
+        let handle = dlopen(filename.as_ptr(), RTLD_NOW);
+        if handle.is_null() {
+            return Err(last_error("could not load the library"));
+        }
+
+        let handler = dlsym(handle, symbol.as_ptr());
+        if handler.is_null() {
+            let error = last_error("could not find the handler function");
+            dlclose(handle);
+            return Err(error);
+        }
+
+        Ok(Library {
+            handle: handle,
+            handler: ::std::mem::transmute::<*mut c_void, HandlerFn>(handler)
+        })
+    }
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        unsafe {
+            dlclose(self.handle);
+        }
+    }
+}
+
+//Creates a new directory with a random name, that only the current user
+//can access. Creating it fails if it already exists, so no one else can
+//have put anything in it.
+fn private_temp_dir() -> io::Result<PathBuf> {
+    let mut attempts = 0;
+    loop {
+        let mut random = [0; 16];
+        try!(fs::File::open("/dev/urandom").and_then(|mut file| file.read_exact(&mut random)));
+        let directory = env::temp_dir().join(format!("rustful-{}", to_hex(&random)));
+
+        match fs::DirBuilder::new().mode(0o700).create(&directory) {
+            Ok(()) => return Ok(directory),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists && attempts < 10 => attempts += 1,
+            Err(e) => return Err(e)
+        }
+    }
+}
+
+fn last_error(fallback: &str) -> io::Error {
+    let message = unsafe {
+        let error = dlerror();
+        if error.is_null() {
+            fallback.to_owned()
+        } else {
+            CStr::from_ptr(error).to_string_lossy().into_owned()
+        }
+    };
+
+    io::Error::new(io::ErrorKind::Other, message)
+}
+
+fn invalid_path() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "the library path contains a null byte")
+}
+
+///A handler that is loaded from a dynamic library.
+pub struct DynamicHandler {
+    library: RwLock<Arc<Library>>,
+    file: WatchedFile
+}
+
+impl DynamicHandler {
+    ///Load the handler from the library at `path`.
+    pub fn open<P: Into<PathBuf>>(path: P) -> io::Result<DynamicHandler> {
+        let file = WatchedFile::new(path.into());
+        let library = try!(Library::load(&file.path));
+
+        Ok(DynamicHandler {
+            library: RwLock::new(Arc::new(library)),
+            file: file
+        })
+    }
+
+    ///Check if the library has changed, at most once per `interval`, and
+    ///reload it if it has. The current library is kept if the new one can't
+    ///be loaded.
+    pub fn reload_interval(mut self, interval: Duration) -> DynamicHandler {
+        self.file.interval = Some(interval);
+        self
+    }
+
+    ///Load the library again. The previous library is closed when the
+    ///requests that are using it are done.
+    pub fn reload(&self) -> io::Result<()> {
+        let library = try!(self.file.reload(|path| Library::load(path)));
+
+        if let Ok(mut current) = self.library.write() {
+            *current = Arc::new(library);
+        }
+
+        Ok(())
+    }
+
+    fn library(&self) -> Option<Arc<Library>> {
+        self.library.read().map(|library| library.clone()).ok()
+    }
+}
+
+impl Handler for DynamicHandler {
+    fn handle_request(&self, mut context: Context, mut response: Response) {
+        if self.file.is_outdated() {
+            if let Err(e) = self.reload() {
+                context.log.error(&format!("could not reload {}: {}", self.file.path.display(), e));
+            }
+        }
+
+        let library = match self.library() {
+            Some(library) => library,
+            None => {
+                response.set_status(StatusCode::InternalServerError);
+                return;
+            }
+        };
+
+        let body = match read_body(&mut context, &mut response) {
+            Some(body) => body,
+            None => return
+        };
+
+        let method = context.method.to_string();
+        let path = context.uri.as_utf8_path().unwrap_or("").to_owned();
+        let headers: Vec<(String, String)> = context.headers.iter().map(|header| {
+            (header.name().to_owned(), header.value_string())
+        }).collect();
+        let raw_headers: Vec<RawHeader> = headers.iter().map(|&(ref name, ref value)| RawHeader {
+            name: RawSlice::new(name.as_bytes()),
+            value: RawSlice::new(value.as_bytes())
+        }).collect();
+
+        let request = RawRequest {
+            method: RawSlice::new(method.as_bytes()),
+            path: RawSlice::new(path.as_bytes()),
+            query: RawSlice::new(context.query.raw()),
+            headers: raw_headers.as_ptr(),
+            header_count: raw_headers.len(),
+            body: RawSlice::new(&body)
+        };
+
+        let mut collected = Collected::default();
+        let result = unsafe {
+            let raw_response = collected.as_raw();
+            (library.handler)(&request, &raw_response)
+        };
+
+        if result != 0 {
+            context.log.error(&format!("{} returned {}", self.file.path.display(), result));
+            response.set_status(StatusCode::InternalServerError);
+            return;
+        }
+
+        if let Some(status) = collected.status {
+            response.set_status(StatusCode::from_u16(status));
+        }
+
+        for (name, value) in collected.headers {
+            response.headers_mut().set_raw(name, vec![value]);
+        }
+
+        response.send(collected.body);
+    }
+}
+
+//The response, as it's built by the library.
+#[derive(Default)]
+struct Collected {
+    status: Option<u16>,
+    headers: Vec<(String, Vec<u8>)>,
+    body: Vec<u8>
+}
+
+impl Collected {
+    fn as_raw(&mut self) -> RawResponse {
+        RawResponse {
+            context: self as *mut Collected as *mut c_void,
+            set_status: collect_status,
+            set_header: collect_header,
+            write: collect_body
+        }
+    }
+}
+
+extern "C" fn collect_status(context: *mut c_void, status: u16) {
+    let collected = unsafe { &mut *(context as *mut Collected) };
+    collected.status = Some(status);
+}
+
+extern "C" fn collect_header(context: *mut c_void, name: RawSlice, value: RawSlice) {
+    let collected = unsafe { &mut *(context as *mut Collected) };
+    let (name, value) = unsafe { (name.as_bytes(), value.as_bytes()) };
+    let name = String::from_utf8_lossy(name).into_owned();
+    let lowercase = name.to_lowercase();
+
+    collected.headers.retain(|header| header.0.to_lowercase() != lowercase);
+    collected.headers.push((name, value.to_owned()));
+}
+
+extern "C" fn collect_body(context: *mut c_void, data: RawSlice) {
+    let collected = unsafe { &mut *(context as *mut Collected) };
+    collected.body.extend_from_slice(unsafe { data.as_bytes() });
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use super::{Collected, RawSlice, DynamicHandler, private_temp_dir};
+
+    #[test]
+    fn collect_response() {
+        let mut collected = Collected::default();
+        {
+            let raw = collected.as_raw();
+            (raw.set_status)(raw.context, 201);
+            (raw.set_header)(raw.context, RawSlice::new(b"X-A"), RawSlice::new(b"1"));
+            (raw.set_header)(raw.context, RawSlice::new(b"x-a"), RawSlice::new(b"2"));
+            (raw.write)(raw.context, RawSlice::new(b"hello "));
+            (raw.write)(raw.context, RawSlice::new(b"world"));
+        }
+
+        assert_eq!(collected.status, Some(201));
+        assert_eq!(collected.headers, vec![("x-a".to_owned(), b"2".to_vec())]);
+        assert_eq!(collected.body, b"hello world".to_vec());
+    }
+
+    #[test]
+    fn private_directories() {
+        let first = private_temp_dir().unwrap();
+        let second = private_temp_dir().unwrap();
+        assert!(first != second);
+
+        let mode = fs::metadata(&first).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        fs::remove_dir(first).unwrap();
+        fs::remove_dir(second).unwrap();
+    }
+
+    #[test]
+    fn missing_library() {
+        assert!(DynamicHandler::open("/no/such/library.so").is_err());
+    }
+}
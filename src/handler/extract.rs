@@ -0,0 +1,315 @@
+//!Typed handler input, extracted from the context.
+//!
+//!A function that takes any number of [`FromContext`][from_context] types,
+//!followed by a `Response`, can be turned into a handler using
+//![`extract`][extract]. Each argument is extracted from the `Context` before
+//!the function is called, and the first failed extraction is answered with
+//!its error, which is `400 Bad Request` by default.
+//!
+//!```
+//!#[macro_use]
+//!extern crate rustful;
+//!extern crate rustc_serialize;
+//!use std::sync::Arc;
+//!use rustful::{TreeRouter, Response};
+//!use rustful::handler::extract::{extract, Json, PathVar, State};
+//!
+//!#[derive(RustcDecodable)]
+//!struct NewThing {
+//!    name: String
+//!}
+//!
+//!struct Config {
+//!    greeting: String
+//!}
+//!
+//!fn add_thing(Json(thing): Json<NewThing>, PathVar(list): PathVar<String>, config: State<Arc<Config>>, response: Response) {
+//!    response.send(format!("{} Added {} to {}.", config.0.greeting, thing.name, list));
+//!}
+//!
+//!# fn main() {
+//!let router = insert_routes! {
+//!    TreeRouter::new() => {
+//!        "lists/:list" => Post: extract(add_thing)
+//!    }
+//!};
+//!# }
+//!```
+//!
+//![from_context]: trait.FromContext.html
+//![extract]: fn.extract.html
+
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+#[cfg(feature = "rustc_json_body")]
+use rustc_serialize::Decodable;
+
+use context::{Context, Parameters};
+use context::body::ExtQueryBody;
+#[cfg(feature = "rustc_json_body")]
+use context::body::ExtJsonBody;
+use handler::Handler;
+use response::Response;
+use StatusCode;
+
+///A type that can be extracted from the handler context.
+pub trait FromContext: Sized {
+    ///Try to extract the value from `context`.
+    fn from_context(context: &mut Context) -> Result<Self, ExtractError>;
+}
+
+///The error that is produced when a value could not be extracted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtractError {
+    ///The response status. Default is `400 Bad Request`.
+    pub status: StatusCode,
+
+    ///A description of what went wrong. It's sent as the response body.
+    pub message: String
+}
+
+impl ExtractError {
+    ///Create a `400 Bad Request` error.
+    pub fn bad_request<M: Into<String>>(message: M) -> ExtractError {
+        ExtractError {
+            status: StatusCode::BadRequest,
+            message: message.into()
+        }
+    }
+
+    ///Create an `500 Internal Server Error` error, for when something is
+    ///wrong with the server, rather than with the request.
+    pub fn internal<M: Into<String>>(message: M) -> ExtractError {
+        ExtractError {
+            status: StatusCode::InternalServerError,
+            message: message.into()
+        }
+    }
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.status, self.message)
+    }
+}
+
+impl Error for ExtractError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+///Functions that can be used with `extract`.
+///
+///It's implemented for functions that take up to eight `FromContext`
+///arguments, followed by a `Response`.
+pub trait ExtractFn<Args>: Send + Sync + 'static {
+    ///Extract the arguments from `context` and call the function.
+    fn call(&self, context: Context, response: Response);
+}
+
+macro_rules! extract_fn {
+    ($first: ident, $($t: ident),+) => (
+        impl<F, $first: FromContext, $($t: FromContext),+> ExtractFn<($first, $($t),+)> for F where
+            F: Fn($first, $($t),+ , Response) + Send + Sync + 'static
+        {
+            #[allow(non_snake_case)]
+            fn call(&self, mut context: Context, response: Response) {
+                let $first = try_extract!(context, response, $first);
+                $(
+                    let $t = try_extract!(context, response, $t);
+                )+
+
+                self($first, $($t),+ , response);
+            }
+        }
+
+        extract_fn!($($t),+);
+    );
+    ($ty: ident) => (
+        impl<F, $ty: FromContext> ExtractFn<($ty,)> for F where
+            F: Fn($ty, Response) + Send + Sync + 'static
+        {
+            #[allow(non_snake_case)]
+            fn call(&self, mut context: Context, response: Response) {
+                let $ty = try_extract!(context, response, $ty);
+                self($ty, response);
+            }
+        }
+    );
+}
+
+macro_rules! try_extract {
+    ($context: ident, $response: ident, $ty: ident) => (
+        match <$ty as FromContext>::from_context(&mut $context) {
+            Ok(value) => value,
+            Err(e) => {
+                respond_with_error(&$context, $response, e);
+                return;
+            }
+        }
+    );
+}
+
+extract_fn!(T0, T1, T2, T3, T4, T5, T6, T7);
+
+fn respond_with_error(context: &Context, mut response: Response, error: ExtractError) {
+    context.log.note(&format!("could not extract handler input: {}", error));
+    response.set_status(error.status);
+    response.send(error.message);
+}
+
+///A handler that extracts the arguments of a function from the context.
+///It's created using `extract`.
+pub struct Extract<F, Args> {
+    function: F,
+    args: PhantomData<fn() -> Args>
+}
+
+///Turn a function with `FromContext` arguments into a handler.
+pub fn extract<Args, F: ExtractFn<Args>>(function: F) -> Extract<F, Args> {
+    Extract {
+        function: function,
+        args: PhantomData
+    }
+}
+
+impl<Args: 'static, F: ExtractFn<Args>> Handler for Extract<F, Args> {
+    fn handle_request(&self, context: Context, response: Response) {
+        ExtractFn::call(&self.function, context, response);
+    }
+}
+
+///The route variables.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Variables(pub Parameters);
+
+impl FromContext for Variables {
+    fn from_context(context: &mut Context) -> Result<Variables, ExtractError> {
+        Ok(Variables(context.variables.to_parameters()))
+    }
+}
+
+///The only route variable, parsed as `T`. A value that can't be parsed is
+///answered with `400 Bad Request`, and a route without exactly one variable
+///is a server error. Use `Variables` for routes with more variables.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathVar<T>(pub T);
+
+impl<T: FromStr> FromContext for PathVar<T> {
+    fn from_context(context: &mut Context) -> Result<PathVar<T>, ExtractError> {
+        let mut variables = context.variables.iter();
+        match (variables.next(), variables.next()) {
+            (Some((name, value)), None) => value.as_utf8()
+                .and_then(|value| value.parse().ok())
+                .map(PathVar)
+                .ok_or_else(|| ExtractError::bad_request(format!("invalid value for {}", name.as_utf8_lossy()))),
+            _ => Err(ExtractError::internal(format!("expected one route variable, but found {}", context.variables.len())))
+        }
+    }
+}
+
+///The query parameters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Query(pub Parameters);
+
+impl FromContext for Query {
+    fn from_context(context: &mut Context) -> Result<Query, ExtractError> {
+        Ok(Query(context.query.clone().into()))
+    }
+}
+
+///A request body, parsed as a query string. See
+///`ExtQueryBody::read_query_body`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Form(pub Parameters);
+
+impl FromContext for Form {
+    fn from_context(context: &mut Context) -> Result<Form, ExtractError> {
+        context.body.read_query_body()
+            .map(Form)
+            .map_err(|e| ExtractError::bad_request(format!("could not read the request body: {}", e)))
+    }
+}
+
+///A clone of a value of type `T` from the global storage. Use `Arc<T>` to
+///share it without copying.
+#[derive(Clone, Debug, PartialEq)]
+pub struct State<T>(pub T);
+
+impl<T: Clone + Any + Send + Sync> FromContext for State<T> {
+    fn from_context(context: &mut Context) -> Result<State<T>, ExtractError> {
+        context.global.get::<T>()
+            .map(|value| State(value.clone()))
+            .ok_or_else(|| ExtractError::internal("the requested state is missing from the global storage"))
+    }
+}
+
+///A JSON request body, decoded as `T`. See
+///`ExtJsonBody::decode_json_body`.
+///
+///It's only available with the `rustc_json_body` feature.
+#[cfg(feature = "rustc_json_body")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Json<T>(pub T);
+
+#[cfg(feature = "rustc_json_body")]
+impl<T: Decodable> FromContext for Json<T> {
+    fn from_context(context: &mut Context) -> Result<Json<T>, ExtractError> {
+        context.body.decode_json_body()
+            .map(Json)
+            .map_err(|e| ExtractError::bad_request(format!("invalid JSON body: {}", e)))
+    }
+}
+
+impl<T: FromContext> FromContext for Option<T> {
+    fn from_context(context: &mut Context) -> Result<Option<T>, ExtractError> {
+        Ok(T::from_context(context).ok())
+    }
+}
+
+impl<T: FromContext> FromContext for Result<T, ExtractError> {
+    fn from_context(context: &mut Context) -> Result<Result<T, ExtractError>, ExtractError> {
+        Ok(T::from_context(context))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {Server, TreeRouter, Response};
+    use server::Dispatcher;
+    use super::{extract, PathVar};
+
+    fn show_id(PathVar(id): PathVar<u32>, response: Response) {
+        response.send(format!("id {}", id));
+    }
+
+    #[test]
+    fn parse_path_variable() {
+        let router = insert_routes! {
+            TreeRouter::new() => {
+                "users/:id" => Get: extract(show_id),
+                "users/:id/:tab" => Get: extract(show_id)
+            }
+        };
+        let (instance, _scheme) = Server::new(router).build();
+        let address = "127.0.0.1:8080".parse().unwrap();
+
+        let output = instance.dispatch(b"GET /users/12 HTTP/1.1\r\nHost: localhost\r\n\r\n", address);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("HTTP/1.1 200"), "{}", output);
+        assert!(output.contains("id 12"), "{}", output);
+
+        let output = instance.dispatch(b"GET /users/twelve HTTP/1.1\r\nHost: localhost\r\n\r\n", address);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("HTTP/1.1 400"), "{}", output);
+
+        let output = instance.dispatch(b"GET /users/12/posts HTTP/1.1\r\nHost: localhost\r\n\r\n", address);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("HTTP/1.1 500"), "{}", output);
+    }
+}
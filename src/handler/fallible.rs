@@ -0,0 +1,192 @@
+//!Handlers that return errors.
+//!
+//!A function that takes a `Context` and a `&mut Response`, and returns a
+//!`Result`, can be turned into a handler using [`fallible`][fallible]. The
+//!`Ok` value is sent as the response body, and the `Err` value is rendered
+//!by the [`ErrorRenderer`][error_renderer] of the server, which is set in
+//!its `error_renderer` field. The same renderer is used by
+//!`Response::send_error`.
+//!
+//!```
+//!#[macro_use]
+//!extern crate rustful;
+//!use std::error::Error;
+//!use std::fmt;
+//!use rustful::{Server, TreeRouter, Context, Response, StatusCode};
+//!use rustful::handler::fallible::fallible;
+//!
+//!#[derive(Debug)]
+//!struct NoSuchThing;
+//!
+//!impl fmt::Display for NoSuchThing {
+//!    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+//!        f.write_str("no such thing")
+//!    }
+//!}
+//!
+//!impl Error for NoSuchThing {
+//!    fn description(&self) -> &str {
+//!        "no such thing"
+//!    }
+//!}
+//!
+//!fn get_thing(context: Context, response: &mut Response) -> Result<String, NoSuchThing> {
+//!    match context.variables.get("id") {
+//!        Some(ref id) if id == "1" => Ok("the first thing".into()),
+//!        _ => Err(NoSuchThing)
+//!    }
+//!}
+//!
+//!fn render_error(error: &(Error + 'static), response: &mut Response) -> String {
+//!    if error.downcast_ref::<NoSuchThing>().is_some() {
+//!        response.set_status(StatusCode::NotFound);
+//!    } else {
+//!        response.set_status(StatusCode::InternalServerError);
+//!    }
+//!    error.to_string()
+//!}
+//!
+//!# fn main() {
+//!let server = Server {
+//!    handlers: insert_routes! {
+//!        TreeRouter::new() => {
+//!            "things/:id" => Get: fallible(get_thing)
+//!        }
+//!    },
+//!    error_renderer: Box::new(render_error),
+//!    ..Server::default()
+//!};
+//!# }
+//!```
+//!
+//![fallible]: fn.fallible.html
+//![error_renderer]: trait.ErrorRenderer.html
+
+use std::error::Error;
+use std::marker::PhantomData;
+
+use context::Context;
+use handler::Handler;
+use response::{Response, Data};
+use StatusCode;
+
+///Renders handler errors as responses.
+pub trait ErrorRenderer: Send + Sync + 'static {
+    ///Set the status and headers of `response` for `error`, and return the
+    ///response body. `Error::downcast_ref` can be used to find the type of
+    ///the error.
+    fn render(&self, error: &(Error + 'static), response: &mut Response) -> String;
+}
+
+impl<F> ErrorRenderer for F where F: Fn(&(Error + 'static), &mut Response) -> String + Send + Sync + 'static {
+    fn render(&self, error: &(Error + 'static), response: &mut Response) -> String {
+        self(error, response)
+    }
+}
+
+///The default error renderer. It responds with `500 Internal Server Error`
+///and an empty body, to not reveal anything about the error. The empty body
+///is replaced with the server's error page for `500`, if it has one.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultErrorRenderer;
+
+impl ErrorRenderer for DefaultErrorRenderer {
+    fn render(&self, _error: &(Error + 'static), response: &mut Response) -> String {
+        response.set_status(StatusCode::InternalServerError);
+        String::new()
+    }
+}
+
+///A handler that sends the result of a function. See `fallible`.
+pub struct Fallible<F, T, E> {
+    function: F,
+    result: PhantomData<fn() -> Result<T, E>>
+}
+
+///Turn a function that returns a `Result` into a handler. The `Ok` value is
+///sent as the body, and the `Err` value is sent using
+///`Response::send_error`.
+pub fn fallible<F, T, E>(function: F) -> Fallible<F, T, E> where
+    F: Fn(Context, &mut Response) -> Result<T, E> + Send + Sync + 'static,
+    T: Into<Data<'static>> + 'static,
+    E: Error + 'static
+{
+    Fallible {
+        function: function,
+        result: PhantomData
+    }
+}
+
+impl<F, T, E> Handler for Fallible<F, T, E> where
+    F: Fn(Context, &mut Response) -> Result<T, E> + Send + Sync + 'static,
+    T: Into<Data<'static>> + 'static,
+    E: Error + 'static
+{
+    fn handle_request(&self, context: Context, mut response: Response) {
+        match (self.function)(context, &mut response) {
+            Ok(body) => response.send(body),
+            Err(error) => response.send_error(&error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::error::Error;
+    use std::fmt;
+    use {Server, Context, Response, StatusCode};
+    use server::Dispatcher;
+    use super::fallible;
+
+    #[derive(Debug)]
+    struct NoSuchThing;
+
+    impl fmt::Display for NoSuchThing {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("no such thing")
+        }
+    }
+
+    impl Error for NoSuchThing {
+        fn description(&self) -> &str {
+            "no such thing"
+        }
+    }
+
+    fn get_thing(context: Context, _response: &mut Response) -> Result<String, NoSuchThing> {
+        match context.uri.as_utf8_path() {
+            Some("/1") => Ok("the first thing".into()),
+            _ => Err(NoSuchThing)
+        }
+    }
+
+    fn render_error(error: &(Error + 'static), response: &mut Response) -> String {
+        if error.downcast_ref::<NoSuchThing>().is_some() {
+            response.set_status(StatusCode::NotFound);
+        }
+        error.to_string()
+    }
+
+    #[test]
+    fn render_errors() {
+        let address = "127.0.0.1:8080".parse().unwrap();
+
+        let (instance, _scheme) = Server::new(fallible(get_thing)).build();
+        let output = String::from_utf8(instance.dispatch(b"GET /1 HTTP/1.1\r\nHost: localhost\r\n\r\n", address)).unwrap();
+        assert!(output.starts_with("HTTP/1.1 200"), "{}", output);
+        assert!(output.ends_with("the first thing"), "{}", output);
+
+        let output = String::from_utf8(instance.dispatch(b"GET /2 HTTP/1.1\r\nHost: localhost\r\n\r\n", address)).unwrap();
+        assert!(output.starts_with("HTTP/1.1 500"), "{}", output);
+        assert!(!output.contains("no such thing"), "{}", output);
+
+        let server = Server {
+            error_renderer: Box::new(render_error),
+            ..Server::new(fallible(get_thing))
+        };
+        let (instance, _scheme) = server.build();
+        let output = String::from_utf8(instance.dispatch(b"GET /2 HTTP/1.1\r\nHost: localhost\r\n\r\n", address)).unwrap();
+        assert!(output.starts_with("HTTP/1.1 404"), "{}", output);
+        assert!(output.ends_with("no such thing"), "{}", output);
+    }
+}
@@ -0,0 +1,212 @@
+//!Mock APIs from configuration files.
+//!
+//!A mock API is a set of routes with canned responses, which are defined in
+//!a JSON file instead of in code. It's useful as a stand-in for a real
+//!service during development and testing. Each route has a path, which may
+//!contain variables, an optional method, which is `GET` by default, and the
+//!response:
+//!
+//!```json
+//![
+//!    {
+//!        "method": "GET",
+//!        "path": "users/:id",
+//!        "status": 200,
+//!        "headers": { "Content-Type": "application/json" },
+//!        "body": "{\"id\": \"{{id}}\", \"name\": \"{{name}}\"}",
+//!        "latency": 250
+//!    },
+//!    { "method": "DELETE", "path": "users/:id", "status": 204 }
+//!]
+//!```
+//!
+//!The body and the header values are templates, where `{{name}}` is
+//!replaced with the route variable or query parameter `name`, in that order.
+//!Unknown names are replaced with nothing, and control characters, such as
+//!line breaks, are percent encoded in the values that are inserted into
+//!headers. The optional `latency` is a delay in milliseconds before the
+//!response is sent.
+//!
+//!```no_run
+//!use rustful::Server;
+//!use rustful::handler::mock;
+//!
+//!let server = Server {
+//!    host: 8080.into(),
+//!    ..Server::new(mock::load("mock.json").unwrap())
+//!};
+//!```
+//!
+//!This module is only available with the `rustc_json_body` feature.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::thread;
+
+use rustc_serialize::json;
+use time::Duration;
+
+use context::Context;
+use handler::Handler;
+use response::Response;
+use router::{Router, TreeRouter};
+use utils::fill_template;
+use {Method, StatusCode};
+
+///A canned response.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockResponse {
+    ///The response status.
+    pub status: StatusCode,
+
+    ///The response headers. The values are templates.
+    pub headers: Vec<(String, String)>,
+
+    ///The response body template.
+    pub body: String,
+
+    ///A delay before the response is sent.
+    pub latency: Option<Duration>
+}
+
+impl MockResponse {
+    ///Create an empty `200 OK` response.
+    pub fn new() -> MockResponse {
+        MockResponse {
+            status: StatusCode::Ok,
+            headers: vec![],
+            body: String::new(),
+            latency: None
+        }
+    }
+
+    //Fills in the header templates. Control characters are encoded in the
+    //values, so they can't end the header line.
+    fn render_headers<F: Fn(&str) -> Option<String>>(&self, lookup: &F) -> Vec<(String, Vec<u8>)> {
+        let header_lookup = |name: &str| lookup(name).map(|value| encode_controls(&value));
+
+        self.headers.iter().map(|&(ref name, ref value)| {
+            (name.clone(), fill_template(value, &header_lookup).into_bytes())
+        }).collect()
+    }
+}
+
+impl Default for MockResponse {
+    fn default() -> MockResponse {
+        MockResponse::new()
+    }
+}
+
+impl Handler for MockResponse {
+    fn handle_request(&self, context: Context, mut response: Response) {
+        if let Some(latency) = self.latency {
+            if let Ok(latency) = latency.to_std() {
+                thread::sleep(latency);
+            }
+        }
+
+        let lookup = |name: &str| {
+            context.variables.get(name)
+                .or_else(|| context.query.get(name))
+                .map(|value| value.into_owned())
+        };
+
+        response.set_status(self.status);
+        for (name, value) in self.render_headers(&lookup) {
+            response.headers_mut().set_raw(name, vec![value]);
+        }
+        response.send(fill_template(&self.body, &lookup));
+    }
+}
+
+fn encode_controls(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\x00'...'\x1f' | '\x7f' => encoded.push_str(&format!("%{:02X}", c as u8)),
+            c => encoded.push(c)
+        }
+    }
+    encoded
+}
+
+///Parse mock routes from JSON, and build a router for them.
+pub fn from_json(source: &str) -> io::Result<TreeRouter<MockResponse>> {
+    let entries: Vec<Entry> = try!(json::decode(source).map_err(|e| invalid(e.to_string())));
+    let mut router = TreeRouter::new();
+
+    for (index, entry) in entries.into_iter().enumerate() {
+        let method = match entry.method {
+            Some(method) => try!(method.to_uppercase().parse().map_err(|_| invalid(format!("invalid method in route {}", index + 1)))),
+            None => Method::Get
+        };
+
+        let status = match entry.status {
+            Some(status @ 100...599) => StatusCode::from_u16(status),
+            Some(_) => return Err(invalid(format!("invalid status in route {}", index + 1))),
+            None => StatusCode::Ok
+        };
+
+        let response = MockResponse {
+            status: status,
+            headers: entry.headers.map(|headers| headers.into_iter().collect()).unwrap_or_else(Vec::new),
+            body: entry.body.unwrap_or_else(String::new),
+            latency: entry.latency.map(|latency| Duration::milliseconds(latency as i64))
+        };
+
+        router.insert(method, &entry.path, response);
+    }
+
+    Ok(router)
+}
+
+///Load mock routes from a JSON file. See `from_json`.
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<TreeRouter<MockResponse>> {
+    let mut source = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut source));
+    from_json(&source)
+}
+
+#[derive(RustcDecodable)]
+struct Entry {
+    method: Option<String>,
+    path: String,
+    status: Option<u16>,
+    headers: Option<BTreeMap<String, String>>,
+    body: Option<String>,
+    latency: Option<u64>
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{from_json, MockResponse};
+
+    #[test]
+    fn parse_routes() {
+        assert!(from_json(r#"[{ "path": "a", "status": 204 }, { "method": "post", "path": "a/:b" }]"#).is_ok());
+        assert!(from_json(r#"[{ "path": "a", "status": 1000 }]"#).is_err());
+        assert!(from_json(r#"[{ "status": 200 }]"#).is_err());
+    }
+
+    #[test]
+    fn encode_header_values() {
+        let mut response = MockResponse::new();
+        response.headers.push(("Location".to_owned(), "/users/{{id}}?tab={{tab}}".to_owned()));
+
+        let lookup = |name: &str| match name {
+            "id" => Some("1\r\nSet-Cookie: a=b".to_owned()),
+            "tab" => Some("\tinfo\x7f".to_owned()),
+            _ => None
+        };
+
+        assert_eq!(response.render_headers(&lookup), vec![
+            ("Location".to_owned(), b"/users/1%0D%0ASet-Cookie: a=b?tab=%09info%7F".to_vec())
+        ]);
+    }
+}
@@ -0,0 +1,803 @@
+//!Request handlers.
+
+#[cfg(feature = "rustc_json_body")]
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+#[cfg(feature = "rustc_json_body")]
+use rustc_serialize::json;
+
+use time::{Tm, Duration, SteadyTime};
+
+use context::Context;
+use context::body::TooLarge;
+use filter::GlobalContext;
+use response::Response;
+use header::UserAgent;
+use headers::LinkValue;
+use utils;
+use StatusCode;
+#[cfg(feature = "rustc_json_body")]
+use header::Headers;
+
+pub mod concurrency;
+#[cfg(all(unix, feature = "dynamic_handlers"))]
+pub mod dynamic;
+pub mod extract;
+pub mod fallible;
+#[cfg(feature = "rustc_json_body")]
+pub mod mock;
+pub mod redirect;
+pub mod scope;
+pub mod script;
+pub mod shortlink;
+
+///A trait for request handlers.
+pub trait Handler: Send + Sync + 'static {
+    ///Handle a request from the client. Panicking within this method is
+    ///discouraged, to allow the server to run smoothly.
+    fn handle_request(&self, context: Context, response: Response);
+
+    ///Set up the handler when the server is built. Handlers that wrap other
+    ///handlers should pass this on, so scoped filters are set up.
+    fn setup(&self, _context: &GlobalContext) {}
+
+    ///Tear down the handler when the server instance is dropped.
+    fn teardown(&self) {}
+}
+
+impl<F: Fn(Context, Response) + Send + Sync + 'static> Handler for F {
+    fn handle_request(&self, context: Context, response: Response) {
+        self(context, response);
+    }
+}
+
+///A handler wrapper that marks an endpoint as deprecated.
+///
+///The `Deprecation` header is added to each response, together with the
+///`Sunset` header and `Link` headers to the successor and the documentation,
+///if they are set. Each request is also logged as a warning, together with
+///the client address and user agent, to make it easier to track down the
+///remaining users of the endpoint.
+///
+///```
+///#[macro_use]
+///extern crate rustful;
+///extern crate time;
+///use rustful::{TreeRouter, Context, Response};
+///use rustful::handler::Deprecated;
+///
+///fn old_list(context: Context, response: Response) {
+///    response.send("a list of things");
+///}
+///
+///# fn main() {
+///let sunset = time::strptime("2016-06-01", "%Y-%m-%d").unwrap();
+///
+///let router = insert_routes! {
+///    TreeRouter::new() => {
+///        "v1/things" => Get: Deprecated::new(old_list).sunset(sunset).successor("/v2/things")
+///    }
+///};
+///# }
+///```
+pub struct Deprecated<H> {
+    handler: H,
+    since: Option<Tm>,
+    sunset: Option<Tm>,
+    successor: Option<String>,
+    documentation: Option<String>
+}
+
+impl<H: Handler> Deprecated<H> {
+    ///Mark `handler` as deprecated.
+    pub fn new(handler: H) -> Deprecated<H> {
+        Deprecated {
+            handler: handler,
+            since: None,
+            sunset: None,
+            successor: None,
+            documentation: None
+        }
+    }
+
+    ///Set the point in time when the endpoint was, or will be, deprecated.
+    ///It's otherwise considered to already be deprecated.
+    pub fn since(mut self, date: Tm) -> Deprecated<H> {
+        self.since = Some(date);
+        self
+    }
+
+    ///Set the point in time when the endpoint will stop responding.
+    pub fn sunset(mut self, date: Tm) -> Deprecated<H> {
+        self.sunset = Some(date);
+        self
+    }
+
+    ///Set a link to the endpoint that replaces this one.
+    pub fn successor<S: Into<String>>(mut self, uri: S) -> Deprecated<H> {
+        self.successor = Some(uri.into());
+        self
+    }
+
+    ///Set a link to documentation about the deprecation.
+    pub fn documentation<S: Into<String>>(mut self, uri: S) -> Deprecated<H> {
+        self.documentation = Some(uri.into());
+        self
+    }
+}
+
+impl<H: Handler> Handler for Deprecated<H> {
+    fn handle_request(&self, context: Context, mut response: Response) {
+        let deprecation = match self.since {
+            Some(ref since) => format!("@{}", since.to_timespec().sec),
+            None => "true".to_owned()
+        };
+        response.headers_mut().set_raw("Deprecation", vec![deprecation.into_bytes()]);
+
+        if let Some(ref sunset) = self.sunset {
+            let sunset = sunset.to_utc().rfc822().to_string();
+            response.headers_mut().set_raw("Sunset", vec![sunset.into_bytes()]);
+        }
+
+        if let Some(ref successor) = self.successor {
+            response.add_link(LinkValue::new(successor.clone()).with_rel("successor-version"));
+        }
+
+        if let Some(ref documentation) = self.documentation {
+            response.add_link(LinkValue::new(documentation.clone()).with_rel("deprecation"));
+        }
+
+        {
+            let user_agent = context.headers.get::<UserAgent>().map(|ua| &*ua.0).unwrap_or("unknown");
+            context.log.warning(&format!(
+                "deprecated endpoint {} {} used by {} ({})",
+                context.method,
+                context.uri,
+                context.client_ip(),
+                user_agent
+            ));
+        }
+
+        self.handler.handle_request(context, response);
+    }
+
+    fn setup(&self, context: &GlobalContext) {
+        self.handler.setup(context);
+    }
+
+    fn teardown(&self) {
+        self.handler.teardown();
+    }
+}
+
+///A handler wrapper that sends a share of the traffic to a canary handler.
+///
+///Each request is assigned to a variant by hashing a client key, which is
+///the client IP address by default, so a client will keep hitting the same
+///variant as long as the percentage stays the same. The number of requests
+///that each variant has received can be followed through
+///[`CanaryMetrics`][metrics].
+///
+///```
+///#[macro_use]
+///extern crate rustful;
+///use rustful::{TreeRouter, Context, Response};
+///use rustful::handler::Canary;
+///
+///fn list(context: Context, response: Response) {
+///    response.send("a list of things");
+///}
+///
+///fn new_list(context: Context, response: Response) {
+///    response.send("a faster list of things");
+///}
+///
+///# fn main() {
+///let canary = Canary::new(list, new_list, 10).key(|context: &Context| {
+///    context.headers.get_raw("X-User-Id")
+///        .and_then(|values| values.first())
+///        .map(|value| String::from_utf8_lossy(value).into_owned())
+///        .unwrap_or_else(|| context.client_ip().to_string())
+///});
+///let metrics = canary.metrics();
+///
+///let router = insert_routes! {
+///    TreeRouter::new() => {
+///        "things" => Get: canary
+///    }
+///};
+///
+///println!("the canary has received {} requests", metrics.canary());
+///# }
+///```
+///
+///[metrics]: struct.CanaryMetrics.html
+pub struct Canary<S, C> {
+    stable: S,
+    canary: C,
+    percent: u8,
+    key: Box<Fn(&Context) -> String + Send + Sync>,
+    metrics: CanaryMetrics
+}
+
+impl<S: Handler, C: Handler> Canary<S, C> {
+    ///Send `percent` percent of the requests to `canary` and the rest to
+    ///`stable`. The percentage is capped at 100.
+    pub fn new(stable: S, canary: C, percent: u8) -> Canary<S, C> {
+        Canary {
+            stable: stable,
+            canary: canary,
+            percent: if percent > 100 { 100 } else { percent },
+            key: Box::new(client_ip_key),
+            metrics: CanaryMetrics {
+                counters: Arc::new((AtomicUsize::new(0), AtomicUsize::new(0)))
+            }
+        }
+    }
+
+    ///Set the function that produces the client key, such as a user ID
+    ///from a header.
+    pub fn key<F: Fn(&Context) -> String + Send + Sync + 'static>(mut self, key: F) -> Canary<S, C> {
+        self.key = Box::new(key);
+        self
+    }
+
+    ///Get a handle to the request counters.
+    pub fn metrics(&self) -> CanaryMetrics {
+        self.metrics.clone()
+    }
+
+    ///Check if requests with the client key `key` goes to the canary.
+    pub fn is_canary(&self, key: &str) -> bool {
+        (utils::fnv1a(key.as_bytes()) % 100) < self.percent as u32
+    }
+}
+
+impl<S: Handler, C: Handler> Handler for Canary<S, C> {
+    fn handle_request(&self, context: Context, response: Response) {
+        let key = (self.key)(&context);
+
+        if self.is_canary(&key) {
+            self.metrics.counters.1.fetch_add(1, Ordering::Relaxed);
+            self.canary.handle_request(context, response);
+        } else {
+            self.metrics.counters.0.fetch_add(1, Ordering::Relaxed);
+            self.stable.handle_request(context, response);
+        }
+    }
+
+    fn setup(&self, context: &GlobalContext) {
+        self.stable.setup(context);
+        self.canary.setup(context);
+    }
+
+    fn teardown(&self) {
+        self.canary.teardown();
+        self.stable.teardown();
+    }
+}
+
+fn client_ip_key(context: &Context) -> String {
+    context.client_ip().to_string()
+}
+
+///Request counters for a `Canary` handler.
+#[derive(Clone)]
+pub struct CanaryMetrics {
+    counters: Arc<(AtomicUsize, AtomicUsize)>
+}
+
+impl CanaryMetrics {
+    ///The number of requests that went to the stable handler.
+    pub fn stable(&self) -> usize {
+        self.counters.0.load(Ordering::Relaxed)
+    }
+
+    ///The number of requests that went to the canary handler.
+    pub fn canary(&self) -> usize {
+        self.counters.1.load(Ordering::Relaxed)
+    }
+}
+
+///A handler for batches of requests, sent as a single JSON array.
+///
+///The request body is expected to be an array of [`SubRequest`][sub_request]
+///objects, and the response body will be an array of
+///[`SubResponse`][sub_response] objects in the same order. The sub-requests
+///are sent through the server's filters, middleware and handlers, one at the
+///time, just like any other request from the same client. They inherit the
+///headers of the batch request, except for the ones that describe its body,
+///and their own headers replace any inherited headers with the same names.
+///
+///The batch is rejected with `400 Bad Request` if it's not a valid JSON
+///array of requests, or if it's a sub-request itself, and `413 Payload Too
+///Large` if it contains more than `max_requests` requests. A sub-request
+///with an invalid method, path or header gets a `400` sub-response.
+///
+///It's only available with the `rustc_json_body` feature.
+///
+///```
+///#[macro_use]
+///extern crate rustful;
+///use rustful::{TreeRouter, Context, Response};
+///use rustful::handler::Batch;
+///
+///fn hello(_context: Context, response: Response) {
+///    response.send("Hello!");
+///}
+///
+///# fn main() {
+///let router = insert_routes! {
+///    TreeRouter::new() => {
+///        "hello" => Get: hello as fn(Context, Response),
+///        "batch" => Post: Batch::new().max_requests(20)
+///    }
+///};
+///# }
+///```
+///
+///[sub_request]: struct.SubRequest.html
+///[sub_response]: struct.SubResponse.html
+#[cfg(feature = "rustc_json_body")]
+pub struct Batch {
+    max_requests: usize
+}
+
+#[cfg(feature = "rustc_json_body")]
+impl Batch {
+    ///Create a batch handler. The default maximum batch size is 100.
+    pub fn new() -> Batch {
+        Batch {
+            max_requests: 100
+        }
+    }
+
+    ///Set the maximum number of sub-requests in a batch.
+    pub fn max_requests(mut self, max_requests: usize) -> Batch {
+        self.max_requests = max_requests;
+        self
+    }
+}
+
+#[cfg(feature = "rustc_json_body")]
+impl Default for Batch {
+    fn default() -> Batch {
+        Batch::new()
+    }
+}
+
+#[cfg(feature = "rustc_json_body")]
+impl Handler for Batch {
+    fn handle_request(&self, mut context: Context, mut response: Response) {
+        use context::body::ExtJsonBody;
+        use header::ContentType;
+        use mime::{Mime, TopLevel, SubLevel, Attr, Value};
+
+        //Sub-requests can't dispatch other requests.
+        let dispatcher = match context.dispatcher {
+            Some(dispatcher) => dispatcher,
+            None => {
+                response.set_status(StatusCode::BadRequest);
+                response.send("batches can't be nested");
+                return;
+            }
+        };
+
+        let requests: Vec<SubRequest> = match context.body.decode_json_body() {
+            Ok(requests) => requests,
+            Err(e) => {
+                context.log.note(&format!("invalid batch request: {}", e));
+                response.set_status(StatusCode::BadRequest);
+                return;
+            }
+        };
+
+        if requests.len() > self.max_requests {
+            response.set_status(StatusCode::PayloadTooLarge);
+            return;
+        }
+
+        let responses: Vec<SubResponse> = requests.iter().map(|request| {
+            match encode_sub_request(request, &context.headers) {
+                Some(raw) => decode_sub_response(&dispatcher.dispatch(&raw, context.address)),
+                None => SubResponse::new(400, "")
+            }
+        }).collect();
+
+        match json::encode(&responses) {
+            Ok(body) => {
+                response.headers_mut().set(ContentType(Mime(
+                    TopLevel::Application,
+                    SubLevel::Json,
+                    vec![(Attr::Charset, Value::Utf8)]
+                )));
+                response.send(body);
+            },
+            Err(e) => {
+                context.log.error(&format!("could not encode batch response: {}", e));
+                response.set_status(StatusCode::InternalServerError);
+            }
+        }
+    }
+}
+
+//Headers that describe the body of the batch request, and are not
+//inherited by the sub-requests.
+#[cfg(feature = "rustc_json_body")]
+const BATCH_BODY_HEADERS: &'static [&'static str] = &["content-length", "transfer-encoding", "content-type", "content-encoding", "expect"];
+
+//Writes `request` as a raw HTTP request, with the headers from the batch
+//request. Returns `None` if the method, path or any header is invalid.
+#[cfg(feature = "rustc_json_body")]
+fn encode_sub_request(request: &SubRequest, batch_headers: &Headers) -> Option<Vec<u8>> {
+    if !utils::is_token(&request.method) || !request.path.starts_with('/') || request.path.bytes().any(|b| b <= b' ' || b == 0x7f) {
+        return None;
+    }
+
+    let mut headers = BTreeMap::new();
+    for header in batch_headers.iter() {
+        let name = header.name().to_lowercase();
+        if !BATCH_BODY_HEADERS.contains(&&*name) {
+            if let Some(lines) = batch_headers.get_raw(header.name()) {
+                headers.insert(name, (header.name().to_owned(), lines.to_vec()));
+            }
+        }
+    }
+
+    if let Some(ref own_headers) = request.headers {
+        for (name, value) in own_headers {
+            let lower_name = name.to_lowercase();
+            if !utils::is_token(name) || lower_name == "content-length" || lower_name == "transfer-encoding" {
+                return None;
+            }
+            if value.bytes().any(|b| b == b'\r' || b == b'\n') {
+                return None;
+            }
+            headers.insert(lower_name, (name.clone(), vec![value.clone().into_bytes()]));
+        }
+    }
+
+    let body = request.body.as_ref().map(|body| body.as_bytes()).unwrap_or(&[]);
+    let mut raw = format!("{} {} HTTP/1.1\r\n", request.method, request.path).into_bytes();
+    for &(ref name, ref lines) in headers.values() {
+        for line in lines {
+            raw.extend_from_slice(name.as_bytes());
+            raw.extend_from_slice(b": ");
+            raw.extend_from_slice(line);
+            raw.extend_from_slice(b"\r\n");
+        }
+    }
+    raw.extend_from_slice(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes());
+    raw.extend_from_slice(body);
+    Some(raw)
+}
+
+//Reads a raw HTTP response. Repeated headers are joined with commas.
+#[cfg(feature = "rustc_json_body")]
+fn decode_sub_response(raw: &[u8]) -> SubResponse {
+    use std::io::{Cursor, Read};
+    use hyper::http::h1::HttpReader;
+
+    let (head, body) = match raw.windows(4).position(|window| window == b"\r\n\r\n") {
+        Some(index) => (String::from_utf8_lossy(&raw[..index]), &raw[index + 4..]),
+        None => return SubResponse::new(500, "")
+    };
+
+    let mut lines = head.split("\r\n");
+    let status = lines.next().and_then(|line| line.split(' ').nth(1)).and_then(|status| status.parse().ok()).unwrap_or(500);
+    let mut response = SubResponse::new(status, "");
+    let mut chunked = false;
+
+    for line in lines {
+        let mut parts = line.splitn(2, ':');
+        if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+            let value = value.trim();
+            match &*name.to_lowercase() {
+                "transfer-encoding" => chunked = value.to_lowercase().contains("chunked"),
+                "content-length" => {},
+                _ => {
+                    let joined = response.headers.get(name).map(|existing| format!("{}, {}", existing, value));
+                    response.headers.insert(name.to_owned(), joined.unwrap_or_else(|| value.to_owned()));
+                }
+            }
+        }
+    }
+
+    let body = if chunked {
+        let mut decoded = vec![];
+        if HttpReader::ChunkedReader(Cursor::new(body), None).read_to_end(&mut decoded).is_err() {
+            return SubResponse::new(500, "");
+        }
+        decoded
+    } else {
+        body.to_vec()
+    };
+
+    response.body = String::from_utf8_lossy(&body).into_owned();
+    response
+}
+
+///A request in a batch.
+///
+///It's only available with the `rustc_json_body` feature.
+#[cfg(feature = "rustc_json_body")]
+#[derive(Clone, Debug, PartialEq, RustcDecodable, RustcEncodable)]
+pub struct SubRequest {
+    ///The HTTP method, such as `"GET"`.
+    pub method: String,
+
+    ///The requested path, including any query string.
+    pub path: String,
+
+    ///Request headers, if any.
+    pub headers: Option<BTreeMap<String, String>>,
+
+    ///The request body, if any.
+    pub body: Option<String>
+}
+
+///A response to a request in a batch.
+///
+///It's only available with the `rustc_json_body` feature.
+#[cfg(feature = "rustc_json_body")]
+#[derive(Clone, Debug, PartialEq, RustcDecodable, RustcEncodable)]
+pub struct SubResponse {
+    ///The HTTP status code.
+    pub status: u16,
+
+    ///Response headers.
+    pub headers: BTreeMap<String, String>,
+
+    ///The response body.
+    pub body: String
+}
+
+#[cfg(feature = "rustc_json_body")]
+impl SubResponse {
+    ///Create a response without headers.
+    pub fn new<B: Into<String>>(status: u16, body: B) -> SubResponse {
+        SubResponse {
+            status: status,
+            headers: BTreeMap::new(),
+            body: body.into()
+        }
+    }
+}
+
+//The largest request body that is read by `read_body`, unless the body has
+//a limit of its own.
+const MAX_BODY_SIZE: u64 = 1024 * 1024;
+
+//Reads the whole request body, for handlers that pass it on as a single
+//buffer. The status is set, and `None` is returned, if it's too large or
+//can't be read.
+fn read_body(context: &mut Context, response: &mut Response) -> Option<Vec<u8>> {
+    let mut body = vec![];
+    let max = context.body.limit().unwrap_or(MAX_BODY_SIZE);
+    match context.body.read_to_end_limited(&mut body, max) {
+        Ok(_) => Some(body),
+        Err(ref e) if TooLarge::is_cause_of(e) => {
+            response.set_status(StatusCode::PayloadTooLarge);
+            None
+        },
+        Err(_) => {
+            response.set_status(StatusCode::BadRequest);
+            None
+        }
+    }
+}
+
+//Keeps track of when a file was loaded, to reload it when it changes.
+struct WatchedFile {
+    path: PathBuf,
+    interval: Option<Duration>,
+    state: Mutex<(SteadyTime, Option<SystemTime>)>
+}
+
+impl WatchedFile {
+    //Starts watching `path`. It should be created before the file is
+    //loaded, so changes in the meantime are not missed.
+    fn new(path: PathBuf) -> WatchedFile {
+        let modified = modified(&path);
+        WatchedFile {
+            path: path,
+            interval: None,
+            state: Mutex::new((SteadyTime::now(), modified))
+        }
+    }
+
+    //Reloads the file with `load`, and remembers when it was changed.
+    fn reload<T, F: FnOnce(&Path) -> ::std::io::Result<T>>(&self, load: F) -> ::std::io::Result<T> {
+        let modified = modified(&self.path);
+        let loaded = try!(load(&self.path));
+
+        if let Ok(mut state) = self.state.lock() {
+            state.1 = modified;
+        }
+
+        Ok(loaded)
+    }
+
+    //Checks if the file should be reloaded, at most once per interval, and
+    //resets the timer.
+    fn is_outdated(&self) -> bool {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return false
+        };
+
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return false
+        };
+
+        let now = SteadyTime::now();
+        if now - state.0 < interval {
+            return false;
+        }
+
+        state.0 = now;
+        modified(&self.path) != state.1
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "rustc_json_body")]
+    use std::collections::BTreeMap;
+    use time;
+    use {Server, Context, Response};
+    #[cfg(feature = "rustc_json_body")]
+    use header::Headers;
+    use server::Dispatcher;
+    use super::{Deprecated, Canary};
+    #[cfg(feature = "rustc_json_body")]
+    use super::{SubRequest, encode_sub_request, decode_sub_response};
+
+    #[test]
+    fn mark_deprecated() {
+        fn handler(_context: Context, response: Response) {
+            response.send("old");
+        }
+
+        let sunset = time::strptime("2016-06-01", "%Y-%m-%d").unwrap();
+        let deprecated = Deprecated::new(handler as fn(Context, Response))
+            .since(time::at_utc(time::Timespec::new(1000, 0)))
+            .sunset(sunset)
+            .successor("/v2/things")
+            .documentation("/docs/v1");
+        let (instance, _scheme) = Server::new(deprecated).build();
+        let address = "127.0.0.1:8080".parse().unwrap();
+
+        let output = instance.dispatch(b"GET /v1/things HTTP/1.1\r\nHost: localhost\r\n\r\n", address);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("\r\nDeprecation: @1000\r\n"), "{}", output);
+        assert!(output.contains("\r\nSunset: Wed, 01 Jun 2016 00:00:00 GMT\r\n"), "{}", output);
+        assert!(output.contains("</v2/things>; rel=successor-version"), "{}", output);
+        assert!(output.contains("</docs/v1>; rel=deprecation"), "{}", output);
+        assert!(output.ends_with("old"), "{}", output);
+    }
+
+    #[test]
+    fn split_canary_traffic() {
+        fn stable(_context: Context, response: Response) {
+            response.send("stable");
+        }
+
+        fn canary(_context: Context, response: Response) {
+            response.send("canary");
+        }
+
+        let half = Canary::new(stable as fn(Context, Response), canary as fn(Context, Response), 50);
+        let canaries = (0..1000).filter(|user| half.is_canary(&user.to_string())).count();
+        assert!(canaries > 400 && canaries < 600, "{} of 1000 users got the canary", canaries);
+
+        assert!(!Canary::new(stable as fn(Context, Response), canary as fn(Context, Response), 0).is_canary("a"));
+        assert!(Canary::new(stable as fn(Context, Response), canary as fn(Context, Response), 200).is_canary("a"));
+
+        let user = (0..).map(|user: u32| user.to_string()).find(|user| half.is_canary(user)).unwrap();
+        let half = half.key(|context: &Context| {
+            context.headers.get_raw("X-User-Id")
+                .and_then(|values| values.first())
+                .map(|value| String::from_utf8_lossy(value).into_owned())
+                .unwrap_or_default()
+        });
+        let other = (0..).map(|user: u32| user.to_string()).find(|user| !half.is_canary(user)).unwrap();
+        let metrics = half.metrics();
+
+        let (instance, _scheme) = Server::new(half).build();
+        let address = "127.0.0.1:8080".parse().unwrap();
+        let request = |user: &str| {
+            let request = format!("GET / HTTP/1.1\r\nHost: localhost\r\nX-User-Id: {}\r\n\r\n", user);
+            String::from_utf8(instance.dispatch(request.as_bytes(), address)).unwrap()
+        };
+
+        for _ in 0..3 {
+            assert!(request(&user).ends_with("canary"));
+            assert!(request(&other).ends_with("stable"));
+        }
+
+        assert_eq!(metrics.canary(), 3);
+        assert_eq!(metrics.stable(), 3);
+    }
+
+    #[cfg(feature = "rustc_json_body")]
+    fn sub_request(method: &str, path: &str) -> SubRequest {
+        SubRequest {
+            method: method.into(),
+            path: path.into(),
+            headers: None,
+            body: None
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "rustc_json_body")]
+    fn encode_sub_requests() {
+        let mut batch_headers = Headers::new();
+        batch_headers.set_raw("Authorization", vec![b"Bearer abc".to_vec()]);
+        batch_headers.set_raw("Accept", vec![b"text/plain".to_vec()]);
+        batch_headers.set_raw("Content-Type", vec![b"application/json".to_vec()]);
+        batch_headers.set_raw("Content-Length", vec![b"100".to_vec()]);
+
+        let mut request = sub_request("POST", "/a?b=c");
+        let mut headers = BTreeMap::new();
+        headers.insert("accept".to_owned(), "application/json".to_owned());
+        request.headers = Some(headers);
+        request.body = Some("hello".into());
+
+        let raw = String::from_utf8(encode_sub_request(&request, &batch_headers).unwrap()).unwrap();
+        assert!(raw.starts_with("POST /a?b=c HTTP/1.1\r\n"));
+        assert!(raw.contains("\r\nAuthorization: Bearer abc\r\n"));
+        assert!(raw.contains("\r\naccept: application/json\r\n"));
+        assert!(!raw.contains("text/plain"));
+        assert!(!raw.contains("Content-Type"));
+        assert!(raw.ends_with("\r\nContent-Length: 5\r\n\r\nhello"));
+    }
+
+    #[test]
+    #[cfg(feature = "rustc_json_body")]
+    fn reject_invalid_sub_requests() {
+        let headers = Headers::new();
+        assert!(encode_sub_request(&sub_request("GET", "/a b"), &headers).is_none());
+        assert!(encode_sub_request(&sub_request("GET", "/a\r\nX: y"), &headers).is_none());
+        assert!(encode_sub_request(&sub_request("G ET", "/a"), &headers).is_none());
+        assert!(encode_sub_request(&sub_request("GET", "a"), &headers).is_none());
+
+        let mut request = sub_request("GET", "/a");
+        let mut sub_headers = BTreeMap::new();
+        sub_headers.insert("X-Value".to_owned(), "a\r\nX-Other: b".to_owned());
+        request.headers = Some(sub_headers);
+        assert!(encode_sub_request(&request, &headers).is_none());
+
+        let mut sub_headers = BTreeMap::new();
+        sub_headers.insert("Transfer-Encoding".to_owned(), "chunked".to_owned());
+        request.headers = Some(sub_headers);
+        assert!(encode_sub_request(&request, &headers).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "rustc_json_body")]
+    fn decode_sub_responses() {
+        let sized = decode_sub_response(b"HTTP/1.1 404 Not Found\r\nContent-Length: 4\r\nX-A: 1\r\nX-A: 2\r\n\r\nnope");
+        assert_eq!(sized.status, 404);
+        assert_eq!(sized.body, "nope");
+        assert_eq!(sized.headers.get("X-A").map(|v| &**v), Some("1, 2"));
+        assert!(sized.headers.get("Content-Length").is_none());
+
+        let chunked = decode_sub_response(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n");
+        assert_eq!(chunked.status, 200);
+        assert_eq!(chunked.body, "abcde");
+
+        assert_eq!(decode_sub_response(b"garbage").status, 500);
+    }
+}
@@ -0,0 +1,411 @@
+//!Redirects from a map of old paths to new URLs.
+//!
+//!A [`Redirects`][redirects] handler serves a large set of redirects from a
+//![`RedirectMap`][redirect_map], which can be loaded from a CSV or JSON file
+//!and reloaded when the file changes. It can be used as the only handler
+//!for an old domain, or as a fallback handler when a site is moved, instead
+//!of registering each old path as a route.
+//!
+//!Each CSV line contains the old path, the new URL and, optionally, the
+//!status code, which is `301 Moved Permanently` by default. A path that ends
+//!with `/*` redirects everything below it, and the rest of the path is
+//!percent encoded and appended to the new URL. Empty lines and lines that
+//!start with `#` are ignored.
+//!
+//!```text
+//!#from,to,status
+//!/about.html,/about
+//!/news/*,https://news.example.com/,302
+//!```
+//!
+//!The JSON format is an array of objects with the same fields:
+//!
+//!```json
+//![
+//!    { "from": "/about.html", "to": "/about" },
+//!    { "from": "/news/*", "to": "https://news.example.com/", "status": 302 }
+//!]
+//!```
+//!
+//!```no_run
+//!extern crate rustful;
+//!extern crate time;
+//!use rustful::Server;
+//!use rustful::handler::redirect::Redirects;
+//!
+//!# fn main() {
+//!let redirects = Redirects::open("redirects.csv").unwrap().reload_interval(time::Duration::seconds(10));
+//!
+//!let server = Server {
+//!    host: 8080.into(),
+//!    ..Server::new(redirects)
+//!};
+//!# }
+//!```
+//!
+//![redirects]: struct.Redirects.html
+//![redirect_map]: struct.RedirectMap.html
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+#[cfg(feature = "rustc_json_body")]
+use rustc_serialize::json;
+use time::Duration;
+
+use context::Context;
+use handler::{Handler, WatchedFile};
+use response::Response;
+use StatusCode;
+use utils::encode_path_segment;
+
+///A single redirect.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Redirect {
+    ///The new URL.
+    pub to: String,
+
+    ///The response status.
+    pub status: StatusCode
+}
+
+///A set of redirects from old paths.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RedirectMap {
+    exact: HashMap<String, Redirect>,
+    prefixes: HashMap<String, Redirect>
+}
+
+impl RedirectMap {
+    ///Create an empty map.
+    pub fn new() -> RedirectMap {
+        RedirectMap {
+            exact: HashMap::new(),
+            prefixes: HashMap::new()
+        }
+    }
+
+    ///Parse a map in the CSV format.
+    pub fn from_csv(source: &str) -> io::Result<RedirectMap> {
+        let mut map = RedirectMap::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.split(',').map(|field| field.trim());
+            let (from, to) = match (fields.next(), fields.next()) {
+                (Some(from), Some(to)) if !from.is_empty() && !to.is_empty() => (from, to),
+                _ => return Err(invalid(format!("expected a path and a URL on line {}", index + 1)))
+            };
+
+            if has_line_break(to) {
+                return Err(invalid(format!("the URL on line {} contains a line break", index + 1)));
+            }
+
+            let status = match fields.next() {
+                Some(status) => match status.parse() {
+                    Ok(status) => try!(redirect_status(status)),
+                    Err(_) => return Err(invalid(format!("invalid status on line {}", index + 1)))
+                },
+                None => StatusCode::MovedPermanently
+            };
+
+            map.insert(from, to, status);
+        }
+
+        Ok(map)
+    }
+
+    ///Parse a map in the JSON format.
+    ///
+    ///It's only available with the `rustc_json_body` feature.
+    #[cfg(feature = "rustc_json_body")]
+    pub fn from_json(source: &str) -> io::Result<RedirectMap> {
+        let entries: Vec<Entry> = try!(json::decode(source).map_err(|e| invalid(e.to_string())));
+        let mut map = RedirectMap::new();
+
+        for entry in entries {
+            if has_line_break(&entry.to) {
+                return Err(invalid(format!("the URL for {} contains a line break", entry.from)));
+            }
+
+            let status = match entry.status {
+                Some(status) => try!(redirect_status(status)),
+                None => StatusCode::MovedPermanently
+            };
+            map.insert(&entry.from, entry.to, status);
+        }
+
+        Ok(map)
+    }
+
+    ///Load a map from a file. It's parsed as JSON if the file name ends with
+    ///`.json`, and as CSV otherwise.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<RedirectMap> {
+        let path = path.as_ref();
+        let mut source = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut source));
+
+        if path.extension().map(|extension| extension == "json").unwrap_or(false) {
+            RedirectMap::parse_json(&source)
+        } else {
+            RedirectMap::from_csv(&source)
+        }
+    }
+
+    #[cfg(feature = "rustc_json_body")]
+    fn parse_json(source: &str) -> io::Result<RedirectMap> {
+        RedirectMap::from_json(source)
+    }
+
+    #[cfg(not(feature = "rustc_json_body"))]
+    fn parse_json(_source: &str) -> io::Result<RedirectMap> {
+        Err(invalid("JSON redirect maps require the rustc_json_body feature".to_owned()))
+    }
+
+    ///Add a redirect from `from` to `to`. Everything below `from` is
+    ///redirected if it ends with `/*`.
+    pub fn insert<T: Into<String>>(&mut self, from: &str, to: T, status: StatusCode) {
+        let redirect = Redirect {
+            to: to.into(),
+            status: status
+        };
+
+        if from.ends_with("/*") {
+            self.prefixes.insert(trim_path(&from[..from.len() - 2]).to_owned(), redirect);
+        } else {
+            self.exact.insert(trim_path(from).to_owned(), redirect);
+        }
+    }
+
+    ///Find the redirect for `path`, and return the new URL and the status.
+    ///Exact matches are preferred, followed by the longest matching prefix.
+    ///The part of `path` that is appended to a prefix redirect is percent
+    ///encoded, so `path` should be decoded.
+    pub fn find(&self, path: &str) -> Option<(String, StatusCode)> {
+        let path = trim_path(path);
+
+        if let Some(redirect) = self.exact.get(path) {
+            return Some((redirect.to.clone(), redirect.status));
+        }
+
+        //Try each parent of the path, starting with the longest.
+        let mut end = path.len();
+        loop {
+            if let Some(redirect) = self.prefixes.get(&path[..end]) {
+                let rest = path[end..].trim_left_matches('/');
+                let mut to = redirect.to.clone();
+                if !rest.is_empty() {
+                    if !to.ends_with('/') {
+                        to.push('/');
+                    }
+                    let segments: Vec<_> = rest.split('/').map(encode_path_segment).collect();
+                    to.push_str(&segments.join("/"));
+                }
+                return Some((to, redirect.status));
+            }
+
+            match path[..end].rfind('/') {
+                Some(index) => end = index,
+                None => return None
+            }
+        }
+    }
+
+    ///Get the number of redirects.
+    pub fn len(&self) -> usize {
+        self.exact.len() + self.prefixes.len()
+    }
+
+    ///Check if there are no redirects.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(feature = "rustc_json_body")]
+#[derive(RustcDecodable)]
+struct Entry {
+    from: String,
+    to: String,
+    status: Option<u16>
+}
+
+fn redirect_status(status: u16) -> io::Result<StatusCode> {
+    match status {
+        300...399 => Ok(StatusCode::from_u16(status)),
+        _ => Err(invalid(format!("{} is not a redirect status", status)))
+    }
+}
+
+fn has_line_break(url: &str) -> bool {
+    url.contains('\r') || url.contains('\n')
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+//Removes the trailing slash, except for the root.
+fn trim_path(path: &str) -> &str {
+    if path.len() > 1 && path.ends_with('/') {
+        &path[..path.len() - 1]
+    } else {
+        path
+    }
+}
+
+///A handler that serves redirects from a `RedirectMap`.
+///
+///The query string is passed on to the new URL, unless it already has one.
+///Requests without a matching redirect get `404 Not Found`, and redirects
+///to URLs with line breaks get `500 Internal Server Error`.
+pub struct Redirects {
+    map: RwLock<Arc<RedirectMap>>,
+    source: Option<WatchedFile>
+}
+
+impl Redirects {
+    ///Serve the redirects in `map`.
+    pub fn new(map: RedirectMap) -> Redirects {
+        Redirects {
+            map: RwLock::new(Arc::new(map)),
+            source: None
+        }
+    }
+
+    ///Load the redirects from a file. See `RedirectMap::load`.
+    pub fn open<P: Into<PathBuf>>(path: P) -> io::Result<Redirects> {
+        let source = WatchedFile::new(path.into());
+        let map = try!(RedirectMap::load(&source.path));
+
+        Ok(Redirects {
+            map: RwLock::new(Arc::new(map)),
+            source: Some(source)
+        })
+    }
+
+    ///Check if the file has changed, at most once per `interval`, and
+    ///reload it if it has. The current redirects are kept if the file
+    ///can't be loaded. It does nothing if the redirects were not loaded from
+    ///a file.
+    pub fn reload_interval(mut self, interval: Duration) -> Redirects {
+        if let Some(ref mut source) = self.source {
+            source.interval = Some(interval);
+        }
+        self
+    }
+
+    ///Load the file again, if the redirects were loaded from a file.
+    pub fn reload(&self) -> io::Result<()> {
+        if let Some(ref source) = self.source {
+            let map = try!(source.reload(|path| RedirectMap::load(path)));
+
+            if let Ok(mut current) = self.map.write() {
+                *current = Arc::new(map);
+            }
+        }
+
+        Ok(())
+    }
+
+    ///Get the current redirects.
+    pub fn map(&self) -> Arc<RedirectMap> {
+        self.map.read().map(|map| map.clone()).unwrap_or_else(|_| Arc::new(RedirectMap::new()))
+    }
+
+    //Checks if the file should be reloaded, and resets the timer.
+    fn is_outdated(&self) -> bool {
+        self.source.as_ref().map(WatchedFile::is_outdated).unwrap_or(false)
+    }
+}
+
+impl Handler for Redirects {
+    fn handle_request(&self, context: Context, mut response: Response) {
+        if self.is_outdated() {
+            if let Err(e) = self.reload() {
+                context.log.error(&format!("could not reload the redirects: {}", e));
+            }
+        }
+
+        let target = context.uri.as_utf8_path().and_then(|path| self.map().find(path));
+
+        match target {
+            Some((ref to, _)) if has_line_break(to) => {
+                context.log.error(&format!("the redirect to {:?} contains a line break", to));
+                response.set_status(StatusCode::InternalServerError);
+            },
+            Some((mut to, status)) => {
+                let query = context.query.raw();
+                if !query.is_empty() && !to.contains('?') {
+                    to.push('?');
+                    to.push_str(&String::from_utf8_lossy(query));
+                }
+
+                response.set_status(status);
+                response.headers_mut().set_raw("Location", vec![to.into_bytes()]);
+            },
+            None => response.set_status(StatusCode::NotFound)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use StatusCode;
+    use super::RedirectMap;
+
+    #[test]
+    fn parse_csv() {
+        let map = RedirectMap::from_csv("#from,to,status\n\n/a.html, /a\n/b/*,http://example.com/b/,302\n").unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.find("/a.html"), Some(("/a".to_owned(), StatusCode::MovedPermanently)));
+        assert_eq!(map.find("/b"), Some(("http://example.com/b/".to_owned(), StatusCode::Found)));
+
+        assert!(RedirectMap::from_csv("/a").is_err());
+        assert!(RedirectMap::from_csv("/a,/b,200").is_err());
+    }
+
+    #[test]
+    fn match_prefixes() {
+        let mut map = RedirectMap::new();
+        map.insert("/a/*", "/x", StatusCode::MovedPermanently);
+        map.insert("/a/b/*", "/y/", StatusCode::MovedPermanently);
+        map.insert("/a/b/c", "/z", StatusCode::MovedPermanently);
+
+        assert_eq!(map.find("/a/b/c").map(|r| r.0), Some("/z".to_owned()));
+        assert_eq!(map.find("/a/b/d/e").map(|r| r.0), Some("/y/d/e".to_owned()));
+        assert_eq!(map.find("/a/d/").map(|r| r.0), Some("/x/d".to_owned()));
+        assert_eq!(map.find("/ab"), None);
+    }
+
+    #[test]
+    fn encode_suffixes() {
+        let mut map = RedirectMap::new();
+        map.insert("/a/*", "http://example.com/", StatusCode::MovedPermanently);
+
+        assert_eq!(map.find("/a/b c/d").map(|r| r.0), Some("http://example.com/b%20c/d".to_owned()));
+        assert_eq!(map.find("/a/x\r\nSet-Cookie: y").map(|r| r.0), Some("http://example.com/x%0D%0ASet-Cookie%3A%20y".to_owned()));
+        assert_eq!(map.find("/a/%2F?#").map(|r| r.0), Some("http://example.com/%252F%3F%23".to_owned()));
+    }
+
+    #[test]
+    fn reject_line_breaks() {
+        assert!(RedirectMap::from_csv("/a,/b\r").is_ok());
+        assert!(RedirectMap::from_csv("/a,/b\rSet-Cookie: x").is_err());
+    }
+
+    #[cfg(feature = "rustc_json_body")]
+    #[test]
+    fn parse_json() {
+        let map = RedirectMap::from_json(r#"[{"from": "/a", "to": "/b"}, {"from": "/c/*", "to": "/d", "status": 307}]"#).unwrap();
+        assert_eq!(map.find("/a"), Some(("/b".to_owned(), StatusCode::MovedPermanently)));
+        assert_eq!(map.find("/c/e"), Some(("/d/e".to_owned(), StatusCode::TemporaryRedirect)));
+    }
+}
@@ -0,0 +1,324 @@
+//!Filters and middleware for parts of a router.
+//!
+//!The filters and the middleware of the server are applied to every
+//!request. A [`Scope`][scope] is a set of context filters and middleware
+//!that are only applied to some handlers, such as the handlers in an admin
+//!area. It's applied to a single handler with `Scope::wrap`, or to all of
+//!the handlers in a router with `Scope::wrap_router`.
+//!
+//!The server's context filters and middleware are always the outermost, so
+//!the order is:
+//!
+//! 1. The server's context filters.
+//! 2. The server's middleware.
+//! 3. The scope's context filters.
+//! 4. The scope's middleware.
+//! 5. The handler.
+//!
+//!```
+//!#[macro_use]
+//!extern crate rustful;
+//!use rustful::{Server, TreeRouter, Context, Response, StatusCode};
+//!use rustful::filter::{ContextFilter, ContextAction, FilterContext};
+//!use rustful::handler::scope::Scope;
+//!
+//!struct RequireAuth;
+//!
+//!impl ContextFilter for RequireAuth {
+//!    fn modify(&self, _: FilterContext, context: &mut Context) -> ContextAction {
+//!        if context.headers.get_raw("Authorization").is_some() {
+//!            ContextAction::next()
+//!        } else {
+//!            ContextAction::abort(StatusCode::Unauthorized)
+//!        }
+//!    }
+//!}
+//!
+//!fn public_page(_context: Context, response: Response) {
+//!    response.send("everyone can see this");
+//!}
+//!
+//!fn admin_page(_context: Context, response: Response) {
+//!    response.send("only admins can see this");
+//!}
+//!
+//!# fn main() {
+//!let admin = insert_routes! {
+//!    TreeRouter::new() => {
+//!        "settings" => Get: admin_page as fn(Context, Response)
+//!    }
+//!};
+//!
+//!let router = insert_routes! {
+//!    TreeRouter::new() => {
+//!        "about" => Get: public_page as fn(Context, Response)
+//!    }
+//!};
+//!
+//!let mut router = Scope::new().wrap_router(router);
+//!router.insert_router("admin", Scope::new().context_filter(RequireAuth).wrap_router(admin));
+//!
+//!let server = Server {
+//!    handlers: router,
+//!    ..Server::default()
+//!};
+//!# }
+//!```
+//!
+//!The scope's context filters are set up together with the handlers, when
+//!the server is built, and torn down when the server instance is dropped.
+//!Each scope is only set up once, even if it's applied to several handlers.
+//!
+//!Response filters can't be scoped, since they belong to the response
+//!before the handler is found. Individual response filters can be skipped
+//!by name, using `Response::skip_filter`.
+//!
+//![scope]: struct.Scope.html
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use context::Context;
+use filter::{ContextFilter, ContextAction, FilterContext, GlobalContext};
+use handler::Handler;
+use middleware::{Middleware, Next};
+use response::Response;
+use router::TreeRouter;
+
+///A set of context filters and middleware for some of the handlers.
+pub struct Scope {
+    context_filters: Vec<Box<ContextFilter>>,
+    middleware: Vec<Box<Middleware>>,
+    handlers: AtomicUsize
+}
+
+impl Scope {
+    ///Create an empty scope.
+    pub fn new() -> Scope {
+        Scope {
+            context_filters: vec![],
+            middleware: vec![],
+            handlers: AtomicUsize::new(0)
+        }
+    }
+
+    ///Add a context filter. It runs after the server's context filters and
+    ///middleware, and the scope's earlier context filters.
+    pub fn context_filter<F: ContextFilter + 'static>(mut self, filter: F) -> Scope {
+        self.context_filters.push(Box::new(filter));
+        self
+    }
+
+    ///Add a middleware. It's inside the server's middleware and the
+    ///scope's earlier middleware.
+    pub fn middleware<M: Middleware + 'static>(mut self, middleware: M) -> Scope {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    ///Apply the scope to a single handler.
+    pub fn wrap<H: Handler>(self, handler: H) -> Scoped<H> {
+        Scoped {
+            handler: handler,
+            scope: Arc::new(self)
+        }
+    }
+
+    ///Apply the scope to all of the handlers in `router`.
+    pub fn wrap_router<H: Handler>(self, router: TreeRouter<H>) -> TreeRouter<Scoped<H>> {
+        let scope = Arc::new(self);
+        router.map_handlers(|handler| Scoped {
+            handler: handler,
+            scope: scope.clone()
+        })
+    }
+}
+
+impl Default for Scope {
+    fn default() -> Scope {
+        Scope::new()
+    }
+}
+
+///A handler with a scope. See `Scope`.
+pub struct Scoped<H> {
+    handler: H,
+    scope: Arc<Scope>
+}
+
+impl<H: Handler> Handler for Scoped<H> {
+    fn handle_request(&self, mut context: Context, mut response: Response) {
+        let log = context.log;
+        let global = context.global;
+
+        for filter in &self.scope.context_filters {
+            let filter_context = FilterContext {
+                storage: response.filter_storage_mut(),
+                log: log,
+                global: global,
+            };
+
+            match filter.modify(filter_context, &mut context) {
+                ContextAction::Next => {},
+                ContextAction::Abort(status) => {
+                    response.set_status(status);
+                    return;
+                },
+                ContextAction::Respond(filter_response) => {
+                    response.send_filter_response(filter_response);
+                    return;
+                }
+            }
+        }
+
+        if self.scope.middleware.is_empty() {
+            self.handler.handle_request(context, response);
+        } else {
+            let outbox = response.outbox();
+            Next::new(&self.scope.middleware, Some(&self.handler), outbox).run(context, response);
+        }
+    }
+
+    fn setup(&self, context: &GlobalContext) {
+        //The filters are set up by the first handler in the scope.
+        if self.scope.handlers.fetch_add(1, Ordering::SeqCst) == 0 {
+            for filter in &self.scope.context_filters {
+                filter.setup(context);
+            }
+        }
+        self.handler.setup(context);
+    }
+
+    fn teardown(&self) {
+        self.handler.teardown();
+        //The filters are torn down by the last handler in the scope.
+        if self.scope.handlers.fetch_sub(1, Ordering::SeqCst) == 1 {
+            for filter in self.scope.context_filters.iter().rev() {
+                filter.teardown();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use {Server, TreeRouter, Context, Response, StatusCode};
+    use filter::{ContextFilter, ContextAction, FilterContext, GlobalContext};
+    use middleware::{Middleware, Next};
+    use server::Dispatcher;
+    use super::Scope;
+
+    type Events = Arc<Mutex<Vec<String>>>;
+
+    struct Recorder(&'static str, Events, bool);
+
+    impl ContextFilter for Recorder {
+        fn modify(&self, _context: FilterContext, _request_context: &mut Context) -> ContextAction {
+            self.1.lock().unwrap().push(format!("{} filter", self.0));
+            if self.2 {
+                ContextAction::next()
+            } else {
+                ContextAction::abort(StatusCode::Forbidden)
+            }
+        }
+
+        fn setup(&self, _context: &GlobalContext) {
+            self.1.lock().unwrap().push(format!("setup {}", self.0));
+        }
+
+        fn teardown(&self) {
+            self.1.lock().unwrap().push(format!("teardown {}", self.0));
+        }
+    }
+
+    struct Layer(&'static str, Events);
+
+    impl Middleware for Layer {
+        fn around(&self, context: Context, response: Response, next: Next) {
+            self.1.lock().unwrap().push(format!("{} middleware", self.0));
+            next.run(context, response);
+        }
+    }
+
+    fn run(scope: Scope, events: &Events) -> String {
+        let handler_events = events.clone();
+        let handler = move |_context: Context, response: Response| {
+            handler_events.lock().unwrap().push("handler".to_owned());
+            response.send("");
+        };
+
+        let server = Server {
+            context_filters: vec![Box::new(Recorder("server", events.clone(), true))],
+            middleware: vec![Box::new(Layer("server", events.clone()))],
+            ..Server::new(scope.wrap(handler))
+        };
+        let (instance, _scheme) = server.build();
+        let output = instance.dispatch(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n", "127.0.0.1:8080".parse().unwrap());
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn run_in_order() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let scope = Scope::new()
+            .context_filter(Recorder("scope", events.clone(), true))
+            .middleware(Layer("scope", events.clone()));
+
+        let output = run(scope, &events);
+        assert!(output.starts_with("HTTP/1.1 200"), "{}", output);
+        assert_eq!(*events.lock().unwrap(), vec![
+            "setup server",
+            "setup scope",
+            "server filter",
+            "server middleware",
+            "scope filter",
+            "scope middleware",
+            "handler",
+            "teardown scope",
+            "teardown server"
+        ]);
+    }
+
+    #[test]
+    fn stop_at_filters() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let scope = Scope::new()
+            .context_filter(Recorder("scope", events.clone(), false))
+            .middleware(Layer("scope", events.clone()));
+
+        let output = run(scope, &events);
+        assert!(output.starts_with("HTTP/1.1 403"), "{}", output);
+        assert_eq!(*events.lock().unwrap(), vec![
+            "setup server",
+            "setup scope",
+            "server filter",
+            "server middleware",
+            "scope filter",
+            "teardown scope",
+            "teardown server"
+        ]);
+    }
+
+    #[test]
+    fn set_up_once() {
+        fn handler(_context: Context, response: Response) {
+            response.send("");
+        }
+
+        let events = Arc::new(Mutex::new(vec![]));
+        let router = insert_routes! {
+            TreeRouter::new() => {
+                "a" => Get: handler as fn(Context, Response),
+                "b" => Get: handler as fn(Context, Response)
+            }
+        };
+        let router = Scope::new().context_filter(Recorder("scope", events.clone(), true)).wrap_router(router);
+
+        let (instance, _scheme) = Server::new(router).build();
+        assert_eq!(*events.lock().unwrap(), vec!["setup scope"]);
+
+        drop(instance);
+        assert_eq!(*events.lock().unwrap(), vec!["setup scope", "teardown scope"]);
+    }
+}
@@ -0,0 +1,249 @@
+//!A bridge to embedded scripting engines.
+//!
+//![`ScriptHandler`][script_handler] lets a script handle the request, so
+//!small routes and rewrites can be added through configuration instead of
+//!recompiling the server. The script engine, such as a Lua VM, is plugged in
+//!by implementing [`ScriptEngine`][script_engine]. Engines are usually not
+//!thread safe, so the handler keeps a pool of them, and each engine only
+//!handles one request at a time.
+//!
+//!The script doesn't get access to `Context` and `Response` directly. It
+//!gets a [`ScriptRequest`][script_request], which is a copy of the request,
+//!including the whole body, and it builds a
+//![`ScriptResponse`][script_response], which is sent when it's done. This
+//!keeps the script from holding on to anything that belongs to the server.
+//!
+//!```
+//!use rustful::Server;
+//!use rustful::handler::script::{ScriptHandler, ScriptEngine, ScriptRequest, ScriptResponse};
+//!
+//!//A stand-in for a real script engine.
+//!struct Echo;
+//!
+//!impl ScriptEngine for Echo {
+//!    fn handle(&mut self, request: &ScriptRequest, response: &mut ScriptResponse) -> Result<(), String> {
+//!        response.set_header("Content-Type", "text/plain");
+//!        response.write(request.path.as_bytes());
+//!        Ok(())
+//!    }
+//!}
+//!
+//!let server = Server::new(ScriptHandler::new(|| Ok(Echo)).max_idle(4));
+//!```
+//!
+//![script_handler]: struct.ScriptHandler.html
+//![script_engine]: trait.ScriptEngine.html
+//![script_request]: struct.ScriptRequest.html
+//![script_response]: struct.ScriptResponse.html
+
+use std::sync::Mutex;
+
+use context::{Context, Parameters};
+use handler::{Handler, read_body};
+use response::Response;
+use StatusCode;
+
+///A script engine instance.
+pub trait ScriptEngine: Send {
+    ///Run the script for `request`, and build `response`. An error results
+    ///in `500 Internal Server Error`, and the engine is discarded, in case
+    ///it's left in a broken state.
+    fn handle(&mut self, request: &ScriptRequest, response: &mut ScriptResponse) -> Result<(), String>;
+}
+
+///A copy of the request, as it's given to a script.
+#[derive(Clone, Debug)]
+pub struct ScriptRequest {
+    ///The request method.
+    pub method: String,
+
+    ///The requested path, without the query string.
+    pub path: String,
+
+    ///The query parameters.
+    pub query: Parameters,
+
+    ///The route variables.
+    pub variables: Parameters,
+
+    ///The request headers, with their names as they were sent.
+    pub headers: Vec<(String, String)>,
+
+    ///The whole request body.
+    pub body: Vec<u8>
+}
+
+impl ScriptRequest {
+    ///Get the first value of the header `name`, ignoring its case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        self.headers.iter().find(|header| header.0.to_lowercase() == name).map(|header| &*header.1)
+    }
+}
+
+///The response, as it's built by a script.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScriptResponse {
+    ///The status code. Default is 200.
+    pub status: u16,
+
+    ///The response headers.
+    pub headers: Vec<(String, String)>,
+
+    ///The response body.
+    pub body: Vec<u8>
+}
+
+impl ScriptResponse {
+    ///Set the header `name` to `value`, replacing any previous value.
+    pub fn set_header<N: Into<String>, V: Into<String>>(&mut self, name: N, value: V) {
+        let name = name.into();
+        let lowercase = name.to_lowercase();
+        self.headers.retain(|header| header.0.to_lowercase() != lowercase);
+        self.headers.push((name, value.into()));
+    }
+
+    ///Append `data` to the body.
+    pub fn write(&mut self, data: &[u8]) {
+        self.body.extend_from_slice(data);
+    }
+}
+
+impl Default for ScriptResponse {
+    fn default() -> ScriptResponse {
+        ScriptResponse {
+            status: 200,
+            headers: vec![],
+            body: vec![]
+        }
+    }
+}
+
+///A handler that lets a pool of script engines handle the requests.
+pub struct ScriptHandler<E> {
+    create: Box<Fn() -> Result<E, String> + Send + Sync>,
+    pool: Mutex<Vec<E>>,
+    max_idle: usize
+}
+
+impl<E: ScriptEngine> ScriptHandler<E> {
+    ///Create a handler that uses `create` to start new engines when the
+    ///pool is empty. At most 8 idle engines are kept by default.
+    pub fn new<F>(create: F) -> ScriptHandler<E> where F: Fn() -> Result<E, String> + Send + Sync + 'static {
+        ScriptHandler {
+            create: Box::new(create),
+            pool: Mutex::new(vec![]),
+            max_idle: 8
+        }
+    }
+
+    ///Set the largest number of idle engines to keep in the pool. The rest
+    ///are dropped when they are done.
+    pub fn max_idle(mut self, max_idle: usize) -> ScriptHandler<E> {
+        self.max_idle = max_idle;
+        self
+    }
+
+    ///Get the number of idle engines in the pool.
+    pub fn idle(&self) -> usize {
+        self.pool.lock().map(|pool| pool.len()).unwrap_or(0)
+    }
+
+    ///Run a script for `request`, using an engine from the pool.
+    pub fn run(&self, request: &ScriptRequest) -> Result<ScriptResponse, String> {
+        let pooled = self.pool.lock().ok().and_then(|mut pool| pool.pop());
+        let mut engine = match pooled {
+            Some(engine) => engine,
+            None => try!((self.create)())
+        };
+
+        let mut response = ScriptResponse::default();
+        try!(engine.handle(request, &mut response));
+
+        if let Ok(mut pool) = self.pool.lock() {
+            if pool.len() < self.max_idle {
+                pool.push(engine);
+            }
+        }
+
+        Ok(response)
+    }
+}
+
+impl<E: ScriptEngine + 'static> Handler for ScriptHandler<E> {
+    fn handle_request(&self, mut context: Context, mut response: Response) {
+        let body = match read_body(&mut context, &mut response) {
+            Some(body) => body,
+            None => return
+        };
+
+        let request = ScriptRequest {
+            method: context.method.to_string(),
+            path: context.uri.as_utf8_path().unwrap_or("").to_owned(),
+            query: (*context.query).clone(),
+            variables: context.variables.to_parameters(),
+            headers: context.headers.iter().map(|header| (header.name().to_owned(), header.value_string())).collect(),
+            body: body
+        };
+
+        match self.run(&request) {
+            Ok(script_response) => {
+                response.set_status(StatusCode::from_u16(script_response.status));
+                for (name, value) in script_response.headers {
+                    response.headers_mut().set_raw(name, vec![value.into_bytes()]);
+                }
+                response.send(script_response.body);
+            },
+            Err(e) => {
+                context.log.error(&format!("script error: {}", e));
+                response.set_status(StatusCode::InternalServerError);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use context::Parameters;
+    use super::{ScriptHandler, ScriptEngine, ScriptRequest, ScriptResponse};
+
+    struct Counter(usize);
+
+    impl ScriptEngine for Counter {
+        fn handle(&mut self, request: &ScriptRequest, response: &mut ScriptResponse) -> Result<(), String> {
+            if request.path == "/fail" {
+                return Err("failed".into());
+            }
+
+            self.0 += 1;
+            response.set_header("X-Count", "0");
+            response.set_header("x-count", self.0.to_string());
+            Ok(())
+        }
+    }
+
+    fn request(path: &str) -> ScriptRequest {
+        ScriptRequest {
+            method: "GET".into(),
+            path: path.into(),
+            query: Parameters::new(),
+            variables: Parameters::new(),
+            headers: vec![],
+            body: vec![]
+        }
+    }
+
+    #[test]
+    fn reuse_engines() {
+        let handler = ScriptHandler::new(|| Ok(Counter(0))).max_idle(1);
+        assert_eq!(handler.idle(), 0);
+
+        handler.run(&request("/")).unwrap();
+        let response = handler.run(&request("/")).unwrap();
+        assert_eq!(response.headers, vec![("x-count".to_owned(), "2".to_owned())]);
+        assert_eq!(handler.idle(), 1);
+
+        assert!(handler.run(&request("/fail")).is_err());
+        assert_eq!(handler.idle(), 0);
+    }
+}
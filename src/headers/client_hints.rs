@@ -0,0 +1,117 @@
+use std::str::from_utf8;
+
+use header::Headers;
+
+use headers::structured;
+
+///Parsed HTTP Client Hints from a request.
+///
+///Client hints are sent by browsers that have been asked to send them, using
+///the `Accept-CH` response header. See the
+///[`AcceptClientHints`][filter] filter for a simple way to do that.
+///
+///```
+///use rustful::{Context, Response};
+///
+///fn send_image(context: Context, response: Response) {
+///    let hints = context.client_hints();
+///
+///    //Pick an image variant that fits the client
+///    let width = hints.width.or(hints.viewport_width).unwrap_or(1024);
+///    let quality = if hints.save_data { "low" } else { "high" };
+///
+///    response.send(format!("a {} quality image, {} pixels wide", quality, width));
+///}
+///```
+///
+///[filter]: ../filter/struct.AcceptClientHints.html
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ClientHints {
+    ///The device pixel ratio, from `Sec-CH-DPR` or `DPR`.
+    pub dpr: Option<f32>,
+
+    ///The intended display width of the requested image, in physical
+    ///pixels, from `Sec-CH-Width` or `Width`.
+    pub width: Option<u32>,
+
+    ///The layout viewport width, in CSS pixels, from `Sec-CH-Viewport-Width`
+    ///or `Viewport-Width`.
+    pub viewport_width: Option<u32>,
+
+    ///The approximate amount of device memory, in GiB, from
+    ///`Sec-CH-Device-Memory` or `Device-Memory`.
+    pub device_memory: Option<f32>,
+
+    ///The client has asked for reduced data usage, using `Save-Data: on`.
+    pub save_data: bool,
+
+    ///The raw brand list from `Sec-CH-UA`.
+    pub ua: Option<String>,
+
+    ///The client prefers a mobile experience, from `Sec-CH-UA-Mobile`.
+    pub ua_mobile: Option<bool>,
+
+    ///The client platform, from `Sec-CH-UA-Platform`.
+    pub ua_platform: Option<String>
+}
+
+impl ClientHints {
+    ///Collect the client hints from a set of request headers.
+    pub fn from_headers(headers: &Headers) -> ClientHints {
+        ClientHints {
+            dpr: hint(headers, "Sec-CH-DPR", "DPR").and_then(|v| v.parse().ok()),
+            width: hint(headers, "Sec-CH-Width", "Width").and_then(|v| v.parse().ok()),
+            viewport_width: hint(headers, "Sec-CH-Viewport-Width", "Viewport-Width").and_then(|v| v.parse().ok()),
+            device_memory: hint(headers, "Sec-CH-Device-Memory", "Device-Memory").and_then(|v| v.parse().ok()),
+            save_data: hint(headers, "Save-Data", "Save-Data").map(|v| {
+                v.split(';').next().map(|v| v.trim().to_lowercase() == "on").unwrap_or(false)
+            }).unwrap_or(false),
+            ua: hint(headers, "Sec-CH-UA", "Sec-CH-UA"),
+            ua_mobile: hint(headers, "Sec-CH-UA-Mobile", "Sec-CH-UA-Mobile")
+                .and_then(|v| structured::parse_item(&v).ok())
+                .and_then(|item| item.value.as_bool()),
+            ua_platform: hint(headers, "Sec-CH-UA-Platform", "Sec-CH-UA-Platform")
+                .and_then(|v| structured::parse_item(&v).ok())
+                .and_then(|item| item.value.as_str().map(|s| s.to_owned()))
+        }
+    }
+}
+
+//Gets the trimmed value of the first header that is present of `name` and
+//`legacy_name`.
+fn hint(headers: &Headers, name: &str, legacy_name: &str) -> Option<String> {
+    headers.get_raw(name)
+        .or_else(|| headers.get_raw(legacy_name))
+        .and_then(|lines| lines.first())
+        .and_then(|line| from_utf8(line).ok())
+        .map(|value| value.trim().to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use header::Headers;
+    use super::ClientHints;
+
+    #[test]
+    fn parse_client_hints() {
+        let mut headers = Headers::new();
+        headers.set_raw("DPR", vec![b"2.0".to_vec()]);
+        headers.set_raw("Sec-CH-Width", vec![b" 640".to_vec()]);
+        headers.set_raw("Save-Data", vec![b"on".to_vec()]);
+        headers.set_raw("Sec-CH-UA-Mobile", vec![b"?1".to_vec()]);
+        headers.set_raw("Sec-CH-UA-Platform", vec![b"\"Android\"".to_vec()]);
+
+        let hints = ClientHints::from_headers(&headers);
+        assert_eq!(hints.dpr, Some(2.0));
+        assert_eq!(hints.width, Some(640));
+        assert_eq!(hints.viewport_width, None);
+        assert!(hints.save_data);
+        assert_eq!(hints.ua_mobile, Some(true));
+        assert_eq!(hints.ua_platform, Some("Android".to_owned()));
+    }
+
+    #[test]
+    fn missing_client_hints() {
+        assert_eq!(ClientHints::from_headers(&Headers::new()), ClientHints::default());
+    }
+}
@@ -0,0 +1,384 @@
+use std::fmt;
+use std::str::from_utf8;
+
+use time::Duration;
+
+use header::{Header, HeaderFormat, HttpDate};
+use HttpResult;
+use HttpError;
+
+use utils::is_token;
+
+//The largest `Max-Age` that fits in a `Duration`, in seconds.
+const MAX_AGE: i64 = ::std::i64::MAX / 1000;
+
+///The `Set-Cookie` header, as defined in [RFC 6265][rfc].
+///
+///Each cookie is sent as a separate `Set-Cookie` line, since the values
+///can't be combined into one. It's usually easiest to use
+///`Response::set_cookie`, which appends to this header.
+///
+///```
+///use rustful::headers::{SetCookie, Cookie};
+///
+///let header = SetCookie(vec![Cookie::new("a", "1"), Cookie::new("b", "2").http_only()]);
+///assert_eq!(header.to_string(), "a=1\r\nSet-Cookie: b=2; HttpOnly");
+///```
+///
+///[rfc]: https://tools.ietf.org/html/rfc6265#section-4.1
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetCookie(pub Vec<Cookie>);
+
+impl SetCookie {
+    ///Find the cookie with the name `name`.
+    pub fn find(&self, name: &str) -> Option<&Cookie> {
+        self.0.iter().find(|cookie| cookie.name == name)
+    }
+
+    ///Add `cookie`, and remove any previous cookie with the same name, path
+    ///and domain.
+    pub fn insert(&mut self, cookie: Cookie) {
+        self.0.retain(|c| c.name != cookie.name || c.path != cookie.path || c.domain != cookie.domain);
+        self.0.push(cookie);
+    }
+}
+
+impl Header for SetCookie {
+    fn header_name() -> &'static str {
+        "Set-Cookie"
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> HttpResult<SetCookie> {
+        let mut cookies = vec![];
+
+        for line in raw {
+            let line = try!(from_utf8(line).map_err(|_| HttpError::Header));
+            cookies.push(try!(line.parse::<Cookie>()));
+        }
+
+        if cookies.is_empty() {
+            Err(HttpError::Header)
+        } else {
+            Ok(SetCookie(cookies))
+        }
+    }
+}
+
+impl HeaderFormat for SetCookie {
+    fn fmt_header(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        //The header name is written for each additional line, since each
+        //cookie has to be on a line of its own.
+        for (i, cookie) in self.0.iter().enumerate() {
+            if i > 0 {
+                try!(f.write_str("\r\nSet-Cookie: "));
+            }
+            try!(write!(f, "{}", cookie));
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for SetCookie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_header(f)
+    }
+}
+
+///The `SameSite` attribute of a cookie.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SameSite {
+    ///Only send the cookie with requests from the same site.
+    Strict,
+
+    ///Also send the cookie when the user navigates to the site from
+    ///somewhere else.
+    Lax,
+
+    ///Send the cookie with all requests. It requires `Secure` in most
+    ///browsers.
+    None
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None"
+        })
+    }
+}
+
+///A cookie, as it's set by a response.
+///
+///```
+///extern crate rustful;
+///extern crate time;
+///use rustful::headers::{Cookie, SameSite};
+///
+///# fn main() {
+///let cookie = Cookie::new("session", "abc123")
+///    .path("/")
+///    .http_only()
+///    .secure()
+///    .same_site(SameSite::Lax)
+///    .max_age(time::Duration::hours(1));
+///
+///assert_eq!(cookie.to_string(), "session=abc123; Path=/; Max-Age=3600; Secure; HttpOnly; SameSite=Lax");
+///# }
+///```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cookie {
+    ///The cookie name. It has to be a token.
+    pub name: String,
+
+    ///The cookie value. It can't contain whitespace, `"`, `,`, `;` or `\`.
+    pub value: String,
+
+    ///The path the cookie is limited to.
+    pub path: Option<String>,
+
+    ///The domain the cookie is sent to, including its subdomains.
+    pub domain: Option<String>,
+
+    ///How long the cookie lives.
+    pub max_age: Option<Duration>,
+
+    ///When the cookie expires. Old clients use this instead of `max_age`.
+    pub expires: Option<HttpDate>,
+
+    ///Only send the cookie over secure connections.
+    pub secure: bool,
+
+    ///Hide the cookie from scripts.
+    pub http_only: bool,
+
+    ///Limit cross site requests with the cookie.
+    pub same_site: Option<SameSite>
+}
+
+impl Cookie {
+    ///Create a session cookie, without any attributes.
+    pub fn new<N: Into<String>, V: Into<String>>(name: N, value: V) -> Cookie {
+        Cookie {
+            name: name.into(),
+            value: value.into(),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None
+        }
+    }
+
+    ///Create a cookie that removes the cookie `name` from the client. It has
+    ///to have the same path and domain as the cookie it removes.
+    pub fn removal<N: Into<String>>(name: N) -> Cookie {
+        let mut cookie = Cookie::new(name, "").max_age(Duration::zero());
+        cookie.expires = Some(HttpDate(::time::at_utc(::time::Timespec::new(0, 0))));
+        cookie
+    }
+
+    ///Set the `Path` attribute.
+    pub fn path<P: Into<String>>(mut self, path: P) -> Cookie {
+        self.path = Some(path.into());
+        self
+    }
+
+    ///Set the `Domain` attribute.
+    pub fn domain<D: Into<String>>(mut self, domain: D) -> Cookie {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    ///Set the `Max-Age` attribute. Negative durations are sent as 0.
+    pub fn max_age(mut self, max_age: Duration) -> Cookie {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    ///Set the `Expires` attribute.
+    pub fn expires(mut self, expires: HttpDate) -> Cookie {
+        self.expires = Some(expires);
+        self
+    }
+
+    ///Set the `Secure` attribute.
+    pub fn secure(mut self) -> Cookie {
+        self.secure = true;
+        self
+    }
+
+    ///Set the `HttpOnly` attribute.
+    pub fn http_only(mut self) -> Cookie {
+        self.http_only = true;
+        self
+    }
+
+    ///Set the `SameSite` attribute.
+    pub fn same_site(mut self, same_site: SameSite) -> Cookie {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    ///Check if the cookie can be sent as it is. The name has to be a token,
+    ///the value can only contain the allowed characters, and the path and
+    ///domain can't contain `;` or control characters.
+    pub fn is_valid(&self) -> bool {
+        let attribute_value = |value: &Option<String>| value.as_ref().map(|value| {
+            value.bytes().all(|b| b >= 0x20 && b < 0x7f && b != b';')
+        }).unwrap_or(true);
+
+        is_token(&self.name)
+            && self.value.bytes().all(is_cookie_octet)
+            && attribute_value(&self.path)
+            && attribute_value(&self.domain)
+    }
+}
+
+impl ::std::str::FromStr for Cookie {
+    type Err = HttpError;
+
+    fn from_str(s: &str) -> HttpResult<Cookie> {
+        let mut parts = s.split(';').map(|part| part.trim());
+
+        let (name, value) = match parts.next().map(|pair| pair.splitn(2, '=')) {
+            Some(mut pair) => match (pair.next(), pair.next()) {
+                (Some(name), Some(value)) if !name.trim().is_empty() => (name.trim(), value.trim().trim_matches('"')),
+                _ => return Err(HttpError::Header)
+            },
+            None => return Err(HttpError::Header)
+        };
+
+        let mut cookie = Cookie::new(name, value);
+
+        for attribute in parts {
+            let mut attribute = attribute.splitn(2, '=');
+            let name = attribute.next().unwrap_or("").trim().to_lowercase();
+            let value = attribute.next().map(|value| value.trim());
+
+            //Unknown and malformed attributes are ignored, as required by
+            //the RFC.
+            match (&*name, value) {
+                ("path", Some(path)) => cookie.path = Some(path.to_owned()),
+                ("domain", Some(domain)) => cookie.domain = Some(domain.trim_left_matches('.').to_owned()),
+                ("max-age", Some(max_age)) => if let Ok(max_age) = max_age.parse::<i64>() {
+                    //Values that don't fit in a `Duration` would make it
+                    //panic. Non-positive values expire the cookie.
+                    cookie.max_age = Some(Duration::seconds(::std::cmp::min(::std::cmp::max(max_age, 0), MAX_AGE)));
+                },
+                ("expires", Some(expires)) => if let Ok(expires) = expires.parse() {
+                    cookie.expires = Some(expires);
+                },
+                ("secure", _) => cookie.secure = true,
+                ("httponly", _) => cookie.http_only = true,
+                ("samesite", Some(same_site)) => cookie.same_site = match &*same_site.to_lowercase() {
+                    "strict" => Some(SameSite::Strict),
+                    "lax" => Some(SameSite::Lax),
+                    "none" => Some(SameSite::None),
+                    _ => None
+                },
+                _ => {}
+            }
+        }
+
+        Ok(cookie)
+    }
+}
+
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}={}", self.name, self.value));
+
+        if let Some(ref path) = self.path {
+            try!(write!(f, "; Path={}", path));
+        }
+
+        if let Some(ref domain) = self.domain {
+            try!(write!(f, "; Domain={}", domain));
+        }
+
+        if let Some(max_age) = self.max_age {
+            try!(write!(f, "; Max-Age={}", ::std::cmp::max(max_age.num_seconds(), 0)));
+        }
+
+        if let Some(ref expires) = self.expires {
+            try!(write!(f, "; Expires={}", expires));
+        }
+
+        if self.secure {
+            try!(f.write_str("; Secure"));
+        }
+
+        if self.http_only {
+            try!(f.write_str("; HttpOnly"));
+        }
+
+        if let Some(same_site) = self.same_site {
+            try!(write!(f, "; SameSite={}", same_site));
+        }
+
+        Ok(())
+    }
+}
+
+fn is_cookie_octet(b: u8) -> bool {
+    match b {
+        0x21 | 0x23...0x2b | 0x2d...0x3a | 0x3c...0x5b | 0x5d...0x7e => true,
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use time::Duration;
+    use header::Header;
+    use super::{SetCookie, Cookie, SameSite};
+
+    #[test]
+    fn parse_cookies() {
+        let raw = vec![
+            b"a=1; Path=/; Max-Age=60; secure; HttpOnly; SameSite=strict; Unknown".to_vec(),
+            b"b=\"2\"; Expires=Sun, 06 Nov 1994 08:49:37 GMT".to_vec()
+        ];
+        let header = SetCookie::parse_header(&raw).unwrap();
+
+        assert_eq!(header.0.len(), 2);
+        assert_eq!(header.0[0], Cookie::new("a", "1")
+            .path("/")
+            .max_age(Duration::seconds(60))
+            .secure()
+            .http_only()
+            .same_site(SameSite::Strict));
+        assert_eq!(header.find("b").map(|b| &*b.value), Some("2"));
+        assert!(header.0[1].expires.is_some());
+
+        assert!(SetCookie::parse_header(&[b"=1".to_vec()]).is_err());
+
+        let header = SetCookie::parse_header(&[
+            b"a=1; Max-Age=9223372036854775807".to_vec(),
+            b"b=1; Max-Age=99999999999999999999".to_vec(),
+            b"c=1; Max-Age=-10".to_vec()
+        ]).unwrap();
+        assert_eq!(header.0[0].max_age, Some(Duration::seconds(super::MAX_AGE)));
+        assert_eq!(header.0[1].max_age, None);
+        assert_eq!(header.0[2].max_age, Some(Duration::zero()));
+        assert!(SetCookie::parse_header(&[b"a".to_vec()]).is_err());
+    }
+
+    #[test]
+    fn validate_cookies() {
+        assert!(Cookie::new("a", "1").path("/a").is_valid());
+        assert!(!Cookie::new("a b", "1").is_valid());
+        assert!(!Cookie::new("a", "1;2").is_valid());
+        assert!(!Cookie::new("a", "1").path("/; Secure").is_valid());
+    }
+
+    #[test]
+    fn format_removal() {
+        assert_eq!(Cookie::removal("a").path("/").to_string(), "a=; Path=/; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+}
@@ -0,0 +1,215 @@
+use std::fmt;
+use std::str::from_utf8;
+
+use header::{Header, HeaderFormat};
+use HttpResult;
+use HttpError;
+
+use utils::{split_outside, parse_parameter, write_value};
+
+///The `Link` header, as defined in [RFC 5988][rfc].
+///
+///It contains a list of typed links to other resources, where each link is
+///described by a URI reference and a number of parameters, such as `rel`.
+///
+///```
+///use rustful::headers::{Link, LinkValue};
+///
+///let link = Link(vec![
+///    LinkValue::new("/items?page=3").with_rel("next"),
+///    LinkValue::new("/items?page=1").with_rel("prev")
+///]);
+///
+///assert_eq!(link.find_rel("next").map(|l| &*l.uri), Some("/items?page=3"));
+///assert_eq!(link.to_string(), "</items?page=3>; rel=next, </items?page=1>; rel=prev");
+///```
+///
+///[rfc]: https://tools.ietf.org/html/rfc5988
+#[derive(Clone, Debug, PartialEq)]
+pub struct Link(pub Vec<LinkValue>);
+
+impl Link {
+    ///Find the first link with the relation type `rel`.
+    pub fn find_rel(&self, rel: &str) -> Option<&LinkValue> {
+        self.0.iter().find(|link| link.has_rel(rel))
+    }
+}
+
+impl Header for Link {
+    fn header_name() -> &'static str {
+        "Link"
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> HttpResult<Link> {
+        let mut links = vec![];
+
+        for line in raw {
+            let line = try!(from_utf8(line).map_err(|_| HttpError::Header));
+            for value in split_outside(line, ',') {
+                if !value.is_empty() {
+                    links.push(try!(value.parse::<LinkValue>()));
+                }
+            }
+        }
+
+        if links.is_empty() {
+            Err(HttpError::Header)
+        } else {
+            Ok(Link(links))
+        }
+    }
+}
+
+impl HeaderFormat for Link {
+    fn fmt_header(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, link) in self.0.iter().enumerate() {
+            if i > 0 {
+                try!(f.write_str(", "));
+            }
+            try!(write!(f, "{}", link));
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_header(f)
+    }
+}
+
+///A single link in a `Link` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LinkValue {
+    ///The target URI reference.
+    pub uri: String,
+
+    ///Link parameters, like `rel`, `title` and `type`. The names are stored
+    ///in lower case and valueless parameters have an empty value. Empty
+    ///values are written as a bare parameter name.
+    pub params: Vec<(String, String)>
+}
+
+impl LinkValue {
+    ///Create a link to `uri`, without any parameters.
+    pub fn new<U: Into<String>>(uri: U) -> LinkValue {
+        LinkValue {
+            uri: uri.into(),
+            params: vec![]
+        }
+    }
+
+    ///Add a `rel` parameter, such as `next` or `preload`.
+    pub fn with_rel<R: Into<String>>(self, rel: R) -> LinkValue {
+        self.with_param("rel", rel)
+    }
+
+    ///Add a parameter with an arbitrary name.
+    pub fn with_param<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> LinkValue {
+        self.params.push((name.into().to_lowercase(), value.into()));
+        self
+    }
+
+    ///Get the value of the first parameter called `name`, if any.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        self.params.iter().find(|&&(ref n, _)| *n == name).map(|&(_, ref v)| &**v)
+    }
+
+    ///Get the `rel` parameter, if any.
+    pub fn rel(&self) -> Option<&str> {
+        self.param("rel")
+    }
+
+    ///Check if `rel` is one of the link's relation types. The `rel`
+    ///parameter may contain multiple, space separated, relation types.
+    pub fn has_rel(&self, rel: &str) -> bool {
+        let rel = rel.to_lowercase();
+        self.rel().map(|rels| {
+            rels.split_whitespace().any(|r| r.to_lowercase() == rel)
+        }).unwrap_or(false)
+    }
+}
+
+impl ::std::str::FromStr for LinkValue {
+    type Err = HttpError;
+
+    fn from_str(s: &str) -> HttpResult<LinkValue> {
+        let mut parts = split_outside(s, ';').into_iter();
+
+        let uri = match parts.next() {
+            Some(uri) if uri.len() >= 2 && uri.starts_with('<') && uri.ends_with('>') => {
+                uri[1..uri.len() - 1].trim().to_owned()
+            },
+            _ => return Err(HttpError::Header)
+        };
+
+        let mut params = vec![];
+        for param in parts.filter(|p| !p.is_empty()) {
+            match parse_parameter(param) {
+                Some((name, value)) => params.push((name, value.unwrap_or_else(String::new))),
+                None => return Err(HttpError::Header)
+            }
+        }
+
+        Ok(LinkValue {
+            uri: uri,
+            params: params
+        })
+    }
+}
+
+impl fmt::Display for LinkValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "<{}>", self.uri));
+        for &(ref name, ref value) in &self.params {
+            if value.is_empty() {
+                try!(write!(f, "; {}", name));
+            } else {
+                try!(write!(f, "; {}=", name));
+                try!(write_value(f, value));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use header::Header;
+    use super::{Link, LinkValue};
+
+    #[test]
+    fn parse_multiple_links() {
+        let raw = vec![b"<http://example.com/a,b>; rel=\"next last\"; title=\"a; b\", </prev>; REL=prev".to_vec()];
+        let link = Link::parse_header(&raw).unwrap();
+
+        assert_eq!(link.0.len(), 2);
+        assert_eq!(link.0[0].uri, "http://example.com/a,b");
+        assert_eq!(link.0[0].param("title"), Some("a; b"));
+        assert!(link.0[0].has_rel("last"));
+        assert_eq!(link.find_rel("prev").map(|l| &*l.uri), Some("/prev"));
+    }
+
+    #[test]
+    fn parse_invalid_link() {
+        assert!(Link::parse_header(&[b"http://example.com; rel=next".to_vec()]).is_err());
+        assert!(Link::parse_header(&[b"".to_vec()]).is_err());
+    }
+
+    #[test]
+    fn format_link() {
+        let link = LinkValue::new("/style.css").with_rel("preload").with_param("as", "style").with_param("title", "main style");
+        assert_eq!(link.to_string(), "</style.css>; rel=preload; as=style; title=\"main style\"");
+        assert_eq!(link.to_string().parse::<LinkValue>().ok(), Some(link));
+    }
+
+    #[test]
+    fn format_valueless_params() {
+        let link = LinkValue::new("/font.woff2").with_rel("preload").with_param("crossorigin", "");
+        assert_eq!(link.to_string(), "</font.woff2>; rel=preload; crossorigin");
+        assert_eq!(link.to_string().parse::<LinkValue>().ok(), Some(link));
+    }
+}
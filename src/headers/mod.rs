@@ -0,0 +1,22 @@
+//!Additional header types and header related utilities.
+//!
+//!These complement the types in the [`header`][header] module, which is
+//!provided by Hyper, with support for headers that are not yet covered there.
+//!
+//![header]: ../header/index.html
+
+pub use self::client_hints::ClientHints;
+pub use self::cookie::{SetCookie, Cookie, SameSite};
+pub use self::link::{Link, LinkValue};
+pub use self::prefer::{Prefer, PreferenceApplied, Preference};
+pub use self::priority::Priority;
+pub use self::warning::{Warning, WarningValue};
+
+pub mod structured;
+
+mod client_hints;
+mod cookie;
+mod link;
+mod prefer;
+mod priority;
+mod warning;
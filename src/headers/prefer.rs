@@ -0,0 +1,231 @@
+use std::fmt;
+use std::str::from_utf8;
+
+use header::{Header, HeaderFormat};
+use HttpResult;
+use HttpError;
+
+use utils::{split_outside, parse_parameter, write_value};
+
+///The `Prefer` header, as defined in [RFC 7240][rfc].
+///
+///It's used by the client to indicate that some particular server behavior
+///is preferred, such as `return=minimal` or `respond-async`. The server is
+///free to ignore any of the preferences, but it should list the ones it
+///honored in a [`PreferenceApplied`][applied] header.
+///
+///```
+///use rustful::header::Header;
+///use rustful::headers::{Prefer, Preference};
+///
+///let prefer = Prefer::parse_header(&[b"return=minimal, wait=10".to_vec()]).unwrap();
+///
+///assert!(prefer.contains(&Preference::return_minimal()));
+///assert_eq!(prefer.wait(), Some(10));
+///```
+///
+///[rfc]: https://tools.ietf.org/html/rfc7240
+///[applied]: struct.PreferenceApplied.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prefer(pub Vec<Preference>);
+
+impl Prefer {
+    ///Find the first preference with the name `name`.
+    pub fn get(&self, name: &str) -> Option<&Preference> {
+        let name = name.to_lowercase();
+        self.0.iter().find(|p| p.name == name)
+    }
+
+    ///Check if a preference with the same name and value as `preference` is
+    ///present. Any parameters are ignored.
+    pub fn contains(&self, preference: &Preference) -> bool {
+        self.get(&preference.name).map(|p| p.value == preference.value).unwrap_or(false)
+    }
+
+    ///Get the number of seconds from a `wait` preference, if any.
+    pub fn wait(&self) -> Option<u32> {
+        self.get("wait").and_then(|p| p.value.as_ref()).and_then(|v| v.parse().ok())
+    }
+}
+
+impl Header for Prefer {
+    fn header_name() -> &'static str {
+        "Prefer"
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> HttpResult<Prefer> {
+        parse_preferences(raw).map(Prefer)
+    }
+}
+
+impl HeaderFormat for Prefer {
+    fn fmt_header(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_preferences(&self.0, f)
+    }
+}
+
+///The `Preference-Applied` header, as defined in [RFC 7240][rfc].
+///
+///It lists the preferences from a [`Prefer`][prefer] header that were
+///honored by the server.
+///
+///[rfc]: https://tools.ietf.org/html/rfc7240
+///[prefer]: struct.Prefer.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct PreferenceApplied(pub Vec<Preference>);
+
+impl Header for PreferenceApplied {
+    fn header_name() -> &'static str {
+        "Preference-Applied"
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> HttpResult<PreferenceApplied> {
+        parse_preferences(raw).map(PreferenceApplied)
+    }
+}
+
+impl HeaderFormat for PreferenceApplied {
+    fn fmt_header(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        //Parameters are not echoed back, according to the specification.
+        for (i, preference) in self.0.iter().enumerate() {
+            if i > 0 {
+                try!(f.write_str(", "));
+            }
+            try!(preference.fmt_name_value(f));
+        }
+
+        Ok(())
+    }
+}
+
+///A single preference in a `Prefer` or `Preference-Applied` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Preference {
+    ///The name of the preference, in lower case.
+    pub name: String,
+
+    ///The value of the preference, if any.
+    pub value: Option<String>,
+
+    ///Any additional parameters. The names are stored in lower case.
+    pub params: Vec<(String, Option<String>)>
+}
+
+impl Preference {
+    ///Create a preference without any parameters.
+    pub fn new<N: Into<String>>(name: N, value: Option<String>) -> Preference {
+        Preference {
+            name: name.into().to_lowercase(),
+            value: value,
+            params: vec![]
+        }
+    }
+
+    ///The `return=minimal` preference. The client would prefer an empty
+    ///response body.
+    pub fn return_minimal() -> Preference {
+        Preference::new("return", Some("minimal".into()))
+    }
+
+    ///The `return=representation` preference. The client would prefer to
+    ///get the current representation of the resource in the response.
+    pub fn return_representation() -> Preference {
+        Preference::new("return", Some("representation".into()))
+    }
+
+    ///The `respond-async` preference. The client would prefer the request to
+    ///be processed asynchronously, with a `202 Accepted` response.
+    pub fn respond_async() -> Preference {
+        Preference::new("respond-async", None)
+    }
+
+    ///The `wait` preference. The client would prefer to not wait for more
+    ///than `seconds` before getting a response.
+    pub fn wait(seconds: u32) -> Preference {
+        Preference::new("wait", Some(seconds.to_string()))
+    }
+
+    fn fmt_name_value(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(f.write_str(&self.name));
+        if let Some(ref value) = self.value {
+            try!(f.write_str("="));
+            try!(write_value(f, value));
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Preference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(self.fmt_name_value(f));
+        for &(ref name, ref value) in &self.params {
+            try!(write!(f, "; {}", name));
+            if let Some(ref value) = *value {
+                try!(f.write_str("="));
+                try!(write_value(f, value));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_preferences(raw: &[Vec<u8>]) -> HttpResult<Vec<Preference>> {
+    let mut preferences = vec![];
+
+    for line in raw {
+        let line = try!(from_utf8(line).map_err(|_| HttpError::Header));
+        for preference in split_outside(line, ',') {
+            let mut parts = split_outside(preference, ';').into_iter().filter(|p| !p.is_empty());
+
+            if let Some((name, value)) = parts.next().and_then(parse_parameter) {
+                preferences.push(Preference {
+                    name: name,
+                    value: value,
+                    params: parts.filter_map(parse_parameter).collect()
+                });
+            }
+        }
+    }
+
+    if preferences.is_empty() {
+        Err(HttpError::Header)
+    } else {
+        Ok(preferences)
+    }
+}
+
+fn fmt_preferences(preferences: &[Preference], f: &mut fmt::Formatter) -> fmt::Result {
+    for (i, preference) in preferences.iter().enumerate() {
+        if i > 0 {
+            try!(f.write_str(", "));
+        }
+        try!(write!(f, "{}", preference));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use header::Header;
+    use super::{Prefer, Preference};
+
+    #[test]
+    fn parse_preferences() {
+        let raw = vec![b"respond-async, WAIT=100".to_vec(), b"handling=lenient; foo=\"a, b\"".to_vec()];
+        let prefer = Prefer::parse_header(&raw).unwrap();
+
+        assert!(prefer.contains(&Preference::respond_async()));
+        assert_eq!(prefer.wait(), Some(100));
+        assert_eq!(prefer.get("handling").map(|p| p.params.clone()), Some(vec![("foo".to_owned(), Some("a, b".to_owned()))]));
+    }
+
+    #[test]
+    fn format_preference() {
+        let mut preference = Preference::return_minimal();
+        preference.params.push(("foo".into(), Some("a b".into())));
+        assert_eq!(preference.to_string(), "return=minimal; foo=\"a b\"");
+    }
+}
@@ -0,0 +1,119 @@
+use std::fmt;
+
+use header::{Header, HeaderFormat};
+use HttpResult;
+
+use headers::structured::{self, BareItem, Item, Member};
+
+const DEFAULT_URGENCY: u8 = 3;
+const LOWEST_URGENCY: u8 = 7;
+
+///The `Priority` header, as defined in [RFC 9218][rfc].
+///
+///It's used by the client to signal how urgent a response is and if it can
+///be processed incrementally. Missing or invalid parameters are replaced
+///with their default values, which is urgency `3` and not incremental.
+///
+///The server doesn't schedule requests or responses by their priority.
+///Requests are handled in the order they arrive, so the priority is only a
+///hint for handlers and middleware that make their own decisions, such as
+///shedding low-priority work under load.
+///
+///```
+///use rustful::header::Header;
+///use rustful::headers::Priority;
+///
+///let priority = Priority::parse_header(&[b"u=1, i".to_vec()]).unwrap();
+///
+///assert_eq!(priority, Priority { urgency: 1, incremental: true });
+///assert_eq!(priority.to_string(), "u=1, i");
+///```
+///
+///[rfc]: https://tools.ietf.org/html/rfc9218
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Priority {
+    ///The urgency, from `0` (highest) to `7` (lowest).
+    pub urgency: u8,
+
+    ///The response can be processed incrementally, such as a progressive
+    ///image.
+    pub incremental: bool
+}
+
+impl Default for Priority {
+    fn default() -> Priority {
+        Priority {
+            urgency: DEFAULT_URGENCY,
+            incremental: false
+        }
+    }
+}
+
+impl Header for Priority {
+    fn header_name() -> &'static str {
+        "Priority"
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> HttpResult<Priority> {
+        let dictionary = try!(structured::parse_dictionary(&try!(structured::join_lines(raw))));
+        let mut priority = Priority::default();
+
+        let urgency = structured::get(&dictionary, "u")
+            .and_then(Member::as_item)
+            .and_then(|item| item.value.as_integer());
+        if let Some(urgency) = urgency {
+            if urgency >= 0 && urgency <= LOWEST_URGENCY as i64 {
+                priority.urgency = urgency as u8;
+            }
+        }
+
+        let incremental = structured::get(&dictionary, "i")
+            .and_then(Member::as_item)
+            .and_then(|item| item.value.as_bool());
+        if let Some(incremental) = incremental {
+            priority.incremental = incremental;
+        }
+
+        Ok(priority)
+    }
+}
+
+impl HeaderFormat for Priority {
+    fn fmt_header(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut dictionary = vec![];
+
+        if self.urgency != DEFAULT_URGENCY {
+            dictionary.push(("u".to_owned(), Member::Item(Item::new(self.urgency as i64))));
+        }
+
+        if self.incremental {
+            dictionary.push(("i".to_owned(), Member::Item(Item::new(BareItem::Boolean(true)))));
+        }
+
+        f.write_str(&structured::serialize_dictionary(&dictionary))
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_header(f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use header::Header;
+    use super::Priority;
+
+    #[test]
+    fn parse_priority() {
+        let priority = Priority::parse_header(&[b"i=?0".to_vec(), b"u=5, foo=bar".to_vec()]).unwrap();
+        assert_eq!(priority, Priority { urgency: 5, incremental: false });
+    }
+
+    #[test]
+    fn parse_invalid_priority() {
+        assert_eq!(Priority::parse_header(&[b"u=8, i=1".to_vec()]).unwrap(), Priority::default());
+        assert!(Priority::parse_header(&[b"u=\"1".to_vec()]).is_err());
+    }
+}
@@ -0,0 +1,720 @@
+//!Structured header fields, as defined in [RFC 8941][rfc].
+//!
+//!Many newer header fields, such as the Client Hints or `Priority`, are
+//!defined as structured fields. They are either a single item, a list or a
+//!dictionary, and this module contains functions for parsing and serializing
+//!all three of them.
+//!
+//!```
+//!use rustful::headers::structured::{self, BareItem};
+//!
+//!let dict = structured::parse_dictionary("u=2, i").unwrap();
+//!
+//!assert_eq!(structured::get(&dict, "u").and_then(|m| m.as_item()).map(|i| &i.value), Some(&BareItem::Integer(2)));
+//!assert_eq!(structured::get(&dict, "i").and_then(|m| m.as_item()).map(|i| &i.value), Some(&BareItem::Boolean(true)));
+//!assert_eq!(structured::serialize_dictionary(&dict), "u=2, i");
+//!```
+//!
+//![rfc]: https://tools.ietf.org/html/rfc8941
+
+use std::fmt;
+use std::str::from_utf8;
+
+use HttpResult;
+use HttpError;
+use utils::{base64_encode, base64_decode};
+
+///A list member or dictionary value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Member {
+    ///A single item.
+    Item(Item),
+
+    ///A list of items, within parentheses.
+    InnerList(InnerList)
+}
+
+impl Member {
+    ///Get the member as an item, if it is one.
+    pub fn as_item(&self) -> Option<&Item> {
+        match *self {
+            Member::Item(ref item) => Some(item),
+            Member::InnerList(_) => None
+        }
+    }
+
+    ///Get the member as an inner list, if it is one.
+    pub fn as_inner_list(&self) -> Option<&InnerList> {
+        match *self {
+            Member::Item(_) => None,
+            Member::InnerList(ref list) => Some(list)
+        }
+    }
+}
+
+impl From<Item> for Member {
+    fn from(item: Item) -> Member {
+        Member::Item(item)
+    }
+}
+
+impl From<InnerList> for Member {
+    fn from(list: InnerList) -> Member {
+        Member::InnerList(list)
+    }
+}
+
+impl fmt::Display for Member {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Member::Item(ref item) => write!(f, "{}", item),
+            Member::InnerList(ref list) => write!(f, "{}", list)
+        }
+    }
+}
+
+///A bare item with parameters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Item {
+    ///The value of the item.
+    pub value: BareItem,
+
+    ///The parameters of the item, in order.
+    pub params: Vec<(String, BareItem)>
+}
+
+impl Item {
+    ///Create an item without parameters.
+    pub fn new<V: Into<BareItem>>(value: V) -> Item {
+        Item {
+            value: value.into(),
+            params: vec![]
+        }
+    }
+
+    ///Get the value of the parameter `name`, if it's present.
+    pub fn param(&self, name: &str) -> Option<&BareItem> {
+        find_param(&self.params, name)
+    }
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}", self.value));
+        fmt_params(&self.params, f)
+    }
+}
+
+///A list of items, with parameters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InnerList {
+    ///The items in the list.
+    pub items: Vec<Item>,
+
+    ///The parameters of the list, in order.
+    pub params: Vec<(String, BareItem)>
+}
+
+impl InnerList {
+    ///Get the value of the parameter `name`, if it's present.
+    pub fn param(&self, name: &str) -> Option<&BareItem> {
+        find_param(&self.params, name)
+    }
+}
+
+impl fmt::Display for InnerList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(f.write_str("("));
+        for (i, item) in self.items.iter().enumerate() {
+            if i > 0 {
+                try!(f.write_str(" "));
+            }
+            try!(write!(f, "{}", item));
+        }
+        try!(f.write_str(")"));
+        fmt_params(&self.params, f)
+    }
+}
+
+///A value without parameters.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BareItem {
+    ///An integer, within ±999,999,999,999,999.
+    Integer(i64),
+
+    ///A decimal number with at most three fractional digits.
+    Decimal(f64),
+
+    ///A string of printable ASCII characters.
+    String(String),
+
+    ///A short textual identifier.
+    Token(String),
+
+    ///Arbitrary binary data.
+    ByteSequence(Vec<u8>),
+
+    ///A boolean.
+    Boolean(bool)
+}
+
+impl BareItem {
+    ///Get the value as an integer, if it is one.
+    pub fn as_integer(&self) -> Option<i64> {
+        match *self {
+            BareItem::Integer(i) => Some(i),
+            _ => None
+        }
+    }
+
+    ///Get the value as a decimal number, if it is one. Integers are
+    ///converted.
+    pub fn as_decimal(&self) -> Option<f64> {
+        match *self {
+            BareItem::Integer(i) => Some(i as f64),
+            BareItem::Decimal(d) => Some(d),
+            _ => None
+        }
+    }
+
+    ///Get the value as a string, if it's a string or a token.
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            BareItem::String(ref s) | BareItem::Token(ref s) => Some(s),
+            _ => None
+        }
+    }
+
+    ///Get the value as a byte sequence, if it is one.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match *self {
+            BareItem::ByteSequence(ref bytes) => Some(bytes),
+            _ => None
+        }
+    }
+
+    ///Get the value as a boolean, if it is one.
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            BareItem::Boolean(b) => Some(b),
+            _ => None
+        }
+    }
+}
+
+impl From<i64> for BareItem {
+    fn from(i: i64) -> BareItem {
+        BareItem::Integer(i)
+    }
+}
+
+impl From<f64> for BareItem {
+    fn from(d: f64) -> BareItem {
+        BareItem::Decimal(d)
+    }
+}
+
+impl From<bool> for BareItem {
+    fn from(b: bool) -> BareItem {
+        BareItem::Boolean(b)
+    }
+}
+
+impl From<String> for BareItem {
+    fn from(s: String) -> BareItem {
+        BareItem::String(s)
+    }
+}
+
+impl<'a> From<&'a str> for BareItem {
+    fn from(s: &'a str) -> BareItem {
+        BareItem::String(s.into())
+    }
+}
+
+impl From<Vec<u8>> for BareItem {
+    fn from(bytes: Vec<u8>) -> BareItem {
+        BareItem::ByteSequence(bytes)
+    }
+}
+
+impl fmt::Display for BareItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BareItem::Integer(i) => write!(f, "{}", i),
+            BareItem::Decimal(d) => {
+                //At least one, and at most three, fractional digits.
+                let formatted = format!("{:.3}", d);
+                let trimmed = formatted.trim_right_matches('0');
+                if trimmed.ends_with('.') {
+                    write!(f, "{}0", trimmed)
+                } else {
+                    f.write_str(trimmed)
+                }
+            },
+            BareItem::String(ref s) => {
+                try!(f.write_str("\""));
+                for c in s.chars() {
+                    if c == '"' || c == '\\' {
+                        try!(f.write_str("\\"));
+                    }
+                    try!(write!(f, "{}", c));
+                }
+                f.write_str("\"")
+            },
+            BareItem::Token(ref s) => f.write_str(s),
+            BareItem::ByteSequence(ref bytes) => write!(f, ":{}:", base64_encode(bytes)),
+            BareItem::Boolean(true) => f.write_str("?1"),
+            BareItem::Boolean(false) => f.write_str("?0")
+        }
+    }
+}
+
+///Join the raw lines of a header into a single field value, as they should
+///be combined before being parsed as a list or a dictionary.
+pub fn join_lines(raw: &[Vec<u8>]) -> HttpResult<String> {
+    let mut value = String::new();
+
+    for line in raw {
+        let line = try!(from_utf8(line).map_err(|_| HttpError::Header));
+        if !value.is_empty() {
+            value.push_str(", ");
+        }
+        value.push_str(line);
+    }
+
+    Ok(value)
+}
+
+///Parse a structured field value as a single item.
+pub fn parse_item(s: &str) -> HttpResult<Item> {
+    let mut parser = Parser::new(s);
+    parser.skip_sp();
+    let item = try!(parser.item());
+    parser.finish(item)
+}
+
+///Parse a structured field value as a list.
+pub fn parse_list(s: &str) -> HttpResult<Vec<Member>> {
+    let mut parser = Parser::new(s);
+    parser.skip_sp();
+
+    let mut members = vec![];
+    while !parser.is_empty() {
+        members.push(try!(parser.member()));
+        if !try!(parser.next_member()) {
+            break;
+        }
+    }
+
+    parser.finish(members)
+}
+
+///Parse a structured field value as a dictionary. A key that appears more
+///than once keeps its first position, but gets its last value.
+pub fn parse_dictionary(s: &str) -> HttpResult<Vec<(String, Member)>> {
+    let mut parser = Parser::new(s);
+    parser.skip_sp();
+
+    let mut members: Vec<(String, Member)> = vec![];
+    while !parser.is_empty() {
+        let key = try!(parser.key());
+        let member = if parser.eat(b'=') {
+            try!(parser.member())
+        } else {
+            Member::Item(Item {
+                value: BareItem::Boolean(true),
+                params: try!(parser.params())
+            })
+        };
+
+        insert(&mut members, key, member);
+
+        if !try!(parser.next_member()) {
+            break;
+        }
+    }
+
+    parser.finish(members)
+}
+
+///Find the value of `key` in a parsed dictionary.
+pub fn get<'a>(dictionary: &'a [(String, Member)], key: &str) -> Option<&'a Member> {
+    dictionary.iter().find(|&&(ref k, _)| k == key).map(|&(_, ref member)| member)
+}
+
+///Serialize a list of members.
+pub fn serialize_list(members: &[Member]) -> String {
+    members.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+///Serialize a dictionary. Members with the value `?1` are written as only
+///their keys and parameters.
+pub fn serialize_dictionary(members: &[(String, Member)]) -> String {
+    members.iter().map(|&(ref key, ref member)| {
+        match *member {
+            Member::Item(Item { value: BareItem::Boolean(true), ref params }) => {
+                let mut params_string = String::new();
+                for &(ref name, ref value) in params {
+                    params_string.push_str(&param_to_string(name, value));
+                }
+                format!("{}{}", key, params_string)
+            },
+            ref member => format!("{}={}", key, member)
+        }
+    }).collect::<Vec<_>>().join(", ")
+}
+
+fn insert<T>(members: &mut Vec<(String, T)>, key: String, value: T) {
+    if let Some(existing) = members.iter_mut().find(|member| member.0 == key) {
+        existing.1 = value;
+        return;
+    }
+
+    members.push((key, value));
+}
+
+fn find_param<'a>(params: &'a [(String, BareItem)], name: &str) -> Option<&'a BareItem> {
+    params.iter().find(|&&(ref k, _)| k == name).map(|&(_, ref value)| value)
+}
+
+fn param_to_string(name: &str, value: &BareItem) -> String {
+    if let BareItem::Boolean(true) = *value {
+        format!(";{}", name)
+    } else {
+        format!(";{}={}", name, value)
+    }
+}
+
+fn fmt_params(params: &[(String, BareItem)], f: &mut fmt::Formatter) -> fmt::Result {
+    for &(ref name, ref value) in params {
+        try!(f.write_str(&param_to_string(name, value)));
+    }
+
+    Ok(())
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Parser<'a> {
+        Parser {
+            input: input.as_bytes(),
+            pos: 0
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).cloned()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.input.len()
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_sp(&mut self) {
+        while self.eat(b' ') {}
+    }
+
+    fn skip_ows(&mut self) {
+        while self.eat(b' ') || self.eat(b'\t') {}
+    }
+
+    fn finish<T>(mut self, value: T) -> HttpResult<T> {
+        self.skip_sp();
+        if self.is_empty() {
+            Ok(value)
+        } else {
+            Err(HttpError::Header)
+        }
+    }
+
+    //Moves past the comma between two list or dictionary members. Returns
+    //`false` if there are no more members.
+    fn next_member(&mut self) -> HttpResult<bool> {
+        self.skip_ows();
+        if self.is_empty() {
+            return Ok(false);
+        }
+
+        if !self.eat(b',') {
+            return Err(HttpError::Header);
+        }
+
+        self.skip_ows();
+        if self.is_empty() {
+            //Trailing comma
+            Err(HttpError::Header)
+        } else {
+            Ok(true)
+        }
+    }
+
+    fn member(&mut self) -> HttpResult<Member> {
+        if self.peek() == Some(b'(') {
+            self.inner_list().map(Member::InnerList)
+        } else {
+            self.item().map(Member::Item)
+        }
+    }
+
+    fn inner_list(&mut self) -> HttpResult<InnerList> {
+        if !self.eat(b'(') {
+            return Err(HttpError::Header);
+        }
+
+        let mut items = vec![];
+        loop {
+            self.skip_sp();
+            if self.eat(b')') {
+                return Ok(InnerList {
+                    items: items,
+                    params: try!(self.params())
+                });
+            }
+
+            items.push(try!(self.item()));
+
+            match self.peek() {
+                Some(b' ') | Some(b')') => {},
+                _ => return Err(HttpError::Header)
+            }
+        }
+    }
+
+    fn item(&mut self) -> HttpResult<Item> {
+        Ok(Item {
+            value: try!(self.bare_item()),
+            params: try!(self.params())
+        })
+    }
+
+    fn params(&mut self) -> HttpResult<Vec<(String, BareItem)>> {
+        let mut params = vec![];
+
+        while self.eat(b';') {
+            self.skip_sp();
+            let key = try!(self.key());
+            let value = if self.eat(b'=') {
+                try!(self.bare_item())
+            } else {
+                BareItem::Boolean(true)
+            };
+
+            insert(&mut params, key, value);
+        }
+
+        Ok(params)
+    }
+
+    fn key(&mut self) -> HttpResult<String> {
+        let start = self.pos;
+
+        match self.peek() {
+            Some(b'*') => {},
+            Some(c) if c >= b'a' && c <= b'z' => {},
+            _ => return Err(HttpError::Header)
+        }
+
+        while let Some(c) = self.peek() {
+            let valid = (c >= b'a' && c <= b'z') || (c >= b'0' && c <= b'9') ||
+                c == b'_' || c == b'-' || c == b'.' || c == b'*';
+            if !valid {
+                break;
+            }
+            self.pos += 1;
+        }
+
+        Ok(String::from_utf8_lossy(&self.input[start..self.pos]).into_owned())
+    }
+
+    fn bare_item(&mut self) -> HttpResult<BareItem> {
+        match self.peek() {
+            Some(b'-') => self.number(),
+            Some(c) if c >= b'0' && c <= b'9' => self.number(),
+            Some(b'"') => self.string(),
+            Some(b'*') => self.token(),
+            Some(c) if (c >= b'a' && c <= b'z') || (c >= b'A' && c <= b'Z') => self.token(),
+            Some(b':') => self.byte_sequence(),
+            Some(b'?') => self.boolean(),
+            _ => Err(HttpError::Header)
+        }
+    }
+
+    fn number(&mut self) -> HttpResult<BareItem> {
+        let start = self.pos;
+        self.eat(b'-');
+
+        let mut integer_digits = 0;
+        let mut fraction_digits = None;
+
+        while let Some(c) = self.peek() {
+            if c >= b'0' && c <= b'9' {
+                match fraction_digits {
+                    Some(ref mut digits) => *digits += 1,
+                    None => integer_digits += 1
+                }
+            } else if c == b'.' && fraction_digits.is_none() && integer_digits > 0 {
+                if integer_digits > 12 {
+                    return Err(HttpError::Header);
+                }
+                fraction_digits = Some(0);
+            } else {
+                break;
+            }
+
+            self.pos += 1;
+
+            if fraction_digits.is_none() && integer_digits > 15 {
+                return Err(HttpError::Header);
+            }
+        }
+
+        let number = try!(from_utf8(&self.input[start..self.pos]).map_err(|_| HttpError::Header));
+
+        match fraction_digits {
+            None if integer_digits > 0 => number.parse().map(BareItem::Integer).map_err(|_| HttpError::Header),
+            Some(digits) if digits > 0 && digits <= 3 => number.parse().map(BareItem::Decimal).map_err(|_| HttpError::Header),
+            _ => Err(HttpError::Header)
+        }
+    }
+
+    fn string(&mut self) -> HttpResult<BareItem> {
+        if !self.eat(b'"') {
+            return Err(HttpError::Header);
+        }
+
+        let mut string = String::new();
+        loop {
+            match self.peek() {
+                Some(b'\\') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(c) if c == b'"' || c == b'\\' => string.push(c as char),
+                        _ => return Err(HttpError::Header)
+                    }
+                },
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(BareItem::String(string));
+                },
+                Some(c) if c >= 0x20 && c <= 0x7e => string.push(c as char),
+                _ => return Err(HttpError::Header)
+            }
+
+            self.pos += 1;
+        }
+    }
+
+    fn token(&mut self) -> HttpResult<BareItem> {
+        let start = self.pos;
+        self.pos += 1;
+
+        while let Some(c) = self.peek() {
+            if !::utils::is_token_char(c) && c != b':' && c != b'/' {
+                break;
+            }
+            self.pos += 1;
+        }
+
+        Ok(BareItem::Token(String::from_utf8_lossy(&self.input[start..self.pos]).into_owned()))
+    }
+
+    fn byte_sequence(&mut self) -> HttpResult<BareItem> {
+        if !self.eat(b':') {
+            return Err(HttpError::Header);
+        }
+
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c == b':' {
+                let bytes = try!(base64_decode(&self.input[start..self.pos]).ok_or(HttpError::Header));
+                self.pos += 1;
+                return Ok(BareItem::ByteSequence(bytes));
+            }
+            self.pos += 1;
+        }
+
+        Err(HttpError::Header)
+    }
+
+    fn boolean(&mut self) -> HttpResult<BareItem> {
+        if !self.eat(b'?') {
+            return Err(HttpError::Header);
+        }
+
+        if self.eat(b'1') {
+            Ok(BareItem::Boolean(true))
+        } else if self.eat(b'0') {
+            Ok(BareItem::Boolean(false))
+        } else {
+            Err(HttpError::Header)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_item, parse_list, parse_dictionary, serialize_list, serialize_dictionary, get};
+    use super::{Item, BareItem, Member};
+
+    #[test]
+    fn parse_items() {
+        assert_eq!(parse_item("42").unwrap(), Item::new(42i64));
+        assert_eq!(parse_item("-1.5").unwrap(), Item::new(-1.5));
+        assert_eq!(parse_item("\"a \\\"b\\\"\"").unwrap(), Item::new("a \"b\""));
+        assert_eq!(parse_item("foo/bar:baz").unwrap().value, BareItem::Token("foo/bar:baz".into()));
+        assert_eq!(parse_item(":aGVsbG8=:").unwrap(), Item::new(b"hello".to_vec()));
+        assert_eq!(parse_item("?0;a;b=1").unwrap().param("b"), Some(&BareItem::Integer(1)));
+
+        assert!(parse_item("1.2345").is_err());
+        assert!(parse_item("1234567890123456").is_err());
+        assert!(parse_item("\"unterminated").is_err());
+        assert!(parse_item("?2").is_err());
+        assert!(parse_item("a b").is_err());
+    }
+
+    #[test]
+    fn parse_lists() {
+        let list = parse_list("a, (b \"c\");x=1, ?1").unwrap();
+        assert_eq!(list.len(), 3);
+        assert_eq!(list[1].as_inner_list().map(|l| l.items.len()), Some(2));
+        assert_eq!(list[1].as_inner_list().and_then(|l| l.param("x")), Some(&BareItem::Integer(1)));
+        assert_eq!(serialize_list(&list), "a, (b \"c\");x=1, ?1");
+
+        assert_eq!(parse_list("").unwrap(), vec![]);
+        assert!(parse_list("a,").is_err());
+        assert!(parse_list("(a b").is_err());
+    }
+
+    #[test]
+    fn parse_dictionaries() {
+        let dict = parse_dictionary("a=1, b;x=?0, c=(1 2), a=2").unwrap();
+        assert_eq!(dict.len(), 3);
+        assert_eq!(get(&dict, "a"), Some(&Member::Item(Item::new(2i64))));
+        assert_eq!(get(&dict, "b").and_then(|m| m.as_item()).map(|i| &i.value), Some(&BareItem::Boolean(true)));
+        assert_eq!(serialize_dictionary(&dict), "a=2, b;x=?0, c=(1 2)");
+
+        assert!(parse_dictionary("A=1").is_err());
+    }
+
+    #[test]
+    fn serialize_bare_items() {
+        assert_eq!(BareItem::Decimal(1.0).to_string(), "1.0");
+        assert_eq!(BareItem::Decimal(0.12345).to_string(), "0.123");
+        assert_eq!(BareItem::ByteSequence(b"hi".to_vec()).to_string(), ":aGk=:");
+        assert_eq!(BareItem::Boolean(false).to_string(), "?0");
+    }
+}
@@ -0,0 +1,254 @@
+use std::fmt;
+use std::str::from_utf8;
+
+use header::{Header, HeaderFormat, HttpDate};
+use HttpResult;
+use HttpError;
+
+use utils::{split_outside, write_value};
+
+///The `Warning` header, as defined in [RFC 7234][rfc].
+///
+///It carries additional information about the status or transformation of a
+///message, such as that a cached response is stale. A cache that serves
+///stale content, for example during an outage of the origin server, should
+///add a `110` (or `111` if revalidation failed) warning to the response.
+///
+///```
+///use rustful::headers::{Warning, WarningValue};
+///
+///let warning = Warning(vec![WarningValue::stale("cache.example.com")]);
+///assert_eq!(warning.to_string(), "110 cache.example.com \"Response is Stale\"");
+///```
+///
+///[rfc]: https://tools.ietf.org/html/rfc7234#section-5.5
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning(pub Vec<WarningValue>);
+
+impl Warning {
+    ///Find the first warning with the code `code`.
+    pub fn find_code(&self, code: u16) -> Option<&WarningValue> {
+        self.0.iter().find(|w| w.code == code)
+    }
+}
+
+impl Header for Warning {
+    fn header_name() -> &'static str {
+        "Warning"
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> HttpResult<Warning> {
+        let mut warnings = vec![];
+
+        for line in raw {
+            let line = try!(from_utf8(line).map_err(|_| HttpError::Header));
+            for value in split_outside(line, ',') {
+                if !value.is_empty() {
+                    warnings.push(try!(value.parse::<WarningValue>()));
+                }
+            }
+        }
+
+        if warnings.is_empty() {
+            Err(HttpError::Header)
+        } else {
+            Ok(Warning(warnings))
+        }
+    }
+}
+
+impl HeaderFormat for Warning {
+    fn fmt_header(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, warning) in self.0.iter().enumerate() {
+            if i > 0 {
+                try!(f.write_str(", "));
+            }
+            try!(write!(f, "{}", warning));
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_header(f)
+    }
+}
+
+///A single warning in a `Warning` header.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WarningValue {
+    ///The three digit warning code.
+    pub code: u16,
+
+    ///The name or pseudonym of the server adding the warning. `-` can be
+    ///used if it's unknown.
+    pub agent: String,
+
+    ///A human readable description of the warning.
+    pub text: String,
+
+    ///The date of the message that the warning was added to, if any.
+    pub date: Option<HttpDate>
+}
+
+impl WarningValue {
+    ///Create a warning without a date.
+    pub fn new<A: Into<String>, T: Into<String>>(code: u16, agent: A, text: T) -> WarningValue {
+        WarningValue {
+            code: code,
+            agent: agent.into(),
+            text: text.into(),
+            date: None
+        }
+    }
+
+    ///`110 Response is Stale`. The response is stale.
+    pub fn stale<A: Into<String>>(agent: A) -> WarningValue {
+        WarningValue::new(110, agent, "Response is Stale")
+    }
+
+    ///`111 Revalidation Failed`. A stale response is served because it
+    ///could not be revalidated, for example because the origin server
+    ///could not be reached.
+    pub fn revalidation_failed<A: Into<String>>(agent: A) -> WarningValue {
+        WarningValue::new(111, agent, "Revalidation Failed")
+    }
+
+    ///`112 Disconnected Operation`. The cache is intentionally disconnected
+    ///from the rest of the network.
+    pub fn disconnected<A: Into<String>>(agent: A) -> WarningValue {
+        WarningValue::new(112, agent, "Disconnected Operation")
+    }
+
+    ///`113 Heuristic Expiration`. The freshness lifetime was heuristically
+    ///chosen to be more than 24 hours and the response is older than that.
+    pub fn heuristic_expiration<A: Into<String>>(agent: A) -> WarningValue {
+        WarningValue::new(113, agent, "Heuristic Expiration")
+    }
+
+    ///`214 Transformation Applied`. The content has been transformed by a
+    ///proxy.
+    pub fn transformation_applied<A: Into<String>>(agent: A) -> WarningValue {
+        WarningValue::new(214, agent, "Transformation Applied")
+    }
+
+    ///Check if the warning is of the `1xx` kind, which has to be removed
+    ///from a cached response after a successful revalidation.
+    pub fn is_freshness_warning(&self) -> bool {
+        self.code >= 100 && self.code < 200
+    }
+}
+
+impl ::std::str::FromStr for WarningValue {
+    type Err = HttpError;
+
+    fn from_str(s: &str) -> HttpResult<WarningValue> {
+        let s = s.trim();
+        let (code, rest) = try!(split_word(s).ok_or(HttpError::Header));
+        let (agent, rest) = try!(split_word(rest).ok_or(HttpError::Header));
+        let (text, rest) = try!(split_quoted(rest).ok_or(HttpError::Header));
+
+        let code = match code.parse::<u16>() {
+            Ok(code) if code >= 100 && code < 1000 => code,
+            _ => return Err(HttpError::Header)
+        };
+
+        let date = if rest.is_empty() {
+            None
+        } else {
+            let (date, rest) = try!(split_quoted(rest).ok_or(HttpError::Header));
+            if !rest.is_empty() {
+                return Err(HttpError::Header);
+            }
+            Some(try!(date.parse().map_err(|_| HttpError::Header)))
+        };
+
+        Ok(WarningValue {
+            code: code,
+            agent: agent.to_owned(),
+            text: text,
+            date: date
+        })
+    }
+}
+
+impl fmt::Display for WarningValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{:03} {} ", self.code, self.agent));
+
+        //The text is always quoted, even if it's a token.
+        if ::utils::is_token(&self.text) {
+            try!(write!(f, "\"{}\"", self.text));
+        } else {
+            try!(write_value(f, &self.text));
+        }
+
+        if let Some(ref date) = self.date {
+            try!(write!(f, " \"{}\"", date));
+        }
+
+        Ok(())
+    }
+}
+
+//Splits off the first space separated word.
+fn split_word(s: &str) -> Option<(&str, &str)> {
+    match s.find(' ') {
+        Some(0) | None => None,
+        Some(i) => Some((&s[..i], s[i..].trim_left()))
+    }
+}
+
+//Splits off the first quoted string and unquotes it.
+fn split_quoted(s: &str) -> Option<(String, &str)> {
+    if !s.starts_with('"') {
+        return None;
+    }
+
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            return Some((::utils::unquote(&s[..i + 1]), s[i + 1..].trim_left()));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use header::Header;
+    use super::{Warning, WarningValue};
+
+    #[test]
+    fn parse_warnings() {
+        let raw = vec![b"110 cache.example.com \"Response is Stale\", 299 - \"Deprecated, \\\"really\\\"\" \"Sun, 06 Nov 1994 08:49:37 GMT\"".to_vec()];
+        let warning = Warning::parse_header(&raw).unwrap();
+
+        assert_eq!(warning.0.len(), 2);
+        assert_eq!(warning.0[0], WarningValue::stale("cache.example.com"));
+        assert_eq!(warning.0[1].agent, "-");
+        assert_eq!(warning.0[1].text, "Deprecated, \"really\"");
+        assert!(warning.0[1].date.is_some());
+        assert!(!warning.0[1].is_freshness_warning());
+    }
+
+    #[test]
+    fn parse_invalid_warnings() {
+        assert!(Warning::parse_header(&[b"110 \"Response is Stale\"".to_vec()]).is_err());
+        assert!(Warning::parse_header(&[b"1100 - \"Oops\"".to_vec()]).is_err());
+        assert!(Warning::parse_header(&[b"110 - Oops".to_vec()]).is_err());
+    }
+
+    #[test]
+    fn format_warning() {
+        let warning = WarningValue::new(199, "-", "Quote \"this\"");
+        assert_eq!(warning.to_string(), "199 - \"Quote \\\"this\\\"\"");
+    }
+}
@@ -78,6 +78,16 @@ extern crate rustc_serialize;
 #[cfg(feature = "multipart")]
 extern crate multipart;
 
+#[cfg(any(feature = "decompression", feature = "compression"))]
+extern crate flate2;
+
+#[cfg(feature = "brotli")]
+extern crate brotli2;
+
+#[cfg(feature = "log")]
+#[macro_use]
+extern crate log as log_crate;
+
 extern crate url;
 extern crate time;
 extern crate hyper;
@@ -112,8 +122,31 @@ pub mod handler;
 pub mod context;
 pub mod response;
 pub mod filter;
+pub mod middleware;
+pub mod rate_limit;
+pub mod circuit_breaker;
+pub mod request_id;
+pub mod metrics;
+pub mod tracing;
 pub mod log;
 pub mod file;
+pub mod headers;
+pub mod sync;
+pub mod events;
+pub mod webhooks;
+pub mod store;
+pub mod cluster;
+pub mod cors;
+pub mod security;
+pub mod session;
+pub mod error_pages;
+pub mod template;
+pub mod completion;
+pub mod error_report;
+#[cfg(feature = "rustc_json_body")]
+pub mod formats;
+#[cfg(feature = "compression")]
+pub mod compression;
 
 use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6, Ipv4Addr};
 use std::str::FromStr;
@@ -1,12 +1,41 @@
 //!Log tools.
 
 use std::io::{self, Write};
+use std::fmt;
 use std::fs;
-use std::sync::Mutex;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, SyncSender, Receiver, TrySendError};
+use std::thread::{self, JoinHandle};
+
+use time::{self, Duration, SteadyTime};
+
+#[cfg(feature = "compression")]
+use flate2;
+#[cfg(feature = "compression")]
+use flate2::write::GzEncoder;
+
+#[cfg(feature = "log")]
+use log_crate;
 
 ///The result from a call to any of the `try_*` methods in `Log`.
 pub type Result = io::Result<()>;
 
+///A named value in a structured log message.
+pub type Field<'a> = (&'a str, &'a fmt::Display);
+
+///The severity of a log message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    ///A note.
+    Note,
+    ///A warning.
+    Warning,
+    ///An error.
+    Error
+}
+
 ///Common trait for log tools.
 pub trait Log: Send + Sync {
     ///Print a note to the log or return eventual errors.
@@ -34,6 +63,90 @@ pub trait Log: Send + Sync {
     fn error(&self, message: &str) {
         self.try_error(message);
     }
+
+    ///Print a message with named values to the log or return eventual
+    ///errors.
+    ///
+    ///The default implementation formats the message and the values as
+    ///[`logfmt`][logfmt], and prints it using `try_note`, `try_warning` or
+    ///`try_error`. Log tools that write JSON, or some other structured
+    ///format, can override it to write the values as they are.
+    ///
+    ///[logfmt]: fn.logfmt.html
+    fn try_log(&self, level: Level, message: &str, fields: &[Field]) -> Result {
+        let message = logfmt(message, fields);
+        match level {
+            Level::Note => self.try_note(&message),
+            Level::Warning => self.try_warning(&message),
+            Level::Error => self.try_error(&message)
+        }
+    }
+
+    ///Print a note with named values to the log and ignore any errors.
+    ///
+    ///```
+    ///use rustful::log::{Log, StdOut};
+    ///
+    /////Prints `note: request handled status=200 path=/users`
+    ///StdOut.note_with("request handled", &[("status", &200), ("path", &"/users")]);
+    ///```
+    #[allow(unused_must_use)]
+    #[inline]
+    fn note_with(&self, message: &str, fields: &[Field]) {
+        self.try_log(Level::Note, message, fields);
+    }
+    ///Print a warning with named values to the log and ignore any errors.
+    #[allow(unused_must_use)]
+    #[inline]
+    fn warning_with(&self, message: &str, fields: &[Field]) {
+        self.try_log(Level::Warning, message, fields);
+    }
+    ///Print an error with named values to the log and ignore any errors.
+    #[allow(unused_must_use)]
+    #[inline]
+    fn error_with(&self, message: &str, fields: &[Field]) {
+        self.try_log(Level::Error, message, fields);
+    }
+}
+
+///Format a message and named values as `logfmt`, where the message comes
+///first, followed by the values as `name=value` pairs. Values with spaces,
+///quotes, `=` or control characters are quoted.
+///
+///```
+///use rustful::log::logfmt;
+///
+///let line = logfmt("request failed", &[("status", &500), ("reason", &"no \"db\"")]);
+///assert_eq!(line, r#"request failed status=500 reason="no \"db\"""#);
+///```
+pub fn logfmt(message: &str, fields: &[Field]) -> String {
+    let mut line = message.to_owned();
+
+    for &(name, value) in fields {
+        let value = value.to_string();
+        line.push(' ');
+        line.push_str(name);
+        line.push('=');
+
+        if value.is_empty() || value.chars().any(|c| c == ' ' || c == '=' || c == '"' || c.is_control()) {
+            line.push('"');
+            for c in value.chars() {
+                match c {
+                    '"' => line.push_str("\\\""),
+                    '\\' => line.push_str("\\\\"),
+                    '\n' => line.push_str("\\n"),
+                    '\r' => line.push_str("\\r"),
+                    '\t' => line.push_str("\\t"),
+                    c => line.push(c)
+                }
+            }
+            line.push('"');
+        } else {
+            line.push_str(&value);
+        }
+    }
+
+    line
 }
 
 ///A quiet log tool. Nothing will be printed anywhere.
@@ -74,48 +187,715 @@ impl Log for StdOut {
 }
 
 ///Log tool for printing to a file.
+///
+///A `File` that is opened using `File::open` knows its path, and can be
+///rotated when it grows too large, or when it gets too old. The current
+///file is then renamed to `<path>.1`, the previous `<path>.1` to
+///`<path>.2`, and so on, and a new file is created. It can also be told to
+///reopen the file, for example after it has been moved by an external tool.
+///
+///```no_run
+///extern crate rustful;
+///extern crate time;
+///use rustful::{Server, Context, Response};
+///use rustful::log::File;
+///
+///# fn main() {
+///let log = File::open("server.log").unwrap()
+///    .max_size(10 * 1024 * 1024)
+///    .rotate_every(time::Duration::days(1))
+///    .keep(7);
+///
+///let server = Server {
+///    log: Box::new(log),
+///    ..Server::new(|_: Context, response: Response| response.send("hello"))
+///};
+///# }
+///```
 pub struct File {
-    file: Mutex<fs::File>
+    file: Mutex<OpenFile>,
+    path: Option<PathBuf>,
+    max_size: Option<u64>,
+    interval: Option<Duration>,
+    keep: usize,
+    compress: bool,
+    reopen: Arc<AtomicBool>
+}
+
+struct OpenFile {
+    file: fs::File,
+    size: u64,
+    opened: SteadyTime
 }
 
 impl File {
-    ///Create a new `File` logger with `file` as output destination.
+    ///Create a new `File` logger with `file` as output destination. It
+    ///can't be rotated or reopened, since its path is unknown.
     pub fn new(file: fs::File) -> File {
+        File::with_file(file, None)
+    }
+
+    ///Open, or create, the file at `path` and append log messages to it.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<File> {
+        let path = path.as_ref().to_path_buf();
+        let file = try!(open_append(&path));
+        Ok(File::with_file(file, Some(path)))
+    }
+
+    fn with_file(file: fs::File, path: Option<PathBuf>) -> File {
+        let size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
         File {
-            file: Mutex::new(file)
+            file: Mutex::new(OpenFile {
+                file: file,
+                size: size,
+                opened: SteadyTime::now()
+            }),
+            path: path,
+            max_size: None,
+            interval: None,
+            keep: 5,
+            compress: false,
+            reopen: Arc::new(AtomicBool::new(false))
         }
     }
+
+    ///Rotate the file before it grows larger than `bytes`. This only
+    ///applies to files from `File::open`.
+    pub fn max_size(mut self, bytes: u64) -> File {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    ///Rotate the file when it has been written to for `interval`. This
+    ///only applies to files from `File::open`.
+    pub fn rotate_every(mut self, interval: Duration) -> File {
+        self.interval = Some(interval);
+        self
+    }
+
+    ///Keep this many rotated files, and remove the older ones. Default is
+    ///5, and 0 means that the rotated file is removed right away.
+    pub fn keep(mut self, files: usize) -> File {
+        self.keep = files;
+        self
+    }
+
+    ///Compress the rotated files with gzip, and add `.gz` to their names.
+    ///Default is `false`.
+    ///
+    ///It's only available with the `compression` feature.
+    #[cfg(feature = "compression")]
+    pub fn compress(mut self, compress: bool) -> File {
+        self.compress = compress;
+        self
+    }
+
+    ///Get a handle that can be used to make the logger reopen its file
+    ///before it writes the next message. This is usually done when the
+    ///process receives `SIGHUP`, and it's safe to use from a signal
+    ///handler, since it only sets a flag.
+    pub fn reopen_handle(&self) -> ReopenHandle {
+        ReopenHandle(self.reopen.clone())
+    }
+
+    ///Rotate the file now. Nothing happens if its path is unknown.
+    pub fn rotate(&self) -> Result {
+        let mut file = try!(self.lock());
+        self.rotate_file(&mut file)
+    }
+
+    fn lock(&self) -> io::Result<MutexGuard<OpenFile>> {
+        self.file.lock().map_err(|_| io::Error::new(io::ErrorKind::Other, "poisoned log file lock"))
+    }
+
+    fn write_message(&self, prefix: &str, message: &str) -> Result {
+        let mut file = try!(self.lock());
+
+        if self.reopen.swap(false, Ordering::SeqCst) {
+            if let Some(ref path) = self.path {
+                let new_file = try!(open_append(path));
+                let size = new_file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+                *file = OpenFile {
+                    file: new_file,
+                    size: size,
+                    opened: SteadyTime::now()
+                };
+            }
+        }
+
+        let line = format!("{}: {}", prefix, message);
+        let too_large = self.max_size.map(|max| file.size > 0 && file.size + line.len() as u64 > max).unwrap_or(false);
+        let too_old = self.interval.map(|interval| SteadyTime::now() - file.opened >= interval).unwrap_or(false);
+        if too_large || too_old {
+            try!(self.rotate_file(&mut file));
+        }
+
+        try!(file.file.write_all(line.as_bytes()));
+        file.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate_file(&self, file: &mut OpenFile) -> Result {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(())
+        };
+
+        try!(file.file.flush());
+        let extension = if self.compress { ".gz" } else { "" };
+
+        if self.keep == 0 {
+            try!(fs::remove_file(path));
+        } else {
+            //Make room for the current file by moving the old ones up one step.
+            let _ = fs::remove_file(numbered(path, self.keep, extension));
+            for n in (1..self.keep).rev() {
+                let from = numbered(path, n, extension);
+                if from.exists() {
+                    try!(fs::rename(from, numbered(path, n + 1, extension)));
+                }
+            }
+
+            let rotated = numbered(path, 1, "");
+            try!(fs::rename(path, &rotated));
+            if self.compress {
+                try!(compress_file(&rotated, &numbered(path, 1, extension)));
+            }
+        }
+
+        *file = OpenFile {
+            file: try!(open_append(path)),
+            size: 0,
+            opened: SteadyTime::now()
+        };
+
+        Ok(())
+    }
 }
 
 impl Log for File {
     fn try_note(&self, message: &str) -> Result {
-        let mut f = match self.file.lock() {
-            Ok(f) => f,
-            Err(_e) => return Err(io::Error::new(io::ErrorKind::Other, "poisoned log file lock"))
-        };
-        write!(f, "note: {}", message)
+        self.write_message("note", message)
+    }
+
+    fn try_warning(&self, message: &str) -> Result {
+        self.write_message("warning", message)
+    }
+
+    fn try_error(&self, message: &str) -> Result {
+        self.write_message("error", message)
+    }
+}
+
+///Makes a `File` logger reopen its file. It's created using
+///`File::reopen_handle`.
+///
+///```no_run
+///use rustful::log::File;
+///
+///let log = File::open("server.log").unwrap();
+///let handle = log.reopen_handle();
+///
+/////Call this from a `SIGHUP` handler, after the file has been moved
+///handle.reopen();
+///```
+#[derive(Clone)]
+pub struct ReopenHandle(Arc<AtomicBool>);
+
+impl ReopenHandle {
+    ///Reopen the file before the next message is written.
+    pub fn reopen(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<fs::File> {
+    fs::OpenOptions::new().create(true).append(true).open(path)
+}
+
+//Gets the path of the rotated file number `n`.
+fn numbered(path: &Path, n: usize, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}{}", n, extension));
+    PathBuf::from(name)
+}
+
+#[cfg(feature = "compression")]
+fn compress_file(from: &Path, to: &Path) -> Result {
+    let mut source = try!(fs::File::open(from));
+    let mut encoder = GzEncoder::new(try!(fs::File::create(to)), flate2::Compression::Default);
+    try!(io::copy(&mut source, &mut encoder));
+    try!(encoder.finish());
+    fs::remove_file(from)
+}
+
+#[cfg(not(feature = "compression"))]
+fn compress_file(_from: &Path, _to: &Path) -> Result {
+    Ok(())
+}
+
+///Log tool for printing JSON objects, one per line.
+///
+///Each entry has a `timestamp` in RFC 3339 format and UTC, a `level`, which
+///is `note`, `warning` or `error`, a `message`, and the named values from
+///`Log::try_log`. The values are written as strings, and values that would
+///replace any of the other properties are prefixed with `field_`. The
+///output can be shipped as it is to tools that read JSON lines.
+///
+///```
+///use std::io;
+///use rustful::{Server, Context, Response};
+///use rustful::log::JsonLines;
+///
+/////Prints lines like
+/////{"timestamp":"2016-02-13T14:07:23Z","level":"note","message":"hello","status":"200"}
+///let server = Server {
+///    log: Box::new(JsonLines::new(io::stdout())),
+///    ..Server::new(|_: Context, response: Response| response.send("hello"))
+///};
+///```
+pub struct JsonLines<W> {
+    writer: Mutex<W>
+}
+
+impl<W: Write + Send> JsonLines<W> {
+    ///Create a new `JsonLines` logger with `writer` as output destination.
+    pub fn new(writer: W) -> JsonLines<W> {
+        JsonLines {
+            writer: Mutex::new(writer)
+        }
+    }
+}
+
+impl<W: Write + Send> Log for JsonLines<W> {
+    fn try_note(&self, message: &str) -> Result {
+        self.try_log(Level::Note, message, &[])
     }
 
     fn try_warning(&self, message: &str) -> Result {
-        let mut f = match self.file.lock() {
-            Ok(f) => f,
-            Err(_e) => return Err(io::Error::new(io::ErrorKind::Other, "poisoned log file lock"))
+        self.try_log(Level::Warning, message, &[])
+    }
+
+    fn try_error(&self, message: &str) -> Result {
+        self.try_log(Level::Error, message, &[])
+    }
+
+    fn try_log(&self, level: Level, message: &str, fields: &[Field]) -> Result {
+        let line = json_line(&time::now_utc().rfc3339().to_string(), level, message, fields);
+        let mut writer = match self.writer.lock() {
+            Ok(writer) => writer,
+            Err(_e) => return Err(io::Error::new(io::ErrorKind::Other, "poisoned log writer lock"))
         };
-        write!(f, "warning: {}", message)
+        try!(writer.write_all(line.as_bytes()));
+        writer.flush()
+    }
+}
+
+//Formats an entry as a JSON object, followed by a line break.
+fn json_line(timestamp: &str, level: Level, message: &str, fields: &[Field]) -> String {
+    let level = match level {
+        Level::Note => "note",
+        Level::Warning => "warning",
+        Level::Error => "error"
+    };
+
+    let mut line = String::from("{\"timestamp\":");
+    push_json_string(&mut line, timestamp);
+    line.push_str(",\"level\":");
+    push_json_string(&mut line, level);
+    line.push_str(",\"message\":");
+    push_json_string(&mut line, message);
+
+    for &(name, value) in fields {
+        line.push(',');
+        match name {
+            "timestamp" | "level" | "message" => push_json_string(&mut line, &format!("field_{}", name)),
+            name => push_json_string(&mut line, name)
+        }
+        line.push(':');
+        push_json_string(&mut line, &value.to_string());
+    }
+
+    line.push_str("}\n");
+    line
+}
+
+fn push_json_string(output: &mut String, value: &str) {
+    output.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if (c as u32) < 0x20 => output.push_str(&format!("\\u{:04x}", c as u32)),
+            c => output.push(c)
+        }
+    }
+    output.push('"');
+}
+
+///Log tool that sends every entry to several other log tools.
+///
+///Each of them has a lowest level, and only gets the entries that are at
+///least as severe. They are all used, even if some of them fail, and the
+///first error is returned.
+///
+///```no_run
+///use std::fs;
+///use rustful::{Server, Context, Response};
+///use rustful::log::{Tee, StdOut, File, Level};
+///
+///let errors = fs::File::create("errors.log").unwrap();
+///let log = Tee::new()
+///    .sink(StdOut)
+///    .sink_at(File::new(errors), Level::Error);
+///
+///let server = Server {
+///    log: Box::new(log),
+///    ..Server::new(|_: Context, response: Response| response.send("hello"))
+///};
+///```
+pub struct Tee {
+    sinks: Vec<(Box<Log>, Level)>
+}
+
+impl Tee {
+    ///Create a `Tee` without any log tools.
+    pub fn new() -> Tee {
+        Tee {
+            sinks: vec![]
+        }
+    }
+
+    ///Send every entry to `log`.
+    pub fn sink<L: Log + 'static>(self, log: L) -> Tee {
+        self.sink_at(log, Level::Note)
+    }
+
+    ///Send the entries with `level` or above to `log`.
+    pub fn sink_at<L: Log + 'static>(mut self, log: L, level: Level) -> Tee {
+        self.sinks.push((Box::new(log), level));
+        self
+    }
+}
+
+impl Default for Tee {
+    fn default() -> Tee {
+        Tee::new()
+    }
+}
+
+impl Log for Tee {
+    fn try_note(&self, message: &str) -> Result {
+        self.try_log(Level::Note, message, &[])
+    }
+
+    fn try_warning(&self, message: &str) -> Result {
+        self.try_log(Level::Warning, message, &[])
     }
 
     fn try_error(&self, message: &str) -> Result {
-        let mut f = match self.file.lock() {
-            Ok(f) => f,
-            Err(_e) => return Err(io::Error::new(io::ErrorKind::Other, "poisoned log file lock"))
+        self.try_log(Level::Error, message, &[])
+    }
+
+    fn try_log(&self, level: Level, message: &str, fields: &[Field]) -> Result {
+        let mut result = Ok(());
+
+        for &(ref log, lowest) in &self.sinks {
+            if level < lowest {
+                continue;
+            }
+
+            let sink_result = if fields.is_empty() {
+                match level {
+                    Level::Note => log.try_note(message),
+                    Level::Warning => log.try_warning(message),
+                    Level::Error => log.try_error(message)
+                }
+            } else {
+                log.try_log(level, message, fields)
+            };
+
+            if result.is_ok() {
+                result = sink_result;
+            }
+        }
+
+        result
+    }
+}
+
+///What `Async` does when its queue is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    ///Drop the new entry. The number of dropped entries is logged as a
+    ///warning when there is room again.
+    Drop,
+
+    ///Wait until there is room in the queue.
+    Block
+}
+
+enum Command {
+    Entry(Level, String, Vec<(String, String)>),
+    Flush(SyncSender<()>)
+}
+
+///Log tool that hands the entries over to a background thread, which
+///writes them to another log tool.
+///
+///This keeps slow log destinations, such as files on network drives, from
+///holding back the threads that are handling requests. The entries are
+///queued in a bounded queue, and new entries are either dropped or waited
+///for when it's full, depending on the `Overflow` policy. Errors from the
+///other log tool can't be returned, so they are ignored.
+///
+///The queue is flushed and the thread stops when the `Async` logger is
+///dropped.
+///
+///```no_run
+///use std::fs;
+///use rustful::{Server, Context, Response};
+///use rustful::log::{Async, File, Overflow};
+///
+///let file = fs::File::create("server.log").unwrap();
+///let log = Async::with_capacity(File::new(file), 10000).overflow(Overflow::Block);
+///
+///let server = Server {
+///    log: Box::new(log),
+///    ..Server::new(|_: Context, response: Response| response.send("hello"))
+///};
+///```
+pub struct Async {
+    sender: Mutex<Option<SyncSender<Command>>>,
+    thread: Option<JoinHandle<()>>,
+    dropped: Arc<AtomicUsize>,
+    overflow: Overflow
+}
+
+impl Async {
+    ///Write the entries to `log` from a background thread, with room for
+    ///1024 entries in the queue.
+    pub fn new<L: Log + 'static>(log: L) -> Async {
+        Async::with_capacity(log, 1024)
+    }
+
+    ///Write the entries to `log` from a background thread, with room for
+    ///`capacity` entries in the queue.
+    pub fn with_capacity<L: Log + 'static>(log: L, capacity: usize) -> Async {
+        let (sender, receiver) = sync_channel(capacity);
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let worker_dropped = dropped.clone();
+        let thread = thread::spawn(move || run_async_worker(log, receiver, worker_dropped));
+
+        Async {
+            sender: Mutex::new(Some(sender)),
+            thread: Some(thread),
+            dropped: dropped,
+            overflow: Overflow::Drop
+        }
+    }
+
+    ///Set what happens when the queue is full. Default is
+    ///`Overflow::Drop`.
+    pub fn overflow(mut self, overflow: Overflow) -> Async {
+        self.overflow = overflow;
+        self
+    }
+
+    ///Wait until every entry that has been queued so far has been written.
+    pub fn flush(&self) -> Result {
+        let (done, wait) = sync_channel(1);
+        try!(self.send(Command::Flush(done), Overflow::Block));
+        wait.recv().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the log thread has stopped"))
+    }
+
+    fn send(&self, command: Command, overflow: Overflow) -> Result {
+        //The sender is cloned, to not hold the lock while waiting for room.
+        let sender = match self.sender.lock() {
+            Ok(sender) => sender.clone(),
+            Err(_e) => return Err(io::Error::new(io::ErrorKind::Other, "poisoned log sender lock"))
+        };
+
+        let sender = match sender {
+            Some(sender) => sender,
+            None => return Err(io::Error::new(io::ErrorKind::BrokenPipe, "the log thread has stopped"))
         };
-        write!(f, "error: {}", message)
+
+        let result = match overflow {
+            Overflow::Drop => match sender.try_send(command) {
+                Err(TrySendError::Full(_)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    Ok(())
+                },
+                Err(TrySendError::Disconnected(_)) => Err(()),
+                Ok(()) => Ok(())
+            },
+            Overflow::Block => sender.send(command).map_err(|_| ())
+        };
+
+        result.map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the log thread has stopped"))
+    }
+}
+
+impl Log for Async {
+    fn try_note(&self, message: &str) -> Result {
+        self.try_log(Level::Note, message, &[])
+    }
+
+    fn try_warning(&self, message: &str) -> Result {
+        self.try_log(Level::Warning, message, &[])
+    }
+
+    fn try_error(&self, message: &str) -> Result {
+        self.try_log(Level::Error, message, &[])
+    }
+
+    fn try_log(&self, level: Level, message: &str, fields: &[Field]) -> Result {
+        let fields = fields.iter().map(|&(name, value)| (name.to_owned(), value.to_string())).collect();
+        self.send(Command::Entry(level, message.to_owned(), fields), self.overflow)
+    }
+}
+
+impl Drop for Async {
+    ///Writes the queued entries and stops the background thread.
+    fn drop(&mut self) {
+        if let Ok(mut sender) = self.sender.lock() {
+            sender.take();
+        }
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run_async_worker<L: Log>(log: L, commands: Receiver<Command>, dropped: Arc<AtomicUsize>) {
+    for command in commands {
+        match command {
+            Command::Entry(level, message, fields) => {
+                let fields: Vec<Field> = fields.iter().map(|&(ref name, ref value)| (&**name, value as &fmt::Display)).collect();
+                let _ = log.try_log(level, &message, &fields);
+            },
+            Command::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+
+        let count = dropped.swap(0, Ordering::Relaxed);
+        if count > 0 {
+            log.warning(&format!("{} log entries were dropped, because the log queue was full", count));
+        }
+    }
+}
+
+///Log tool that forwards everything to the `log` crate, using the target
+///`rustful`. Notes are logged as `info`, warnings as `warn` and errors as
+///`error`.
+///
+///It's only available with the `log` feature.
+///
+///```
+///# extern crate rustful;
+///use rustful::{Server, Context, Response};
+///use rustful::log::Facade;
+///
+///# fn main() {
+///let server = Server {
+///    log: Box::new(Facade),
+///    ..Server::new(|_: Context, response: Response| response.send("hello"))
+///};
+///# }
+///```
+#[cfg(feature = "log")]
+pub struct Facade;
+
+#[cfg(feature = "log")]
+impl Log for Facade {
+    fn try_note(&self, message: &str) -> Result {
+        info!(target: "rustful", "{}", message);
+        Ok(())
+    }
+
+    fn try_warning(&self, message: &str) -> Result {
+        warn!(target: "rustful", "{}", message);
+        Ok(())
+    }
+
+    fn try_error(&self, message: &str) -> Result {
+        error!(target: "rustful", "{}", message);
+        Ok(())
+    }
+}
+
+///A logger for the `log` crate that forwards everything to a log tool,
+///such as `File`. Records with the `info`, `debug` and `trace` levels are
+///logged as notes, `warn` as warnings and `error` as errors, and they are
+///prefixed with their target.
+///
+///It's only available with the `log` feature.
+///
+///```
+///# extern crate rustful;
+///extern crate log;
+///use rustful::log::{FacadeLogger, StdOut};
+///
+///# fn main() {
+///log::set_logger(|max_level| {
+///    max_level.set(log::LogLevelFilter::Info);
+///    Box::new(FacadeLogger::new(StdOut))
+///}).unwrap();
+///# }
+///```
+#[cfg(feature = "log")]
+pub struct FacadeLogger<L> {
+    log: L,
+    level: log_crate::LogLevelFilter
+}
+
+#[cfg(feature = "log")]
+impl<L: Log> FacadeLogger<L> {
+    ///Forward records with the `info` level and above to `log`.
+    pub fn new(log: L) -> FacadeLogger<L> {
+        FacadeLogger {
+            log: log,
+            level: log_crate::LogLevelFilter::Info
+        }
+    }
+
+    ///Set the lowest level that is forwarded. Default is `info`.
+    pub fn level(mut self, level: log_crate::LogLevelFilter) -> FacadeLogger<L> {
+        self.level = level;
+        self
+    }
+}
+
+#[cfg(feature = "log")]
+impl<L: Log> log_crate::Log for FacadeLogger<L> {
+    fn enabled(&self, metadata: &log_crate::LogMetadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &log_crate::LogRecord) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let message = format!("{}: {}", record.target(), record.args());
+        match record.level() {
+            log_crate::LogLevel::Error => self.log.error(&message),
+            log_crate::LogLevel::Warn => self.log.warning(&message),
+            _ => self.log.note(&message)
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
     use std::fs;
+    use std::io::Read;
     use log;
     use Server;
     use Context;
@@ -131,4 +911,103 @@ mod test {
             ..Server::new(|_: Context, _: Response| {})
         }.build();
     }
+
+    #[test]
+    fn json_lines() {
+        use super::{json_line, Level};
+
+        let line = json_line("2016-02-13T14:07:23Z", Level::Warning, "slow \"request\"", &[("ms", &1500), ("message", &"a\nb")]);
+        assert_eq!(line, "{\"timestamp\":\"2016-02-13T14:07:23Z\",\"level\":\"warning\",\"message\":\"slow \\\"request\\\"\",\"ms\":\"1500\",\"field_message\":\"a\\nb\"}\n");
+    }
+
+    #[test]
+    fn tee_levels() {
+        use std::sync::{Arc, Mutex};
+        use log::{Log, Tee, Level};
+
+        struct Lines(&'static str, Arc<Mutex<Vec<String>>>);
+
+        impl Log for Lines {
+            fn try_note(&self, message: &str) -> log::Result {
+                self.1.lock().unwrap().push(format!("{} note: {}", self.0, message));
+                Ok(())
+            }
+
+            fn try_warning(&self, message: &str) -> log::Result {
+                self.1.lock().unwrap().push(format!("{} warning: {}", self.0, message));
+                Ok(())
+            }
+
+            fn try_error(&self, message: &str) -> log::Result {
+                self.1.lock().unwrap().push(format!("{} error: {}", self.0, message));
+                Ok(())
+            }
+        }
+
+        let lines = Arc::new(Mutex::new(vec![]));
+        let log = Tee::new()
+            .sink(Lines("all", lines.clone()))
+            .sink_at(Lines("errors", lines.clone()), Level::Error);
+
+        log.note("a");
+        log.error_with("b", &[("c", &1)]);
+        assert_eq!(*lines.lock().unwrap(), vec!["all note: a", "all error: b c=1", "errors error: b c=1"]);
+    }
+
+    #[test]
+    fn async_log() {
+        use std::sync::{Arc, Mutex};
+        use log::{Log, Async, Overflow};
+
+        struct Lines(Arc<Mutex<Vec<String>>>);
+
+        impl Log for Lines {
+            fn try_note(&self, message: &str) -> log::Result {
+                self.0.lock().unwrap().push(message.to_owned());
+                Ok(())
+            }
+
+            fn try_warning(&self, message: &str) -> log::Result {
+                self.try_note(message)
+            }
+
+            fn try_error(&self, message: &str) -> log::Result {
+                self.try_note(message)
+            }
+        }
+
+        let lines = Arc::new(Mutex::new(vec![]));
+        let log = Async::new(Lines(lines.clone())).overflow(Overflow::Block);
+        log.note("a");
+        log.note_with("b", &[("c", &1)]);
+        log.flush().unwrap();
+        assert_eq!(*lines.lock().unwrap(), vec!["a", "b c=1"]);
+
+        log.error("d");
+        drop(log);
+        assert_eq!(*lines.lock().unwrap(), vec!["a", "b c=1", "d"]);
+    }
+
+    #[test]
+    fn rotate_log_file() {
+        use log::Log;
+
+        let dir = tempdir::TempDir::new("rotate_log_file").unwrap();
+        let path = dir.path().join("test.log");
+        let log = log::File::open(&path).unwrap().max_size(16).keep(2);
+
+        log.note("first message");
+        log.note("second message");
+        log.note("third message");
+        log.note("fourth message");
+
+        let mut content = String::new();
+        fs::File::open(&path).unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, "note: fourth message");
+
+        content.clear();
+        fs::File::open(dir.path().join("test.log.2")).unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, "note: second message");
+        assert!(!dir.path().join("test.log.3").exists());
+    }
 }
\ No newline at end of file
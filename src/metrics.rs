@@ -0,0 +1,281 @@
+//!Request metrics in the Prometheus text format.
+//!
+//!A [`Registry`][registry] keeps a request counter per route and status
+//!class (`2xx`, `4xx`, and so on), and a histogram of how long the requests
+//!took. The requests are recorded by the middleware from
+//!`Registry::route`, which is usually added to a [`Scope`][scope] for each
+//!route, or to the server's middleware to record everything under one
+//!name. The numbers are served by the handler from `Registry::handler`:
+//!
+//!```
+//!use rustful::{Server, Context, Response};
+//!use rustful::metrics::Registry;
+//!
+//!let registry = Registry::new();
+//!
+//!let server = Server {
+//!    middleware: vec![Box::new(registry.route("hello"))],
+//!    ..Server::new(|_: Context, response: Response| response.send("hello"))
+//!};
+//!
+//!//The metrics are served from a server of their own, on another port
+//!let metrics_server = Server::new(registry.handler());
+//!```
+//!
+//!The output looks like this:
+//!
+//!```text
+//!# HELP http_requests_total The number of handled requests.
+//!# TYPE http_requests_total counter
+//!http_requests_total{route="hello",status="2xx"} 3
+//!# HELP http_request_duration_seconds How long it took to handle the requests.
+//!# TYPE http_request_duration_seconds histogram
+//!http_request_duration_seconds_bucket{route="hello",le="0.005"} 2
+//!...
+//!http_request_duration_seconds_bucket{route="hello",le="+Inf"} 3
+//!http_request_duration_seconds_sum{route="hello"} 0.0123
+//!http_request_duration_seconds_count{route="hello"} 3
+//!```
+//!
+//![registry]: struct.Registry.html
+//![scope]: ../handler/scope/struct.Scope.html
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use time::{Duration, SteadyTime};
+
+use StatusCode;
+use context::Context;
+use handler::Handler;
+use header::ContentType;
+use middleware::{Middleware, Next};
+use mime::{Mime, TopLevel, SubLevel, Attr, Value};
+use response::Response;
+
+///The default histogram buckets, in seconds. They are the same as in the
+///official Prometheus clients.
+pub const DEFAULT_BUCKETS: &'static [f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+//The status classes, from 1xx to 5xx.
+const CLASSES: usize = 5;
+
+struct RouteStats {
+    classes: [u64; CLASSES],
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64
+}
+
+struct Shared {
+    buckets: Vec<f64>,
+    routes: Mutex<BTreeMap<String, RouteStats>>
+}
+
+///A collection of request metrics. See the [module documentation][module]
+///for more information.
+///
+///It's cheap to clone, and the clones share the same metrics.
+///
+///[module]: index.html
+#[derive(Clone)]
+pub struct Registry {
+    shared: Arc<Shared>
+}
+
+impl Registry {
+    ///Create a registry with the default histogram buckets.
+    pub fn new() -> Registry {
+        Registry::with_buckets(DEFAULT_BUCKETS.to_vec())
+    }
+
+    ///Create a registry with custom histogram buckets, in seconds. The
+    ///`+Inf` bucket is always included.
+    pub fn with_buckets(mut buckets: Vec<f64>) -> Registry {
+        buckets.retain(|bucket| bucket.is_finite());
+        buckets.sort_by(|a, b| a.partial_cmp(b).expect("finite numbers are comparable"));
+        buckets.dedup();
+
+        Registry {
+            shared: Arc::new(Shared {
+                buckets: buckets,
+                routes: Mutex::new(BTreeMap::new())
+            })
+        }
+    }
+
+    ///Create a middleware that records requests under the route name
+    ///`name`.
+    pub fn route<S: Into<String>>(&self, name: S) -> RouteMetrics {
+        RouteMetrics {
+            registry: self.clone(),
+            route: name.into()
+        }
+    }
+
+    ///Create a handler that serves the metrics in the Prometheus text
+    ///format.
+    pub fn handler(&self) -> MetricsHandler {
+        MetricsHandler {
+            registry: self.clone()
+        }
+    }
+
+    ///Record a request to `route` that got the status `status` and took
+    ///`duration` to handle.
+    pub fn record(&self, route: &str, status: StatusCode, duration: Duration) {
+        let seconds = match duration.num_nanoseconds() {
+            Some(nanoseconds) => nanoseconds as f64 / 1_000_000_000.0,
+            None => duration.num_seconds() as f64
+        };
+
+        let mut routes = match self.shared.routes.lock() {
+            Ok(routes) => routes,
+            Err(poisoned) => poisoned.into_inner()
+        };
+
+        if !routes.contains_key(route) {
+            routes.insert(route.to_owned(), RouteStats {
+                classes: [0; CLASSES],
+                buckets: vec![0; self.shared.buckets.len()],
+                sum: 0.0,
+                count: 0
+            });
+        }
+
+        let stats = routes.get_mut(route).expect("the route was just inserted");
+        let class = match status.to_u16() / 100 {
+            class @ 1...5 => class as usize - 1,
+            _ => CLASSES - 1
+        };
+        stats.classes[class] += 1;
+
+        for (count, &bucket) in stats.buckets.iter_mut().zip(&self.shared.buckets) {
+            if seconds <= bucket {
+                *count += 1;
+            }
+        }
+        stats.sum += seconds;
+        stats.count += 1;
+    }
+
+    ///Render the metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let routes = match self.shared.routes.lock() {
+            Ok(routes) => routes,
+            Err(poisoned) => poisoned.into_inner()
+        };
+
+        let mut output = String::new();
+
+        output.push_str("# HELP http_requests_total The number of handled requests.\n");
+        output.push_str("# TYPE http_requests_total counter\n");
+        for (route, stats) in routes.iter() {
+            let route = escape_label(route);
+            for (class, &count) in stats.classes.iter().enumerate() {
+                if count > 0 {
+                    let _ = writeln!(output, "http_requests_total{{route=\"{}\",status=\"{}xx\"}} {}", route, class + 1, count);
+                }
+            }
+        }
+
+        output.push_str("# HELP http_request_duration_seconds How long it took to handle the requests.\n");
+        output.push_str("# TYPE http_request_duration_seconds histogram\n");
+        for (route, stats) in routes.iter() {
+            let route = escape_label(route);
+            for (&count, bucket) in stats.buckets.iter().zip(&self.shared.buckets) {
+                let _ = writeln!(output, "http_request_duration_seconds_bucket{{route=\"{}\",le=\"{}\"}} {}", route, bucket, count);
+            }
+            let _ = writeln!(output, "http_request_duration_seconds_bucket{{route=\"{}\",le=\"+Inf\"}} {}", route, stats.count);
+            let _ = writeln!(output, "http_request_duration_seconds_sum{{route=\"{}\"}} {}", route, stats.sum);
+            let _ = writeln!(output, "http_request_duration_seconds_count{{route=\"{}\"}} {}", route, stats.count);
+        }
+
+        output
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry::new()
+    }
+}
+
+///A middleware that records the requests to a route. It's created using
+///`Registry::route`.
+pub struct RouteMetrics {
+    registry: Registry,
+    route: String
+}
+
+impl Middleware for RouteMetrics {
+    fn around(&self, context: Context, response: Response, next: Next) {
+        let started = SteadyTime::now();
+        let status = next.run(context, response);
+        self.registry.record(&self.route, status, SteadyTime::now() - started);
+    }
+}
+
+///A handler that serves the metrics of a `Registry`. It's created using
+///`Registry::handler`.
+pub struct MetricsHandler {
+    registry: Registry
+}
+
+impl Handler for MetricsHandler {
+    fn handle_request(&self, _context: Context, mut response: Response) {
+        response.headers_mut().set(ContentType(Mime(TopLevel::Text, SubLevel::Plain, vec![
+            (Attr::Ext("version".into()), Value::Ext("0.0.4".into())),
+            (Attr::Charset, Value::Utf8)
+        ])));
+        response.send(self.registry.render());
+    }
+}
+
+//Escapes backslashes, quotes and line breaks in a label value.
+fn escape_label(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use time::Duration;
+    use StatusCode;
+    use super::Registry;
+
+    #[test]
+    fn render_metrics() {
+        let registry = Registry::with_buckets(vec![0.1, 1.0]);
+        registry.record("users", StatusCode::Ok, Duration::microseconds(62500));
+        registry.record("users", StatusCode::NotFound, Duration::milliseconds(500));
+        registry.record("a \"b\"", StatusCode::InternalServerError, Duration::seconds(2));
+
+        let output = registry.render();
+        let lines: Vec<&str> = output.lines().filter(|line| !line.starts_with('#')).collect();
+        assert_eq!(lines, vec![
+            "http_requests_total{route=\"a \\\"b\\\"\",status=\"5xx\"} 1",
+            "http_requests_total{route=\"users\",status=\"2xx\"} 1",
+            "http_requests_total{route=\"users\",status=\"4xx\"} 1",
+            "http_request_duration_seconds_bucket{route=\"a \\\"b\\\"\",le=\"0.1\"} 0",
+            "http_request_duration_seconds_bucket{route=\"a \\\"b\\\"\",le=\"1\"} 0",
+            "http_request_duration_seconds_bucket{route=\"a \\\"b\\\"\",le=\"+Inf\"} 1",
+            "http_request_duration_seconds_sum{route=\"a \\\"b\\\"\"} 2",
+            "http_request_duration_seconds_count{route=\"a \\\"b\\\"\"} 1",
+            "http_request_duration_seconds_bucket{route=\"users\",le=\"0.1\"} 1",
+            "http_request_duration_seconds_bucket{route=\"users\",le=\"1\"} 2",
+            "http_request_duration_seconds_bucket{route=\"users\",le=\"+Inf\"} 2",
+            "http_request_duration_seconds_sum{route=\"users\"} 0.5625",
+            "http_request_duration_seconds_count{route=\"users\"} 2",
+        ]);
+    }
+}
@@ -0,0 +1,170 @@
+//!Middleware that wraps the handlers.
+//!
+//!A [`Middleware`][middleware] is called with the `Context` and the
+//!`Response` of a request, together with a [`Next`][next] that runs the
+//!rest of the middleware stack and the handler. It can do things before and
+//!after the handler is called, change the context and the response on the
+//!way in, or answer the request by itself without calling `next` at all.
+//!This makes it easy to do things that need to see both the request and its
+//!response, such as timing or tracing:
+//!
+//!```
+//!extern crate rustful;
+//!extern crate time;
+//!use rustful::{Server, Context, Response};
+//!use rustful::middleware::Next;
+//!
+//!fn timer(context: Context, response: Response, next: Next) {
+//!    let log = context.log;
+//!    let target = context.request_target.clone();
+//!    let started = time::precise_time_ns();
+//!
+//!    let status = next.run(context, response);
+//!
+//!    let elapsed = (time::precise_time_ns() - started) / 1000;
+//!    log.note(&format!("{} {} in {} µs", target, status, elapsed));
+//!}
+//!
+//!# fn main() {
+//!let server = Server {
+//!    middleware: vec![Box::new(timer)],
+//!    ..Server::new(|_: Context, response: Response| response.send("hello"))
+//!};
+//!# }
+//!```
+//!
+//!The middleware stack runs after the context filters and the router, and
+//!the first middleware is the outermost. It's also used when no handler was
+//!found, where the innermost step is to respond with `404 Not Found`. The
+//!response filters are still applied to everything that is sent.
+//!
+//![middleware]: trait.Middleware.html
+//![next]: struct.Next.html
+
+use context::Context;
+use events::Outbox;
+use handler::Handler;
+use response::Response;
+use StatusCode;
+
+///A trait for middleware. See the [module documentation][module] for more
+///information.
+///
+///[module]: index.html
+pub trait Middleware: Send + Sync {
+    ///Handle a request, and maybe pass it on using `next`.
+    fn around(&self, context: Context, response: Response, next: Next);
+}
+
+impl<F: Fn(Context, Response, Next) + Send + Sync> Middleware for F {
+    fn around(&self, context: Context, response: Response, next: Next) {
+        self(context, response, next);
+    }
+}
+
+///The rest of the middleware stack, and the handler.
+pub struct Next<'n> {
+    middleware: &'n [Box<Middleware>],
+    handler: Option<&'n Handler>,
+    outbox: Outbox
+}
+
+impl<'n> Next<'n> {
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn new(middleware: &'n [Box<Middleware>], handler: Option<&'n Handler>, outbox: Outbox) -> Next<'n> {
+        Next {
+            middleware: middleware,
+            handler: handler,
+            outbox: outbox
+        }
+    }
+
+    ///Run the rest of the middleware stack and the handler, and return the
+    ///status of the response. The status is only final if the response was
+    ///sent before the handler returned, which is not the case if it was
+    ///moved to another thread.
+    pub fn run(self, context: Context, response: Response) -> StatusCode {
+        let outbox = self.outbox.clone();
+
+        match self.middleware.split_first() {
+            Some((middleware, rest)) => middleware.around(context, response, Next {
+                middleware: rest,
+                handler: self.handler,
+                outbox: self.outbox
+            }),
+            None => match self.handler {
+                Some(handler) => handler.handle_request(context, response),
+                None => {
+                    let mut response = response;
+                    response.set_status(StatusCode::NotFound);
+                }
+            }
+        }
+
+        outbox.status()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use {Server, TreeRouter, Context, Response, StatusCode};
+    use server::Dispatcher;
+    use super::{Middleware, Next};
+
+    struct Layer(&'static str, Arc<Mutex<Vec<String>>>);
+
+    impl Middleware for Layer {
+        fn around(&self, context: Context, mut response: Response, next: Next) {
+            self.1.lock().unwrap().push(format!("{} before", self.0));
+
+            if self.0 == "guard" && context.uri.as_utf8_path() == Some("/private") {
+                response.set_status(StatusCode::Unauthorized);
+                return;
+            }
+
+            let status = next.run(context, response);
+            self.1.lock().unwrap().push(format!("{} after {}", self.0, status.to_u16()));
+        }
+    }
+
+    #[test]
+    fn wrap_in_order() {
+        fn handler(_context: Context, response: Response) {
+            response.send("hello");
+        }
+
+        let events = Arc::new(Mutex::new(vec![]));
+        let router = insert_routes! {
+            TreeRouter::new() => {
+                "public" => Get: handler as fn(Context, Response),
+                "private" => Get: handler as fn(Context, Response)
+            }
+        };
+        let server = Server {
+            middleware: vec![Box::new(Layer("outer", events.clone())), Box::new(Layer("guard", events.clone()))],
+            ..Server::new(router)
+        };
+        let (instance, _scheme) = server.build();
+        let address = "127.0.0.1:8080".parse().unwrap();
+        let request = |path: &str| {
+            let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+            let output = String::from_utf8(instance.dispatch(request.as_bytes(), address)).unwrap();
+            (output, ::std::mem::replace(&mut *events.lock().unwrap(), vec![]))
+        };
+
+        let (output, order) = request("/public");
+        assert!(output.ends_with("hello"), "{}", output);
+        assert_eq!(order, vec!["outer before", "guard before", "guard after 200", "outer after 200"]);
+
+        let (output, order) = request("/private");
+        assert!(output.starts_with("HTTP/1.1 401"), "{}", output);
+        assert_eq!(order, vec!["outer before", "guard before", "outer after 401"]);
+
+        let (output, order) = request("/missing");
+        assert!(output.starts_with("HTTP/1.1 404"), "{}", output);
+        assert_eq!(order, vec!["outer before", "guard before", "guard after 404", "outer after 404"]);
+    }
+}
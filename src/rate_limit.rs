@@ -0,0 +1,365 @@
+//!Request rate limits.
+//!
+//![`RateLimit`][rate_limit] is a middleware that gives each client a token
+//!bucket. Each request takes a token, and the bucket is refilled at a
+//!steady rate, up to its capacity. A request that finds the bucket empty is
+//!answered with `429 Too Many Requests` and a `Retry-After` header, without
+//!calling the handler.
+//!
+//!The clients are identified by their IP address, as given by
+//!`Context::client_ip`, by default. Any other key can be used instead, such
+//!as an API key:
+//!
+//!```
+//!use rustful::{Server, Context, Response};
+//!use rustful::rate_limit::{RateLimit, Rate};
+//!
+//!let per_address = RateLimit::new(Rate::per_second(10).burst(50));
+//!
+//!let per_api_key = RateLimit::new(Rate::per_minute(600)).key(|context: &Context| {
+//!    context.headers.get_raw("X-Api-Key")
+//!        .and_then(|lines| lines.first())
+//!        .map(|key| String::from_utf8_lossy(key).into_owned())
+//!});
+//!
+//!let server = Server {
+//!    middleware: vec![Box::new(per_address), Box::new(per_api_key)],
+//!    ..Server::new(|_: Context, response: Response| response.send("hello"))
+//!};
+//!```
+//!
+//!The buckets are kept in memory by default, in a [`MemoryStore`][memory].
+//!A [`Store`][store] keeps them on the disk, across restarts, and servers
+//!that share their limits can implement
+//![`BucketStore`][bucket_store] for an external store, and use
+//![`Bucket`][bucket] for the arithmetic.
+//!
+//![rate_limit]: struct.RateLimit.html
+//![memory]: struct.MemoryStore.html
+//![store]: ../store/struct.Store.html
+//![bucket_store]: trait.BucketStore.html
+//![bucket]: struct.Bucket.html
+
+use std::collections::{HashMap, BTreeMap};
+use std::sync::Mutex;
+
+use time::{self, Timespec};
+
+use context::Context;
+use middleware::{Middleware, Next};
+use response::Response;
+use StatusCode;
+
+//Full buckets are removed from a MemoryStore at most this often, in
+//seconds.
+const PRUNE_INTERVAL: f64 = 10.0;
+
+///The capacity and refill rate of a token bucket.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rate {
+    ///The maximum number of tokens in the bucket.
+    pub capacity: f64,
+
+    ///The number of tokens that are added each second.
+    pub refill: f64
+}
+
+impl Rate {
+    ///Allow `requests` requests per second. The capacity is one second's
+    ///worth of requests.
+    pub fn per_second(requests: u32) -> Rate {
+        Rate {
+            capacity: requests as f64,
+            refill: requests as f64
+        }
+    }
+
+    ///Allow `requests` requests per minute. The capacity is one minute's
+    ///worth of requests.
+    pub fn per_minute(requests: u32) -> Rate {
+        Rate {
+            capacity: requests as f64,
+            refill: requests as f64 / 60.0
+        }
+    }
+
+    ///Set the capacity, which is how many requests can be made in a burst.
+    pub fn burst(mut self, capacity: u32) -> Rate {
+        self.capacity = capacity as f64;
+        self
+    }
+}
+
+///A token bucket.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bucket {
+    ///The number of tokens in the bucket, when it was last updated.
+    pub tokens: f64,
+
+    ///When the bucket was last updated, in seconds since the Unix epoch.
+    pub updated: f64
+}
+
+impl Bucket {
+    ///Create a full bucket.
+    pub fn full(rate: Rate, now: Timespec) -> Bucket {
+        Bucket {
+            tokens: rate.capacity,
+            updated: seconds(now)
+        }
+    }
+
+    ///Refill the bucket and try to take a token from it. The result is
+    ///`None` if a token was taken, or the number of seconds until there is
+    ///one.
+    pub fn take(&mut self, rate: Rate, now: Timespec) -> Option<f64> {
+        let now = seconds(now);
+        if now > self.updated {
+            self.tokens = (self.tokens + (now - self.updated) * rate.refill).min(rate.capacity);
+            self.updated = now;
+        }
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else if rate.refill > 0.0 {
+            Some((1.0 - self.tokens) / rate.refill)
+        } else {
+            Some(::std::f64::INFINITY)
+        }
+    }
+
+    ///Check if the bucket would be full at `now`.
+    pub fn is_full(&self, rate: Rate, now: Timespec) -> bool {
+        self.tokens + (seconds(now) - self.updated) * rate.refill >= rate.capacity
+    }
+}
+
+fn seconds(time: Timespec) -> f64 {
+    time.sec as f64 + time.nsec as f64 / 1_000_000_000.0
+}
+
+///Storage for token buckets.
+pub trait BucketStore: Send + Sync {
+    ///Try to take a token from the bucket for `key`, which is full if it
+    ///doesn't exist. The result is `None` if a token was taken, or the
+    ///number of seconds until there is one.
+    fn take(&self, key: &str, rate: Rate, now: Timespec) -> Option<f64>;
+}
+
+///Keeps token buckets in memory. Full buckets are removed from time to
+///time, since they are the same as missing buckets, and the least recently
+///used bucket is removed when the store is full.
+pub struct MemoryStore {
+    buckets: Mutex<Buckets>,
+    max_buckets: usize
+}
+
+impl MemoryStore {
+    ///Create an empty store, with room for 100 000 buckets.
+    pub fn new() -> MemoryStore {
+        MemoryStore {
+            buckets: Mutex::new(Buckets {
+                entries: HashMap::new(),
+                recent: BTreeMap::new(),
+                counter: 0,
+                pruned: 0.0
+            }),
+            max_buckets: 100_000
+        }
+    }
+
+    ///Keep at most `max_buckets` buckets. The least recently used bucket is
+    ///removed, which is the same as filling it, when a new one doesn't fit.
+    ///It's at least 1.
+    pub fn max_buckets(mut self, max_buckets: usize) -> MemoryStore {
+        self.max_buckets = if max_buckets == 0 { 1 } else { max_buckets };
+        self
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> MemoryStore {
+        MemoryStore::new()
+    }
+}
+
+impl BucketStore for MemoryStore {
+    fn take(&self, key: &str, rate: Rate, now: Timespec) -> Option<f64> {
+        let mut guard = match self.buckets.lock() {
+            Ok(buckets) => buckets,
+            Err(poisoned) => poisoned.into_inner()
+        };
+        let buckets = &mut *guard;
+
+        buckets.counter += 1;
+        let counter = buckets.counter;
+
+        if let Some(entry) = buckets.entries.get_mut(key) {
+            if let Some(key) = buckets.recent.remove(&entry.1) {
+                buckets.recent.insert(counter, key);
+            }
+            entry.1 = counter;
+            return entry.0.take(rate, now);
+        }
+
+        if seconds(now) - buckets.pruned >= PRUNE_INTERVAL {
+            buckets.pruned = seconds(now);
+            buckets.remove_full(rate, now);
+        }
+
+        if buckets.entries.len() >= self.max_buckets {
+            buckets.remove_oldest();
+        }
+
+        let mut bucket = Bucket::full(rate, now);
+        let result = bucket.take(rate, now);
+        buckets.entries.insert(key.to_owned(), (bucket, counter));
+        buckets.recent.insert(counter, key.to_owned());
+        result
+    }
+}
+
+//The buckets in a MemoryStore, and when they were last used.
+struct Buckets {
+    entries: HashMap<String, (Bucket, u64)>,
+    recent: BTreeMap<u64, String>,
+    counter: u64,
+    pruned: f64
+}
+
+impl Buckets {
+    fn remove_full(&mut self, rate: Rate, now: Timespec) {
+        let recent = &mut self.recent;
+        self.entries.retain(|_, &mut (ref bucket, used)| {
+            if bucket.is_full(rate, now) {
+                recent.remove(&used);
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    fn remove_oldest(&mut self) {
+        let oldest = self.recent.keys().next().cloned();
+        if let Some(key) = oldest.and_then(|oldest| self.recent.remove(&oldest)) {
+            self.entries.remove(&key);
+        }
+    }
+}
+
+///A middleware that limits the request rate of each client. See the
+///[module documentation][module] for more information.
+///
+///[module]: index.html
+pub struct RateLimit<S = MemoryStore> {
+    rate: Rate,
+    store: S,
+    key: Box<Fn(&Context) -> Option<String> + Send + Sync>
+}
+
+impl RateLimit<MemoryStore> {
+    ///Limit each client address to `rate`, with buckets in memory.
+    pub fn new(rate: Rate) -> RateLimit<MemoryStore> {
+        RateLimit::with_store(rate, MemoryStore::new())
+    }
+}
+
+impl<S: BucketStore> RateLimit<S> {
+    ///Limit each client address to `rate`, with buckets in `store`.
+    pub fn with_store(rate: Rate, store: S) -> RateLimit<S> {
+        RateLimit {
+            rate: rate,
+            store: store,
+            key: Box::new(|context: &Context| Some(context.client_ip().to_string()))
+        }
+    }
+
+    ///Identify clients using `key`, instead of their address. Requests with
+    ///the key `None` are not limited.
+    pub fn key<F: Fn(&Context) -> Option<String> + Send + Sync + 'static>(mut self, key: F) -> RateLimit<S> {
+        self.key = Box::new(key);
+        self
+    }
+}
+
+impl<S: BucketStore> Middleware for RateLimit<S> {
+    fn around(&self, context: Context, mut response: Response, next: Next) {
+        let wait = match (self.key)(&context) {
+            Some(key) => self.store.take(&key, self.rate, time::get_time()),
+            None => None
+        };
+
+        match wait {
+            Some(wait) => {
+                let seconds = if wait.is_finite() { wait.ceil().max(1.0) as u64 } else { u64::max_value() };
+                response.headers_mut().set_raw("Retry-After", vec![seconds.to_string().into_bytes()]);
+                response.set_status(StatusCode::TooManyRequests);
+            },
+            None => {
+                next.run(context, response);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use time::Timespec;
+    use super::{Rate, BucketStore, MemoryStore};
+
+    #[test]
+    fn take_tokens() {
+        let store = MemoryStore::new();
+        let rate = Rate::per_second(2).burst(3);
+        let start = Timespec::new(1000, 0);
+
+        assert_eq!(store.take("a", rate, start), None);
+        assert_eq!(store.take("a", rate, start), None);
+        assert_eq!(store.take("a", rate, start), None);
+        assert_eq!(store.take("a", rate, start), Some(0.5));
+        assert_eq!(store.take("b", rate, start), None);
+
+        let later = Timespec::new(1000, 500_000_000);
+        assert_eq!(store.take("a", rate, later), None);
+        assert_eq!(store.take("a", rate, later), Some(0.5));
+    }
+
+    #[test]
+    fn remove_least_recently_used() {
+        let store = MemoryStore::new().max_buckets(2);
+        let rate = Rate::per_minute(1);
+        let now = Timespec::new(1000, 0);
+
+        assert_eq!(store.take("a", rate, now), None);
+        assert_eq!(store.take("b", rate, now), None);
+        assert!(store.take("a", rate, now).is_some());
+
+        //"b" is the least recently used, so it's removed for "c".
+        assert_eq!(store.take("c", rate, now), None);
+        assert!(store.take("a", rate, now).is_some());
+        assert_eq!(store.take("b", rate, now), None);
+        assert!(store.take("a", rate, now).is_some());
+
+        let buckets = store.buckets.lock().unwrap();
+        assert_eq!(buckets.entries.len(), 2);
+        assert_eq!(buckets.recent.len(), 2);
+    }
+
+    #[test]
+    fn remove_full_buckets() {
+        let store = MemoryStore::new();
+        let rate = Rate::per_second(1);
+
+        assert_eq!(store.take("a", rate, Timespec::new(1000, 0)), None);
+        assert_eq!(store.take("b", rate, Timespec::new(1005, 0)), None);
+        assert_eq!(store.buckets.lock().unwrap().entries.len(), 2);
+
+        //Pruning only happens once per interval.
+        assert_eq!(store.take("c", rate, Timespec::new(1010, 0)), None);
+        let buckets = store.buckets.lock().unwrap();
+        assert_eq!(buckets.entries.len(), 1);
+        assert_eq!(buckets.recent.len(), 1);
+    }
+}
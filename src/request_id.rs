@@ -0,0 +1,213 @@
+//!Request IDs.
+//!
+//![`RequestIds`][request_ids] is a middleware that gives each request an
+//!ID, to make it easier to follow a request through the logs of several
+//!services. The ID is taken from the `X-Request-Id` header if the request
+//!came through a trusted proxy, and it's generated otherwise. It's then:
+//!
+//! * put in the filter storage as a [`RequestId`][request_id],
+//! * added to everything that is written to `Context::log`, and the log of
+//!the response,
+//! * sent back to the client in the `X-Request-Id` header.
+//!
+//!```
+//!use rustful::{Server, Context, Response};
+//!use rustful::request_id::{RequestIds, RequestId};
+//!
+//!fn my_handler(context: Context, response: Response) {
+//!    //Logged as "[<the ID>] handling a request"
+//!    context.log.note("handling a request");
+//!
+//!    let id = response.filter_storage().get::<RequestId>().map(|id| id.0.clone());
+//!    response.send(format!("your request ID is {:?}", id));
+//!}
+//!
+//!let server = Server {
+//!    middleware: vec![Box::new(RequestIds::new())],
+//!    ..Server::new(my_handler)
+//!};
+//!```
+//!
+//![request_ids]: struct.RequestIds.html
+//![request_id]: struct.RequestId.html
+
+use context::Context;
+use log::{self, Log, Level, Field};
+use middleware::{Middleware, Next};
+use response::Response;
+use utils::{generate_id, is_token};
+
+//Incoming IDs that are longer than this are replaced.
+const MAX_LENGTH: usize = 200;
+
+///The ID of the current request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+///A middleware that gives each request an ID. See the [module
+///documentation][module] for more information.
+///
+///[module]: index.html
+pub struct RequestIds {
+    header: String,
+    trust_all: bool
+}
+
+impl RequestIds {
+    ///Create a middleware that uses the `X-Request-Id` header.
+    pub fn new() -> RequestIds {
+        RequestIds {
+            header: "X-Request-Id".into(),
+            trust_all: false
+        }
+    }
+
+    ///Use the header `name` instead of `X-Request-Id`.
+    pub fn header<S: Into<String>>(mut self, name: S) -> RequestIds {
+        self.header = name.into();
+        self
+    }
+
+    ///Accept IDs from any client, and not only from trusted proxies. This
+    ///is fine if the server can only be reached through other services.
+    pub fn trust_all(mut self) -> RequestIds {
+        self.trust_all = true;
+        self
+    }
+
+    fn incoming_id(&self, context: &Context) -> Option<String> {
+        if !self.trust_all && !context.trusted_proxies.contains(&context.address.ip()) {
+            return None;
+        }
+
+        context.headers.get_raw(&self.header)
+            .and_then(|lines| lines.first())
+            .and_then(|line| ::std::str::from_utf8(line).ok())
+            .map(|id| id.trim())
+            .and_then(|id| if id.len() <= MAX_LENGTH && is_token(id) { Some(id.to_owned()) } else { None })
+    }
+}
+
+impl Default for RequestIds {
+    fn default() -> RequestIds {
+        RequestIds::new()
+    }
+}
+
+impl Middleware for RequestIds {
+    fn around(&self, context: Context, response: Response, next: Next) {
+        let id = self.incoming_id(&context).unwrap_or_else(|| generate_id(16));
+
+        let log = TaggedLog {
+            log: context.log,
+            id: id.clone()
+        };
+
+        //Rebound to let them borrow the tagged log.
+        let mut context = context;
+        let mut response = response;
+
+        context.log = &log;
+        response.set_log(&log);
+        response.headers_mut().set_raw(self.header.clone(), vec![id.clone().into_bytes()]);
+        response.outbox().set_request_id(id.clone());
+        response.filter_storage_mut().insert(RequestId(id));
+
+        next.run(context, response);
+    }
+}
+
+//Writes the request ID before each message, or as the `request_id` field
+//of structured messages.
+struct TaggedLog<'a> {
+    log: &'a Log,
+    id: String
+}
+
+impl<'a> Log for TaggedLog<'a> {
+    fn try_note(&self, message: &str) -> log::Result {
+        self.log.try_note(&format!("[{}] {}", self.id, message))
+    }
+
+    fn try_warning(&self, message: &str) -> log::Result {
+        self.log.try_warning(&format!("[{}] {}", self.id, message))
+    }
+
+    fn try_error(&self, message: &str) -> log::Result {
+        self.log.try_error(&format!("[{}] {}", self.id, message))
+    }
+
+    fn try_log(&self, level: Level, message: &str, fields: &[Field]) -> log::Result {
+        let mut tagged: Vec<Field> = Vec::with_capacity(fields.len() + 1);
+        tagged.push(("request_id", &self.id));
+        tagged.extend_from_slice(fields);
+        self.log.try_log(level, message, &tagged)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use {Server, Context, Response};
+    use log::{self, Log};
+    use server::Dispatcher;
+    use super::RequestIds;
+
+    struct Lines(Arc<Mutex<Vec<String>>>);
+
+    impl Log for Lines {
+        fn try_note(&self, message: &str) -> log::Result {
+            self.0.lock().unwrap().push(message.to_owned());
+            Ok(())
+        }
+
+        fn try_warning(&self, message: &str) -> log::Result {
+            self.try_note(message)
+        }
+
+        fn try_error(&self, message: &str) -> log::Result {
+            self.try_note(message)
+        }
+    }
+
+    fn handler(context: Context, response: Response) {
+        context.log.note("plain");
+        context.log.note_with("structured", &[("a", &1)]);
+        response.send("");
+    }
+
+    #[test]
+    fn tag_logs() {
+        let lines = Arc::new(Mutex::new(vec![]));
+        let server = Server {
+            log: Box::new(Lines(lines.clone())),
+            middleware: vec![Box::new(RequestIds::new().trust_all())],
+            ..Server::new(handler as fn(Context, Response))
+        };
+        let (instance, _scheme) = server.build();
+        let address = "127.0.0.1:8080".parse().unwrap();
+
+        let output = instance.dispatch(b"GET / HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: abc\r\n\r\n", address);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("X-Request-Id: abc\r\n"), "{}", output);
+
+        let lines = lines.lock().unwrap();
+        assert!(lines.contains(&"[abc] plain".to_owned()), "{:?}", *lines);
+        assert!(lines.contains(&"structured request_id=abc a=1".to_owned()), "{:?}", *lines);
+    }
+
+    #[test]
+    fn replace_untrusted_ids() {
+        let server = Server {
+            middleware: vec![Box::new(RequestIds::new())],
+            ..Server::new(handler as fn(Context, Response))
+        };
+        let (instance, _scheme) = server.build();
+        let address = "127.0.0.1:8080".parse().unwrap();
+
+        let output = instance.dispatch(b"GET / HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: abc\r\n\r\n", address);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("X-Request-Id: "), "{}", output);
+        assert!(!output.contains("X-Request-Id: abc"), "{}", output);
+    }
+}
@@ -2,11 +2,14 @@
 //!
 //!The response writers are the output channel from the handlers to the
 //!client. These are used to set the response headers, as well as writing the
-//!response body. Rustful provides three different types of response writers
+//!response body. Rustful provides four different types of response writers
 //!with different purposes:
 //!
 //! * [`Response`][res] - It's used to write data with a known, fixed size,
 //!that is already stored in some kind of buffer.
+//! * [`Buffered`][buf] - A buffered response is written in parts, like a
+//!chunked response, but it's collected and sent with a known size when it
+//!ends. The status code and headers can be changed until then.
 //! * [`Chunked`][chu] - A chunked response is a streaming response, where the final
 //!size can be unknown.
 //! * [`Raw`][raw] - This is also a streaming response, but with a fixed size. It is
@@ -24,15 +27,23 @@
 //!|            | No extra overhead | Guaranteed correct `content-length` | Streaming |
 //!|------------|-------------------|-------------------------------------|-----------|
 //!| `Response` | &check;           | &check;                             | &cross;   |
+//!| `Buffered` | &check;           | &check;                             | &cross;   |
 //!| `Raw`      | &check;           | &cross;                             | &check;   |
 //!| `Chunked`  | &cross;           | &check;                             | &check;   |
 //!
+//!The body is discarded for responses to `HEAD` requests, and for
+//!`204 No Content` and `304 Not Modified` responses, no matter which type
+//!of writer is used. The headers, including `Content-Length` and `ETag`,
+//!are still the same as if it was sent, so handlers don't have to check the
+//!method.
+//!
 //![res]: struct.Response.html
+//![buf]: struct.Buffered.html
 //![chu]: struct.Chunked.html
 //![raw]: struct.Raw.html
 
 use std;
-use std::io::{self, Write};
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::error;
 use std::borrow::Cow;
 use std::convert::From;
@@ -40,21 +51,93 @@ use std::str::{from_utf8, Utf8Error};
 use std::string::{FromUtf8Error};
 use std::fs::File;
 use std::path::Path;
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::rc::Rc;
+use std::time::UNIX_EPOCH;
 
 use hyper;
 
 use anymap::AnyMap;
 
-use StatusCode;
+use {StatusCode, Method};
 
-use header::{Headers, ContentType};
-use filter::{FilterContext, ResponseFilter};
+use header::{Headers, ContentType, ETag, EntityTag, IfNoneMatch, IfModifiedSince, LastModified, HttpDate};
+use headers::{Link, LinkValue, PreferenceApplied, Preference, Warning, WarningValue, SetCookie, Cookie};
+use filter::{FilterContext, ResponseFilter, FilterResponse, EnforcedDeadline};
 use filter::ResponseAction as Action;
 use log::Log;
+use events::{Event, Outbox};
+use file::FileRange;
+use handler::fallible::{ErrorRenderer, DefaultErrorRenderer};
+use error_pages::ErrorPages;
+use template::{Renderer, RenderError, TemplateData};
+use cors::{CorsPolicy, CorsRequest};
+use security::SecurityHeaders;
 use mime::{Mime, TopLevel, SubLevel};
+use utils;
+
+#[cfg(feature = "compression")]
+use compression::{Compression, Coding, Encoder};
+
+#[cfg(feature = "rustc_json_body")]
+use rustc_serialize::Encodable;
+#[cfg(feature = "rustc_json_body")]
+use rustc_serialize::json;
+#[cfg(feature = "rustc_json_body")]
+use formats::{self, Format};
 
 use Global;
 
+///The size of the blocks that are written by `Response::send_reader` and
+///`Response::send_all`.
+pub const BLOCK_SIZE: usize = 64 * 1024;
+
+///Receives the errors that would otherwise be lost, because the result of a
+///response writer wasn't checked, such as when `send` is used instead of
+///`try_send`, or when a writer is dropped. It's set in the `error_sink`
+///field of the server.
+///
+///```
+///use std::net::SocketAddr;
+///use rustful::{Server, Context, Response};
+///use rustful::response::Error;
+///
+///fn report(error: &Error, request_target: &str, address: SocketAddr) {
+///    println!("failed to respond to {} for {}: {}", address, request_target, error);
+///}
+///
+///let server = Server {
+///    error_sink: Some(Box::new(report)),
+///    ..Server::new(|_: Context, response: Response| response.send("hello"))
+///};
+///```
+pub trait ErrorSink: Send + Sync {
+    ///Handle `error`, from the response to `request_target`, which was
+    ///requested by `address`.
+    fn report(&self, error: &Error, request_target: &str, address: SocketAddr);
+}
+
+impl<F: Fn(&Error, &str, SocketAddr) + Send + Sync> ErrorSink for F {
+    fn report(&self, error: &Error, request_target: &str, address: SocketAddr) {
+        self(error, request_target, address);
+    }
+}
+
+//Reports ignored errors to the server's error sink.
+#[derive(Clone)]
+struct Reporter<'b> {
+    sink: &'b ErrorSink,
+    request_target: Rc<String>,
+    address: SocketAddr
+}
+
+fn report<T>(reporter: Option<&Reporter>, result: Result<T, Error>) {
+    if let (Some(reporter), Err(e)) = (reporter, result) {
+        reporter.sink.report(&e, &reporter.request_target, reporter.address);
+    }
+}
+
 ///The result of a response action.
 #[derive(Debug)]
 pub enum Error {
@@ -256,10 +339,22 @@ impl<'a> Into<Data<'a>> for &'a str {
 ///its size is known.
 pub struct Response<'a, 'b> {
     writer: Option<hyper::server::response::Response<'a>>,
-    filters: &'b Vec<Box<ResponseFilter>>,
+    filters: Filters<'b>,
     log: &'b (Log + 'b),
     global: &'b Global,
-    filter_storage: Option<AnyMap>
+    filter_storage: Option<AnyMap>,
+    outbox: Outbox,
+    auto_etag: bool,
+    if_none_match: Option<IfNoneMatch>,
+    if_modified_since: Option<IfModifiedSince>,
+    head: bool,
+    error_renderer: Option<&'b ErrorRenderer>,
+    error_pages: Option<&'b ErrorPages>,
+    renderer: Option<&'b Renderer>,
+    reporter: Option<Reporter<'b>>,
+
+    #[cfg(feature = "compression")]
+    compression: Option<(&'b Compression, Option<Coding>)>
 }
 
 impl<'a, 'b> Response<'a, 'b> {
@@ -269,14 +364,234 @@ impl<'a, 'b> Response<'a, 'b> {
         response: hyper::server::response::Response<'a>,
         filters: &'b Vec<Box<ResponseFilter>>,
         log: &'b Log,
-        global: &'b Global
+        global: &'b Global,
+        outbox: Outbox
     ) -> Response<'a, 'b> {
         Response {
             writer: Some(response),
-            filters: filters,
+            filters: Filters::new(filters),
             log: log,
             global: global,
-            filter_storage: Some(AnyMap::new())
+            filter_storage: Some(AnyMap::new()),
+            outbox: outbox,
+            auto_etag: false,
+            if_none_match: None,
+            if_modified_since: None,
+            head: false,
+            error_renderer: None,
+            error_pages: None,
+            renderer: None,
+            reporter: None,
+            #[cfg(feature = "compression")]
+            compression: None
+        }
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn set_auto_etag(&mut self) {
+        self.auto_etag = true;
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    ///
+    ///Set the conditions from a `GET` or `HEAD` request, for automatic
+    ///ETags and files.
+    pub fn set_conditions(&mut self, if_none_match: Option<IfNoneMatch>, if_modified_since: Option<IfModifiedSince>) {
+        self.if_none_match = if_none_match;
+        self.if_modified_since = if_modified_since;
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn set_request_method(&mut self, method: &Method) {
+        self.head = *method == Method::Head;
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn set_log(&mut self, log: &'b Log) {
+        self.log = log;
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn outbox(&self) -> Outbox {
+        self.outbox.clone()
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn set_error_renderer(&mut self, renderer: &'b ErrorRenderer) {
+        self.error_renderer = Some(renderer);
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn set_error_pages(&mut self, error_pages: &'b ErrorPages) {
+        self.error_pages = Some(error_pages);
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn set_error_sink(&mut self, sink: &'b ErrorSink, request_target: String, address: SocketAddr) {
+        self.reporter = Some(Reporter {
+            sink: sink,
+            request_target: Rc::new(request_target),
+            address: address
+        });
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn set_renderer(&mut self, renderer: &'b Renderer) {
+        self.renderer = Some(renderer);
+    }
+
+    //Checks if a response with `status` should have a body. Responses to
+    //`HEAD` requests, `204 No Content` and `304 Not Modified` are sent
+    //without one, but with the same headers.
+    fn has_body(&self, status: StatusCode) -> bool {
+        !self.head && status != StatusCode::NoContent && status != StatusCode::NotModified
+    }
+
+    //Replaces the status with the one from an enforced deadline that has
+    //expired, and returns `true` if the body should be discarded. The
+    //handler was too slow, so whatever it's about to send is not the
+    //response anymore.
+    fn check_deadline(&self, writer: &mut hyper::server::response::Response<'a>, filter_storage: &AnyMap) -> bool {
+        let status = match filter_storage.get::<EnforcedDeadline>() {
+            Some(enforced) if enforced.deadline.has_expired() => enforced.status,
+            _ => return false
+        };
+
+        self.log.warning(&format!("the handler missed its deadline, so the response was replaced with {}", status));
+        *writer.status_mut() = status;
+        let headers = writer.headers_mut();
+        headers.remove::<ContentType>();
+        headers.remove::<ETag>();
+        utils::remove_content_length(headers);
+        true
+    }
+
+    //Renders the error page for the current status, if there is one.
+    fn render_error_page(&mut self) -> Option<String> {
+        let error_pages = match self.error_pages {
+            Some(error_pages) => error_pages,
+            None => return None
+        };
+
+        let status = self.status();
+        error_pages.get(status).map(|page| page.render(status, self))
+    }
+
+    //Sets a strong ETag for the final body, if it's enabled and applicable,
+    //and returns the new status.
+    fn check_etag(&self, status: StatusCode, headers: &mut Headers, body: &[u8]) -> StatusCode {
+        if !self.auto_etag || status != StatusCode::Ok || body.is_empty() || headers.has::<ETag>() {
+            return status;
+        }
+
+        let tag = utils::to_hex(&utils::sha256(body)[..16]);
+        let not_modified = match self.if_none_match {
+            Some(IfNoneMatch::Any) => true,
+            Some(IfNoneMatch::Items(ref tags)) => tags.iter().any(|t| t.tag() == tag),
+            None => false
+        };
+
+        headers.set(ETag(EntityTag::new(false, tag)));
+        if not_modified {
+            StatusCode::NotModified
+        } else {
+            status
+        }
+    }
+
+    #[cfg(feature = "compression")]
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    pub fn set_compression(&mut self, compression: &'b Compression, coding: Option<Coding>) {
+        self.compression = Some((compression, coding));
+    }
+
+    //Finds the content coding for the response body, and sets the headers
+    //that goes with it.
+    #[cfg(feature = "compression")]
+    fn start_encoding(&self, status: StatusCode, headers: &mut Headers, length: Option<usize>) -> Option<Coding> {
+        let (compression, coding) = match self.compression {
+            Some((compression, coding)) if compression.applies_to(status, headers) => (compression, coding),
+            _ => return None
+        };
+
+        utils::add_vary(headers, "Accept-Encoding");
+
+        match (coding, length) {
+            (Some(_), Some(length)) if length < compression.min_size => None,
+            (Some(coding), _) => {
+                headers.set(::header::ContentEncoding(vec![coding.encoding()]));
+                Some(coding)
+            },
+            (None, _) => None
+        }
+    }
+
+    #[cfg(feature = "compression")]
+    fn encode_body<'d>(&self, status: StatusCode, headers: &mut Headers, body: &'d [u8]) -> Cow<'d, [u8]> {
+        match self.start_encoding(status, headers, Some(body.len())) {
+            Some(coding) => match ::compression::compress(coding, body) {
+                Ok(compressed) => compressed.into(),
+                Err(e) => {
+                    self.log.error(&format!("failed to compress the response body: {}", e));
+                    headers.remove::<::header::ContentEncoding>();
+                    body.into()
+                }
+            },
+            None => body.into()
+        }
+    }
+
+    #[cfg(not(feature = "compression"))]
+    fn encode_body<'d>(&self, _status: StatusCode, _headers: &mut Headers, body: &'d [u8]) -> Cow<'d, [u8]> {
+        body.into()
+    }
+
+    #[cfg(feature = "compression")]
+    fn start_chunked(&self, mut writer: hyper::server::response::Response<'a>, filter_storage: &mut AnyMap) -> io::Result<ChunkWriter<'a>> {
+        let timed_out = self.check_deadline(&mut writer, filter_storage);
+        let status = writer.status();
+        let coding = if timed_out { None } else { self.start_encoding(status, writer.headers_mut(), None) };
+        *writer.status_mut() = finish_headers(&self.filters, status, writer.headers_mut(), self.log, self.global, filter_storage);
+        self.outbox.set_status(writer.status());
+
+        Ok(ChunkWriter {
+            writer: try!(self.start_sink(writer, timed_out)),
+            encoder: coding.map(Encoder::new)
+        })
+    }
+
+    #[cfg(not(feature = "compression"))]
+    fn start_chunked(&self, mut writer: hyper::server::response::Response<'a>, filter_storage: &mut AnyMap) -> io::Result<ChunkWriter<'a>> {
+        let timed_out = self.check_deadline(&mut writer, filter_storage);
+        let status = writer.status();
+        *writer.status_mut() = finish_headers(&self.filters, status, writer.headers_mut(), self.log, self.global, filter_storage);
+        self.outbox.set_status(writer.status());
+
+        Ok(ChunkWriter {
+            writer: try!(self.start_sink(writer, timed_out))
+        })
+    }
+
+    //The body of a response without a body is only counted, and the headers
+    //are sent with its length when it ends. A late response is sent right
+    //away, without a body, and the rest is discarded.
+    fn start_sink(&self, writer: hyper::server::response::Response<'a>, timed_out: bool) -> io::Result<ChunkSink<'a>> {
+        if timed_out {
+            end_without_body(writer, 0).map(|_| ChunkSink::Discard)
+        } else if self.has_body(writer.status()) {
+            writer.start().map(|writer| ChunkSink::Stream(writer, self.outbox.clone()))
+        } else {
+            Ok(ChunkSink::Count(writer, 0))
         }
     }
 
@@ -302,6 +617,173 @@ impl<'a, 'b> Response<'a, 'b> {
         self.writer.as_mut().expect("headers mutably accessed after drop").headers_mut()
     }
 
+    ///Append a link to the `Link` header. The header will be created if it
+    ///doesn't already exist.
+    ///
+    ///```
+    ///use rustful::{Context, Response};
+    ///use rustful::headers::LinkValue;
+    ///
+    ///fn my_handler(context: Context, mut response: Response) {
+    ///    response.add_link(LinkValue::new("/style.css").with_rel("preload").with_param("as", "style"));
+    ///    response.add_link(LinkValue::new("/items?page=2").with_rel("next"));
+    ///    response.send("page 1");
+    ///}
+    ///```
+    pub fn add_link(&mut self, link: LinkValue) {
+        let headers = self.headers_mut();
+        if let Some(&mut Link(ref mut links)) = headers.get_mut::<Link>() {
+            links.push(link);
+            return;
+        }
+
+        headers.set(Link(vec![link]));
+    }
+
+    ///Mark a preference from the request's `Prefer` header as applied, by
+    ///adding it to the `Preference-Applied` header.
+    pub fn apply_preference(&mut self, preference: Preference) {
+        let headers = self.headers_mut();
+        if let Some(&mut PreferenceApplied(ref mut preferences)) = headers.get_mut::<PreferenceApplied>() {
+            if !preferences.contains(&preference) {
+                preferences.push(preference);
+            }
+            return;
+        }
+
+        headers.set(PreferenceApplied(vec![preference]));
+    }
+
+    ///Append a warning to the `Warning` header. The header will be created
+    ///if it doesn't already exist.
+    pub fn add_warning(&mut self, warning: WarningValue) {
+        let headers = self.headers_mut();
+        if let Some(&mut Warning(ref mut warnings)) = headers.get_mut::<Warning>() {
+            warnings.push(warning);
+            return;
+        }
+
+        headers.set(Warning(vec![warning]));
+    }
+
+    ///Set the security headers in `security_headers`. See the
+    ///[`security`][security] module for more information.
+    ///
+    ///[security]: ../security/index.html
+    pub fn apply_security_headers(&mut self, security_headers: &SecurityHeaders) {
+        security_headers.apply(self.headers_mut());
+    }
+
+    ///Set the default security headers, from `SecurityHeaders::new()`.
+    ///
+    ///```
+    ///use rustful::{Context, Response};
+    ///
+    ///fn my_handler(context: Context, mut response: Response) {
+    ///    response.apply_security_defaults();
+    ///    response.send("<h1>Hello</h1>");
+    ///}
+    ///```
+    pub fn apply_security_defaults(&mut self) {
+        self.apply_security_headers(&SecurityHeaders::new());
+    }
+
+    ///Set the CORS headers for a request with the method `method` and the
+    ///headers `request_headers`, according to `policy`. The status is set
+    ///to `204 No Content` if it's a preflight request. See the
+    ///[`cors`][cors] module for more information.
+    ///
+    ///[cors]: ../cors/index.html
+    pub fn apply_cors(&mut self, policy: &CorsPolicy, method: &Method, request_headers: &Headers) -> CorsRequest {
+        let (request, status) = policy.apply(method, request_headers, self.headers_mut());
+        if let Some(status) = status {
+            self.set_status(status);
+        }
+        request
+    }
+
+    ///Add a cookie to the `Set-Cookie` header. Each cookie is sent on its
+    ///own line, and a cookie with the same name, path and domain as a
+    ///previous one replaces it. Invalid cookies are logged and left out.
+    ///
+    ///```
+    ///extern crate rustful;
+    ///extern crate time;
+    ///use rustful::{Context, Response};
+    ///use rustful::headers::Cookie;
+    ///
+    ///fn my_handler(context: Context, mut response: Response) {
+    ///    response.set_cookie(Cookie::new("session", "abc123").path("/").http_only().secure().max_age(time::Duration::days(1)));
+    ///    response.set_cookie(Cookie::new("theme", "dark").path("/"));
+    ///    response.send("cookies!");
+    ///}
+    ///# fn main() {}
+    ///```
+    pub fn set_cookie(&mut self, cookie: Cookie) {
+        if !cookie.is_valid() {
+            self.log.error(&format!("the cookie {:?} is invalid and was not set", cookie.name));
+            return;
+        }
+
+        let headers = self.headers_mut();
+        if let Some(cookies) = headers.get_mut::<SetCookie>() {
+            cookies.insert(cookie);
+            return;
+        }
+
+        headers.set(SetCookie(vec![cookie]));
+    }
+
+    ///Mark the response as stale, by adding a `110 Response is Stale`
+    ///warning, or `111 Revalidation Failed` if `revalidation_failed` is
+    ///`true`. The content of the `Server` header is used as warning agent.
+    ///
+    ///This is meant for caching handlers and filters that serve stale
+    ///content, for example while the origin is unreachable.
+    pub fn mark_stale(&mut self, revalidation_failed: bool) {
+        let agent = self.headers().get::<::header::Server>().map(|s| s.0.clone()).unwrap_or_else(|| "-".to_owned());
+        let agent = if ::utils::is_token(&agent) { agent } else { "-".to_owned() };
+
+        if revalidation_failed {
+            self.add_warning(WarningValue::revalidation_failed(agent));
+        } else {
+            self.add_warning(WarningValue::stale(agent));
+        }
+    }
+
+    ///Require caches to revalidate the response with the server once it has
+    ///become stale, by adding `proxy-revalidate` to the `Cache-Control`
+    ///header. `must-revalidate` is added instead if `proxies_only` is
+    ///`false`, which applies to private caches as well.
+    pub fn require_revalidation(&mut self, proxies_only: bool) {
+        use header::{CacheControl, CacheDirective};
+
+        let directive = if proxies_only {
+            CacheDirective::ProxyRevalidate
+        } else {
+            CacheDirective::MustRevalidate
+        };
+
+        let headers = self.headers_mut();
+        if let Some(&mut CacheControl(ref mut directives)) = headers.get_mut::<CacheControl>() {
+            if !directives.contains(&directive) {
+                directives.push(directive);
+            }
+            return;
+        }
+
+        headers.set(CacheControl(vec![directive]));
+    }
+
+    ///Emit a domain event. It will be delivered to the server's event sink,
+    ///together with the final response status, after the response has been
+    ///sent. See the [`events`][events] module for more information.
+    ///
+    ///[events]: ../events/index.html
+    pub fn emit(&mut self, event: Event) {
+        self.outbox.push(event);
+    }
+
     ///Get a reference to the filter storage.
     pub fn filter_storage(&self) -> &AnyMap {
         self.filter_storage.as_ref().expect("filter storage accessed after drop")
@@ -313,8 +795,43 @@ impl<'a, 'b> Response<'a, 'b> {
         self.filter_storage.as_mut().expect("filter storage mutably accessed after drop")
     }
 
+    ///Skip all of the server's response filters for this response.
+    ///
+    ///```
+    ///use rustful::{Context, Response};
+    ///
+    ///fn my_handler(context: Context, mut response: Response) {
+    ///    //This is sent exactly as it is
+    ///    response.skip_filters();
+    ///    response.send("raw data");
+    ///}
+    ///```
+    pub fn skip_filters(&mut self) {
+        self.filters.skip_all();
+    }
+
+    ///Skip the server's response filters with the name `name` for this
+    ///response. Filters are named with [`Named`][named], and nothing
+    ///happens if there is no filter with the name.
+    ///
+    ///```
+    ///use rustful::{Context, Response};
+    ///
+    /////The server has a JSONP filter, named "jsonp"
+    ///fn my_handler(context: Context, mut response: Response) {
+    ///    response.skip_filter("jsonp");
+    ///    response.send("not wrapped");
+    ///}
+    ///```
+    ///
+    ///[named]: ../filter/struct.Named.html
+    pub fn skip_filter(&mut self, name: &str) {
+        self.filters.skip_named(name);
+    }
+
     ///Send data to the client and finish the response, ignoring eventual
-    ///errors. Use `try_send` to get error information.
+    ///errors. Use `try_send` to get error information. The errors are still
+    ///reported to the server's `error_sink`, if it has one.
     ///
     ///```
     ///use rustful::{Context, Response};
@@ -323,9 +840,9 @@ impl<'a, 'b> Response<'a, 'b> {
     ///    response.send("hello");
     ///}
     ///```
-    #[allow(unused_must_use)]
     pub fn send<'d, Content: Into<Data<'d>>>(self, content: Content) {
-        self.try_send(content);
+        let reporter = self.reporter.clone();
+        report(reporter.as_ref(), self.try_send(content));
     }
 
     ///Try to send data to the client and finish the response. This is the
@@ -345,52 +862,462 @@ impl<'a, 'b> Response<'a, 'b> {
         self.send_sized(content)
     }
 
+    ///Set the status and send the response with an empty body, ignoring
+    ///eventual errors. The body is the error page for the status instead,
+    ///if the server has one. Use `try_send_status` to get error information.
+    ///
+    ///```
+    ///use rustful::{Context, Response, StatusCode};
+    ///
+    ///fn my_handler(context: Context, response: Response) {
+    ///    if context.variables.get("id").is_none() {
+    ///        response.send_status(StatusCode::NotFound);
+    ///    } else {
+    ///        response.send_status(StatusCode::NoContent);
+    ///    }
+    ///}
+    ///```
+    pub fn send_status(self, status: StatusCode) {
+        let reporter = self.reporter.clone();
+        report(reporter.as_ref(), self.try_send_status(status));
+    }
+
+    ///Try to set the status and send the response with an empty body. This
+    ///is the same as `send_status`, but errors are not ignored.
+    pub fn try_send_status(mut self, status: StatusCode) -> Result<(), Error> {
+        self.set_status(status);
+        match self.render_error_page() {
+            Some(page) => self.send_sized(page),
+            None => self.send_sized(&[][..])
+        }
+    }
+
+    ///Serialize `value` as JSON and send it to the client, with
+    ///`Content-Type: application/json; charset=utf-8`, ignoring eventual
+    ///errors. Use `try_send_json` to get error information.
+    ///
+    ///It's only available with the `rustc_json_body` feature.
+    ///
+    ///```
+    ///extern crate rustful;
+    ///extern crate rustc_serialize;
+    ///
+    ///use rustful::{Context, Response};
+    ///
+    ///#[derive(RustcEncodable)]
+    ///struct Greeting {
+    ///    message: String
+    ///}
+    ///
+    ///fn my_handler(context: Context, response: Response) {
+    ///    response.send_json(&Greeting {
+    ///        message: "hello".into()
+    ///    });
+    ///}
+    ///# fn main() {}
+    ///```
+    #[cfg(feature = "rustc_json_body")]
+    pub fn send_json<T: Encodable>(self, value: &T) {
+        let reporter = self.reporter.clone();
+        report(reporter.as_ref(), self.try_send_json(value));
+    }
+
+    ///Try to serialize `value` as JSON and send it to the client. This is
+    ///the same as `send_json`, but errors are not ignored. Nothing is sent if
+    ///the serialization fails.
+    ///
+    ///It's only available with the `rustc_json_body` feature.
+    #[cfg(feature = "rustc_json_body")]
+    pub fn try_send_json<T: Encodable>(mut self, value: &T) -> Result<(), Error> {
+        use mime::{Attr, Value};
+
+        let json = try!(json::encode(value).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)));
+        self.headers_mut().set(ContentType(Mime(
+            TopLevel::Application,
+            SubLevel::Json,
+            vec![(Attr::Charset, Value::Utf8)]
+        )));
+        self.send_sized(json)
+    }
+
+    ///Serialize `value` as MessagePack and send it to the client, ignoring
+    ///eventual errors. Use `try_send_msgpack` to get error information.
+    ///
+    ///It's only available with the `msgpack` feature.
+    #[cfg(feature = "msgpack")]
+    pub fn send_msgpack<T: Encodable>(self, value: &T) {
+        let reporter = self.reporter.clone();
+        report(reporter.as_ref(), self.try_send_as(Format::MsgPack, value));
+    }
+
+    ///Try to serialize `value` as MessagePack and send it to the client.
+    ///This is the same as `send_msgpack`, but errors are not ignored.
+    ///
+    ///It's only available with the `msgpack` feature.
+    #[cfg(feature = "msgpack")]
+    pub fn try_send_msgpack<T: Encodable>(self, value: &T) -> Result<(), Error> {
+        self.try_send_as(Format::MsgPack, value)
+    }
+
+    ///Serialize `value` as CBOR and send it to the client, ignoring
+    ///eventual errors. Use `try_send_cbor` to get error information.
+    ///
+    ///It's only available with the `cbor` feature.
+    #[cfg(feature = "cbor")]
+    pub fn send_cbor<T: Encodable>(self, value: &T) {
+        let reporter = self.reporter.clone();
+        report(reporter.as_ref(), self.try_send_as(Format::Cbor, value));
+    }
+
+    ///Try to serialize `value` as CBOR and send it to the client. This is
+    ///the same as `send_cbor`, but errors are not ignored.
+    ///
+    ///It's only available with the `cbor` feature.
+    #[cfg(feature = "cbor")]
+    pub fn try_send_cbor<T: Encodable>(self, value: &T) -> Result<(), Error> {
+        self.try_send_as(Format::Cbor, value)
+    }
+
+    ///Serialize `value` in the format that is preferred by the `Accept`
+    ///header in `request_headers`, and send it to the client, ignoring
+    ///eventual errors. Use `try_send_negotiated` to get error information.
+    ///
+    ///The response is `406 Not Acceptable` if none of the available formats
+    ///are accepted. See the [`formats`][formats] module for more
+    ///information.
+    ///
+    ///It's only available with the `rustc_json_body` feature.
+    ///
+    ///[formats]: ../formats/index.html
+    #[cfg(feature = "rustc_json_body")]
+    pub fn send_negotiated<T: Encodable>(self, value: &T, request_headers: &Headers) {
+        let reporter = self.reporter.clone();
+        report(reporter.as_ref(), self.try_send_negotiated(value, request_headers));
+    }
+
+    ///Try to serialize `value` in the format that is preferred by the
+    ///`Accept` header in `request_headers`, and send it to the client. This
+    ///is the same as `send_negotiated`, but errors are not ignored.
+    ///
+    ///It's only available with the `rustc_json_body` feature.
+    #[cfg(feature = "rustc_json_body")]
+    pub fn try_send_negotiated<T: Encodable>(mut self, value: &T, request_headers: &Headers) -> Result<(), Error> {
+        utils::add_vary(self.headers_mut(), "Accept");
+
+        match formats::negotiate(request_headers) {
+            Some(format) => self.try_send_as(format, value),
+            None => {
+                self.set_status(StatusCode::NotAcceptable);
+                Ok(())
+            }
+        }
+    }
+
+    ///Try to serialize `value` in the format `format` and send it to the
+    ///client. Nothing is sent if the serialization fails.
+    ///
+    ///It's only available with the `rustc_json_body` feature.
+    #[cfg(feature = "rustc_json_body")]
+    pub fn try_send_as<T: Encodable>(mut self, format: Format, value: &T) -> Result<(), Error> {
+        let json = try!(json::encode(value).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)));
+        let value = try!(json::Json::from_str(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)));
+        self.headers_mut().set(ContentType(format.content_type()));
+        self.send_sized(format.encode(&value))
+    }
+
     fn send_sized<'d, Content: Into<Data<'d>>>(&mut self, content: Content) -> Result<(), Error> {
         let mut writer = self.writer.take().expect("response used after drop");
         let mut filter_storage = self.filter_storage.take().expect("response used after drop");
 
-        if self.filters.is_empty() {
-            writer.send(content.into().as_bytes()).map_err(|e| e.into())
+        if self.filters.iter().next().is_none() {
+            let content: Data = content.into();
+            self.write_sized(writer, content.as_bytes(), &mut filter_storage)
         } else {
             let mut buffer = vec![];
 
-            let (status, write_queue) = try!(filter_headers(
-                self.filters,
-                writer.status(),
-                writer.headers_mut(),
-                self.log,
-                self.global,
-                &mut filter_storage
-            ));
-            *writer.status_mut() = status;
-            for action in write_queue {
-                match action {
-                    Action::Next(Some(content)) => try!(buffer.write_all(content.as_bytes())),
-                    Action::Next(None) => {},
-                    Action::Abort(e) => return Err(Error::Filter(e)),
-                    Action::SilentAbort => break
-                }
+            match self.filter_sized(&mut writer, &mut filter_storage, content, &mut buffer) {
+                Ok(()) => self.write_sized(writer, &buffer, &mut filter_storage),
+                Err(Error::Filter(e)) => Err(self.recover_from_abort(writer, filter_storage, e)),
+                Err(e) => Err(e)
+            }
+        }
+    }
+
+    //Runs a whole sized body through the filters. Nothing is written to the
+    //client.
+    fn filter_sized<'d, Content: Into<Data<'d>>>(&self, writer: &mut hyper::server::response::Response<'a>, filter_storage: &mut AnyMap, content: Content, buffer: &mut Vec<u8>) -> Result<(), Error> {
+        let (status, write_queue) = try!(filter_headers(
+            &self.filters,
+            writer.status(),
+            writer.headers_mut(),
+            self.log,
+            self.global,
+            filter_storage
+        ));
+        *writer.status_mut() = status;
+        for action in write_queue {
+            match action {
+                Action::Next(Some(content)) => try!(buffer.write_all(content.as_bytes())),
+                Action::Next(None) => {},
+                Action::Abort(e) => return Err(Error::Filter(e)),
+                Action::SilentAbort => break
             }
+        }
+
+        let filter_result = filter_content(&self.filters, content, self.log, self.global, filter_storage);
+        match filter_result {
+            Action::Next(Some(content)) => try!(buffer.write_all(content.as_bytes())),
+            Action::Abort(e) => return Err(Error::Filter(e)),
+            _ => {}
+        }
 
-            let filter_result = filter_content(self.filters, content, self.log, self.global, &mut filter_storage);
-            match filter_result {
+        let write_queue = try!(filter_end(&self.filters, self.log, self.global, filter_storage));
+        for action in write_queue {
+            match action {
                 Action::Next(Some(content)) => try!(buffer.write_all(content.as_bytes())),
+                Action::Next(None) => {},
                 Action::Abort(e) => return Err(Error::Filter(e)),
-                _ => {}
+                Action::SilentAbort => break
             }
+        }
 
-            let write_queue = try!(filter_end(self.filters, self.log, self.global, &mut filter_storage));
-            for action in write_queue {
-                match action {
-                    Action::Next(Some(content)) => try!(buffer.write_all(content.as_bytes())),
-                    Action::Next(None) => {},
-                    Action::Abort(e) => return Err(Error::Filter(e)),
-                    Action::SilentAbort => break
-                }
+        Ok(())
+    }
+
+    //Replaces the response with the `FilterResponse` in the filter storage,
+    //or `500 Internal Server Error`, after a filter has aborted before
+    //anything was written. The original error is returned, to be reported.
+    fn recover_from_abort(&mut self, writer: hyper::server::response::Response<'a>, mut filter_storage: AnyMap, error: String) -> Error {
+        let replacement = filter_storage.remove::<FilterResponse>()
+            .unwrap_or_else(|| FilterResponse::new(StatusCode::InternalServerError));
+        self.log.error(&format!("a response filter aborted, so the response was replaced with {}: {}", replacement.status, error));
+
+        self.writer = Some(writer);
+        self.filter_storage = Some(filter_storage);
+        let result = self.respond_with(replacement);
+        report(self.reporter.as_ref(), result);
+
+        Error::Filter(error)
+    }
+
+    #[doc(hidden)]
+    ///Internal and may change without warning.
+    ///
+    ///Replace the response with `response`, bypassing the response
+    ///filters.
+    pub fn send_filter_response(mut self, response: FilterResponse) {
+        let result = self.respond_with(response);
+        report(self.reporter.as_ref(), result);
+    }
+
+    fn respond_with(&mut self, response: FilterResponse) -> Result<(), Error> {
+        self.set_status(response.status);
+        {
+            let headers = self.headers_mut();
+            headers.remove::<ContentType>();
+            headers.remove::<ETag>();
+            utils::remove_content_length(headers);
+            for header in response.headers.iter() {
+                headers.set_raw(header.name().to_owned(), vec![header.value_string().into_bytes()]);
+            }
+        }
+
+        let body = match response.body {
+            Some(body) => body,
+            None => self.render_error_page().map(String::into_bytes).unwrap_or_else(Vec::new)
+        };
+
+        //The filters may have failed already, so they are not trusted with
+        //the replacement.
+        self.skip_filters();
+        self.send_sized(body)
+    }
+
+    fn write_sized(&self, mut writer: hyper::server::response::Response<'a>, body: &[u8], filter_storage: &mut AnyMap) -> Result<(), Error> {
+        let body = if self.check_deadline(&mut writer, filter_storage) {
+            &[][..]
+        } else {
+            check_content_length(writer.headers(), body.len(), self.log);
+            body
+        };
+        let status = writer.status();
+        let body = self.encode_body(status, writer.headers_mut(), body);
+        let status = self.check_etag(status, writer.headers_mut(), &body);
+        let status = finish_headers(&self.filters, status, writer.headers_mut(), self.log, self.global, filter_storage);
+        *writer.status_mut() = status;
+        self.outbox.set_status(status);
+
+        if self.has_body(status) {
+            try!(writer.send(&body));
+            self.outbox.add_bytes(body.len() as u64);
+            Ok(())
+        } else {
+            end_without_body(writer, body.len() as u64).map_err(|e| e.into())
+        }
+    }
+
+    ///Log `error` and send it to the client, using the error renderer of
+    ///the server. The default renderer sends an empty `500 Internal Server
+    ///Error` response. See the [`fallible`][fallible] module for more
+    ///information.
+    ///
+    ///```
+    ///use std::fs::File;
+    ///use std::io::Read;
+    ///use rustful::{Context, Response};
+    ///
+    ///fn my_handler(context: Context, response: Response) {
+    ///    let mut text = String::new();
+    ///    match File::open("text.txt").and_then(|mut file| file.read_to_string(&mut text)) {
+    ///        Ok(_) => response.send(text),
+    ///        Err(e) => response.send_error(&e)
+    ///    }
+    ///}
+    ///```
+    ///
+    ///[fallible]: ../handler/fallible/index.html
+    pub fn send_error<E: error::Error + 'static>(mut self, error: &E) {
+        self.log.error(&format!("handler error: {}", error));
+
+        let renderer = self.error_renderer;
+        let body = match renderer {
+            Some(renderer) => renderer.render(error, &mut self),
+            None => DefaultErrorRenderer.render(error, &mut self)
+        };
+
+        //An empty body is replaced with the error page, if there is one,
+        //when the response is dropped.
+        if !body.is_empty() {
+            self.send(body);
+        }
+    }
+
+    ///Render the template `name` with `data`, using the renderer of the
+    ///server, and send it to the client. The `Content-Type` header is set
+    ///to the content type of the template.
+    ///
+    ///A missing template, a failed rendering, or a server without a
+    ///renderer, is sent as an error with `send_error`. See the
+    ///[`template`][template] module for more information.
+    ///
+    ///```
+    ///use rustful::{Context, Response};
+    ///use rustful::template::TemplateData;
+    ///
+    ///fn my_handler(context: Context, response: Response) {
+    ///    let mut data = TemplateData::new();
+    ///    data.insert("path".into(), context.uri.to_string());
+    ///    response.render("page.html", &data);
+    ///}
+    ///```
+    ///
+    ///[template]: ../template/index.html
+    pub fn render(mut self, name: &str, data: &TemplateData) {
+        let rendered = match self.renderer {
+            Some(renderer) => renderer.render(name, data),
+            None => Err(RenderError::Failed("the server has no renderer".into()))
+        };
+
+        match rendered {
+            Ok(rendered) => {
+                self.headers_mut().set(ContentType(rendered.content_type));
+                self.send(rendered.body);
+            },
+            Err(e) => self.send_error(&e)
+        }
+    }
+
+    ///Stream the body from `source` to the client, as a chunked response,
+    ///and finish the response. The source is read in blocks of `BLOCK_SIZE`
+    ///bytes, so the whole body is never held in memory.
+    ///
+    ///```
+    ///use std::io::Cursor;
+    ///use rustful::{Context, Response};
+    ///
+    ///fn my_handler(context: Context, response: Response) {
+    ///    let source = Cursor::new(vec![b'a'; 1024 * 1024]);
+    ///
+    ///    if let Err(e) = response.send_reader(source) {
+    ///        context.log.note(&format!("failed to send the body: {}", e));
+    ///    }
+    ///}
+    ///```
+    pub fn send_reader<R: Read>(self, mut source: R) -> Result<(), Error> {
+        let mut writer = self.into_chunked();
+        let mut buffer = vec![0; BLOCK_SIZE];
+
+        loop {
+            match source.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(length) => {
+                    try!(writer.try_send(&buffer[..length]));
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(Error::Io(e))
             }
-            
-            writer.send(&buffer).map_err(|e| e.into())
         }
+
+        writer.end()
+    }
+
+    ///Send the chunks from `chunks` to the client, as a chunked response,
+    ///and finish the response, ignoring eventual errors. Use `try_send_all`
+    ///to get error information.
+    ///
+    ///The chunks are produced one at a time, so the whole body doesn't have
+    ///to be built before it's sent. Small chunks are collected into blocks
+    ///of up to `BLOCK_SIZE` bytes, to not write each of them separately.
+    ///
+    ///```
+    ///use rustful::{Context, Response};
+    ///
+    ///fn my_handler(context: Context, response: Response) {
+    ///    let count = context.variables.get("count")
+    ///        .and_then(|n| n.parse().ok())
+    ///        .unwrap_or(0u32);
+    ///
+    ///    response.send_all((0..count).map(|i| format!("{{\"row\": {}}}\n", i)));
+    ///}
+    ///```
+    pub fn send_all<'d, I>(self, chunks: I) where
+        I: IntoIterator,
+        I::Item: Into<Data<'d>>
+    {
+        let reporter = self.reporter.clone();
+        report(reporter.as_ref(), self.try_send_all(chunks));
+    }
+
+    ///Send the chunks from `chunks` to the client, as a chunked response,
+    ///and finish the response. This is the same as `send_all`, but errors
+    ///are not ignored. Nothing more is sent after the first error.
+    pub fn try_send_all<'d, I>(self, chunks: I) -> Result<(), Error> where
+        I: IntoIterator,
+        I::Item: Into<Data<'d>>
+    {
+        let mut writer = self.into_chunked();
+        let mut buffer = Vec::with_capacity(BLOCK_SIZE);
+
+        for chunk in chunks {
+            let chunk = chunk.into();
+
+            if !buffer.is_empty() && buffer.len() + chunk.as_bytes().len() > BLOCK_SIZE {
+                try!(writer.try_send(&buffer[..]));
+                buffer.clear();
+            }
+
+            if buffer.is_empty() && chunk.as_bytes().len() >= BLOCK_SIZE {
+                try!(writer.try_send(chunk));
+            } else {
+                buffer.extend_from_slice(chunk.as_bytes());
+            }
+        }
+
+        if !buffer.is_empty() {
+            try!(writer.try_send(buffer));
+        }
+
+        writer.end()
     }
 
     ///Send a static file to the client.
@@ -399,7 +1326,12 @@ impl<'a, 'b> Response<'a, 'b> {
     ///extension, and `application/octet-stream` is used as a fallback if the
     ///extension is unknown. Use `send_file_with_mime` to override the MIME
     ///guessing. See also [`ext_to_mime`](../file/fn.ext_to_mime.html) for more
-    ///information.
+    ///information. The `Last-Modified` header is set to the modification
+    ///time of the file, and `ETag` is set to a tag that is based on the
+    ///modification time and the size of the file. A `GET` or `HEAD` request
+    ///gets `304 Not Modified` if the tag matches its `If-None-Match` header
+    ///or, if there is no `If-None-Match` header, if the file hasn't been
+    ///modified since the time in its `If-Modified-Since` header.
     ///
     ///An error is returned upon failure and the response may be recovered
     ///from there if the file could not be opened.
@@ -486,11 +1418,50 @@ impl<'a, 'b> Response<'a, 'b> {
     ///}
     ///# fn main() {}
     ///```
-    pub fn send_file_with_mime<P, F>(mut self, path: P, to_mime: F) -> Result<(), FileError<'a, 'b>> where
+    pub fn send_file_with_mime<P, F>(self, path: P, to_mime: F) -> Result<(), FileError<'a, 'b>> where
         P: AsRef<Path>,
         F: FnOnce(&str) -> Option<Mime>
     {
-        let path: &Path = path.as_ref();
+        self.send_file_part(path.as_ref(), to_mime, None)
+    }
+
+    ///Send a static file, or the part of it that is requested by the `Range`
+    ///header in `request_headers`, to the client.
+    ///
+    ///It works like `send_file`, but a request for a single byte range gets
+    ///`206 Partial Content`, with only the requested bytes, and a range
+    ///outside the file gets `416 Range Not Satisfiable`. The response has
+    ///`Accept-Ranges: bytes`, to tell the client that ranges are supported.
+    ///See [`requested_range`](../file/fn.requested_range.html) for more
+    ///information. Ranges only apply to `GET` requests, so other requests
+    ///should use `send_file`.
+    ///
+    ///The response is `304 Not Modified` if the file's `ETag` matches the
+    ///`If-None-Match` header or, if there is no `If-None-Match` header, if
+    ///the file hasn't been modified since the time in the `If-Modified-Since`
+    ///header. The file content is not read in that case. The whole file is
+    ///sent with `200 OK` if the request has an `If-Range` header that
+    ///doesn't match the file's `ETag` or `Last-Modified` time.
+    ///
+    ///```
+    ///use rustful::{Context, Response};
+    ///
+    ///fn my_handler(context: Context, response: Response) {
+    ///    let res = response.send_file_ranged("path/to/video.mp4", &context.headers)
+    ///        .or_else(|e| e.send_not_found("the file was not found"));
+    ///
+    ///    if let Err(e) = res.or_else(|e| e.ignore_send_error()) {
+    ///        context.log.error(&format!("failed to send the video: {}", e.0));
+    ///    }
+    ///}
+    ///```
+    pub fn send_file_ranged<P: AsRef<Path>>(self, path: P, request_headers: &Headers) -> Result<(), FileError<'a, 'b>> {
+        self.send_file_part(path.as_ref(), ::file::ext_to_mime, Some(request_headers))
+    }
+
+    fn send_file_part<F>(mut self, path: &Path, to_mime: F, request_headers: Option<&Headers>) -> Result<(), FileError<'a, 'b>> where
+        F: FnOnce(&str) -> Option<Mime>
+    {
         let mime = path
             .extension()
             .and_then(|ext| to_mime(&ext.to_string_lossy()))
@@ -507,9 +1478,67 @@ impl<'a, 'b> Response<'a, 'b> {
 
         self.headers_mut().set(ContentType(mime));
 
-        let mut writer = unsafe { self.into_raw(metadata.len()) };
+        let modified = last_modified(&metadata);
+        if let Some(ref modified) = modified {
+            self.headers_mut().set(LastModified(modified.clone()));
+        }
+
+        let etag = file_etag(&metadata);
+        self.headers_mut().set(ETag(etag.clone()));
+
+        let not_modified = match request_headers {
+            Some(request_headers) => is_not_modified(request_headers.get(), request_headers.get(), &etag, modified.as_ref()),
+            None => is_not_modified(self.if_none_match.as_ref(), self.if_modified_since.as_ref(), &etag, modified.as_ref())
+        };
+
+        if not_modified {
+            self.set_status(StatusCode::NotModified);
+            return response_to_io_result(self.try_send(&[][..])).map_err(FileError::Send);
+        }
+
+        let length = metadata.len();
+        let range = match request_headers {
+            Some(request_headers) => {
+                self.headers_mut().set_raw("Accept-Ranges", vec![b"bytes".to_vec()]);
+                if if_range_matches(request_headers, &etag, modified.as_ref()) {
+                    ::file::requested_range(request_headers, length)
+                } else {
+                    FileRange::Full
+                }
+            },
+            None => FileRange::Full
+        };
+
+        match range {
+            FileRange::Full => {
+                let mut writer = unsafe { self.into_raw(length) };
+                if writer.discard {
+                    return writer.end().map_err(FileError::Send);
+                }
+                io::copy(&mut file, &mut writer).map_err(|e| FileError::Send(e)).map(|_| ())
+            },
+            FileRange::Partial(first, last) => {
+                if let Err(e) = file.seek(SeekFrom::Start(first)) {
+                    return Err(FileError::Open(e, self));
+                }
+
+                self.set_status(StatusCode::PartialContent);
+                let content_range = format!("bytes {}-{}/{}", first, last, length);
+                self.headers_mut().set_raw("Content-Range", vec![content_range.into_bytes()]);
 
-        io::copy(&mut file, &mut writer).map_err(|e| FileError::Send(e)).map(|_| ())
+                let part_length = last - first + 1;
+                let mut writer = unsafe { self.into_raw(part_length) };
+                if writer.discard {
+                    return writer.end().map_err(FileError::Send);
+                }
+                io::copy(&mut file.take(part_length), &mut writer).map_err(|e| FileError::Send(e)).map(|_| ())
+            },
+            FileRange::Unsatisfiable => {
+                self.set_status(StatusCode::RangeNotSatisfiable);
+                self.headers_mut().set_raw("Content-Range", vec![format!("bytes */{}", length).into_bytes()]);
+                response_to_io_result(self.try_send(&[][..])).map_err(FileError::Send)
+            }
+        }
     }
 
     ///Write the status code and headers to the client and turn the `Response`
@@ -518,65 +1547,243 @@ impl<'a, 'b> Response<'a, 'b> {
         let mut writer = self.writer.take().expect("response used after drop");
         
         //Make sure it's chunked
-        writer.headers_mut().remove::<::header::ContentLength>();
-        writer.headers_mut().remove_raw("content-length");
+        utils::remove_content_length(writer.headers_mut());
+
+        let mut filter_storage = self.filter_storage.take().expect("response used after drop");
 
-        let writer = filter_headers(
-            self.filters,
+        let header_result = filter_headers(
+            &self.filters,
             writer.status(),
             writer.headers_mut(),
             self.log,
             self.global,
-            self.filter_storage_mut()
-        ).and_then(|(status, write_queue)|{
-            *writer.status_mut() = status;
-            let mut writer = try!(writer.start());
+            &mut filter_storage
+        );
+
+        let writer = match header_result {
+            Ok((status, write_queue)) => {
+                *writer.status_mut() = status;
+                self.start_chunked(writer, &mut filter_storage).and_then(|mut writer| {
+                    for action in write_queue {
+                        match action {
+                            Action::Next(Some(content)) => try!(writer.write_all(content.as_bytes())),
+                            Action::Next(None) => {},
+                            Action::Abort(e) => return Err(Error::Filter(e)),
+                            Action::SilentAbort => break
+                        }
+                    }
 
-            for action in write_queue {
-                match action {
-                    Action::Next(Some(content)) => try!(writer.write_all(content.as_bytes())),
-                    Action::Next(None) => {},
-                    Action::Abort(e) => return Err(Error::Filter(e)),
-                    Action::SilentAbort => break
-                }
-            }
+                    Ok(writer)
+                })
+            },
+            Err(Error::Filter(e)) => {
+                //Nothing has been written yet, so the response can be replaced.
+                let error = self.recover_from_abort(writer, filter_storage, e);
+                filter_storage = AnyMap::new();
+                Err(error)
+            },
+            Err(e) => Err(e)
+        };
 
-            Ok(writer)
-        });
+        Chunked {
+            writer: Some(writer),
+            filters: self.filters.clone(),
+            log: self.log,
+            global: self.global,
+            filter_storage: filter_storage,
+            outbox: self.outbox.clone(),
+            reporter: self.reporter.clone()
+        }
+    }
+
+    ///Turn the `Response` into a `Buffered` response, where the body can be
+    ///written in parts, like with `Chunked`, but is sent with a
+    ///`Content-Length` header when it ends. Nothing is written to the client
+    ///until then, so the status and the headers can still be changed.
+    ///
+    ///```
+    ///use rustful::{Context, Response, StatusCode};
+    ///
+    ///fn my_handler(context: Context, response: Response) {
+    ///    let count = context.variables.get("count")
+    ///        .and_then(|n| n.parse().ok())
+    ///        .unwrap_or(0u32);
+    ///    let mut buffered = response.into_buffered();
+    ///
+    ///    for i in 0..count {
+    ///        buffered.send(format!("line #{}\n", i + 1));
+    ///    }
+    ///
+    ///    if buffered.is_empty() {
+    ///        buffered.set_status(StatusCode::NoContent);
+    ///    }
+    ///}
+    ///```
+    pub fn into_buffered(self) -> Buffered<'a, 'b> {
+        Buffered {
+            response: Some(self),
+            buffer: vec![]
+        }
+    }
+
+    ///Write the status code and headers to the client and turn the `Response`
+    ///into a `Raw` response. Any eventual response filters are bypassed to
+    ///make sure that the data is not modified, except for their
+    ///`finish_headers` stage. The body is discarded if the response
+    ///shouldn't have one.
+    ///
+    ///__Unsafety__: The content length is set beforehand, which makes it
+    ///possible to send responses that are too short.
+    ///
+    ///Writes that would make the body longer than `content_length` are
+    ///refused, and a body that ends up shorter is logged as an error when
+    ///the `Raw` response ends.
+    pub unsafe fn into_raw(mut self, content_length: u64) -> Raw<'a, 'b> {
+        let mut writer = self.writer.take().expect("response used after drop");
+
+        let mut filter_storage = self.filter_storage.take().expect("response used after drop");
+
+        //A late body is accepted, but not sent.
+        let timed_out = self.check_deadline(&mut writer, &filter_storage);
+        utils::remove_content_length(writer.headers_mut());
+        writer.headers_mut().set(::header::ContentLength(if timed_out { 0 } else { content_length }));
+
+        let status = writer.status();
+        *writer.status_mut() = finish_headers(&self.filters, status, writer.headers_mut(), self.log, self.global, &mut filter_storage);
+        self.outbox.set_status(writer.status());
+
+        Raw {
+            discard: timed_out || !self.has_body(writer.status()),
+            writer: Some(writer.start()),
+            log: self.log,
+            outbox: self.outbox.clone(),
+            reporter: self.reporter.clone(),
+            content_length: content_length,
+            remaining: content_length
+        }
+    }
+}
+
+impl<'a, 'b> Drop for Response<'a, 'b> {
+    ///Writes status code and headers and closes the connection. The body is
+    ///the error page for the status, if the server has one.
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let result = match self.render_error_page() {
+                Some(page) => self.send_sized(page),
+                None => self.send_sized(&[][..])
+            };
+            report(self.reporter.as_ref(), result);
+        }
+    }
+}
+
+
+///An interface for writing a buffered response body.
+///
+///The body is collected in memory and sent with a `Content-Length` header
+///when `end` is called, or when the writer drops out of scope. The response
+///filters are applied to the whole body at once, just like with
+///`Response::send`.
+pub struct Buffered<'a, 'b> {
+    response: Option<Response<'a, 'b>>,
+    buffer: Vec<u8>
+}
+
+impl<'a, 'b> Buffered<'a, 'b> {
+    ///Get the current status code.
+    pub fn status(&self) -> StatusCode {
+        self.response().status()
+    }
+
+    ///Change the status code. `200 Ok` is used by default.
+    pub fn set_status(&mut self, status: StatusCode) {
+        self.response_mut().set_status(status);
+    }
+
+    ///Get a reference to the headers.
+    pub fn headers(&self) -> &Headers {
+        self.response().headers()
+    }
+
+    ///Get a mutable reference to the headers.
+    pub fn headers_mut(&mut self) -> &mut Headers {
+        self.response_mut().headers_mut()
+    }
+
+    ///Emit a domain event. See `Response::emit`.
+    pub fn emit(&mut self, event: Event) {
+        self.response_mut().emit(event);
+    }
+
+    ///Get a reference to the filter storage.
+    pub fn filter_storage(&self) -> &AnyMap {
+        self.response().filter_storage()
+    }
+
+    ///Get a mutable reference to the filter storage. It can be used to
+    ///communicate with the response filters.
+    pub fn filter_storage_mut(&mut self) -> &mut AnyMap {
+        self.response_mut().filter_storage_mut()
+    }
+
+    ///Append data to the body.
+    pub fn send<'d, Content: Into<Data<'d>>>(&mut self, content: Content) {
+        self.buffer.extend_from_slice(content.into().as_bytes());
+    }
+
+    ///Get the length of the buffered body.
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    ///Check if the buffered body is empty.
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    ///Send the buffered body and finish the response, and collect eventual
+    ///errors.
+    ///
+    ///This is optional and will happen silently when the writer drops out of
+    ///scope.
+    pub fn end(mut self) -> Result<(), Error> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        let response = self.response.take().expect("can only finish once");
+        let body = std::mem::replace(&mut self.buffer, vec![]);
+        response.try_send(body)
+    }
 
-        Chunked {
-            writer: Some(writer),
-            filters: self.filters,
-            log: self.log,
-            global: self.global,
-            filter_storage: self.filter_storage.take().expect("response used after drop")
-        }
+    fn response(&self) -> &Response<'a, 'b> {
+        self.response.as_ref().expect("response used after drop")
     }
 
-    ///Write the status code and headers to the client and turn the `Response`
-    ///into a `Raw` response. Any eventual response filters are bypassed to
-    ///make sure that the data is not modified.
-    ///
-    ///__Unsafety__: The content length is set beforehand, which makes it
-    ///possible to send responses that are too short.
-    pub unsafe fn into_raw(mut self, content_length: u64) -> Raw<'a> {
-        let mut writer = self.writer.take().expect("response used after drop");
+    fn response_mut(&mut self) -> &mut Response<'a, 'b> {
+        self.response.as_mut().expect("response used after drop")
+    }
+}
 
-        writer.headers_mut().remove_raw("content-length");
-        writer.headers_mut().set(::header::ContentLength(content_length));
+impl<'a, 'b> Write for Buffered<'a, 'b> {
+    fn write(&mut self, content: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(content);
+        Ok(content.len())
+    }
 
-        Raw {
-            writer: Some(writer.start())
-        }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
     }
 }
 
-#[allow(unused_must_use)]
-impl<'a, 'b> Drop for Response<'a, 'b> {
-    ///Writes status code and headers and closes the connection.
+impl<'a, 'b> Drop for Buffered<'a, 'b> {
+    ///Sends the buffered body and closes the connection.
     fn drop(&mut self) {
-        if self.writer.is_some() {
-            self.send_sized(&[][..]);
+        if self.response.is_some() {
+            let reporter = self.response().reporter.clone();
+            let result = self.finish();
+            report(reporter.as_ref(), result);
         }
     }
 }
@@ -588,14 +1795,25 @@ impl<'a, 'b> Drop for Response<'a, 'b> {
 ///This is useful for when the size of the data is unknown, but it comes with
 ///an overhead for each time `send` or `try_send` is called (simply put).
 pub struct Chunked<'a, 'b> {
-    writer: Option<Result<hyper::server::response::Response<'a, hyper::net::Streaming>, Error>>,
-    filters: &'b Vec<Box<ResponseFilter>>,
+    writer: Option<Result<ChunkWriter<'a>, Error>>,
+    filters: Filters<'b>,
     log: &'b (Log + 'b),
     global: &'b Global,
-    filter_storage: AnyMap
+    filter_storage: AnyMap,
+    outbox: Outbox,
+    reporter: Option<Reporter<'b>>
 }
 
 impl<'a, 'b> Chunked<'a, 'b> {
+    ///Emit a domain event. It will be delivered to the server's event sink,
+    ///together with the final response status, after the response has been
+    ///sent. See the [`events`][events] module for more information.
+    ///
+    ///[events]: ../events/index.html
+    pub fn emit(&mut self, event: Event) {
+        self.outbox.push(event);
+    }
+
     ///Get a reference to the filter storage.
     pub fn filter_storage(&self) -> &AnyMap {
         &self.filter_storage
@@ -624,9 +1842,9 @@ impl<'a, 'b> Chunked<'a, 'b> {
     ///    }
     ///}
     ///```
-    #[allow(unused_must_use)]
     pub fn send<'d, Content: Into<Data<'d>>>(&mut self, content: Content) {
-        self.try_send(content);
+        let result = self.try_send(content);
+        report(self.reporter.as_ref(), result);
     }
 
     ///Send a chunk of data to the client. This is the same as `send`, but
@@ -658,7 +1876,7 @@ impl<'a, 'b> Chunked<'a, 'b> {
             } else { unreachable!(); }
         };
 
-        let filter_result = filter_content(self.filters, content, self.log, self.global, &mut self.filter_storage);
+        let filter_result = filter_content(&self.filters, content, self.log, self.global, &mut self.filter_storage);
 
         let write_result = match filter_result {
             Action::Next(Some(ref s)) => {
@@ -692,7 +1910,7 @@ impl<'a, 'b> Chunked<'a, 'b> {
 
     fn finish(&mut self) -> Result<(), Error> {
         let mut writer = try!(self.writer.take().expect("can only finish once"));
-        let write_queue = try!(filter_end(self.filters, self.log, self.global, &mut self.filter_storage));
+        let write_queue = try!(filter_end(&self.filters, self.log, self.global, &mut self.filter_storage));
 
         for action in write_queue {
             try!{
@@ -707,7 +1925,7 @@ impl<'a, 'b> Chunked<'a, 'b> {
         writer.end().map_err(|e| Error::Io(e))
     }
 
-    fn borrow_writer(&mut self) -> Result<&mut hyper::server::response::Response<'a, hyper::net::Streaming>, Error> {
+    fn borrow_writer(&mut self) -> Result<&mut ChunkWriter<'a>, Error> {
         match self.writer {
             Some(Ok(ref mut writer)) => Ok(writer),
             None => Err(Error::Io(io::Error::new(io::ErrorKind::BrokenPipe, "write after close"))),
@@ -733,13 +1951,118 @@ impl<'a, 'b> Write for Chunked<'a, 'b> {
     }
 }
 
-#[allow(unused_must_use)]
 impl<'a, 'b> Drop for Chunked<'a, 'b> {
     ///Finishes writing and closes the connection.
     fn drop(&mut self) {
         if self.writer.is_some() {
-            self.finish();
+            let result = self.finish();
+            report(self.reporter.as_ref(), result);
+        }
+    }
+}
+
+//Writes the chunks of a `Chunked` response, and compresses them if
+//necessary.
+struct ChunkWriter<'a> {
+    writer: ChunkSink<'a>,
+    #[cfg(feature = "compression")]
+    encoder: Option<Encoder>
+}
+
+impl<'a> ChunkWriter<'a> {
+    #[cfg(feature = "compression")]
+    fn end(self) -> io::Result<()> {
+        let mut writer = self.writer;
+        if let Some(encoder) = self.encoder {
+            try!(writer.write_all(&try!(encoder.finish())));
+        }
+        writer.end()
+    }
+
+    #[cfg(not(feature = "compression"))]
+    fn end(self) -> io::Result<()> {
+        self.writer.end()
+    }
+}
+
+//Where a `ChunkWriter` writes the body.
+enum ChunkSink<'a> {
+    Stream(hyper::server::response::Response<'a, hyper::net::Streaming>, Outbox),
+
+    //Counts the length of a body that shouldn't be sent, and sends only the
+    //headers when it ends.
+    Count(hyper::server::response::Response<'a>, u64),
+
+    //Ignores the body of a response that has already been sent.
+    Discard
+}
+
+impl<'a> ChunkSink<'a> {
+    fn end(self) -> io::Result<()> {
+        match self {
+            ChunkSink::Stream(writer, _) => writer.end(),
+            ChunkSink::Count(writer, length) => end_without_body(writer, length),
+            ChunkSink::Discard => Ok(())
+        }
+    }
+}
+
+impl<'a> Write for ChunkSink<'a> {
+    fn write(&mut self, content: &[u8]) -> io::Result<usize> {
+        match *self {
+            ChunkSink::Stream(ref mut writer, ref outbox) => {
+                let written = try!(writer.write(content));
+                outbox.add_bytes(written as u64);
+                Ok(written)
+            },
+            ChunkSink::Count(_, ref mut length) => {
+                *length += content.len() as u64;
+                Ok(content.len())
+            },
+            ChunkSink::Discard => Ok(content.len())
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            ChunkSink::Stream(ref mut writer, _) => writer.flush(),
+            ChunkSink::Count(..) | ChunkSink::Discard => Ok(())
+        }
+    }
+}
+
+impl<'a> Write for ChunkWriter<'a> {
+    fn write(&mut self, content: &[u8]) -> io::Result<usize> {
+        try!(self.write_all(content));
+        Ok(content.len())
+    }
+
+    //An empty chunk would end the body, so only non-empty compressed data
+    //is written.
+    #[cfg(feature = "compression")]
+    fn write_all(&mut self, content: &[u8]) -> io::Result<()> {
+        match self.encoder {
+            Some(ref mut encoder) => self.writer.write_all(&try!(encoder.write(content))),
+            None => self.writer.write_all(content)
+        }
+    }
+
+    #[cfg(not(feature = "compression"))]
+    fn write_all(&mut self, content: &[u8]) -> io::Result<()> {
+        self.writer.write_all(content)
+    }
+
+    #[cfg(feature = "compression")]
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(ref mut encoder) = self.encoder {
+            try!(self.writer.write_all(&try!(encoder.flush())));
         }
+        self.writer.flush()
+    }
+
+    #[cfg(not(feature = "compression"))]
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
     }
 }
 
@@ -750,11 +2073,17 @@ impl<'a, 'b> Drop for Chunked<'a, 'b> {
 ///
 ///__Unsafety__: The content length is set beforehand, which makes it possible
 ///to send responses that are too short.
-pub struct Raw<'a> {
-    writer: Option<Result<hyper::server::response::Response<'a, hyper::net::Streaming>, io::Error>>
+pub struct Raw<'a, 'b> {
+    writer: Option<Result<hyper::server::response::Response<'a, hyper::net::Streaming>, io::Error>>,
+    discard: bool,
+    log: &'b (Log + 'b),
+    outbox: Outbox,
+    reporter: Option<Reporter<'b>>,
+    content_length: u64,
+    remaining: u64
 }
 
-impl<'a> Raw<'a> {
+impl<'a, 'b> Raw<'a, 'b> {
     ///Send a piece of data to the client, ignoring any eventual errors. Use
     ///`try_send` to get error information.
     ///
@@ -772,9 +2101,9 @@ impl<'a> Raw<'a> {
     ///    }
     ///}
     ///```
-    #[allow(unused_must_use)]
     pub fn send<'d, Content: Into<Data<'d>>>(&mut self, content: Content) {
-        self.try_send(content);
+        let result = self.try_send(content).map_err(Error::Io);
+        report(self.reporter.as_ref(), result);
     }
 
     ///Send a piece of data to the client. This is the same as `send`, but
@@ -801,17 +2130,82 @@ impl<'a> Raw<'a> {
         self.write_all(content.into().as_bytes())
     }
 
-    ///Finish writing the response and collect eventual errors.
+    ///Get the number of bytes that are left to write before the body is as
+    ///long as its `Content-Length`.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    ///Finish writing the response and collect eventual errors. An error is
+    ///returned, and logged, if the body is shorter than its
+    ///`Content-Length`, since the client will see it as truncated. The
+    ///connection is closed in that case, so the client doesn't wait for the
+    ///missing bytes.
     ///
     ///This is optional and will happen silently when the writer drops out of
-    ///scope.
+    ///scope, but the truncation will still be logged.
     pub fn end(mut self) -> io::Result<()> {
+        self.finish()
+    }
+
+    ///Give up on the response and close the connection, without sending the
+    ///rest of the body. The client will see the body as truncated. This is
+    ///for when the rest of the body can't be produced, such as when reading
+    ///a file fails, and it's not logged as an error.
+    ///
+    ///```
+    ///use std::io::Read;
+    ///use rustful::{Context, Response};
+    ///
+    ///fn my_handler(context: Context, response: Response) {
+    ///    let mut raw = unsafe { response.into_raw(4) };
+    ///    let mut buffer = [0; 4];
+    ///
+    ///    match ::std::io::repeat(1).read_exact(&mut buffer) {
+    ///        Ok(()) => raw.send(&buffer[..]),
+    ///        Err(e) => {
+    ///            context.log.note(&format!("failed to read: {}", e));
+    ///            raw.abort();
+    ///        }
+    ///    }
+    ///}
+    ///```
+    pub fn abort(mut self) {
+        if let Some(Ok(writer)) = self.writer.take() {
+            close(writer);
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
         let writer = match self.writer.take() {
             Some(Ok(writer)) => writer,
             None => return Ok(()), //It has already ended
             Some(Err(e)) => return Err(e)
         };
-        writer.end()
+
+        if self.remaining > 0 && !self.discard {
+            close(writer);
+
+            let message = format!(
+                "the response body is truncated: {} of {} bytes were written",
+                self.content_length - self.remaining,
+                self.content_length
+            );
+            self.log.error(&message);
+            Err(io::Error::new(io::ErrorKind::UnexpectedEof, message))
+        } else {
+            writer.end()
+        }
+    }
+
+    fn too_long(&self, length: usize) -> io::Error {
+        let message = format!(
+            "tried to write {} bytes past the end of a response body with a Content-Length of {} bytes",
+            length as u64 - self.remaining,
+            self.content_length
+        );
+        self.log.error(&message);
+        io::Error::new(io::ErrorKind::InvalidInput, message)
     }
 
     fn borrow_writer(&mut self) -> io::Result<&mut hyper::server::response::Response<'a, hyper::net::Streaming>> {
@@ -825,15 +2219,43 @@ impl<'a> Raw<'a> {
     }
 }
 
-impl<'a> Write for Raw<'a> {
+impl<'a, 'b> Write for Raw<'a, 'b> {
     fn write(&mut self, content: &[u8]) -> io::Result<usize> {
-        let mut writer = try!(self.borrow_writer());
-        writer.write(content)
+        if self.remaining == 0 && !content.is_empty() {
+            return Err(self.too_long(content.len()));
+        }
+
+        let length = if content.len() as u64 > self.remaining { self.remaining as usize } else { content.len() };
+        let written = if self.discard {
+            length
+        } else {
+            let written = {
+                let writer = try!(self.borrow_writer());
+                try!(writer.write(&content[..length]))
+            };
+            self.outbox.add_bytes(written as u64);
+            written
+        };
+        self.remaining -= written as u64;
+        Ok(written)
     }
 
     fn write_all(&mut self, content: &[u8]) -> io::Result<()> {
-        let mut writer = try!(self.borrow_writer());
-        writer.write_all(content)
+        //Nothing is written if it doesn't fit, to not leave a partial piece
+        //of data behind.
+        if content.len() as u64 > self.remaining {
+            return Err(self.too_long(content.len()));
+        }
+
+        if !self.discard {
+            {
+                let writer = try!(self.borrow_writer());
+                try!(writer.write_all(content));
+            }
+            self.outbox.add_bytes(content.len() as u64);
+        }
+        self.remaining -= content.len() as u64;
+        Ok(())
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -842,6 +2264,105 @@ impl<'a> Write for Raw<'a> {
     }
 }
 
+impl<'a, 'b> Drop for Raw<'a, 'b> {
+    ///Finishes writing and logs if the body is truncated.
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let result = self.finish().map_err(Error::Io);
+            report(self.reporter.as_ref(), result);
+        }
+    }
+}
+
+//Closes the connection after a body that was cut short. The client would
+//otherwise read the next response as the rest of this one.
+fn close(writer: hyper::server::response::Response<hyper::net::Streaming>) {
+    let (_, mut body, _, headers) = writer.deconstruct();
+    headers.set(hyper::header::Connection::close());
+    let _ = body.flush();
+}
+
+//Gets the modification time of a file, in whole seconds.
+fn last_modified(metadata: &::std::fs::Metadata) -> Option<HttpDate> {
+    metadata.modified().ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| HttpDate(::time::at_utc(::time::Timespec::new(modified.as_secs() as i64, 0))))
+}
+
+//Makes an entity tag from the modification time and size of a file.
+fn file_etag(metadata: &::std::fs::Metadata) -> EntityTag {
+    let modified = metadata.modified().ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| (modified.as_secs(), modified.subsec_nanos()))
+        .unwrap_or((0, 0));
+
+    EntityTag::new(false, format!("{:x}.{:x}-{:x}", modified.0, modified.1, metadata.len()))
+}
+
+//Sends only the headers of a response without a body. `Content-Length` is
+//set to the length the body would have had, except for `204 No Content`.
+fn end_without_body(mut writer: hyper::server::response::Response, length: u64) -> io::Result<()> {
+    match writer.status() {
+        StatusCode::NoContent => writer.send(&[]),
+        //The length of a `304 Not Modified` response would have to be the
+        //length of the full representation, so it's left out.
+        StatusCode::NotModified => {
+            utils::remove_content_length(writer.headers_mut());
+            try!(writer.start()).end()
+        },
+        _ => {
+            writer.headers_mut().set(::header::ContentLength(length));
+            try!(writer.start()).end()
+        }
+    }
+}
+
+//Checks if a file with `etag` and `modified` matches the conditions.
+//If-None-Match takes precedence over If-Modified-Since.
+fn is_not_modified(if_none_match: Option<&IfNoneMatch>, if_modified_since: Option<&IfModifiedSince>, etag: &EntityTag, modified: Option<&HttpDate>) -> bool {
+    match if_none_match {
+        Some(&IfNoneMatch::Any) => true,
+        Some(&IfNoneMatch::Items(ref tags)) => tags.iter().any(|t| t.tag() == etag.tag()),
+        None => match (modified, if_modified_since) {
+            (Some(&HttpDate(modified)), Some(&IfModifiedSince(HttpDate(since)))) => modified <= since,
+            _ => false
+        }
+    }
+}
+
+//Checks if the `If-Range` header in `headers` matches a file with `etag`
+//and `modified`, or if it's missing. An entity tag has to be strong and
+//equal to `etag`, and a date has to be equal to `modified`.
+fn if_range_matches(headers: &Headers, etag: &EntityTag, modified: Option<&HttpDate>) -> bool {
+    let value = match headers.get_raw("If-Range").and_then(|lines| lines.first()) {
+        Some(value) => String::from_utf8_lossy(value).trim().to_owned(),
+        None => return true
+    };
+
+    if value.starts_with('"') || value.starts_with("W/") {
+        value.parse::<EntityTag>().map(|tag| tag.strong_eq(etag)).unwrap_or(false)
+    } else {
+        match (value.parse::<HttpDate>(), modified) {
+            (Ok(HttpDate(date)), Some(&HttpDate(modified))) => date.to_timespec() == modified.to_timespec(),
+            _ => false
+        }
+    }
+}
+
+//Logs a `Content-Length` header that doesn't match the actual body. It's
+//replaced with the correct length when the body is sent.
+fn check_content_length(headers: &Headers, length: usize, log: &Log) {
+    if let Some(&::header::ContentLength(declared)) = headers.get() {
+        if declared != length as u64 {
+            log.warning(&format!(
+                "the Content-Length header says {} bytes, but the response body is {} bytes, so it was corrected",
+                declared,
+                length
+            ));
+        }
+    }
+}
+
 fn response_to_io_result<T>(res:  Result<T, Error>) -> io::Result<T> {
     match res {
         Ok(v) => Ok(v),
@@ -850,8 +2371,87 @@ fn response_to_io_result<T>(res:  Result<T, Error>) -> io::Result<T> {
     }
 }
 
+//The server's response filters, except the ones that are skipped.
+#[derive(Clone)]
+struct Filters<'a> {
+    all: &'a [Box<ResponseFilter>],
+
+    //Only allocated if any filters are skipped.
+    skipped: Vec<usize>
+}
+
+impl<'a> Filters<'a> {
+    fn new(filters: &'a [Box<ResponseFilter>]) -> Filters<'a> {
+        Filters {
+            all: filters,
+            skipped: vec![]
+        }
+    }
+
+    fn skip_all(&mut self) {
+        self.all = &[];
+        self.skipped.clear();
+    }
+
+    fn skip_named(&mut self, name: &str) {
+        for (index, filter) in self.all.iter().enumerate() {
+            if filter.name() == Some(name) && !self.skipped.contains(&index) {
+                self.skipped.push(index);
+            }
+        }
+    }
+
+    fn iter<'f>(&'f self) -> ActiveFilters<'f, 'a> {
+        ActiveFilters {
+            filters: self,
+            range: 0..self.all.len()
+        }
+    }
+}
+
+struct ActiveFilters<'f, 'a: 'f> {
+    filters: &'f Filters<'a>,
+    range: Range<usize>
+}
+
+impl<'f, 'a> ActiveFilters<'f, 'a> {
+    fn get(&self, index: usize) -> Option<&'a ResponseFilter> {
+        if self.filters.skipped.contains(&index) {
+            None
+        } else {
+            Some(&*self.filters.all[index])
+        }
+    }
+}
+
+impl<'f, 'a> Iterator for ActiveFilters<'f, 'a> {
+    type Item = &'a ResponseFilter;
+
+    fn next(&mut self) -> Option<&'a ResponseFilter> {
+        while let Some(index) = self.range.next() {
+            if let Some(filter) = self.get(index) {
+                return Some(filter);
+            }
+        }
+
+        None
+    }
+}
+
+impl<'f, 'a> DoubleEndedIterator for ActiveFilters<'f, 'a> {
+    fn next_back(&mut self) -> Option<&'a ResponseFilter> {
+        while let Some(index) = self.range.next_back() {
+            if let Some(filter) = self.get(index) {
+                return Some(filter);
+            }
+        }
+
+        None
+    }
+}
+
 fn filter_headers<'a>(
-    filters: &'a [Box<ResponseFilter>],
+    filters: &Filters<'a>,
     status: StatusCode,
     headers: &mut Headers,
     log: &Log,
@@ -861,7 +2461,7 @@ fn filter_headers<'a>(
     let mut write_queue = Vec::new();
     let mut header_result = (status, Action::Next(None));
 
-    for filter in filters {
+    for filter in filters.iter() {
         header_result = match header_result {
             (_, Action::SilentAbort) => break,
             (_, Action::Abort(_)) => break,
@@ -917,10 +2517,30 @@ fn filter_headers<'a>(
     }
 }
 
-fn filter_content<'a, 'd: 'a, Content: Into<Data<'d>>>(filters: &'a [Box<ResponseFilter>], content: Content, log: &Log, global: &Global, filter_storage: &mut AnyMap) -> Action<'a> {
+fn finish_headers(
+    filters: &Filters,
+    mut status: StatusCode,
+    headers: &mut Headers,
+    log: &Log,
+    global: &Global,
+    filter_storage: &mut AnyMap
+) -> StatusCode {
+    for filter in filters.iter() {
+        let filter_context = FilterContext {
+            storage: filter_storage,
+            log: log,
+            global: global,
+        };
+        status = filter.finish_headers(filter_context, status, headers);
+    }
+
+    status
+}
+
+fn filter_content<'a, 'd: 'a, Content: Into<Data<'d>>>(filters: &Filters<'a>, content: Content, log: &Log, global: &Global, filter_storage: &mut AnyMap) -> Action<'a> {
     let mut filter_result = Action::next(Some(content));
 
-    for filter in filters {
+    for filter in filters.iter() {
         filter_result = match filter_result {
             Action::Next(content) => {
                 let filter_context = FilterContext {
@@ -937,8 +2557,8 @@ fn filter_content<'a, 'd: 'a, Content: Into<Data<'d>>>(filters: &'a [Box<Respons
     filter_result
 }
 
-fn filter_end<'a>(filters: &'a [Box<ResponseFilter>], log: &Log, global: &Global, filter_storage: &mut AnyMap) -> Result<Vec<Action<'a>>, Error> {
-    let otuputs: Vec<_> = filters.into_iter()
+fn filter_end<'a>(filters: &Filters<'a>, log: &Log, global: &Global, filter_storage: &mut AnyMap) -> Result<Vec<Action<'a>>, Error> {
+    let otuputs: Vec<_> = filters.iter()
         .rev()
         .map(|filter| {
             let filter_context = FilterContext {
@@ -955,7 +2575,7 @@ fn filter_end<'a>(filters: &'a [Box<ResponseFilter>], log: &Log, global: &Global
 
     let mut write_queue = vec![];
 
-    for (filter, action) in filters.into_iter().zip(otuputs.into_iter().chain(::std::iter::repeat(None))) {
+    for (filter, action) in filters.iter().zip(otuputs.into_iter().chain(::std::iter::repeat(None))) {
         let mut error = None;
 
         write_queue = write_queue.into_iter().filter_map(|action| match action {
@@ -984,4 +2604,270 @@ fn filter_end<'a>(filters: &'a [Box<ResponseFilter>], log: &Log, global: &Global
     }
 
     Ok(write_queue)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::io::{self, Write};
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use hyper;
+    use tempdir;
+    use time::{self, Duration};
+
+    use {Server, Context, Global, StatusCode};
+    use context::Deadline;
+    use error_pages::ErrorPages;
+    use server::Dispatcher;
+    use events::Outbox;
+    use filter::EnforcedDeadline;
+    use header::{Headers, IfNoneMatch, IfModifiedSince, HttpDate, Connection, ConnectionOption};
+    use log::Quiet;
+    use super::{Response, Error, BLOCK_SIZE};
+
+    //Runs `handler` with a response that writes to a buffer, and returns
+    //what was written.
+    fn respond<F: FnOnce(Response)>(handler: F) -> String {
+        respond_with_headers(handler).0
+    }
+
+    //Same as `respond`, but the headers are returned as well, as they are
+    //seen by the connection when the response ends.
+    fn respond_with_headers<F: FnOnce(Response)>(handler: F) -> (String, Headers) {
+        let mut buffer = vec![];
+        let mut headers = Headers::new();
+        {
+            let writer = hyper::server::response::Response::new(&mut buffer, &mut headers);
+            let filters = vec![];
+            let global = Global::default();
+            handler(Response::new(writer, &filters, &Quiet, &global, Outbox::new()));
+        }
+        (String::from_utf8_lossy(&buffer).into_owned(), headers)
+    }
+
+    fn closes_connection(headers: &Headers) -> bool {
+        headers.get::<Connection>().map_or(false, |connection| connection.contains(&ConnectionOption::Close))
+    }
+
+    //Finds the value of the header `name` in a raw response.
+    fn header<'o>(output: &'o str, name: &str) -> Option<&'o str> {
+        let prefix = format!("{}: ", name);
+        output.split("\r\n").find(|line| line.starts_with(&prefix)).map(|line| &line[prefix.len()..])
+    }
+
+    fn enforce(response: &mut Response, milliseconds: i64) {
+        response.filter_storage_mut().insert(EnforcedDeadline {
+            deadline: Deadline::from_now(Duration::milliseconds(milliseconds)),
+            status: StatusCode::GatewayTimeout
+        });
+    }
+
+    #[test]
+    fn replace_late_responses() {
+        let output = respond(|mut response| {
+            enforce(&mut response, 10);
+            thread::sleep(::std::time::Duration::from_millis(50));
+            response.send("too late");
+        });
+        assert!(output.starts_with("HTTP/1.1 504"), "{}", output);
+        assert!(!output.contains("too late"), "{}", output);
+
+        let output = respond(|mut response| {
+            enforce(&mut response, 10_000);
+            response.send("in time");
+        });
+        assert!(output.starts_with("HTTP/1.1 200"), "{}", output);
+        assert!(output.ends_with("in time"), "{}", output);
+    }
+
+    #[test]
+    fn send_only_status() {
+        fn handler(context: Context, response: Response) {
+            match context.uri.as_utf8_path() {
+                Some("/missing") => response.send_status(StatusCode::NotFound),
+                _ => response.send_status(StatusCode::NoContent)
+            }
+        }
+
+        fn not_found(_status: StatusCode, _response: &mut Response) -> String {
+            "not found".to_owned()
+        }
+
+        let mut error_pages = ErrorPages::new();
+        error_pages.insert(StatusCode::NotFound, not_found);
+        let server = Server {
+            error_pages: error_pages,
+            ..Server::new(handler as fn(Context, Response))
+        };
+        let (instance, _scheme) = server.build();
+        let address = "127.0.0.1:8080".parse().unwrap();
+
+        let output = String::from_utf8(instance.dispatch(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n", address)).unwrap();
+        assert!(output.starts_with("HTTP/1.1 204"), "{}", output);
+        assert!(output.ends_with("\r\n\r\n"), "{}", output);
+
+        let output = String::from_utf8(instance.dispatch(b"GET /missing HTTP/1.1\r\nHost: localhost\r\n\r\n", address)).unwrap();
+        assert!(output.starts_with("HTTP/1.1 404"), "{}", output);
+        assert!(output.ends_with("not found"), "{}", output);
+    }
+
+    #[test]
+    fn buffer_body() {
+        let output = respond(|response| {
+            let mut buffered = response.into_buffered();
+            buffered.send("abc");
+            buffered.send("de");
+            assert_eq!(buffered.len(), 5);
+            buffered.set_status(StatusCode::Created);
+        });
+        assert!(output.starts_with("HTTP/1.1 201"), "{}", output);
+        assert_eq!(header(&output, "Content-Length"), Some("5"));
+        assert!(header(&output, "Transfer-Encoding").is_none(), "{}", output);
+        assert!(output.ends_with("\r\n\r\nabcde"), "{}", output);
+    }
+
+    #[test]
+    fn stream_bodies() {
+        let output = respond(|response| {
+            assert!(response.send_reader(io::Cursor::new(vec![b'a'; BLOCK_SIZE + 16])).is_ok());
+        });
+        assert_eq!(header(&output, "Transfer-Encoding"), Some("chunked"));
+        let body = &output[output.find("\r\n\r\n").unwrap()..];
+        assert_eq!(body.matches('a').count(), BLOCK_SIZE + 16);
+        assert!(output.ends_with("\r\n0\r\n\r\n"), "{}", &output[output.len() - 20..]);
+
+        let output = respond(|response| {
+            response.send_all(vec!["a", "b", "c"]);
+        });
+        assert_eq!(header(&output, "Transfer-Encoding"), Some("chunked"));
+        assert!(output.ends_with("\r\n\r\n3\r\nabc\r\n0\r\n\r\n"), "{}", output);
+    }
+
+    #[test]
+    fn report_ignored_errors() {
+        fn handler(_context: Context, response: Response) {
+            let mut raw = unsafe { response.into_raw(10) };
+            raw.send("abc");
+            raw.send("too long");
+        }
+
+        let reports = Arc::new(Mutex::new(vec![]));
+        let sink = reports.clone();
+        let server = Server {
+            error_sink: Some(Box::new(move |error: &Error, request_target: &str, address: SocketAddr| {
+                sink.lock().unwrap().push((error.to_string(), request_target.to_owned(), address));
+            })),
+            ..Server::new(handler as fn(Context, Response))
+        };
+        let (instance, _scheme) = server.build();
+        let address = "127.0.0.1:8080".parse().unwrap();
+        instance.dispatch(b"GET /a?b=c HTTP/1.1\r\nHost: localhost\r\n\r\n", address);
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 2, "{:?}", *reports);
+        assert!(reports[0].0.contains("past the end"), "{}", reports[0].0);
+        assert!(reports[1].0.contains("truncated"), "{}", reports[1].0);
+        assert!(reports.iter().all(|report| report.1 == "/a?b=c" && report.2 == address));
+    }
+
+    #[test]
+    fn check_raw_length() {
+        let (output, headers) = respond_with_headers(|response| {
+            let mut raw = unsafe { response.into_raw(3) };
+            assert!(raw.try_send("abcd").is_err());
+            assert!(raw.try_send("abc").is_ok());
+            assert!(raw.try_send("d").is_err());
+            assert!(raw.end().is_ok());
+        });
+        assert!(output.ends_with("\r\n\r\nabc"), "{}", output);
+        assert!(!closes_connection(&headers));
+
+        let (output, headers) = respond_with_headers(|response| {
+            let mut raw = unsafe { response.into_raw(10) };
+            raw.send("abc");
+            assert!(raw.end().is_err());
+        });
+        assert!(output.ends_with("\r\n\r\nabc"), "{}", output);
+        assert!(closes_connection(&headers));
+    }
+
+    #[test]
+    fn abort_raw_responses() {
+        let (output, headers) = respond_with_headers(|response| {
+            let mut raw = unsafe { response.into_raw(10) };
+            raw.send("abc");
+            raw.abort();
+        });
+        assert!(output.starts_with("HTTP/1.1 200"), "{}", output);
+        assert!(output.ends_with("\r\n\r\nabc"), "{}", output);
+        assert!(closes_connection(&headers));
+    }
+
+    #[test]
+    fn send_files_conditionally() {
+        let dir = tempdir::TempDir::new("send_files_conditionally").unwrap();
+        let path = dir.path().join("file.txt");
+        File::create(&path).unwrap().write_all(b"file content").unwrap();
+
+        let output = respond(|response| {
+            assert!(response.send_file(&path).is_ok());
+        });
+        assert!(output.starts_with("HTTP/1.1 200"), "{}", output);
+        assert!(output.ends_with("file content"), "{}", output);
+
+        let mut request_headers = Headers::new();
+        request_headers.set_raw("If-None-Match", vec![header(&output, "ETag").unwrap().as_bytes().to_vec()]);
+        let if_none_match = request_headers.get::<IfNoneMatch>().cloned();
+        assert!(if_none_match.is_some());
+
+        let output = respond(|mut response| {
+            response.set_conditions(if_none_match, None);
+            assert!(response.send_file(&path).is_ok());
+        });
+        assert!(output.starts_with("HTTP/1.1 304"), "{}", output);
+        assert!(header(&output, "Content-Length").is_none(), "{}", output);
+        assert!(header(&output, "Transfer-Encoding").is_none(), "{}", output);
+        assert!(output.ends_with("\r\n\r\n"), "{}", output);
+
+        let output = respond(|mut response| {
+            let tomorrow = time::now_utc() + Duration::days(1);
+            response.set_conditions(None, Some(IfModifiedSince(HttpDate(tomorrow))));
+            assert!(response.send_file(&path).is_ok());
+        });
+        assert!(output.starts_with("HTTP/1.1 304"), "{}", output);
+    }
+
+    #[test]
+    fn ignore_mismatched_if_range() {
+        let dir = tempdir::TempDir::new("ignore_mismatched_if_range").unwrap();
+        let path = dir.path().join("file.txt");
+        File::create(&path).unwrap().write_all(b"file content").unwrap();
+
+        let send_range = |if_range: Option<&str>| respond(|response| {
+            let mut request_headers = Headers::new();
+            request_headers.set_raw("Range", vec![b"bytes=0-3".to_vec()]);
+            if let Some(if_range) = if_range {
+                request_headers.set_raw("If-Range", vec![if_range.as_bytes().to_vec()]);
+            }
+            assert!(response.send_file_ranged(&path, &request_headers).is_ok());
+        });
+
+        let partial = send_range(None);
+        assert!(partial.starts_with("HTTP/1.1 206"), "{}", partial);
+        assert!(partial.ends_with("\r\n\r\nfile"), "{}", partial);
+
+        let etag = header(&partial, "ETag").unwrap().to_owned();
+        let modified = header(&partial, "Last-Modified").unwrap().to_owned();
+        assert!(send_range(Some(&etag)).starts_with("HTTP/1.1 206"));
+        assert!(send_range(Some(&modified)).starts_with("HTTP/1.1 206"));
+
+        for if_range in &["\"other\"", &*format!("W/{}", etag), "Thu, 01 Jan 1970 00:00:00 GMT"] {
+            let full = send_range(Some(if_range));
+            assert!(full.starts_with("HTTP/1.1 200"), "{}", full);
+            assert!(full.ends_with("file content"), "{}", full);
+        }
+    }
+}
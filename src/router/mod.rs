@@ -80,14 +80,14 @@
 //!
 //![insert_routes]: ../macro.insert_routes!.html
 
-use std::collections::HashMap;
 use std::iter::{Iterator, FlatMap};
 use std::slice::Split;
-use std::ops::Deref;
+use std::ops::{Deref, Range};
 use hyper::method::Method;
 
 use handler::Handler;
-use context::MaybeUtf8Owned;
+use filter::GlobalContext;
+use context::MaybeUtf8Slice;
 use context::hypermedia::Hypermedia;
 
 pub use self::tree_router::TreeRouter;
@@ -98,9 +98,10 @@ mod tree_router;
 pub struct Endpoint<'a, T: 'a> {
     ///A request handler, if found.
     pub handler: Option<&'a T>,
-    ///Path variables for the matching endpoint. May be empty, depending on
-    ///the router implementation.
-    pub variables: HashMap<MaybeUtf8Owned, MaybeUtf8Owned>,
+    ///Path variables for the matching endpoint, as names and byte ranges of
+    ///the searched route. May be empty, depending on the router
+    ///implementation.
+    pub variables: Vec<(MaybeUtf8Slice<'a>, Range<usize>)>,
     ///Any associated hypermedia, such as links.
     pub hypermedia: Hypermedia<'a>
 }
@@ -109,7 +110,7 @@ impl<'a, T> From<Option<&'a T>> for Endpoint<'a, T> {
     fn from(handler: Option<&'a T>) -> Endpoint<'a, T> {
         Endpoint {
             handler: handler,
-            variables: HashMap::new(),
+            variables: vec![],
             hypermedia: Hypermedia::new()
         }
     }
@@ -128,6 +129,13 @@ pub trait Router: Send + Sync + 'static {
 
     ///Find and return the matching handler and variable values.
     fn find<'a>(&'a self, method: &Method, route: &[u8]) -> Endpoint<'a, Self::Handler>;
+
+    ///Set up all of the handlers when the server is built.
+    fn setup(&self, _context: &GlobalContext) {}
+
+    ///Tear down all of the handlers when the server instance is dropped,
+    ///in the opposite order of how they were set up.
+    fn teardown(&self) {}
 }
 
 impl<H: Handler> Router for H {
@@ -138,6 +146,14 @@ impl<H: Handler> Router for H {
     }
 
     fn insert<'a, D: ?Sized + Deref<Target=R> + 'a, R: ?Sized + Route<'a> + 'a>(&mut self, _method: Method, _route: &'a D, _handler: H) {}
+
+    fn setup(&self, context: &GlobalContext) {
+        Handler::setup(self, context);
+    }
+
+    fn teardown(&self) {
+        Handler::teardown(self);
+    }
 }
 
 ///A segmented route.
@@ -9,6 +9,7 @@ use router::{Router, Route, Endpoint};
 use context::MaybeUtf8Owned;
 use context::hypermedia::{Link, LinkSegment};
 use handler::Handler;
+use filter::GlobalContext;
 
 use self::Branch::{Static, Variable, Wildcard};
 
@@ -103,6 +104,42 @@ impl<T> TreeRouter<T> {
         endpoint.merge_router(variable_names, router);
     }
 
+    ///Turn each handler in the router into something else, such as a
+    ///wrapped version of itself. The routes stay the same.
+    pub fn map_handlers<U, F: FnMut(T) -> U>(self, mut map: F) -> TreeRouter<U> {
+        self.map_with(&mut map)
+    }
+
+    fn map_with<U, F: FnMut(T) -> U>(self, map: &mut F) -> TreeRouter<U> {
+        TreeRouter {
+            items: self.items.into_iter().map(|(method, (item, variable_names))| (method, (map(item), variable_names))).collect(),
+            static_routes: self.static_routes.into_iter().map(|(key, router)| (key, router.map_with(map))).collect(),
+            variable_route: self.variable_route.map(|router| Box::new(router.map_with(map))),
+            wildcard_route: self.wildcard_route.map(|router| Box::new(router.map_with(map))),
+            find_hyperlinks: self.find_hyperlinks
+        }
+    }
+
+    //Collects all of the items in this TreeRouter and its branches.
+    fn handlers(&self) -> Vec<&T> {
+        let mut handlers = vec![];
+        self.collect_handlers(&mut handlers);
+        handlers
+    }
+
+    fn collect_handlers<'a>(&'a self, handlers: &mut Vec<&'a T>) {
+        handlers.extend(self.items.values().map(|&(ref item, _)| item));
+        for router in self.static_routes.values() {
+            router.collect_handlers(handlers);
+        }
+        if let Some(ref router) = self.variable_route {
+            router.collect_handlers(handlers);
+        }
+        if let Some(ref router) = self.wildcard_route {
+            router.collect_handlers(handlers);
+        }
+    }
+
     //Mergers this TreeRouter with an other TreeRouter.
     fn merge_router(&mut self, variable_names: Vec<MaybeUtf8Owned>, router: TreeRouter<T>) {
         for (key, (item, var_names)) in router.items {
@@ -160,18 +197,20 @@ impl<T: Handler> Router for TreeRouter<T> {
                 if let Some(&(ref item, ref variable_names)) = current.items.get(&method) {
                     let values = path.iter().zip(variables.iter()).filter_map(|(v, keep)| {
                         if *keep {
-                            Some(v.clone())
+                            //The segments are slices of `route`.
+                            let start = v.as_ptr() as usize - route.as_ptr() as usize;
+                            Some(start..start + v.len())
                         } else {
                             None
                         }
                     });
 
-                    let var_map = variable_names.iter().zip(values).map(|(key, value)| {
-                        (key.clone().into(), value.to_owned().into())
+                    let var_list = variable_names.iter().zip(values).map(|(key, value)| {
+                        (key.as_slice(), value)
                     });
 
                     result.handler = Some(item);
-                    result.variables = var_map.collect();
+                    result.variables = var_list.collect();
                     if !self.find_hyperlinks {
                         return result;
                     }
@@ -269,6 +308,18 @@ impl<T: Handler> Router for TreeRouter<T> {
 
         endpoint.items.insert(method, (item, variable_names));
     }
+
+    fn setup(&self, context: &GlobalContext) {
+        for handler in self.handlers() {
+            Handler::setup(handler, context);
+        }
+    }
+
+    fn teardown(&self) {
+        for handler in self.handlers().into_iter().rev() {
+            Handler::teardown(handler);
+        }
+    }
 }
 
 impl<T: Handler, D: Deref<Target=R>, R: ?Sized + for<'a> Route<'a>> FromIterator<(Method, D, T)> for TreeRouter<T> {
@@ -365,16 +416,15 @@ mod test {
 
     pub use self::LinkType::{SelfLink, ForwardLink};
 
-    fn check_variable(result: Endpoint<TestHandler>, expected: Option<&[&str]>) {
+    fn check_variable(result: Endpoint<TestHandler>, route: &[u8], expected: Option<&[&str]>) {
         assert_eq!(result.handler.is_some(), expected.is_some());
 
         if let Some(expected) = expected {
             let keys = vec!("a".as_bytes(), "b".as_bytes(), "c".as_bytes());
             let result = keys.into_iter().filter_map(|key| {
-                match result.variables.get(key) {
-                    Some(value) => Some(value.as_ref()),
-                    None => None
-                }
+                result.variables.iter().find(|&&(ref name, _)| name.as_bytes() == key).map(|&(_, ref range)| {
+                    &route[range.start..range.end]
+                })
             }).collect::<Vec<&[u8]>>();
 
             for (&result, expected) in result.iter().zip(expected.iter()) {
@@ -445,9 +495,9 @@ mod test {
         let mut router = routes.into_iter().collect::<TreeRouter<_>>();
         router.find_hyperlinks = true;
 
-        check_variable(router.find(&Get, b"path/to/test1"), Some(&["to"]));
-        check_variable(router.find(&Get, b"path/to"), None);
-        check_variable(router.find(&Get, b"path/to/test1/nothing"), None);
+        check_variable(router.find(&Get, b"path/to/test1"), b"path/to/test1", Some(&["to"]));
+        check_variable(router.find(&Get, b"path/to"), b"path/to", None);
+        check_variable(router.find(&Get, b"path/to/test1/nothing"), b"path/to/test1/nothing", None);
     }
 
     #[test]
@@ -462,11 +512,11 @@ mod test {
         let mut router = routes.into_iter().collect::<TreeRouter<_>>();
         router.find_hyperlinks = true;
 
-        check_variable(router.find(&Get, b"path/to/test1"), Some(&[]));
-        check_variable(router.find(&Get, b"path/to/test/no2"), Some(&["to"]));
-        check_variable(router.find(&Get, b"path/to/test1/no/test3"), Some(&["test3", "test1", "no"]));
-        check_variable(router.find(&Post, b"path/to/test1/no/test3"), Some(&["no", "test3", "test1"]));
-        check_variable(router.find(&Get, b"path/to/test1/no"), None);
+        check_variable(router.find(&Get, b"path/to/test1"), b"path/to/test1", Some(&[]));
+        check_variable(router.find(&Get, b"path/to/test/no2"), b"path/to/test/no2", Some(&["to"]));
+        check_variable(router.find(&Get, b"path/to/test1/no/test3"), b"path/to/test1/no/test3", Some(&["test3", "test1", "no"]));
+        check_variable(router.find(&Post, b"path/to/test1/no/test3"), b"path/to/test1/no/test3", Some(&["no", "test3", "test1"]));
+        check_variable(router.find(&Get, b"path/to/test1/no"), b"path/to/test1/no", None);
     }
 
     #[test]
@@ -635,8 +685,8 @@ mod test {
 
         router1.insert_router(":a", router2);
         
-        check_variable(router1.find(&Get, b"path/to/test1"), Some(&["path", "to", "test1"]));
-        check_variable(router1.find(&Get, b"path/to/test1/test"), Some(&["path", "to", "test1"]));
+        check_variable(router1.find(&Get, b"path/to/test1"), b"path/to/test1", Some(&["path", "to", "test1"]));
+        check_variable(router1.find(&Get, b"path/to/test1/test"), b"path/to/test1/test", Some(&["path", "to", "test1"]));
     }
 
 
@@ -658,6 +708,25 @@ mod test {
         check(router1.find(&Get, b"path"), None, vec![ForwardLink(LinkSegment::Static("to".into())), ForwardLink(LinkSegment::RecursiveWildcard)]);
     }
 
+    #[test]
+    fn map_handlers() {
+        let routes = vec![
+            (Get, "path/to", "test 1".into()),
+            (Get, "path/:a/test", "test 2".into()),
+            (Post, "*/test", "test 3".into())
+        ];
+
+        let router = routes.into_iter().collect::<TreeRouter<TestHandler>>().map_handlers(|TestHandler(name)| match name {
+            "test 1" => TestHandler("mapped 1"),
+            "test 2" => TestHandler("mapped 2"),
+            _ => TestHandler("mapped 3")
+        });
+
+        check(router.find(&Get, b"path/to"), Some("mapped 1"), vec![]);
+        check(router.find(&Post, b"path/to/test"), Some("mapped 3"), vec![]);
+        check_variable(router.find(&Get, b"path/x/test"), b"path/x/test", Some(&["x"]));
+    }
+
     
     #[bench]
     #[cfg(feature = "benchmark")]
@@ -0,0 +1,179 @@
+//!Security related response headers.
+//!
+//![`SecurityHeaders`][headers] is a set of headers that make browsers more
+//!careful with a response. The defaults are strict, and each of them can be
+//!changed or turned off. They can be applied to a single response with
+//!`Response::apply_security_headers`, or to all responses with the
+//!`security_headers` field of the server. Handlers can still change them
+//!afterwards.
+//!
+//!```
+//!use rustful::{Server, Context, Response};
+//!use rustful::security::{SecurityHeaders, FrameOptions};
+//!
+//!let server = Server {
+//!    security_headers: Some(
+//!        SecurityHeaders::new()
+//!            .frame_options(Some(FrameOptions::SameOrigin))
+//!            .content_security_policy(Some("default-src 'self'; img-src *"))
+//!    ),
+//!    ..Server::new(|_: Context, response: Response| response.send("hello"))
+//!};
+//!```
+//!
+//![headers]: struct.SecurityHeaders.html
+
+use std::fmt;
+
+use header::Headers;
+
+///The `X-Frame-Options` header, which decides if the response may be shown
+///in a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameOptions {
+    ///Never show it in a frame.
+    Deny,
+
+    ///Only show it in a frame on the same origin.
+    SameOrigin
+}
+
+impl fmt::Display for FrameOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            FrameOptions::Deny => "DENY",
+            FrameOptions::SameOrigin => "SAMEORIGIN"
+        })
+    }
+}
+
+///A set of security headers. A header is not set if its value is `None`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SecurityHeaders {
+    ///The `Strict-Transport-Security` header. Default is one year, including
+    ///subdomains.
+    pub strict_transport_security: Option<String>,
+
+    ///Set `X-Content-Type-Options: nosniff`. Default is `true`.
+    pub no_sniff: bool,
+
+    ///The `X-Frame-Options` header. Default is `DENY`.
+    pub frame_options: Option<FrameOptions>,
+
+    ///The `Referrer-Policy` header. Default is
+    ///`strict-origin-when-cross-origin`.
+    pub referrer_policy: Option<String>,
+
+    ///The `Content-Security-Policy` header. Default is `default-src 'self'`.
+    pub content_security_policy: Option<String>
+}
+
+impl SecurityHeaders {
+    ///Create a set of headers with the default values.
+    pub fn new() -> SecurityHeaders {
+        SecurityHeaders {
+            strict_transport_security: Some("max-age=31536000; includeSubDomains".into()),
+            no_sniff: true,
+            frame_options: Some(FrameOptions::Deny),
+            referrer_policy: Some("strict-origin-when-cross-origin".into()),
+            content_security_policy: Some("default-src 'self'".into())
+        }
+    }
+
+    ///Require HTTPS for `max_age` seconds, and maybe for all subdomains.
+    pub fn strict_transport_security(mut self, max_age: u64, include_subdomains: bool) -> SecurityHeaders {
+        self.strict_transport_security = Some(if include_subdomains {
+            format!("max-age={}; includeSubDomains", max_age)
+        } else {
+            format!("max-age={}", max_age)
+        });
+        self
+    }
+
+    ///Don't set `Strict-Transport-Security`. It should be left out for
+    ///servers that are only available over HTTP.
+    pub fn no_strict_transport_security(mut self) -> SecurityHeaders {
+        self.strict_transport_security = None;
+        self
+    }
+
+    ///Set or remove `X-Content-Type-Options: nosniff`.
+    pub fn no_sniff(mut self, no_sniff: bool) -> SecurityHeaders {
+        self.no_sniff = no_sniff;
+        self
+    }
+
+    ///Set or remove `X-Frame-Options`.
+    pub fn frame_options(mut self, frame_options: Option<FrameOptions>) -> SecurityHeaders {
+        self.frame_options = frame_options;
+        self
+    }
+
+    ///Set or remove `Referrer-Policy`.
+    pub fn referrer_policy<P: Into<String>>(mut self, policy: Option<P>) -> SecurityHeaders {
+        self.referrer_policy = policy.map(Into::into);
+        self
+    }
+
+    ///Set or remove `Content-Security-Policy`.
+    pub fn content_security_policy<P: Into<String>>(mut self, policy: Option<P>) -> SecurityHeaders {
+        self.content_security_policy = policy.map(Into::into);
+        self
+    }
+
+    ///Set the headers in `headers`. Headers with the value `None` are left
+    ///as they are.
+    pub fn apply(&self, headers: &mut Headers) {
+        if let Some(ref value) = self.strict_transport_security {
+            headers.set_raw("Strict-Transport-Security", vec![value.clone().into_bytes()]);
+        }
+
+        if self.no_sniff {
+            headers.set_raw("X-Content-Type-Options", vec![b"nosniff".to_vec()]);
+        }
+
+        if let Some(frame_options) = self.frame_options {
+            headers.set_raw("X-Frame-Options", vec![frame_options.to_string().into_bytes()]);
+        }
+
+        if let Some(ref value) = self.referrer_policy {
+            headers.set_raw("Referrer-Policy", vec![value.clone().into_bytes()]);
+        }
+
+        if let Some(ref value) = self.content_security_policy {
+            headers.set_raw("Content-Security-Policy", vec![value.clone().into_bytes()]);
+        }
+    }
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> SecurityHeaders {
+        SecurityHeaders::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use header::Headers;
+    use super::{SecurityHeaders, FrameOptions};
+
+    fn raw(headers: &Headers, name: &str) -> Option<String> {
+        headers.get_raw(name).map(|lines| String::from_utf8_lossy(&lines[0]).into_owned())
+    }
+
+    #[test]
+    fn apply_headers() {
+        let mut headers = Headers::new();
+        SecurityHeaders::new()
+            .strict_transport_security(600, false)
+            .frame_options(Some(FrameOptions::SameOrigin))
+            .content_security_policy(None::<String>)
+            .apply(&mut headers);
+
+        assert_eq!(raw(&headers, "Strict-Transport-Security"), Some("max-age=600".to_owned()));
+        assert_eq!(raw(&headers, "X-Content-Type-Options"), Some("nosniff".to_owned()));
+        assert_eq!(raw(&headers, "X-Frame-Options"), Some("SAMEORIGIN".to_owned()));
+        assert_eq!(raw(&headers, "Referrer-Policy"), Some("strict-origin-when-cross-origin".to_owned()));
+        assert_eq!(raw(&headers, "Content-Security-Policy"), None);
+    }
+}
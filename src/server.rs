@@ -1,19 +1,24 @@
 //!Server configuration and instance.
 
-use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::net::{SocketAddr, IpAddr};
 use std::borrow::ToOwned;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use time;
+use time::{self, Duration};
 
 use url::percent_encoding::{percent_decode, percent_decode_to};
 use url::{Url, SchemeData};
 
 use hyper;
 use hyper::server::Handler as HyperHandler;
-use hyper::header::{Date, ContentType};
+use hyper::header::{Date, ContentType, IfNoneMatch, IfModifiedSince};
 use hyper::mime::Mime;
 use hyper::uri::RequestUri;
+use hyper::buffer::BufReader;
+use hyper::net::NetworkStream;
 #[cfg(feature = "ssl")]
 use hyper::net::Openssl;
 
@@ -22,23 +27,37 @@ pub use hyper::server::Listening;
 use anymap::AnyMap;
 
 use StatusCode;
-
-use context::{self, Context, Uri, MaybeUtf8Owned, Parameters};
+use Method;
+
+use context::{Context, Uri, MaybeUtf8Owned, RouteVariables, Query, Deadline};
+use context::body::BodyReader;
+#[cfg(feature = "rustc_json_body")]
+use context::body::BodyDecoders;
+#[cfg(feature = "decompression")]
+use context::body::DecompressionLimits;
+#[cfg(feature = "compression")]
+use compression::Compression;
 use context::hypermedia::Hypermedia;
-use filter::{FilterContext, ContextFilter, ContextAction, ResponseFilter};
+use filter::{FilterContext, GlobalContext, ContextFilter, ContextAction, FilterResponse, ResponseFilter, AdmissionFilter, Peer, RequestPath};
+use middleware::{Middleware, Next};
 use router::{Router, Endpoint};
 use handler::Handler;
-use response::Response;
+use handler::fallible::{ErrorRenderer, DefaultErrorRenderer};
+use error_pages::ErrorPages;
+use template::Renderer;
+use security::SecurityHeaders;
+use response::{Response, ErrorSink};
 use log::{Log, StdOut};
-use header::HttpDate;
+use events::{EventSink, Outbox};
+use completion::{CompletionHook, Completing};
+use error_report::{ErrorReporter, Reporting};
+use header::{Headers, HttpDate};
 
 use Scheme;
 use Host;
 use Global;
 use HttpResult;
 
-use utils;
-
 ///Used to set up and run a server.
 ///
 ///```no_run
@@ -94,11 +113,165 @@ pub struct Server<R: Router> {
     ///Globally accessible data.
     pub global: Global,
 
-    ///The context filter stack.
+    ///The context filter stack. Named filters can be found with
+    ///`insert_context_filter_before` and the other methods with
+    ///`context_filter` in their names.
     pub context_filters: Vec<Box<ContextFilter>>,
 
-    ///The response filter stack.
-    pub response_filters: Vec<Box<ResponseFilter>>
+    ///The middleware stack, which wraps the handlers. The first one is the
+    ///outermost. See the [`middleware`][middleware] module for more
+    ///information. Default is empty.
+    ///
+    ///[middleware]: ../middleware/index.html
+    pub middleware: Vec<Box<Middleware>>,
+
+    ///The response filter stack. Handlers can skip them with
+    ///`Response::skip_filters`, or by name with `Response::skip_filter`.
+    ///Named filters can be found with `insert_response_filter_before` and
+    ///the other methods with `response_filter` in their names.
+    pub response_filters: Vec<Box<ResponseFilter>>,
+
+    ///Addresses of proxies that are trusted to report the client address in
+    ///the `Forwarded` or `X-Forwarded-For` headers. See
+    ///`Context::client_ip`. Default is an empty list.
+    pub trusted_proxies: Vec<IpAddr>,
+
+    ///The default size limit for request bodies, in bytes, when using the
+    ///convenience readers, such as `read_query_body`. It can be changed for
+    ///each request, using `BodyReader::set_limit`. Default is `None`, for no
+    ///limit.
+    pub max_body_size: Option<u64>,
+
+    ///Decompress request bodies with `Content-Encoding: gzip` or `deflate`
+    ///before they are given to the handlers, and remove the
+    ///`Content-Encoding` and `Content-Length` headers. `max_body_size` will
+    ///then limit the decompressed size. A body that can't be decompressed
+    ///will cause a `400 Bad Request` response. Default is `false`.
+    ///
+    ///This is only available with the `decompression` feature.
+    #[cfg(feature = "decompression")]
+    pub decompress_body: bool,
+
+    ///Limits for the compression ratio of decompressed request bodies, and
+    ///for nested archives. Use `DecompressionLimits::triggered` to see how
+    ///many bodies they have stopped. Default is
+    ///`DecompressionLimits::default()`.
+    ///
+    ///This is only available with the `decompression` feature.
+    #[cfg(feature = "decompression")]
+    pub decompression_limits: DecompressionLimits,
+
+    ///A receiver for the domain events that are emitted by the handlers,
+    ///using `Response::emit`. The events are dropped if it's not set.
+    ///Default is `None`.
+    pub event_sink: Option<Box<EventSink>>,
+
+    ///The time budget for each request, counted from when the request is
+    ///received. It's not enforced by the server, but it's made available to
+    ///handlers through `Context::deadline`, so they can give up early or
+    ///pass shorter timeouts on to other services. Default is `None`.
+    pub request_timeout: Option<Duration>,
+
+    ///How `//`, `.` and `..` segments in the request path are treated before
+    ///it's given to the filters and the router. The path is normalized as it
+    ///was sent, before it's percent decoded, so encoded characters, such as
+    ///`%2F` and `%2E`, are never treated as separators or dot segments.
+    ///Paths that escape above the root are always rejected with `400 Bad
+    ///Request`, unless the normalization is disabled. Default is
+    ///`PathNormalization::Disabled`.
+    pub path_normalization: PathNormalization,
+
+    ///The host names that the server accepts in the `Host` header, or in
+    ///absolute request URIs. A name can be prefixed with `*.` to accept any
+    ///subdomain, and suffixed with a port, as in `example.com:8080`, to only
+    ///accept that port. Requests for other hosts are rejected with `421
+    ///Misdirected Request`, and requests without a host with `400 Bad
+    ///Request`, before they reach the filters. This prevents attacks that
+    ///rely on handlers trusting the host, such as cache poisoning or
+    ///forged links in password reset emails. Default is an empty list, which
+    ///accepts any host.
+    pub allowed_hosts: Vec<String>,
+
+    ///The decoders that are used by `BodyReader::decode`, keyed by media
+    ///type. Default is `BodyDecoders::default()`, with decoders for JSON and
+    ///URL encoded forms.
+    ///
+    ///This is only available with the `rustc_json_body` feature.
+    #[cfg(feature = "rustc_json_body")]
+    pub body_decoders: BodyDecoders,
+
+    ///The admission filter stack. They are applied before anything else is
+    ///done with a request. See [`AdmissionFilter`][admission] for more
+    ///information. Default is an empty list.
+    ///
+    ///[admission]: ../filter/trait.AdmissionFilter.html
+    pub admission_filters: Vec<Box<AdmissionFilter>>,
+
+    ///Compress response bodies with gzip or deflate, if the client accepts
+    ///it. See the [`compression`][compression] module for more information.
+    ///Default is `None`.
+    ///
+    ///This is only available with the `compression` feature.
+    ///
+    ///[compression]: ../compression/index.html
+    #[cfg(feature = "compression")]
+    pub compression: Option<Compression>,
+
+    ///Give `200 OK` responses with a buffered body a strong `ETag`, based on
+    ///the body, and respond with `304 Not Modified` if it matches the
+    ///`If-None-Match` header of the request. The body is hashed after the
+    ///response filters and the compression, so it's always the body that is
+    ///sent. Responses that already have an `ETag` are left as they are, as
+    ///well as `Chunked` and `Raw` responses. Default is `false`.
+    pub auto_etag: bool,
+
+    ///Renders the errors from `Response::send_error` and the handlers from
+    ///`handler::fallible`. Default is `DefaultErrorRenderer`, which sends an
+    ///empty `500 Internal Server Error` response.
+    pub error_renderer: Box<ErrorRenderer>,
+
+    ///Pages for responses with an error status and no body, such as `404
+    ///Not Found` or the status of an aborting filter. See the
+    ///[`error_pages`][error_pages] module for more information. Default is
+    ///no pages.
+    ///
+    ///[error_pages]: ../error_pages/index.html
+    pub error_pages: ErrorPages,
+
+    ///Renders the templates from `Response::render`. See the
+    ///[`template`][template] module for more information. Default is
+    ///`None`.
+    ///
+    ///[template]: ../template/index.html
+    pub renderer: Option<Box<Renderer>>,
+
+    ///Receives the status, body size and duration of each request, after
+    ///the response has been sent. See the [`completion`][completion] module
+    ///for more information. Default is `None`.
+    ///
+    ///[completion]: ../completion/index.html
+    pub completion_hook: Option<Box<CompletionHook>>,
+
+    ///Receives the response errors that are not checked by the handlers,
+    ///such as when `Response::send` fails, or when a response writer fails
+    ///to finish when it's dropped. See `response::ErrorSink`. Default is
+    ///`None`, where these errors are ignored.
+    pub error_sink: Option<Box<ErrorSink>>,
+
+    ///Receives reports about requests that failed, because the handler
+    ///panicked, or because the response has a `5xx` status. See the
+    ///[`error_report`][error_report] module for more information. Default
+    ///is `None`.
+    ///
+    ///[error_report]: ../error_report/index.html
+    pub error_reporter: Option<Box<ErrorReporter>>,
+
+    ///Security headers that are set for all responses, before they are
+    ///given to the handlers. See the [`security`][security] module for more
+    ///information. Default is `None`.
+    ///
+    ///[security]: ../security/index.html
+    pub security_headers: Option<SecurityHeaders>
 }
 
 impl<R: Router> Server<R> {
@@ -133,10 +306,86 @@ impl<R: Router> Server<R> {
             log: Box::new(StdOut),
             global: Global::default(),
             context_filters: Vec::new(),
+            middleware: Vec::new(),
             response_filters: Vec::new(),
+            trusted_proxies: Vec::new(),
+            max_body_size: None,
+            #[cfg(feature = "decompression")]
+            decompress_body: false,
+            #[cfg(feature = "decompression")]
+            decompression_limits: DecompressionLimits::default(),
+            event_sink: None,
+            request_timeout: None,
+            path_normalization: PathNormalization::Disabled,
+            allowed_hosts: Vec::new(),
+            #[cfg(feature = "rustc_json_body")]
+            body_decoders: BodyDecoders::default(),
+            admission_filters: Vec::new(),
+            #[cfg(feature = "compression")]
+            compression: None,
+            auto_etag: false,
+            error_renderer: Box::new(DefaultErrorRenderer),
+            error_pages: ErrorPages::new(),
+            renderer: None,
+            completion_hook: None,
+            error_sink: None,
+            error_reporter: None,
+            security_headers: None
         }
     }
 
+    ///Insert `filter` right before the context filter named `name`. The
+    ///filter is given back if there is no such filter. See
+    ///[`Named`][named] for how to name a filter.
+    ///
+    ///[named]: ../filter/struct.Named.html
+    pub fn insert_context_filter_before(&mut self, name: &str, filter: Box<ContextFilter>) -> Result<(), Box<ContextFilter>> {
+        insert_filter(&mut self.context_filters, name, 0, filter)
+    }
+
+    ///Insert `filter` right after the context filter named `name`. The
+    ///filter is given back if there is no such filter.
+    pub fn insert_context_filter_after(&mut self, name: &str, filter: Box<ContextFilter>) -> Result<(), Box<ContextFilter>> {
+        insert_filter(&mut self.context_filters, name, 1, filter)
+    }
+
+    ///Replace the context filter named `name` with `filter`, and get the
+    ///old filter. The new filter is given back if there is no such filter.
+    pub fn replace_context_filter(&mut self, name: &str, filter: Box<ContextFilter>) -> Result<Box<ContextFilter>, Box<ContextFilter>> {
+        replace_filter(&mut self.context_filters, name, filter)
+    }
+
+    ///Remove the context filter named `name`, if there is one.
+    pub fn remove_context_filter(&mut self, name: &str) -> Option<Box<ContextFilter>> {
+        remove_filter(&mut self.context_filters, name)
+    }
+
+    ///Insert `filter` right before the response filter named `name`. The
+    ///filter is given back if there is no such filter. See
+    ///[`Named`][named] for how to name a filter.
+    ///
+    ///[named]: ../filter/struct.Named.html
+    pub fn insert_response_filter_before(&mut self, name: &str, filter: Box<ResponseFilter>) -> Result<(), Box<ResponseFilter>> {
+        insert_filter(&mut self.response_filters, name, 0, filter)
+    }
+
+    ///Insert `filter` right after the response filter named `name`. The
+    ///filter is given back if there is no such filter.
+    pub fn insert_response_filter_after(&mut self, name: &str, filter: Box<ResponseFilter>) -> Result<(), Box<ResponseFilter>> {
+        insert_filter(&mut self.response_filters, name, 1, filter)
+    }
+
+    ///Replace the response filter named `name` with `filter`, and get the
+    ///old filter. The new filter is given back if there is no such filter.
+    pub fn replace_response_filter(&mut self, name: &str, filter: Box<ResponseFilter>) -> Result<Box<ResponseFilter>, Box<ResponseFilter>> {
+        replace_filter(&mut self.response_filters, name, filter)
+    }
+
+    ///Remove the response filter named `name`, if there is one.
+    pub fn remove_response_filter(&mut self, name: &str) -> Option<Box<ResponseFilter>> {
+        remove_filter(&mut self.response_filters, name)
+    }
+
     ///Start the server.
     #[cfg(feature = "ssl")]
     pub fn run(self) -> HttpResult<Listening> {
@@ -179,8 +428,32 @@ impl<R: Router> Server<R> {
         })
     }
 
-    ///Build a runnable instance of the server.
+    ///Build a runnable instance of the server. The admission, context and
+    ///response filters are set up here, in that order, followed by the
+    ///handlers and their scoped filters. They are torn down when the
+    ///instance is dropped.
     pub fn build(self) -> (ServerInstance<R>, Scheme) {
+        {
+            let context = GlobalContext {
+                log: &*self.log,
+                global: &self.global
+            };
+
+            for filter in &self.admission_filters {
+                filter.setup(&context);
+            }
+            for filter in &self.context_filters {
+                filter.setup(&context);
+            }
+            for filter in &self.response_filters {
+                filter.setup(&context);
+            }
+            self.handlers.setup(&context);
+            if let Some(ref handler) = self.fallback_handler {
+                Handler::setup(handler, &context);
+            }
+        }
+
         (ServerInstance {
             handlers: self.handlers,
             fallback_handler: self.fallback_handler,
@@ -189,8 +462,33 @@ impl<R: Router> Server<R> {
             content_type: self.content_type,
             log: self.log,
             context_filters: self.context_filters,
+            middleware: self.middleware,
             response_filters: self.response_filters,
             global: self.global,
+            trusted_proxies: self.trusted_proxies,
+            max_body_size: self.max_body_size,
+            #[cfg(feature = "decompression")]
+            decompress_body: self.decompress_body,
+            #[cfg(feature = "decompression")]
+            decompression_limits: self.decompression_limits,
+            event_sink: self.event_sink,
+            request_timeout: self.request_timeout,
+            path_normalization: self.path_normalization,
+            allowed_hosts: self.allowed_hosts,
+            #[cfg(feature = "rustc_json_body")]
+            body_decoders: self.body_decoders,
+            admission_filters: self.admission_filters,
+            in_flight: AtomicUsize::new(0),
+            #[cfg(feature = "compression")]
+            compression: self.compression,
+            auto_etag: self.auto_etag,
+            error_renderer: self.error_renderer,
+            error_pages: self.error_pages,
+            renderer: self.renderer,
+            completion_hook: self.completion_hook,
+            error_sink: self.error_sink,
+            error_reporter: self.error_reporter,
+            security_headers: self.security_headers
         },
         self.scheme)
     }
@@ -202,6 +500,48 @@ impl<R: Router + Default> Default for Server<R> {
     }
 }
 
+//Filters that may have names.
+trait FilterName {
+    fn filter_name(&self) -> Option<&str>;
+}
+
+impl FilterName for Box<ContextFilter> {
+    fn filter_name(&self) -> Option<&str> {
+        self.name()
+    }
+}
+
+impl FilterName for Box<ResponseFilter> {
+    fn filter_name(&self) -> Option<&str> {
+        self.name()
+    }
+}
+
+fn filter_position<F: FilterName>(filters: &[F], name: &str) -> Option<usize> {
+    filters.iter().position(|filter| filter.filter_name() == Some(name))
+}
+
+fn insert_filter<F: FilterName>(filters: &mut Vec<F>, name: &str, offset: usize, filter: F) -> Result<(), F> {
+    match filter_position(filters, name) {
+        Some(index) => {
+            filters.insert(index + offset, filter);
+            Ok(())
+        },
+        None => Err(filter)
+    }
+}
+
+fn replace_filter<F: FilterName>(filters: &mut Vec<F>, name: &str, filter: F) -> Result<F, F> {
+    match filter_position(filters, name) {
+        Some(index) => Ok(mem::replace(&mut filters[index], filter)),
+        None => Err(filter)
+    }
+}
+
+fn remove_filter<F: FilterName>(filters: &mut Vec<F>, name: &str) -> Option<F> {
+    filter_position(filters, name).map(|index| filters.remove(index))
+}
+
 ///A runnable instance of a server.
 ///
 ///It's not meant to be used directly,
@@ -233,12 +573,79 @@ pub struct ServerInstance<R: Router> {
     log: Box<Log>,
 
     context_filters: Vec<Box<ContextFilter>>,
+    middleware: Vec<Box<Middleware>>,
     response_filters: Vec<Box<ResponseFilter>>,
 
-    global: Global
+    global: Global,
+
+    trusted_proxies: Vec<IpAddr>,
+
+    max_body_size: Option<u64>,
+
+    #[cfg(feature = "decompression")]
+    decompress_body: bool,
+
+    #[cfg(feature = "decompression")]
+    decompression_limits: DecompressionLimits,
+
+    event_sink: Option<Box<EventSink>>,
+
+    request_timeout: Option<Duration>,
+
+    path_normalization: PathNormalization,
+
+    allowed_hosts: Vec<String>,
+
+    #[cfg(feature = "rustc_json_body")]
+    body_decoders: BodyDecoders,
+
+    admission_filters: Vec<Box<AdmissionFilter>>,
+    in_flight: AtomicUsize,
+
+    #[cfg(feature = "compression")]
+    compression: Option<Compression>,
+
+    auto_etag: bool,
+    error_renderer: Box<ErrorRenderer>,
+    error_pages: ErrorPages,
+    renderer: Option<Box<Renderer>>,
+    completion_hook: Option<Box<CompletionHook>>,
+    error_sink: Option<Box<ErrorSink>>,
+    error_reporter: Option<Box<ErrorReporter>>,
+    security_headers: Option<SecurityHeaders>
+}
+
+impl<R: Router> Drop for ServerInstance<R> {
+    ///Tears down the handlers and the filters, in the opposite order of how
+    ///they were set up.
+    fn drop(&mut self) {
+        if let Some(ref handler) = self.fallback_handler {
+            Handler::teardown(handler);
+        }
+        self.handlers.teardown();
+        for filter in self.response_filters.iter().rev() {
+            filter.teardown();
+        }
+        for filter in self.context_filters.iter().rev() {
+            filter.teardown();
+        }
+        for filter in self.admission_filters.iter().rev() {
+            filter.teardown();
+        }
+    }
 }
 
 impl<R: Router> ServerInstance<R> {
+    fn admit(&self, peer: &Peer) -> ContextAction {
+        for filter in &self.admission_filters {
+            match filter.admit(peer) {
+                ContextAction::Next => {},
+                action => return action
+            }
+        }
+
+        ContextAction::Next
+    }
 
     fn modify_context(&self, filter_storage: &mut AnyMap, context: &mut Context) -> ContextAction {
         let mut result = ContextAction::Next;
@@ -260,17 +667,336 @@ impl<R: Router> ServerInstance<R> {
         result
     }
 
+    #[cfg(feature = "decompression")]
+    fn decode_body<'a, 'b>(&self, body: BodyReader<'a, 'b>, headers: &mut Headers) -> io::Result<BodyReader<'a, 'b>> {
+        if self.decompress_body {
+            body.decompress(headers, &self.decompression_limits)
+        } else {
+            Ok(body)
+        }
+    }
+
+    #[cfg(not(feature = "decompression"))]
+    fn decode_body<'a, 'b>(&self, body: BodyReader<'a, 'b>, _headers: &mut Headers) -> io::Result<BodyReader<'a, 'b>> {
+        Ok(body)
+    }
+
+    #[cfg(feature = "rustc_json_body")]
+    fn set_body_decoders<'a, 'b>(&'a self, body: &mut BodyReader<'a, 'b>) {
+        body.set_decoders(&self.body_decoders);
+    }
+
+    #[cfg(not(feature = "rustc_json_body"))]
+    fn set_body_decoders<'a, 'b>(&'a self, _body: &mut BodyReader<'a, 'b>) {}
+
+    #[cfg(feature = "compression")]
+    fn set_compression<'a, 'b>(&'b self, response: &mut Response<'a, 'b>, headers: &Headers) {
+        if let Some(ref compression) = self.compression {
+            response.set_compression(compression, compression.negotiate(headers));
+        }
+    }
+
+    #[cfg(not(feature = "compression"))]
+    fn set_compression<'a, 'b>(&'b self, _response: &mut Response<'a, 'b>, _headers: &Headers) {}
+
+    //Returns `None` if the path is not allowed.
+    fn normalize_uri(&self, uri: Uri, request_target: &str) -> Option<Uri> {
+        match (uri, self.path_normalization) {
+            (Uri::Asterisk, _) => Some(Uri::Asterisk),
+            (uri, PathNormalization::Disabled) => Some(uri),
+            (_, normalization) => normalize_request_path(request_target, normalization).map(|path| Uri::Path(path.into()))
+        }
+    }
+}
+
+///Ways to treat request paths that are not in their canonical form, such as
+///`/a//b`, `/a/./b` or `/a/../b`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathNormalization {
+    ///Use the path as it is.
+    Disabled,
+
+    ///Remove empty and `.` segments and resolve `..` segments, so
+    ///`/a//b`, `/a/./b` and `/a/c/../b` all become `/a/b`.
+    Normalize,
+
+    ///Respond with `400 Bad Request` if the path is not already normalized.
+    Reject
+}
+
+//Normalizes the path in `request_target`, before it's percent decoded, and
+//returns the decoded result. Encoded unreserved characters, such as `%2E`,
+//are decoded first, so they can't hide dot-segments, while reserved
+//characters, such as `%2F`, stay encoded until the end. Returns `None` if
+//the path is not allowed.
+fn normalize_request_path(request_target: &str, normalization: PathNormalization) -> Option<Vec<u8>> {
+    let path = if request_target.starts_with('/') {
+        request_target
+    } else {
+        //An absolute URI, where the path starts after the authority.
+        match request_target.find("://") {
+            Some(index) => {
+                let rest = &request_target[index + 3..];
+                rest.find('/').map(|index| &rest[index..]).unwrap_or("")
+            },
+            None => request_target
+        }
+    };
+
+    let path = match path.find(|c| c == '?' || c == '#') {
+        Some(index) => &path[..index],
+        None => path
+    };
+    let path = if path.is_empty() { "/" } else { path };
+    let path = decode_unreserved(path.as_bytes());
+
+    let normalized = match normalize_path(&path) {
+        Some(normalized) => normalized,
+        None => return None
+    };
+
+    if normalization == PathNormalization::Reject && normalized != path {
+        return None;
+    }
+
+    Some(percent_decode(&normalized))
+}
+
+//Decodes the percent encoded unreserved characters in `path`, as described
+//in RFC 3986, section 6.2.2.2, and keeps everything else as it is.
+fn decode_unreserved(path: &[u8]) -> Vec<u8> {
+    fn hex_value(b: u8) -> Option<u8> {
+        match b {
+            b'0'...b'9' => Some(b - b'0'),
+            b'a'...b'f' => Some(b - b'a' + 10),
+            b'A'...b'F' => Some(b - b'A' + 10),
+            _ => None
+        }
+    }
+
+    let mut decoded = Vec::with_capacity(path.len());
+    let mut i = 0;
+    while i < path.len() {
+        if path[i] == b'%' && i + 2 < path.len() {
+            if let (Some(high), Some(low)) = (hex_value(path[i + 1]), hex_value(path[i + 2])) {
+                let byte = high * 16 + low;
+                match byte {
+                    b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'.' | b'_' | b'~' => {
+                        decoded.push(byte);
+                        i += 3;
+                        continue;
+                    },
+                    _ => {}
+                }
+            }
+        }
+
+        decoded.push(path[i]);
+        i += 1;
+    }
+
+    decoded
+}
+
+//Removes empty and `.` segments, and resolves `..` segments. A trailing
+//slash is kept. Returns `None` if the path escapes above the root.
+fn normalize_path(path: &[u8]) -> Option<Vec<u8>> {
+    let mut segments: Vec<&[u8]> = vec![];
+
+    for segment in path.split(|&b| b == b'/') {
+        if segment == &b".."[..] {
+            if segments.pop().is_none() {
+                return None;
+            }
+        } else if !segment.is_empty() && segment != &b"."[..] {
+            segments.push(segment);
+        }
+    }
+
+    let mut normalized = Vec::with_capacity(path.len());
+    for segment in &segments {
+        normalized.push(b'/');
+        normalized.extend_from_slice(segment);
+    }
+
+    let ends_with_directory = path.ends_with(b"/") || path.ends_with(b"/.") || path.ends_with(b"/..");
+    if normalized.is_empty() || ends_with_directory {
+        normalized.push(b'/');
+    }
+
+    Some(normalized)
+}
+
+//Checks `hostname` and `port` against a list of patterns, such as
+//`example.com`, `*.example.com` or `example.com:8080`.
+fn is_allowed_host(patterns: &[String], hostname: &str, port: Option<u16>) -> bool {
+    let hostname = hostname.trim_right_matches('.').to_lowercase();
+
+    patterns.iter().any(|pattern| {
+        let pattern = pattern.to_lowercase();
+        let (name, pattern_port) = match pattern.rfind(':') {
+            Some(index) if !pattern.ends_with(']') => (&pattern[..index], pattern[index + 1..].parse().ok()),
+            _ => (&pattern[..], None)
+        };
+
+        if pattern_port.is_some() && pattern_port != port {
+            return false;
+        }
+
+        if name == "*" {
+            true
+        } else if name.starts_with("*.") {
+            hostname.ends_with(&name[1..])
+        } else {
+            hostname == name
+        }
+    })
+}
+
+//Counts a request as in flight until it's dropped.
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl<'a> InFlight<'a> {
+    fn new(counter: &'a AtomicUsize) -> InFlight<'a> {
+        counter.fetch_add(1, Ordering::Relaxed);
+        InFlight(counter)
+    }
+}
+
+impl<'a> Drop for InFlight<'a> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+//Sends the response to a request that was turned away by an admission
+//filter, without any of the usual response processing.
+fn reject(mut writer: hyper::server::response::Response, response: FilterResponse) {
+    *writer.status_mut() = response.status;
+    {
+        let headers = writer.headers_mut();
+        for header in response.headers.iter() {
+            headers.set_raw(header.name().to_owned(), vec![header.value_string().into_bytes()]);
+        }
+        headers.set(hyper::header::Connection::close());
+    }
+
+    let body = response.body.unwrap_or_else(Vec::new);
+    let _ = writer.send(&body);
 }
 
 struct ParsedUri {
     host: Option<(String, Option<u16>)>,
     uri: Uri,
-    query: Parameters,
+    request_target: String,
+    query: Query,
     fragment: Option<MaybeUtf8Owned>
 }
 
 impl<R: Router> HyperHandler for ServerInstance<R> {
     fn handle(&self, request: hyper::server::request::Request, writer: hyper::server::response::Response) {
+        if !self.admission_filters.is_empty() {
+            let peer = Peer {
+                address: request.remote_addr,
+                in_flight: self.in_flight.load(Ordering::Relaxed) + 1
+            };
+
+            match self.admit(&peer) {
+                ContextAction::Next => {},
+                ContextAction::Abort(status) => return reject(writer, FilterResponse::new(status)),
+                ContextAction::Respond(filter_response) => return reject(writer, filter_response)
+            }
+        }
+
+        let outbox = Outbox::new();
+
+        let reporter = match self.error_reporter {
+            Some(ref reporter) => reporter,
+            None => return self.serve(request, writer, outbox, false)
+        };
+
+        let reporting = Reporting::new(
+            &**reporter,
+            outbox.clone(),
+            request.method.clone(),
+            raw_request_target(&request.uri),
+            request.remote_addr,
+            &request.headers
+        );
+
+        match panic::catch_unwind(AssertUnwindSafe(|| self.serve(request, writer, outbox, false))) {
+            Ok(()) => reporting.finish(None),
+            Err(payload) => {
+                reporting.finish(Some(&*payload));
+                panic::resume_unwind(payload);
+            }
+        }
+    }
+}
+
+#[doc(hidden)]
+///Internal and may change without warning.
+///
+///Sends requests through the server's filters, middleware and handlers,
+///such as the requests in a batch.
+pub trait Dispatcher: Sync {
+    ///Handle the raw HTTP request `request` from `address`, and return the
+    ///raw response.
+    fn dispatch(&self, request: &[u8], address: SocketAddr) -> Vec<u8>;
+}
+
+impl<R: Router> Dispatcher for ServerInstance<R> {
+    fn dispatch(&self, request: &[u8], address: SocketAddr) -> Vec<u8> {
+        let mut output = vec![];
+
+        {
+            let mut stream = RequestStream(io::Cursor::new(request.to_vec()), address);
+            let mut reader = BufReader::new(&mut stream as &mut NetworkStream);
+            let mut headers = Headers::new();
+            let writer = hyper::server::response::Response::new(&mut output, &mut headers);
+
+            match hyper::server::request::Request::new(&mut reader, address) {
+                Ok(request) => self.serve(request, writer, Outbox::new(), true),
+                Err(_) => reject(writer, FilterResponse::new(StatusCode::BadRequest))
+            }
+        }
+
+        output
+    }
+}
+
+//A stream that reads a dispatched request. Nothing is written to it.
+struct RequestStream(io::Cursor<Vec<u8>>, SocketAddr);
+
+impl Read for RequestStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for RequestStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl NetworkStream for RequestStream {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        Ok(self.1)
+    }
+}
+
+impl<R: Router> ServerInstance<R> {
+    //Serves a request. Dispatched requests are `nested`, and can't dispatch
+    //other requests.
+    fn serve(&self, request: hyper::server::request::Request, writer: hyper::server::response::Response, outbox: Outbox, nested: bool) {
+        let deadline = self.request_timeout.map(Deadline::from_now);
+        let _in_flight = InFlight::new(&self.in_flight);
+
         let (
             request_addr,
             request_method,
@@ -280,19 +1006,56 @@ impl<R: Router> HyperHandler for ServerInstance<R> {
             request_reader
         ) = request.deconstruct();
 
-        let mut response = Response::new(writer, &self.response_filters, &*self.log, &self.global);
+        //The same target is used everywhere, so the logs, the error reports
+        //and the handler agree on it.
+        let raw_target = raw_request_target(&request_uri);
+
+        //Declared before the response, to be dropped after it.
+        let _completing = self.completion_hook.as_ref().map(|hook| Completing::new(
+            &**hook,
+            outbox.clone(),
+            request_method.clone(),
+            raw_target.clone(),
+            request_version.clone(),
+            &request_headers,
+            request_addr
+        ));
+
+        let mut response = Response::new(writer, &self.response_filters, &*self.log, &self.global, outbox.clone());
         response.headers_mut().set(Date(HttpDate(time::now_utc())));
         response.headers_mut().set(ContentType(self.content_type.clone()));
         response.headers_mut().set(hyper::header::Server(self.server.clone()));
+        if let Some(ref security_headers) = self.security_headers {
+            response.apply_security_headers(security_headers);
+        }
+        self.set_compression(&mut response, &request_headers);
+        response.set_request_method(&request_method);
+        response.set_error_renderer(&*self.error_renderer);
+        if !self.error_pages.is_empty() {
+            response.set_error_pages(&self.error_pages);
+        }
+        if let Some(ref renderer) = self.renderer {
+            response.set_renderer(&**renderer);
+        }
+        if let Some(ref error_sink) = self.error_sink {
+            response.set_error_sink(&**error_sink, raw_target.clone(), request_addr);
+        }
+        if self.auto_etag {
+            response.set_auto_etag();
+        }
+        if request_method == Method::Get || request_method == Method::Head {
+            response.set_conditions(request_headers.get::<IfNoneMatch>().cloned(), request_headers.get::<IfModifiedSince>().cloned());
+        }
 
         let path_components = match request_uri {
-            RequestUri::AbsoluteUri(url) => Some(parse_url(url)),
+            RequestUri::AbsoluteUri(url) => Some(parse_url(url, raw_target)),
             RequestUri::AbsolutePath(path) => Some(parse_path(&path)),
             RequestUri::Star => {
                 Some(ParsedUri {
                     host: None,
                     uri: Uri::Asterisk,
-                    query: Parameters::new(),
+                    request_target: "*".into(),
+                    query: Query::new(),
                     fragment: None
                 })
             },
@@ -300,7 +1063,15 @@ impl<R: Router> HyperHandler for ServerInstance<R> {
         };
 
         match path_components {
-            Some(ParsedUri{ host, uri, query, fragment }) => {
+            Some(ParsedUri{ host, uri, request_target, query, fragment }) => {
+                let uri = match self.normalize_uri(uri, &request_target) {
+                    Some(uri) => uri,
+                    None => {
+                        response.set_status(StatusCode::BadRequest);
+                        return;
+                    }
+                };
+
                 if let Some((name, port)) = host {
                     request_headers.set(::header::Host {
                         hostname: name,
@@ -308,7 +1079,29 @@ impl<R: Router> HyperHandler for ServerInstance<R> {
                     });
                 }
 
-                let body = context::body::BodyReader::from_reader(request_reader, &request_headers);
+                if !self.allowed_hosts.is_empty() {
+                    match request_headers.get::<::header::Host>() {
+                        Some(host) => if !is_allowed_host(&self.allowed_hosts, &host.hostname, host.port) {
+                            response.set_status(StatusCode::from_u16(421));
+                            return;
+                        },
+                        None => {
+                            response.set_status(StatusCode::BadRequest);
+                            return;
+                        }
+                    }
+                }
+
+                let body = BodyReader::from_reader(request_reader, &request_headers);
+                let mut body = match self.decode_body(body, &mut request_headers) {
+                    Ok(body) => body,
+                    Err(_) => {
+                        response.set_status(StatusCode::BadRequest);
+                        return;
+                    }
+                };
+                body.set_limit(self.max_body_size);
+                self.set_body_decoders(&mut body);
 
                 let mut context = Context {
                     headers: request_headers,
@@ -316,46 +1109,63 @@ impl<R: Router> HyperHandler for ServerInstance<R> {
                     method: request_method,
                     address: request_addr,
                     uri: uri,
+                    request_target: request_target,
                     hypermedia: Hypermedia::new(),
-                    variables: Parameters::new(),
-                    query: query.into(),
+                    variables: RouteVariables::empty(),
+                    query: query,
                     fragment: fragment,
                     log: &*self.log,
                     global: &self.global,
-                    body: body
+                    body: body,
+                    trusted_proxies: &self.trusted_proxies,
+                    deadline: deadline,
+                    dispatcher: if nested { None } else { Some(self) }
                 };
 
                 let mut filter_storage = AnyMap::new();
 
+                //Response filters don't see the request, but `OnPath` needs to know the path.
+                if !self.response_filters.is_empty() {
+                    if let Some(path) = context.uri.as_path() {
+                        filter_storage.insert(RequestPath(path.as_utf8_lossy().into_owned()));
+                    }
+                }
+
                 match self.modify_context(&mut filter_storage, &mut context) {
                     ContextAction::Next => {
                         *response.filter_storage_mut() = filter_storage;
 
-                        let endpoint = context.uri.as_path().map(|path| self.handlers.find(&context.method, &path)).unwrap_or_else(|| {
-                            Endpoint {
-                                handler: None,
-                                variables: HashMap::new(),
-                                hypermedia: Hypermedia::new()
-                            }
-                        });
-
-                        let Endpoint {
-                            handler,
-                            variables,
-                            hypermedia
-                        } = endpoint;
-
-                        if let Some(handler) = handler.or(self.fallback_handler.as_ref()) {
-                            context.hypermedia = hypermedia;
-                            context.variables = variables.into();
-                            handler.handle_request(context, response);
-                        } else {
-                            response.set_status(StatusCode::NotFound);
+                        let (handler, hypermedia, variables) = match context.uri.as_path() {
+                            Some(path) => {
+                                let Endpoint {
+                                    handler,
+                                    variables,
+                                    hypermedia
+                                } = self.handlers.find(&context.method, &path);
+
+                                (handler, hypermedia, RouteVariables::new(path, variables))
+                            },
+                            None => (None, Hypermedia::new(), RouteVariables::empty())
+                        };
+
+                        let handler = handler.or(self.fallback_handler.as_ref()).map(|handler| handler as &Handler);
+
+                        context.hypermedia = hypermedia;
+                        context.variables = variables;
+                        Next::new(&self.middleware, handler, outbox.clone()).run(context, response);
+
+                        //The response has been sent at this point.
+                        if let (Some(sink), Some(delivery)) = (self.event_sink.as_ref(), outbox.take()) {
+                            sink.deliver(delivery);
                         }
                     },
                     ContextAction::Abort(status) => {
                         *response.filter_storage_mut() = filter_storage;
                         response.set_status(status);
+                    },
+                    ContextAction::Respond(filter_response) => {
+                        *response.filter_storage_mut() = filter_storage;
+                        response.send_filter_response(filter_response);
                     }
                 }
             },
@@ -366,7 +1176,20 @@ impl<R: Router> HyperHandler for ServerInstance<R> {
     }
 }
 
+//Gets the request-target from the request line. Hyper only keeps the parsed
+//URL of an absolute-form target, so it's serialized again, while the other
+//forms are kept as they were sent.
+fn raw_request_target(uri: &RequestUri) -> String {
+    match *uri {
+        RequestUri::AbsolutePath(ref path) => path.clone(),
+        RequestUri::AbsoluteUri(ref url) => url.to_string(),
+        RequestUri::Authority(ref authority) => authority.clone(),
+        RequestUri::Star => "*".into()
+    }
+}
+
 fn parse_path(path: &str) -> ParsedUri {
+    let request_target = path;
     match path.find('?') {
         Some(index) => {
             let (query, fragment) = parse_fragment(&path[index+1..]);
@@ -379,7 +1202,8 @@ fn parse_path(path: &str) -> ParsedUri {
             ParsedUri {
                 host: None,
                 uri: Uri::Path(path.into()),
-                query: utils::parse_parameters(query.as_bytes()),
+                request_target: request_target.into(),
+                query: Query::from_raw(query),
                 fragment: fragment.map(|f| percent_decode(f.as_bytes()).into())
             }
         },
@@ -394,7 +1218,8 @@ fn parse_path(path: &str) -> ParsedUri {
             ParsedUri {
                 host: None,
                 uri: Uri::Path(path.into()),
-                query: Parameters::new(),
+                request_target: request_target.into(),
+                query: Query::new(),
                 fragment: fragment.map(|f| percent_decode(f.as_bytes()).into())
             }
         }
@@ -408,7 +1233,7 @@ fn parse_fragment<'a>(path: &'a str) -> (&'a str, Option<&'a str>) {
     }
 }
 
-fn parse_url(url: Url) -> ParsedUri {
+fn parse_url(url: Url, request_target: String) -> ParsedUri {
     let mut path = Vec::new();
     for component in url.path().unwrap_or(&[]) {
         path.push('/' as u8);
@@ -418,10 +1243,7 @@ fn parse_url(url: Url) -> ParsedUri {
         path.push('/' as u8);
     }
 
-    let query = url.query_pairs()
-            .unwrap_or_else(|| Vec::new())
-            .into_iter()
-            .collect();
+    let query = url.query.map(Query::from_raw).unwrap_or_else(Query::new);
 
     let host = if let SchemeData::Relative(data) = url.scheme_data {
         Some((data.host.serialize(), data.port))
@@ -432,6 +1254,7 @@ fn parse_url(url: Url) -> ParsedUri {
     ParsedUri {
         host: host,
         uri: Uri::Path(path.into()),
+        request_target: request_target,
         query: query,
         fragment: url.fragment.map(|f| percent_decode(f.as_bytes()).into())
     }
@@ -442,8 +1265,10 @@ fn parse_url(url: Url) -> ParsedUri {
 fn parse_path_parts() {
     let with = "this".to_owned().into();
     let and = "that".to_owned().into();
-    let ParsedUri { uri, query, fragment, .. } = parse_path("/path/to/something?with=this&and=that#lol");
+    let ParsedUri { uri, request_target, query, fragment, .. } = parse_path("/path/to/something?with=this&and=that#lol");
     assert_eq!(uri.as_path(), Some("/path/to/something".into()));
+    assert_eq!(request_target, "/path/to/something?with=this&and=that#lol");
+    assert_eq!(query.raw(), b"with=this&and=that");
     assert_eq!(query.get_raw("with"), Some(&with));
     assert_eq!(query.get_raw("and"), Some(&and));
     assert_eq!(fragment, Some("lol".to_owned().into()));
@@ -490,7 +1315,9 @@ fn parse_url_parts() {
     let with = "this".to_owned().into();
     let and = "that".to_owned().into();
     let url = Url::parse("http://example.com/path/to/something?with=this&and=that#lol").unwrap();
-    let ParsedUri { uri, query, fragment, .. } = parse_url(url);
+    let target = raw_request_target(&RequestUri::AbsoluteUri(url.clone()));
+    let ParsedUri { uri, request_target, query, fragment, .. } = parse_url(url, target);
+    assert_eq!(request_target, "http://example.com/path/to/something?with=this&and=that#lol");
     assert_eq!(uri.as_path(), Some("/path/to/something".into()));
     assert_eq!(query.get_raw("with"), Some(&with));
     assert_eq!(query.get_raw("and"), Some(&and));
@@ -502,7 +1329,7 @@ fn parse_strange_url() {
     let with = "this".to_owned().into();
     let and = "what?".to_owned().into();
     let url = Url::parse("http://example.com/path/to/something?with=this&and=what?#").unwrap();
-    let ParsedUri { uri, query, fragment, .. } = parse_url(url);
+    let ParsedUri { uri, query, fragment, .. } = parse_url(url, String::new());
     assert_eq!(uri.as_path(), Some("/path/to/something".into()));
     assert_eq!(query.get_raw("with"), Some(&with));
     assert_eq!(query.get_raw("and"), Some(&and));
@@ -514,7 +1341,7 @@ fn parse_missing_url_parts() {
     let with = "this".to_owned().into();
     let and = "that".to_owned().into();
     let url = Url::parse("http://example.com/path/to/something?with=this&and=that").unwrap();
-    let ParsedUri { uri, query, fragment, .. } = parse_url(url);
+    let ParsedUri { uri, query, fragment, .. } = parse_url(url, String::new());
     assert_eq!(uri.as_path(), Some("/path/to/something".into()));
     assert_eq!(query.get_raw("with"), Some(&with));
     assert_eq!(query.get_raw("and"), Some(&and));
@@ -522,16 +1349,372 @@ fn parse_missing_url_parts() {
 
 
     let url = Url::parse("http://example.com/path/to/something#lol").unwrap();
-    let ParsedUri { uri, query, fragment, .. } = parse_url(url);
+    let ParsedUri { uri, query, fragment, .. } = parse_url(url, String::new());
     assert_eq!(uri.as_path(), Some("/path/to/something".into()));
     assert_eq!(query.len(), 0);
     assert_eq!(fragment, Some("lol".to_owned().into()));
 
 
     let url = Url::parse("http://example.com?with=this&and=that#lol").unwrap();
-    let ParsedUri { uri, query, fragment, .. } = parse_url(url);
+    let ParsedUri { uri, query, fragment, .. } = parse_url(url, String::new());
     assert_eq!(uri.as_path(), Some("/".into()));
     assert_eq!(query.get_raw("with"), Some(&with));
     assert_eq!(query.get_raw("and"), Some(&and));
     assert_eq!(fragment, Some("lol".to_owned().into()));
-}
\ No newline at end of file
+}
+
+#[test]
+fn normalize_paths() {
+    assert_eq!(normalize_path(b"/a/b"), Some(b"/a/b".to_vec()));
+    assert_eq!(normalize_path(b"/a//b"), Some(b"/a/b".to_vec()));
+    assert_eq!(normalize_path(b"/a/./b/"), Some(b"/a/b/".to_vec()));
+    assert_eq!(normalize_path(b"/a/c/../b"), Some(b"/a/b".to_vec()));
+    assert_eq!(normalize_path(b"/a/.."), Some(b"/".to_vec()));
+    assert_eq!(normalize_path(b"/"), Some(b"/".to_vec()));
+    assert_eq!(normalize_path(b"/a/../../b"), None);
+}
+
+#[test]
+fn normalize_request_paths() {
+    let normalize = |target: &str| normalize_request_path(target, PathNormalization::Normalize);
+    assert_eq!(normalize("/a/c/../b?c=d"), Some(b"/a/b".to_vec()));
+    assert_eq!(normalize("/a%2F..%2Fb"), Some(b"/a/../b".to_vec()));
+    assert_eq!(normalize("/a/%2e%2e/secret"), Some(b"/secret".to_vec()));
+    assert_eq!(normalize("/a/.%2E/%2e/b/%7Ec"), Some(b"/b/~c".to_vec()));
+    assert_eq!(normalize("/%2e%2e/b"), None);
+    assert_eq!(normalize("/a%2f%2e%2e%2fb"), Some(b"/a/../b".to_vec()));
+    assert_eq!(normalize("/a/../../b"), None);
+    assert_eq!(normalize("http://example.com/a//b?c=/../d"), Some(b"/a/b".to_vec()));
+    assert_eq!(normalize("http://example.com"), Some(b"/".to_vec()));
+
+    let reject = |target: &str| normalize_request_path(target, PathNormalization::Reject);
+    assert_eq!(reject("/a%2F..%2Fb"), Some(b"/a/../b".to_vec()));
+    assert_eq!(reject("/a/./b"), None);
+    assert_eq!(reject("/a/%2e%2e/b"), None);
+    assert_eq!(reject("/%7Ea"), Some(b"/~a".to_vec()));
+    assert_eq!(reject("?a=b"), Some(b"/".to_vec()));
+}
+
+#[test]
+fn allowed_hosts() {
+    let patterns = vec!["example.com".to_owned(), "*.example.org".to_owned(), "localhost:8080".to_owned()];
+    assert!(is_allowed_host(&patterns, "example.com", None));
+    assert!(is_allowed_host(&patterns, "Example.COM.", Some(80)));
+    assert!(!is_allowed_host(&patterns, "www.example.com", None));
+    assert!(is_allowed_host(&patterns, "a.b.example.org", None));
+    assert!(!is_allowed_host(&patterns, "example.org", None));
+    assert!(!is_allowed_host(&patterns, "evilexample.org", None));
+    assert!(is_allowed_host(&patterns, "localhost", Some(8080)));
+    assert!(!is_allowed_host(&patterns, "localhost", Some(80)));
+    assert!(!is_allowed_host(&patterns, "localhost", None));
+}
+
+#[test]
+fn named_filters() {
+    use filter::Named;
+
+    struct Nothing;
+
+    impl ContextFilter for Nothing {
+        fn modify(&self, _context: FilterContext, _request_context: &mut Context) -> ContextAction {
+            ContextAction::next()
+        }
+    }
+
+    fn names(server: &Server<fn(Context, Response)>) -> Vec<Option<&str>> {
+        server.context_filters.iter().map(|filter| filter.name()).collect()
+    }
+
+    fn handler(_context: Context, _response: Response) {}
+
+    let mut server = Server {
+        context_filters: vec![Box::new(Named::new("a", Nothing)), Box::new(Nothing)],
+        ..Server::new(handler as fn(Context, Response))
+    };
+
+    assert!(server.insert_context_filter_before("a", Box::new(Named::new("b", Nothing))).is_ok());
+    assert!(server.insert_context_filter_after("a", Box::new(Named::new("c", Nothing))).is_ok());
+    assert!(server.insert_context_filter_after("x", Box::new(Nothing)).is_err());
+    assert_eq!(names(&server), vec![Some("b"), Some("a"), Some("c"), None]);
+
+    assert!(server.replace_context_filter("a", Box::new(Named::new("d", Nothing))).is_ok());
+    assert!(server.remove_context_filter("b").is_some());
+    assert!(server.remove_context_filter("b").is_none());
+    assert_eq!(names(&server), vec![Some("d"), Some("c"), None]);
+}
+
+#[test]
+fn filter_lifecycle() {
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder(&'static str, Arc<Mutex<Vec<String>>>);
+
+    impl ContextFilter for Recorder {
+        fn modify(&self, _context: FilterContext, _request_context: &mut Context) -> ContextAction {
+            ContextAction::next()
+        }
+
+        fn setup(&self, _context: &GlobalContext) {
+            self.1.lock().unwrap().push(format!("setup {}", self.0));
+        }
+
+        fn teardown(&self) {
+            self.1.lock().unwrap().push(format!("teardown {}", self.0));
+        }
+    }
+
+    fn handler(_context: Context, _response: Response) {}
+
+    let events = Arc::new(Mutex::new(vec![]));
+    let server = Server {
+        context_filters: vec![
+            Box::new(Recorder("a", events.clone())),
+            Box::new(Recorder("b", events.clone()))
+        ],
+        ..Server::new(handler as fn(Context, Response))
+    };
+
+    let (instance, _scheme) = server.build();
+    assert_eq!(*events.lock().unwrap(), vec!["setup a", "setup b"]);
+
+    drop(instance);
+    assert_eq!(*events.lock().unwrap(), vec!["setup a", "setup b", "teardown b", "teardown a"]);
+}
+
+#[cfg(feature = "rustc_json_body")]
+#[test]
+fn dispatch_batches() {
+    use handler::Batch;
+
+    struct RequireKey;
+
+    impl ContextFilter for RequireKey {
+        fn modify(&self, _context: FilterContext, request_context: &mut Context) -> ContextAction {
+            if request_context.headers.get_raw("X-Key").is_some() {
+                ContextAction::next()
+            } else {
+                ContextAction::abort(StatusCode::Unauthorized)
+            }
+        }
+    }
+
+    fn handler(context: Context, response: Response) {
+        let path = context.uri.as_utf8_path().unwrap_or("").to_owned();
+        if path == "/batch" {
+            Batch::new().handle_request(context, response);
+        } else {
+            response.send(format!("{} {}", context.method, path));
+        }
+    }
+
+    let server = Server {
+        context_filters: vec![Box::new(RequireKey)],
+        ..Server::new(handler as fn(Context, Response))
+    };
+    let (instance, _scheme) = server.build();
+    let address = "127.0.0.1:8080".parse().unwrap();
+
+    let unauthorized = instance.dispatch(b"GET /a HTTP/1.1\r\nHost: localhost\r\n\r\n", address);
+    assert!(unauthorized.starts_with(b"HTTP/1.1 401"));
+
+    let batch = r#"[{"method":"GET","path":"/a"},{"method":"DELETE","path":"/b?c"},{"method":"POST","path":"/batch","body":"[]"}]"#;
+    let request = format!(
+        "POST /batch HTTP/1.1\r\nHost: localhost\r\nX-Key: k\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        batch.len(),
+        batch
+    );
+    let output = String::from_utf8(instance.dispatch(request.as_bytes(), address)).unwrap();
+    assert!(output.starts_with("HTTP/1.1 200"), "{}", output);
+
+    let first = output.find(r#""body":"GET /a""#).expect(&output);
+    let second = output.find(r#""body":"DELETE /b""#).expect(&output);
+    let third = output.find(r#""body":"batches can't be nested""#).expect(&output);
+    assert!(first < second && second < third);
+}
+
+#[test]
+fn admit_requests() {
+    use std::sync::Arc;
+
+    struct Block(IpAddr);
+
+    impl AdmissionFilter for Block {
+        fn admit(&self, peer: &Peer) -> ContextAction {
+            assert_eq!(peer.in_flight, 1);
+            if peer.address.ip() == self.0 {
+                ContextAction::abort(StatusCode::Forbidden)
+            } else {
+                ContextAction::next()
+            }
+        }
+    }
+
+    let handled = Arc::new(AtomicUsize::new(0));
+    let counter = handled.clone();
+    let server = Server {
+        admission_filters: vec![Box::new(Block("10.0.0.1".parse().unwrap()))],
+        ..Server::new(move |_: Context, response: Response| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            response.send("hello");
+        })
+    };
+    let (instance, _scheme) = server.build();
+
+    let handle = |address: SocketAddr| {
+        let mut output = vec![];
+        {
+            let mut stream = RequestStream(io::Cursor::new(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n".to_vec()), address);
+            let mut reader = BufReader::new(&mut stream as &mut NetworkStream);
+            let mut headers = Headers::new();
+            let writer = hyper::server::response::Response::new(&mut output, &mut headers);
+            let request = hyper::server::request::Request::new(&mut reader, address).unwrap();
+            instance.handle(request, writer);
+        }
+        String::from_utf8(output).unwrap()
+    };
+
+    let output = handle("10.0.0.1:8080".parse().unwrap());
+    assert!(output.starts_with("HTTP/1.1 403"), "{}", output);
+    assert!(output.contains("Connection: close"), "{}", output);
+    assert_eq!(handled.load(Ordering::SeqCst), 0);
+
+    let output = handle("10.0.0.2:8080".parse().unwrap());
+    assert!(output.starts_with("HTTP/1.1 200"), "{}", output);
+    assert!(output.ends_with("hello"), "{}", output);
+    assert_eq!(handled.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn skip_response_filters() {
+    use filter::{Named, ResponseAction};
+    use response::Data;
+
+    struct Mark(&'static str);
+
+    impl ResponseFilter for Mark {
+        fn begin(&self, _context: FilterContext, status: StatusCode, headers: &mut Headers) -> (StatusCode, ResponseAction) {
+            headers.set_raw(self.0, vec![b"1".to_vec()]);
+            (status, ResponseAction::next(None::<Data>))
+        }
+
+        fn write<'a>(&'a self, _context: FilterContext, content: Option<Data<'a>>) -> ResponseAction {
+            ResponseAction::next(content)
+        }
+
+        fn end(&self, _context: FilterContext) -> ResponseAction {
+            ResponseAction::next(None::<Data>)
+        }
+    }
+
+    fn handler(context: Context, mut response: Response) {
+        match context.uri.as_utf8_path() {
+            Some("/skip_b") => response.skip_filter("b"),
+            Some("/skip_unknown") => response.skip_filter("c"),
+            Some("/skip_all") => response.skip_filters(),
+            _ => {}
+        }
+        response.send("hello");
+    }
+
+    let server = Server {
+        response_filters: vec![Box::new(Named::new("a", Mark("X-A"))), Box::new(Named::new("b", Mark("X-B")))],
+        ..Server::new(handler as fn(Context, Response))
+    };
+    let (instance, _scheme) = server.build();
+    let address = "127.0.0.1:8080".parse().unwrap();
+    let request = |path: &str| {
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+        String::from_utf8(instance.dispatch(request.as_bytes(), address)).unwrap()
+    };
+
+    let output = request("/");
+    assert!(output.contains("X-A: 1") && output.contains("X-B: 1"), "{}", output);
+
+    let output = request("/skip_b");
+    assert!(output.contains("X-A: 1") && !output.contains("X-B"), "{}", output);
+
+    let output = request("/skip_unknown");
+    assert!(output.contains("X-A: 1") && output.contains("X-B: 1"), "{}", output);
+
+    let output = request("/skip_all");
+    assert!(!output.contains("X-A") && !output.contains("X-B"), "{}", output);
+    assert!(output.ends_with("hello"), "{}", output);
+}
+
+#[test]
+fn respond_from_filters() {
+    use filter::ResponseAction;
+    use response::Data;
+
+    struct Guard;
+
+    impl ContextFilter for Guard {
+        fn modify(&self, _context: FilterContext, request_context: &mut Context) -> ContextAction {
+            match request_context.uri.as_utf8_path() {
+                Some("/guarded") => ContextAction::respond(
+                    FilterResponse::new(StatusCode::Unauthorized)
+                        .header(ContentType(Mime(hyper::mime::TopLevel::Text, hyper::mime::SubLevel::Plain, vec![])))
+                        .body("go away")
+                ),
+                _ => ContextAction::next()
+            }
+        }
+    }
+
+    struct Censor;
+
+    impl ResponseFilter for Censor {
+        fn begin(&self, _context: FilterContext, status: StatusCode, _headers: &mut Headers) -> (StatusCode, ResponseAction) {
+            (status, ResponseAction::next(None::<Data>))
+        }
+
+        fn write<'a>(&'a self, context: FilterContext, content: Option<Data<'a>>) -> ResponseAction {
+            let text = content.as_ref().map(|content| content.as_bytes().to_vec());
+            match text.as_ref().map(|text| &text[..]) {
+                Some(b"secret") => {
+                    context.storage.insert(FilterResponse::new(StatusCode::Forbidden).body("censored"));
+                    ResponseAction::abort("found a secret".into())
+                },
+                Some(b"crash") => ResponseAction::abort("crashed".into()),
+                _ => ResponseAction::next(content)
+            }
+        }
+
+        fn end(&self, _context: FilterContext) -> ResponseAction {
+            ResponseAction::next(None::<Data>)
+        }
+    }
+
+    fn handler(context: Context, response: Response) {
+        match context.uri.as_utf8_path() {
+            Some("/secret") => response.send("secret"),
+            Some("/crash") => response.send("crash"),
+            _ => response.send("hello")
+        }
+    }
+
+    let server = Server {
+        context_filters: vec![Box::new(Guard)],
+        response_filters: vec![Box::new(Censor)],
+        ..Server::new(handler as fn(Context, Response))
+    };
+    let (instance, _scheme) = server.build();
+    let address = "127.0.0.1:8080".parse().unwrap();
+    let request = |path: &str| {
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+        String::from_utf8(instance.dispatch(request.as_bytes(), address)).unwrap()
+    };
+
+    let output = request("/");
+    assert!(output.starts_with("HTTP/1.1 200") && output.ends_with("hello"), "{}", output);
+
+    let output = request("/guarded");
+    assert!(output.starts_with("HTTP/1.1 401"), "{}", output);
+    assert!(output.contains("Content-Type: text/plain") && output.ends_with("go away"), "{}", output);
+
+    let output = request("/secret");
+    assert!(output.starts_with("HTTP/1.1 403") && output.ends_with("censored"), "{}", output);
+
+    let output = request("/crash");
+    assert!(output.starts_with("HTTP/1.1 500") && !output.contains("crash"), "{}", output);
+}
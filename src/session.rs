@@ -0,0 +1,394 @@
+//!Sessions that are stored in signed cookies.
+//!
+//![`CookieSessions`][sessions] keeps the whole session in a cookie, so no
+//!state has to be stored on the server, and any server in a cluster can
+//!handle any request. The cookie is signed with HMAC-SHA256, so it can't be
+//!changed by the client, but it can still be read. Secrets should therefore
+//!not be stored in the session.
+//!
+//!It's both a context filter, that reads the session from the request, and
+//!a response filter, that writes it back to the client if it was changed.
+//!The session is available as a [`Session`][session] in the filter storage:
+//!
+//!```
+//!use rustful::{Server, Context, Response};
+//!use rustful::session::{CookieSessions, Session};
+//!
+//!fn count_visits(_context: Context, mut response: Response) {
+//!    let visits = {
+//!        let session = response.filter_storage_mut().get_mut::<Session>().expect("no session");
+//!        let visits = session.get("visits").and_then(|v| v.parse().ok()).unwrap_or(0u32) + 1;
+//!        session.set("visits", visits.to_string());
+//!        visits
+//!    };
+//!
+//!    response.send(format!("you have been here {} times", visits));
+//!}
+//!
+//!let sessions = CookieSessions::new("a long and random secret key").secure();
+//!
+//!let server = Server {
+//!    context_filters: vec![Box::new(sessions.clone())],
+//!    response_filters: vec![Box::new(sessions)],
+//!    ..Server::new(count_visits)
+//!};
+//!```
+//!
+//!The keys can be rotated by adding the previous key with `old_key`. It's
+//!still accepted, but new cookies are signed with the current key.
+//!
+//![sessions]: struct.CookieSessions.html
+//![session]: struct.Session.html
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use time::{self, Duration};
+
+use context::Context;
+use filter::{FilterContext, ContextFilter, ContextAction, ResponseFilter, ResponseAction};
+use header::Headers;
+use headers::{Cookie, SetCookie, SameSite};
+use response::Data;
+use utils::{hmac_sha256, base64_encode, base64_decode, constant_time_eq};
+use StatusCode;
+
+//Most clients don't store cookies that are larger than this.
+const MAX_COOKIE_SIZE: usize = 4096;
+
+///The values of a session.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Session {
+    values: BTreeMap<String, String>,
+    changed: bool
+}
+
+impl Session {
+    ///Create an empty session.
+    pub fn new() -> Session {
+        Session::default()
+    }
+
+    ///Get the value of `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(|value| &**value)
+    }
+
+    ///Set the value of `name`.
+    pub fn set<N: Into<String>, V: Into<String>>(&mut self, name: N, value: V) {
+        self.values.insert(name.into(), value.into());
+        self.changed = true;
+    }
+
+    ///Remove the value of `name`.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let value = self.values.remove(name);
+        self.changed |= value.is_some();
+        value
+    }
+
+    ///Remove all of the values. The cookie is removed from the client if
+    ///nothing is added afterwards.
+    pub fn clear(&mut self) {
+        self.changed |= !self.values.is_empty();
+        self.values.clear();
+    }
+
+    ///Check if the session has any values.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    ///Check if the session has been changed, and will be sent to the
+    ///client.
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+}
+
+#[derive(Clone)]
+struct Settings {
+    key: Vec<u8>,
+    old_keys: Vec<Vec<u8>>,
+    cookie: Cookie,
+    max_age: Duration
+}
+
+///Stores sessions in signed cookies. See the [module
+///documentation][module] for more information.
+///
+///It's cheap to clone, and the clones share the same settings.
+///
+///[module]: index.html
+#[derive(Clone)]
+pub struct CookieSessions {
+    settings: Arc<Settings>
+}
+
+impl CookieSessions {
+    ///Create a session store that signs the cookies with `key`. The key
+    ///should be long and random, and kept secret.
+    ///
+    ///The cookie is named `session`, with the path `/`, `HttpOnly` and
+    ///`SameSite=Lax`. It lives for one day.
+    pub fn new<K: Into<Vec<u8>>>(key: K) -> CookieSessions {
+        CookieSessions {
+            settings: Arc::new(Settings {
+                key: key.into(),
+                old_keys: vec![],
+                cookie: Cookie::new("session", "").path("/").http_only().same_site(SameSite::Lax),
+                max_age: Duration::days(1)
+            })
+        }
+    }
+
+    ///Accept cookies that are signed with `key`, such as the key that was
+    ///used before the current one.
+    pub fn old_key<K: Into<Vec<u8>>>(mut self, key: K) -> CookieSessions {
+        self.settings_mut().old_keys.push(key.into());
+        self
+    }
+
+    ///Set the name of the cookie.
+    pub fn name<N: Into<String>>(mut self, name: N) -> CookieSessions {
+        self.settings_mut().cookie.name = name.into();
+        self
+    }
+
+    ///Set the path of the cookie.
+    pub fn path<P: Into<String>>(mut self, path: P) -> CookieSessions {
+        self.settings_mut().cookie.path = Some(path.into());
+        self
+    }
+
+    ///Set the domain of the cookie.
+    pub fn domain<D: Into<String>>(mut self, domain: D) -> CookieSessions {
+        self.settings_mut().cookie.domain = Some(domain.into());
+        self
+    }
+
+    ///Only send the cookie over secure connections.
+    pub fn secure(mut self) -> CookieSessions {
+        self.settings_mut().cookie.secure = true;
+        self
+    }
+
+    ///Set how long a session lives after it was last changed.
+    pub fn max_age(mut self, max_age: Duration) -> CookieSessions {
+        self.settings_mut().max_age = max_age;
+        self
+    }
+
+    fn settings_mut(&mut self) -> &mut Settings {
+        Arc::make_mut(&mut self.settings)
+    }
+
+    ///Encode and sign `session`, to be used as a cookie value. The session
+    ///expires at `expires`, in seconds since the Unix epoch.
+    pub fn encode(&self, session: &Session, expires: i64) -> String {
+        let mut payload = vec![];
+        push_u64(&mut payload, expires as u64);
+        for (name, value) in &session.values {
+            push_u64(&mut payload, name.len() as u64);
+            payload.extend_from_slice(name.as_bytes());
+            push_u64(&mut payload, value.len() as u64);
+            payload.extend_from_slice(value.as_bytes());
+        }
+
+        let payload = base64_encode(&payload);
+        let signature = base64_encode(&hmac_sha256(&self.settings.key, payload.as_bytes()));
+        format!("{}.{}", payload, signature)
+    }
+
+    ///Check the signature and expiration time of a cookie value, and decode
+    ///the session. `now` is the current time, in seconds since the Unix
+    ///epoch.
+    pub fn decode(&self, value: &str, now: i64) -> Option<Session> {
+        let dot = match value.find('.') {
+            Some(dot) => dot,
+            None => return None
+        };
+        let (payload, signature) = (&value[..dot], &value[dot + 1..]);
+
+        //The encoded signatures are compared, since the decoder accepts
+        //more than one encoding of the same bytes.
+        let valid = Some(&self.settings.key).into_iter().chain(&self.settings.old_keys).any(|key| {
+            constant_time_eq(base64_encode(&hmac_sha256(key, payload.as_bytes())).as_bytes(), signature.as_bytes())
+        });
+        if !valid {
+            return None;
+        }
+
+        let payload = match base64_decode(payload.as_bytes()) {
+            Some(payload) => payload,
+            None => return None
+        };
+
+        let mut input = &payload[..];
+        match read_u64(&mut input) {
+            Some(expires) if expires as i64 > now => {},
+            _ => return None
+        }
+
+        let mut values = BTreeMap::new();
+        while !input.is_empty() {
+            match (read_string(&mut input), read_string(&mut input)) {
+                (Some(name), Some(value)) => values.insert(name, value),
+                _ => return None
+            };
+        }
+
+        Some(Session {
+            values: values,
+            changed: false
+        })
+    }
+
+    fn read_cookie(&self, headers: &Headers) -> Option<Session> {
+        let now = time::get_time().sec;
+        let name = &self.settings.cookie.name;
+
+        headers.get_raw("Cookie").into_iter().flat_map(|lines| lines).filter_map(|line| ::std::str::from_utf8(line).ok()).flat_map(|line| line.split(';')).filter_map(|pair| {
+            let pair = pair.trim();
+            match pair.find('=') {
+                Some(index) if &pair[..index] == name => self.decode(&pair[index + 1..], now),
+                _ => None
+            }
+        }).next()
+    }
+
+    fn session_cookie(&self, session: &Session) -> Cookie {
+        let settings = &self.settings;
+        let mut cookie = if session.is_empty() {
+            Cookie::removal(settings.cookie.name.clone())
+        } else {
+            let expires = time::get_time().sec + settings.max_age.num_seconds();
+            Cookie::new(settings.cookie.name.clone(), self.encode(session, expires)).max_age(settings.max_age)
+        };
+
+        cookie.path = settings.cookie.path.clone();
+        cookie.domain = settings.cookie.domain.clone();
+        cookie.secure = settings.cookie.secure;
+        cookie.http_only = settings.cookie.http_only;
+        cookie.same_site = settings.cookie.same_site;
+        cookie
+    }
+}
+
+impl ContextFilter for CookieSessions {
+    fn modify(&self, context: FilterContext, request_context: &mut Context) -> ContextAction {
+        let session = self.read_cookie(&request_context.headers).unwrap_or_else(Session::new);
+        context.storage.insert(session);
+        ContextAction::next()
+    }
+}
+
+impl ResponseFilter for CookieSessions {
+    fn begin(&self, _context: FilterContext, status: StatusCode, _headers: &mut Headers) -> (StatusCode, ResponseAction) {
+        (status, ResponseAction::next(None::<Data>))
+    }
+
+    fn write<'a>(&'a self, _context: FilterContext, content: Option<Data<'a>>) -> ResponseAction {
+        ResponseAction::next(content)
+    }
+
+    fn end(&self, _context: FilterContext) -> ResponseAction {
+        ResponseAction::next(None::<Data>)
+    }
+
+    fn finish_headers(&self, context: FilterContext, status: StatusCode, headers: &mut Headers) -> StatusCode {
+        let cookie = match context.storage.get::<Session>() {
+            Some(session) if session.is_changed() => self.session_cookie(session),
+            _ => return status
+        };
+
+        if cookie.value.len() > MAX_COOKIE_SIZE {
+            context.log.warning(&format!("the session cookie is {} bytes, and may not be stored by the client", cookie.value.len()));
+        }
+
+        if let Some(cookies) = headers.get_mut::<SetCookie>() {
+            cookies.insert(cookie);
+            return status;
+        }
+
+        headers.set(SetCookie(vec![cookie]));
+        status
+    }
+}
+
+fn push_u64(buffer: &mut Vec<u8>, n: u64) {
+    for i in (0..8).rev() {
+        buffer.push((n >> (i * 8)) as u8);
+    }
+}
+
+fn read_u64(input: &mut &[u8]) -> Option<u64> {
+    if input.len() < 8 {
+        return None;
+    }
+
+    let n = input[..8].iter().fold(0, |n, &b| (n << 8) | b as u64);
+    *input = &input[8..];
+    Some(n)
+}
+
+fn read_string(input: &mut &[u8]) -> Option<String> {
+    let length = match read_u64(input) {
+        Some(length) if length <= input.len() as u64 => length,
+        _ => return None
+    };
+
+    let (string, rest) = input.split_at(length as usize);
+    *input = rest;
+    String::from_utf8(string.to_vec()).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CookieSessions, Session};
+
+    #[test]
+    fn sign_sessions() {
+        let sessions = CookieSessions::new("key");
+        let mut session = Session::new();
+        session.set("user", "alice");
+        session.set("theme", "dark; light");
+
+        let value = sessions.encode(&session, 2000);
+        let decoded = sessions.decode(&value, 1000).expect("the session was not decoded");
+        assert_eq!(decoded.get("user"), Some("alice"));
+        assert_eq!(decoded.get("theme"), Some("dark; light"));
+        assert!(!decoded.is_changed());
+
+        assert!(sessions.decode(&value, 3000).is_none());
+        assert!(CookieSessions::new("other key").decode(&value, 1000).is_none());
+        assert!(CookieSessions::new("new key").old_key("key").decode(&value, 1000).is_some());
+
+    }
+
+    #[test]
+    fn reject_tampered_sessions() {
+        let sessions = CookieSessions::new("key");
+        let mut session = Session::new();
+        session.set("user", "alice");
+
+        let value = sessions.encode(&session, 2000);
+        let dot = value.find('.').unwrap();
+        let signature_end = value.trim_right_matches('=').len();
+
+        //The first and last characters of the payload and the signature,
+        //including the last character before the padding, which has unused
+        //bits.
+        for &position in &[0, dot / 2, dot - 1, dot + 1, signature_end - 1, value.len() - 1] {
+            for &flip in &[1u8, 2, 32] {
+                let mut tampered = value.clone().into_bytes();
+                tampered[position] ^= flip;
+                let tampered = String::from_utf8(tampered).unwrap();
+                assert!(sessions.decode(&tampered, 1000).is_none(), "{} was accepted", tampered);
+            }
+        }
+
+        assert!(sessions.decode(value.trim_right_matches('='), 1000).is_none());
+        assert!(sessions.decode(&value, 1000).is_some());
+    }
+}
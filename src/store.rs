@@ -0,0 +1,493 @@
+//!An embedded, file backed key-value store.
+//!
+//![`Store`][store] keeps its entries in memory and persists every change to
+//!an append-only log file, so a small application can keep its state
+//!without any external services. Each change is synced to the disk before
+//!the call returns. Each record carries its length and a checksum, so a
+//!record that was cut short by a crash, or damaged on the disk, is skipped
+//!when the store is opened again. The log is compacted when it's mostly made
+//!of overwritten or removed entries.
+//!
+//!```no_run
+//!use rustful::store::Store;
+//!
+//!let store = Store::open("app.db").unwrap();
+//!store.set("greeting", "hello").unwrap();
+//!assert_eq!(store.get("greeting"), Some(b"hello".to_vec()));
+//!```
+//!
+//!A `Store` is also a [`BucketStore`][bucket_store], so rate limits can be
+//!kept across restarts. It should have a file of its own, since the client
+//!keys are used as they are:
+//!
+//!```no_run
+//!use rustful::store::Store;
+//!use rustful::rate_limit::{RateLimit, Rate};
+//!
+//!let limit = RateLimit::with_store(Rate::per_minute(60), Store::open("limits.db").unwrap());
+//!```
+//!
+//![store]: struct.Store.html
+//![bucket_store]: ../rate_limit/trait.BucketStore.html
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use time::Timespec;
+
+use rate_limit::{Bucket, BucketStore, Rate};
+use utils::fnv1a;
+
+const OP_REMOVE: u8 = 0;
+const OP_SET: u8 = 1;
+
+//The size of the record header: operation, key length and value length.
+const HEADER_SIZE: usize = 9;
+
+//The log is never compacted if it has less garbage records than this.
+const MIN_COMPACTION_GARBAGE: usize = 1000;
+
+///A persistent key-value store, backed by an append-only log file.
+///
+///It can be shared between threads, for example by putting it in the
+///server's global storage.
+pub struct Store {
+    path: PathBuf,
+    inner: Mutex<Inner>
+}
+
+struct Inner {
+    file: File,
+    entries: HashMap<String, Vec<u8>>,
+    garbage: usize
+}
+
+impl Store {
+    ///Open the store at `path`, or create it if it doesn't exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Store> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = try!(OpenOptions::new().read(true).append(true).create(true).open(&path));
+
+        let mut log = vec![];
+        try!(file.read_to_end(&mut log));
+
+        let mut entries = HashMap::new();
+        let mut garbage = 0;
+        let mut position = 0;
+        let mut valid_length = 0;
+        let mut damaged = false;
+
+        while position < log.len() {
+            let (length, record) = match read_record(&log[position..]) {
+                Some(record) => record,
+                None => {
+                    //A damaged record is skipped by looking for the next
+                    //intact record after it.
+                    position += 1;
+                    continue;
+                }
+            };
+
+            damaged |= position > valid_length;
+            position += length;
+            valid_length = position;
+
+            match record {
+                Record::Set(key, value) => if entries.insert(key, value).is_some() {
+                    garbage += 1;
+                },
+                Record::Remove(key) => {
+                    entries.remove(&key);
+                    garbage += 2;
+                }
+            }
+        }
+
+        if valid_length < log.len() {
+            //The last record is incomplete or damaged, probably because of a
+            //crash, so it was never acknowledged.
+            try!(file.set_len(valid_length as u64));
+            try!(file.sync_all());
+        }
+
+        let store = Store {
+            path: path,
+            inner: Mutex::new(Inner {
+                file: file,
+                entries: entries,
+                garbage: garbage
+            })
+        };
+
+        if damaged {
+            //The log is rewritten without the damaged records, so they
+            //are not mistaken for intact records later.
+            try!(store.compact());
+        }
+
+        Ok(store)
+    }
+
+    ///Get the value of `key`.
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        self.inner.lock().ok().and_then(|inner| inner.entries.get(key).cloned())
+    }
+
+    ///Check if `key` is in the store.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.inner.lock().map(|inner| inner.entries.contains_key(key)).unwrap_or(false)
+    }
+
+    ///Get all of the keys in the store, in no particular order.
+    pub fn keys(&self) -> Vec<String> {
+        self.inner.lock().map(|inner| inner.entries.keys().cloned().collect()).unwrap_or_else(|_| vec![])
+    }
+
+    ///Get the number of entries in the store.
+    pub fn len(&self) -> usize {
+        self.inner.lock().map(|inner| inner.entries.len()).unwrap_or(0)
+    }
+
+    ///Check if the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///Set the value of `key`. The change is on the disk when this returns.
+    pub fn set<K: Into<String>, V: Into<Vec<u8>>>(&self, key: K, value: V) -> io::Result<()> {
+        let key = key.into();
+        let value = value.into();
+        let mut inner = try!(self.lock());
+
+        try!(append(&mut inner.file, OP_SET, &key, &value));
+        if inner.entries.insert(key, value).is_some() {
+            inner.garbage += 1;
+        }
+
+        self.compact_if_needed(&mut inner)
+    }
+
+    ///Remove `key` and return `true` if it was in the store. The change is
+    ///on the disk when this returns.
+    pub fn remove(&self, key: &str) -> io::Result<bool> {
+        let mut inner = try!(self.lock());
+
+        if !inner.entries.contains_key(key) {
+            return Ok(false);
+        }
+
+        try!(append(&mut inner.file, OP_REMOVE, key, &[]));
+        inner.entries.remove(key);
+        inner.garbage += 2;
+
+        try!(self.compact_if_needed(&mut inner));
+        Ok(true)
+    }
+
+    ///Rewrite the log file, so it only contains the current entries. This
+    ///is done automatically when the log is mostly made of garbage.
+    pub fn compact(&self) -> io::Result<()> {
+        let mut inner = try!(self.lock());
+        self.compact_inner(&mut inner)
+    }
+
+    fn lock(&self) -> io::Result<MutexGuard<Inner>> {
+        self.inner.lock().map_err(|_| io::Error::new(io::ErrorKind::Other, "poisoned store lock"))
+    }
+
+    fn compact_if_needed(&self, inner: &mut Inner) -> io::Result<()> {
+        if inner.garbage >= MIN_COMPACTION_GARBAGE && inner.garbage > inner.entries.len() {
+            self.compact_inner(inner)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn compact_inner(&self, inner: &mut Inner) -> io::Result<()> {
+        let mut temp_path = OsString::from(self.path.as_os_str());
+        temp_path.push(".compact");
+        let temp_path = PathBuf::from(temp_path);
+
+        {
+            let mut temp = try!(File::create(&temp_path));
+            let mut buffer = vec![];
+            for (key, value) in &inner.entries {
+                write_record(&mut buffer, OP_SET, key, value);
+            }
+            try!(temp.write_all(&buffer));
+            try!(temp.sync_all());
+        }
+
+        //The rename replaces the old log in one step, so a crash leaves
+        //either the old or the new log behind.
+        try!(fs::rename(&temp_path, &self.path));
+        try!(sync_parent(&self.path));
+
+        inner.file = try!(OpenOptions::new().read(true).append(true).open(&self.path));
+        inner.garbage = 0;
+        Ok(())
+    }
+}
+
+impl BucketStore for Store {
+    fn take(&self, key: &str, rate: Rate, now: Timespec) -> Option<f64> {
+        let mut inner = match self.lock() {
+            Ok(inner) => inner,
+            Err(_) => return None
+        };
+
+        let mut bucket = inner.entries.get(key).and_then(|value| decode_bucket(value)).unwrap_or_else(|| Bucket::full(rate, now));
+        let result = bucket.take(rate, now);
+        let value = encode_bucket(&bucket);
+
+        //The request is let through if the bucket can't be stored, rather
+        //than failing every request while the disk is unavailable.
+        if append(&mut inner.file, OP_SET, key, &value).is_ok() {
+            if inner.entries.insert(key.to_owned(), value).is_some() {
+                inner.garbage += 1;
+            }
+            let _ = self.compact_if_needed(&mut inner);
+        }
+
+        result
+    }
+}
+
+//A bucket is stored as its tokens and update time, as big endian f64 bits.
+fn encode_bucket(bucket: &Bucket) -> Vec<u8> {
+    let mut value = Vec::with_capacity(16);
+    write_u64(&mut value, bucket.tokens.to_bits());
+    write_u64(&mut value, bucket.updated.to_bits());
+    value
+}
+
+fn decode_bucket(value: &[u8]) -> Option<Bucket> {
+    if value.len() != 16 {
+        return None;
+    }
+
+    Some(Bucket {
+        tokens: f64::from_bits(read_u64(&value[..8])),
+        updated: f64::from_bits(read_u64(&value[8..]))
+    })
+}
+
+//A renamed file is only durable when its directory entry is synced.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new(".")
+    };
+    try!(File::open(parent)).sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+enum Record {
+    Set(String, Vec<u8>),
+    Remove(String)
+}
+
+fn append(file: &mut File, op: u8, key: &str, value: &[u8]) -> io::Result<()> {
+    let mut buffer = Vec::with_capacity(HEADER_SIZE + key.len() + value.len() + 4);
+    write_record(&mut buffer, op, key, value);
+    try!(file.write_all(&buffer));
+    file.sync_data()
+}
+
+//Record layout: operation (1 byte), key length (4 bytes), value length (4
+//bytes), key, value and an FNV-1a checksum of everything before it (4 bytes).
+//The numbers are big endian.
+fn write_record(buffer: &mut Vec<u8>, op: u8, key: &str, value: &[u8]) {
+    let start = buffer.len();
+    buffer.push(op);
+    write_u32(buffer, key.len() as u32);
+    write_u32(buffer, value.len() as u32);
+    buffer.extend_from_slice(key.as_bytes());
+    buffer.extend_from_slice(value);
+    let checksum = fnv1a(&buffer[start..]);
+    write_u32(buffer, checksum);
+}
+
+//Reads the first record in `log`, if it's complete and intact, and returns
+//it together with its length.
+fn read_record(log: &[u8]) -> Option<(usize, Record)> {
+    if log.len() < HEADER_SIZE {
+        return None;
+    }
+
+    let key_length = read_u32(&log[1..5]) as usize;
+    let value_length = read_u32(&log[5..9]) as usize;
+    let end = HEADER_SIZE + key_length + value_length;
+
+    if log.len() < end + 4 || read_u32(&log[end..end + 4]) != fnv1a(&log[..end]) {
+        return None;
+    }
+
+    let key = match String::from_utf8(log[HEADER_SIZE..HEADER_SIZE + key_length].to_vec()) {
+        Ok(key) => key,
+        Err(_) => return None
+    };
+
+    let record = match log[0] {
+        OP_SET => Record::Set(key, log[HEADER_SIZE + key_length..end].to_vec()),
+        OP_REMOVE => Record::Remove(key),
+        _ => return None
+    };
+
+    Some((end + 4, record))
+}
+
+fn write_u32(buffer: &mut Vec<u8>, n: u32) {
+    buffer.push((n >> 24) as u8);
+    buffer.push((n >> 16) as u8);
+    buffer.push((n >> 8) as u8);
+    buffer.push(n as u8);
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    ((bytes[0] as u32) << 24) | ((bytes[1] as u32) << 16) | ((bytes[2] as u32) << 8) | bytes[3] as u32
+}
+
+fn write_u64(buffer: &mut Vec<u8>, n: u64) {
+    write_u32(buffer, (n >> 32) as u32);
+    write_u32(buffer, n as u32);
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    ((read_u32(&bytes[..4]) as u64) << 32) | read_u32(&bytes[4..8]) as u64
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{File, OpenOptions};
+    use std::io::{Read, Write};
+    use time::Timespec;
+    use tempdir;
+    use rate_limit::{BucketStore, Rate};
+    use super::Store;
+
+    #[test]
+    fn reopen_store() {
+        let dir = tempdir::TempDir::new("reopen_store").unwrap();
+        let path = dir.path().join("test.db");
+
+        {
+            let store = Store::open(&path).unwrap();
+            store.set("a", "1").unwrap();
+            store.set("b", "2").unwrap();
+            store.set("a", "3").unwrap();
+            assert_eq!(store.remove("b").unwrap(), true);
+            assert_eq!(store.remove("c").unwrap(), false);
+        }
+
+        let store = Store::open(&path).unwrap();
+        assert_eq!(store.get("a"), Some(b"3".to_vec()));
+        assert_eq!(store.get("b"), None);
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn discard_incomplete_record() {
+        let dir = tempdir::TempDir::new("discard_incomplete_record").unwrap();
+        let path = dir.path().join("test.db");
+
+        {
+            let store = Store::open(&path).unwrap();
+            store.set("a", "1").unwrap();
+        }
+
+        {
+            let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+            file.write_all(&[1, 0, 0, 0, 1, 0, 0]).unwrap();
+        }
+
+        let store = Store::open(&path).unwrap();
+        assert_eq!(store.get("a"), Some(b"1".to_vec()));
+        store.set("b", "2").unwrap();
+
+        let store = Store::open(&path).unwrap();
+        assert_eq!(store.get("b"), Some(b"2".to_vec()));
+    }
+
+    #[test]
+    fn skip_damaged_record() {
+        let dir = tempdir::TempDir::new("skip_damaged_record").unwrap();
+        let path = dir.path().join("test.db");
+
+        let first_length = {
+            let store = Store::open(&path).unwrap();
+            store.set("a", "1").unwrap();
+            let first_length = path.metadata().unwrap().len();
+            store.set("b", "2").unwrap();
+            store.set("c", "3").unwrap();
+            first_length
+        };
+
+        {
+            let mut log = vec![];
+            File::open(&path).unwrap().read_to_end(&mut log).unwrap();
+            //The key length of "b" is made too long.
+            log[first_length as usize + 4] = 200;
+            File::create(&path).unwrap().write_all(&log).unwrap();
+        }
+
+        let store = Store::open(&path).unwrap();
+        assert_eq!(store.get("a"), Some(b"1".to_vec()));
+        assert_eq!(store.get("b"), None);
+        assert_eq!(store.get("c"), Some(b"3".to_vec()));
+        store.set("d", "4").unwrap();
+
+        let store = Store::open(&path).unwrap();
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.get("d"), Some(b"4".to_vec()));
+    }
+
+    #[test]
+    fn persist_buckets() {
+        let dir = tempdir::TempDir::new("persist_buckets").unwrap();
+        let path = dir.path().join("test.db");
+        let rate = Rate::per_second(1).burst(2);
+        let now = Timespec::new(1000, 0);
+
+        {
+            let store = Store::open(&path).unwrap();
+            assert_eq!(store.take("a", rate, now), None);
+            assert_eq!(store.take("a", rate, now), None);
+        }
+
+        let store = Store::open(&path).unwrap();
+        assert_eq!(store.take("a", rate, now), Some(1.0));
+        assert_eq!(store.take("b", rate, now), None);
+    }
+
+    #[test]
+    fn compact_log() {
+        let dir = tempdir::TempDir::new("compact_log").unwrap();
+        let path = dir.path().join("test.db");
+
+        let store = Store::open(&path).unwrap();
+        for i in 0..10 {
+            store.set("a", i.to_string()).unwrap();
+        }
+        store.set("b", "x").unwrap();
+        let before = path.metadata().unwrap().len();
+
+        store.compact().unwrap();
+        assert!(path.metadata().unwrap().len() < before);
+        store.set("c", "y").unwrap();
+
+        let store = Store::open(&path).unwrap();
+        assert_eq!(store.get("a"), Some(b"9".to_vec()));
+        assert_eq!(store.get("b"), Some(b"x".to_vec()));
+        assert_eq!(store.get("c"), Some(b"y".to_vec()));
+    }
+}
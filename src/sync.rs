@@ -0,0 +1,204 @@
+//!Helpers for delta synchronization of collections.
+//!
+//!A client that keeps a local copy of a collection can avoid downloading
+//!the whole collection each time it's synchronized. The server gives each
+//!state of the collection a [`SyncToken`][token], which is sent to the client
+//!as an `ETag` and in the response body. The client can then either ask if
+//!the collection has changed at all, using `If-None-Match`, or ask for the
+//!changes since a token, using the `since` query parameter.
+//!
+//!```
+//!use rustful::{Context, Response};
+//!use rustful::sync::{self, SyncToken};
+//!
+//!# fn current_version() -> u64 { 42 }
+//!fn list_things(context: Context, mut response: Response) {
+//!    let token = SyncToken::from_version(current_version());
+//!
+//!    if sync::not_modified(&context, &mut response, &token) {
+//!        //The response will be an empty `304 Not Modified`.
+//!        return;
+//!    }
+//!
+//!    match SyncToken::from_context(&context).and_then(|since| since.version()) {
+//!        Some(version) => response.send(format!("the changes since version {}", version)),
+//!        None => response.send("all of the things")
+//!    }
+//!}
+//!```
+//!
+//![token]: struct.SyncToken.html
+
+use std::fmt;
+
+use header::{ETag, IfNoneMatch, EntityTag};
+use context::Context;
+use response::Response;
+use StatusCode;
+use Method;
+
+///An opaque token that identifies a state of a collection.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SyncToken(String);
+
+impl SyncToken {
+    ///Create a token from an arbitrary string. It should not contain `"` or
+    ///`\`, since it has to fit in an `ETag`.
+    pub fn new<S: Into<String>>(token: S) -> SyncToken {
+        SyncToken(token.into())
+    }
+
+    ///Create a token from a numeric version of the collection, such as a
+    ///revision number or a modification timestamp.
+    pub fn from_version(version: u64) -> SyncToken {
+        SyncToken(format!("v{}", version))
+    }
+
+    ///Get the token from the `since` query parameter, if any.
+    pub fn from_context(context: &Context) -> Option<SyncToken> {
+        context.query.get("since").map(|since| SyncToken(since.into_owned()))
+    }
+
+    ///Get the numeric version, if the token was created using
+    ///`from_version`.
+    pub fn version(&self) -> Option<u64> {
+        if self.0.starts_with('v') {
+            self.0[1..].parse().ok()
+        } else {
+            None
+        }
+    }
+
+    ///Get the token as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    ///Create a strong `ETag` value from the token.
+    pub fn to_etag(&self) -> EntityTag {
+        EntityTag::new(false, self.0.clone())
+    }
+
+    ///Check if the token is listed in an `If-None-Match` header.
+    pub fn matches(&self, if_none_match: &IfNoneMatch) -> bool {
+        match *if_none_match {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(ref tags) => tags.iter().any(|tag| tag.tag() == self.0)
+        }
+    }
+}
+
+impl fmt::Display for SyncToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+///Set the `ETag` of `response` to `token` and check if the client already
+///has the current state of the collection, according to `If-None-Match`. The
+///status of `response` is set to `304 Not Modified` if it does, and `true` is
+///returned. Other methods than `GET` and `HEAD` get `412 Precondition
+///Failed` instead, since they must not change a state the client already
+///has.
+pub fn not_modified(context: &Context, response: &mut Response, token: &SyncToken) -> bool {
+    response.headers_mut().set(ETag(token.to_etag()));
+
+    let not_modified = context.headers.get::<IfNoneMatch>().map(|tags| token.matches(tags)).unwrap_or(false);
+    if not_modified {
+        if context.method == Method::Get || context.method == Method::Head {
+            response.set_status(StatusCode::NotModified);
+        } else {
+            response.set_status(StatusCode::PreconditionFailed);
+        }
+    }
+
+    not_modified
+}
+
+///A standard envelope for the changes to a collection since a token.
+///
+///It can be encoded as JSON with the `rustc_json_body` feature:
+///
+///```json
+///{
+///    "token": "v43",
+///    "changed": [{ "id": "a", "name": "something new" }],
+///    "deleted": ["b"],
+///    "more": false
+///}
+///```
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "rustc_json_body", derive(RustcEncodable, RustcDecodable))]
+pub struct Changes<T> {
+    ///The token for the state after the changes. The client should use it
+    ///as `since` the next time.
+    pub token: String,
+
+    ///Items that were added or modified.
+    pub changed: Vec<T>,
+
+    ///The identifiers of items that were removed.
+    pub deleted: Vec<String>,
+
+    ///There are more changes that did not fit in this response. The client
+    ///should ask again, using the new token.
+    pub more: bool
+}
+
+impl<T> Changes<T> {
+    ///Create an empty set of changes, ending with `token`.
+    pub fn new(token: &SyncToken) -> Changes<T> {
+        Changes {
+            token: token.0.clone(),
+            changed: vec![],
+            deleted: vec![],
+            more: false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {Server, Context, Response};
+    use header::{IfNoneMatch, EntityTag};
+    use server::Dispatcher;
+    use super::{SyncToken, not_modified};
+
+    #[test]
+    fn version_tokens() {
+        assert_eq!(SyncToken::from_version(42).version(), Some(42));
+        assert_eq!(SyncToken::new("abc").version(), None);
+    }
+
+    #[test]
+    fn match_if_none_match() {
+        let token = SyncToken::from_version(1);
+        assert!(token.matches(&IfNoneMatch::Any));
+        assert!(token.matches(&IfNoneMatch::Items(vec![EntityTag::new(true, "v1".into())])));
+        assert!(!token.matches(&IfNoneMatch::Items(vec![EntityTag::new(false, "v2".into())])));
+    }
+
+    #[test]
+    fn fail_unsafe_methods() {
+        fn handler(context: Context, mut response: Response) {
+            if !not_modified(&context, &mut response, &SyncToken::from_version(1)) {
+                response.send("changed");
+            }
+        }
+
+        let (instance, _scheme) = Server::new(handler as fn(Context, Response)).build();
+        let address = "127.0.0.1:8080".parse().unwrap();
+
+        let output = instance.dispatch(b"GET / HTTP/1.1\r\nHost: localhost\r\nIf-None-Match: \"v1\"\r\n\r\n", address);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("HTTP/1.1 304"), "{}", output);
+
+        let output = instance.dispatch(b"PUT / HTTP/1.1\r\nHost: localhost\r\nIf-None-Match: *\r\nContent-Length: 0\r\n\r\n", address);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("HTTP/1.1 412"), "{}", output);
+
+        let output = instance.dispatch(b"PUT / HTTP/1.1\r\nHost: localhost\r\nIf-None-Match: \"v2\"\r\nContent-Length: 0\r\n\r\n", address);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("HTTP/1.1 200"), "{}", output);
+    }
+}
@@ -0,0 +1,219 @@
+//!Template rendering.
+//!
+//!A [`Renderer`][renderer] turns a template name and some data into a
+//!response body and its content type. The server's renderer is set in its
+//!`renderer` field, and it's used by `Response::render`:
+//!
+//!```
+//!use rustful::{Server, Context, Response};
+//!use rustful::template::{Templates, TemplateData};
+//!
+//!fn greet(context: Context, response: Response) {
+//!    let mut data = TemplateData::new();
+//!    data.insert("name".into(), context.variables.get("name").unwrap_or("stranger".into()).into_owned());
+//!    response.render("greeting.html", &data);
+//!}
+//!
+//!let mut templates = Templates::new();
+//!templates.insert("greeting.html", "<h1>Hello, {{name}}!</h1>");
+//!
+//!let server = Server {
+//!    renderer: Some(Box::new(templates)),
+//!    ..Server::new(greet)
+//!};
+//!```
+//!
+//!Other template engines can be used by implementing `Renderer` for them.
+//!
+//![renderer]: trait.Renderer.html
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use mime::{Mime, TopLevel, SubLevel, Attr, Value};
+
+use file::ext_to_mime;
+use utils::fill_template;
+
+///The data that a template is rendered with.
+pub type TemplateData = BTreeMap<String, String>;
+
+///Renders templates.
+pub trait Renderer: Send + Sync + 'static {
+    ///Render the template `name` with `data`.
+    fn render(&self, name: &str, data: &TemplateData) -> Result<Rendered, RenderError>;
+}
+
+///A rendered template.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rendered {
+    ///The rendered body.
+    pub body: String,
+
+    ///The content type of the body.
+    pub content_type: Mime
+}
+
+///An error from a `Renderer`.
+#[derive(Debug)]
+pub enum RenderError {
+    ///There is no template with this name.
+    NotFound(String),
+
+    ///The template could not be rendered.
+    Failed(String)
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RenderError::NotFound(ref name) => write!(f, "the template '{}' was not found", name),
+            RenderError::Failed(ref message) => write!(f, "failed to render a template: {}", message)
+        }
+    }
+}
+
+impl Error for RenderError {
+    fn description(&self) -> &str {
+        match *self {
+            RenderError::NotFound(_) => "template not found",
+            RenderError::Failed(_) => "failed to render a template"
+        }
+    }
+}
+
+///A simple template renderer, where `{{name}}` is replaced with the value
+///of `name`, or nothing if it's missing.
+///
+///The content type is guessed from the extension of the template name, and
+///it's `text/html; charset=utf-8` if there is none. The values are escaped
+///for HTML and XML templates, including `+xml` types, such as SVG.
+pub struct Templates {
+    templates: HashMap<String, String>
+}
+
+impl Templates {
+    ///Create an empty set of templates.
+    pub fn new() -> Templates {
+        Templates {
+            templates: HashMap::new()
+        }
+    }
+
+    ///Add the template `name`, with the source `source`.
+    pub fn insert<N: Into<String>, S: Into<String>>(&mut self, name: N, source: S) {
+        self.templates.insert(name.into(), source.into());
+    }
+
+    ///Load the template `name` from the file at `path`.
+    pub fn load<N: Into<String>, P: AsRef<Path>>(&mut self, name: N, path: P) -> io::Result<()> {
+        let mut source = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut source));
+        self.insert(name, source);
+        Ok(())
+    }
+}
+
+impl Default for Templates {
+    fn default() -> Templates {
+        Templates::new()
+    }
+}
+
+impl Renderer for Templates {
+    fn render(&self, name: &str, data: &TemplateData) -> Result<Rendered, RenderError> {
+        let template = match self.templates.get(name) {
+            Some(template) => template,
+            None => return Err(RenderError::NotFound(name.to_owned()))
+        };
+
+        let content_type = match Path::new(name).extension() {
+            Some(ext) => ext_to_mime(&ext.to_string_lossy()).unwrap_or(Mime(TopLevel::Text, SubLevel::Plain, vec![])),
+            None => Mime(TopLevel::Text, SubLevel::Html, vec![])
+        };
+
+        let escape = is_markup(&content_type);
+
+        let body = fill_template(template, &|name: &str| data.get(name).map(|value| {
+            if escape { escape_html(value) } else { value.clone() }
+        }));
+
+        let content_type = match content_type {
+            Mime(TopLevel::Text, sub_level, _) => Mime(TopLevel::Text, sub_level, vec![(Attr::Charset, Value::Utf8)]),
+            content_type => content_type
+        };
+
+        Ok(Rendered {
+            body: body,
+            content_type: content_type
+        })
+    }
+}
+
+//Checks if values should be escaped for `content_type`, which is HTML,
+//XML, or any `+xml` type, such as SVG or XHTML.
+fn is_markup(content_type: &Mime) -> bool {
+    match *content_type {
+        Mime(_, SubLevel::Html, _) | Mime(_, SubLevel::Xml, _) => true,
+        Mime(_, SubLevel::Ext(ref sub_level), _) => sub_level.to_lowercase().ends_with("+xml"),
+        _ => false
+    }
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use mime::{Mime, TopLevel, SubLevel, Attr, Value};
+    use super::{Templates, TemplateData, Renderer, RenderError, is_markup};
+
+    #[test]
+    fn render_templates() {
+        let mut templates = Templates::new();
+        templates.insert("page.html", "<p>{{text}}</p>");
+        templates.insert("page.txt", "{{text}}");
+
+        let mut data = TemplateData::new();
+        data.insert("text".into(), "a < b".into());
+
+        let rendered = templates.render("page.html", &data).unwrap();
+        assert_eq!(rendered.body, "<p>a &lt; b</p>");
+        assert_eq!(rendered.content_type, Mime(TopLevel::Text, SubLevel::Html, vec![(Attr::Charset, Value::Utf8)]));
+
+        let rendered = templates.render("page.txt", &data).unwrap();
+        assert_eq!(rendered.body, "a < b");
+        assert_eq!(rendered.content_type, Mime(TopLevel::Text, SubLevel::Plain, vec![(Attr::Charset, Value::Utf8)]));
+
+        match templates.render("missing.html", &data) {
+            Err(RenderError::NotFound(name)) => assert_eq!(name, "missing.html"),
+            _ => panic!("expected a missing template")
+        }
+    }
+
+    #[test]
+    fn escape_xml_types() {
+        assert!(is_markup(&"image/svg+xml".parse().unwrap()));
+        assert!(is_markup(&"application/xhtml+xml".parse().unwrap()));
+        assert!(is_markup(&"application/Atom+XML".parse().unwrap()));
+        assert!(is_markup(&"text/xml".parse().unwrap()));
+        assert!(!is_markup(&"text/plain".parse().unwrap()));
+        assert!(!is_markup(&"application/json".parse().unwrap()));
+    }
+}
@@ -0,0 +1,359 @@
+//!Distributed tracing.
+//!
+//![`Tracing`][tracing] is a middleware that makes each request a span in a
+//!distributed trace. The trace context is taken from the W3C `traceparent`
+//!header, or from the B3 headers, if the request is part of a trace, and a
+//!new trace is started otherwise. The handler is timed, and the finished
+//!span is given to a [`SpanExporter`][exporter], which sends it to the
+//!tracing system.
+//!
+//!The trace context of the request is put in the filter storage as a
+//![`TraceContext`][trace_context], so it can be passed on to other
+//!services:
+//!
+//!```
+//!use rustful::{Server, Context, Response};
+//!use rustful::tracing::{Tracing, TraceContext, Span};
+//!
+//!fn my_handler(_context: Context, response: Response) {
+//!    let traceparent = response.filter_storage().get::<TraceContext>().map(|trace| trace.traceparent());
+//!    //Add `traceparent` to the requests to other services...
+//!    response.send(format!("traceparent: {:?}", traceparent));
+//!}
+//!
+//!let tracing = Tracing::new("my-service", |span: Span| {
+//!    println!("{} {} took {} µs", span.trace_id, span.name, span.duration.num_microseconds().unwrap_or(0));
+//!});
+//!
+//!let server = Server {
+//!    middleware: vec![Box::new(tracing)],
+//!    ..Server::new(my_handler)
+//!};
+//!```
+//!
+//!The exporter is called from the thread that handled the request, so it
+//!should hand the spans over to another thread if it's slow.
+//!
+//![tracing]: struct.Tracing.html
+//![exporter]: trait.SpanExporter.html
+//![trace_context]: struct.TraceContext.html
+
+
+use time::{self, Duration, SteadyTime, Timespec};
+
+use {Method, StatusCode};
+use context::Context;
+use header::Headers;
+use middleware::{Middleware, Next};
+use response::Response;
+use utils::generate_id;
+
+///The trace context of the current request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceContext {
+    ///The ID of the trace, as 32 hexadecimal digits.
+    pub trace_id: String,
+
+    ///The ID of the request's span, as 16 hexadecimal digits.
+    pub span_id: String,
+
+    ///The ID of the span that made the request, if any.
+    pub parent_id: Option<String>,
+
+    ///If the trace is recorded.
+    pub sampled: bool
+}
+
+impl TraceContext {
+    ///Format the context as a W3C `traceparent` header, with the request's
+    ///span as the parent.
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-{}", self.trace_id, self.span_id, if self.sampled { "01" } else { "00" })
+    }
+
+    ///Format the context as a single B3 header, with the request's span as
+    ///the parent.
+    pub fn b3(&self) -> String {
+        format!("{}-{}-{}", self.trace_id, self.span_id, if self.sampled { "1" } else { "0" })
+    }
+
+    ///Parse the trace context from the W3C `traceparent` header or the B3
+    ///headers, and give the request a new span ID. A new trace is started
+    ///if the `b3` header only contains the sampling decision, such as `b3:
+    ///0`. `None` is returned if the request is not part of a trace.
+    pub fn from_headers(headers: &Headers) -> Option<TraceContext> {
+        let trace = parse_traceparent(headers).or_else(|| parse_b3(headers)).map(|(trace_id, parent_id, sampled)| TraceContext {
+            trace_id: trace_id,
+            span_id: generate_id(8),
+            parent_id: Some(parent_id),
+            sampled: sampled
+        });
+
+        trace.or_else(|| parse_b3_sampling(headers).map(TraceContext::new_trace))
+    }
+
+    ///Start a new trace.
+    pub fn new_trace(sampled: bool) -> TraceContext {
+        TraceContext {
+            trace_id: generate_id(16),
+            span_id: generate_id(8),
+            parent_id: None,
+            sampled: sampled
+        }
+    }
+}
+
+///A finished span.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    ///The ID of the trace.
+    pub trace_id: String,
+
+    ///The ID of the span.
+    pub span_id: String,
+
+    ///The ID of the parent span, if any.
+    pub parent_id: Option<String>,
+
+    ///The name of the service.
+    pub service: String,
+
+    ///The name of the span, which is the method and the path.
+    pub name: String,
+
+    ///The request method.
+    pub method: Method,
+
+    ///The request target, as it was sent by the client.
+    pub request_target: String,
+
+    ///The final status of the response.
+    pub status: StatusCode,
+
+    ///When the span started.
+    pub start: Timespec,
+
+    ///How long the span lasted.
+    pub duration: Duration
+}
+
+///Sends finished spans to a tracing system.
+pub trait SpanExporter: Send + Sync {
+    ///Export a sampled span.
+    fn export(&self, span: Span);
+}
+
+impl<F: Fn(Span) + Send + Sync> SpanExporter for F {
+    fn export(&self, span: Span) {
+        self(span);
+    }
+}
+
+///A middleware that traces requests. See the [module
+///documentation][module] for more information.
+///
+///[module]: index.html
+pub struct Tracing<E> {
+    service: String,
+    exporter: E,
+    sample_new: bool
+}
+
+impl<E: SpanExporter> Tracing<E> {
+    ///Trace the requests to the service `service`, and export the spans
+    ///with `exporter`.
+    pub fn new<S: Into<String>>(service: S, exporter: E) -> Tracing<E> {
+        Tracing {
+            service: service.into(),
+            exporter: exporter,
+            sample_new: true
+        }
+    }
+
+    ///Record the new traces that are started by this service. Traces that
+    ///are started by other services are recorded if the caller says so.
+    ///Default is `true`.
+    pub fn sample_new(mut self, sample: bool) -> Tracing<E> {
+        self.sample_new = sample;
+        self
+    }
+}
+
+impl<E: SpanExporter> Middleware for Tracing<E> {
+    fn around(&self, context: Context, mut response: Response, next: Next) {
+        let trace = TraceContext::from_headers(&context.headers).unwrap_or_else(|| TraceContext::new_trace(self.sample_new));
+        response.filter_storage_mut().insert(trace.clone());
+
+        let method = context.method.clone();
+        let request_target = context.request_target.clone();
+        let name = format!("{} {}", method, context.uri.as_path().map(|path| path.as_utf8_lossy().into_owned()).unwrap_or_else(|| request_target.clone()));
+
+        let start = time::get_time();
+        let started = SteadyTime::now();
+        let status = next.run(context, response);
+
+        if trace.sampled {
+            self.exporter.export(Span {
+                trace_id: trace.trace_id,
+                span_id: trace.span_id,
+                parent_id: trace.parent_id,
+                service: self.service.clone(),
+                name: name,
+                method: method,
+                request_target: request_target,
+                status: status,
+                start: start,
+                duration: SteadyTime::now() - started
+            });
+        }
+    }
+}
+
+//Parses `traceparent` into the trace ID, the parent ID, and the sampled
+//flag.
+fn parse_traceparent(headers: &Headers) -> Option<(String, String, bool)> {
+    let value = match first_header(headers, "traceparent") {
+        Some(value) => value,
+        None => return None
+    };
+
+    //The fields are lowercase, as required by the specification.
+    let parts: Vec<&str> = value.split('-').collect();
+    if parts.len() < 4 || parts[0].len() != 2 || !is_lower_hex(parts[0]) || parts[0] == "ff" {
+        return None;
+    }
+
+    //Later versions may have more fields.
+    if parts[0] == "00" && parts.len() != 4 {
+        return None;
+    }
+
+    let (trace_id, parent_id, flags) = (parts[1], parts[2], parts[3]);
+    if !is_id(trace_id, 32) || !is_id(parent_id, 16) || flags.len() != 2 {
+        return None;
+    }
+    if !is_lower_hex(trace_id) || !is_lower_hex(parent_id) || !is_lower_hex(flags) {
+        return None;
+    }
+
+    let sampled = u8::from_str_radix(flags, 16).map(|flags| flags & 1 == 1).unwrap_or(false);
+    Some((trace_id.to_owned(), parent_id.to_owned(), sampled))
+}
+
+//Parses the single `b3` header, or the multiple `X-B3-*` headers.
+fn parse_b3(headers: &Headers) -> Option<(String, String, bool)> {
+    let (trace_id, parent_id, sampled) = match first_header(headers, "b3") {
+        Some(value) => {
+            let parts: Vec<&str> = value.split('-').collect();
+            if parts.len() < 2 {
+                return None;
+            }
+            (parts[0].to_owned(), parts[1].to_owned(), parts.get(2).map(|s| *s != "0").unwrap_or(true))
+        },
+        None => {
+            let trace_id = match first_header(headers, "X-B3-TraceId") {
+                Some(id) => id,
+                None => return None
+            };
+            let parent_id = match first_header(headers, "X-B3-SpanId") {
+                Some(id) => id,
+                None => return None
+            };
+            let debug = first_header(headers, "X-B3-Flags").map(|flags| flags == "1").unwrap_or(false);
+            let sampled = match first_header(headers, "X-B3-Sampled") {
+                Some(sampled) => sampled == "1" || sampled == "true",
+                None => true
+            };
+            (trace_id, parent_id, sampled || debug)
+        }
+    };
+
+    //64 bit trace IDs are padded to 128 bits.
+    let trace_id = if trace_id.len() == 16 { format!("{:0>32}", trace_id) } else { trace_id };
+    if !is_id(&trace_id, 32) || !is_id(&parent_id, 16) {
+        return None;
+    }
+
+    Some((trace_id.to_lowercase(), parent_id.to_lowercase(), sampled))
+}
+
+//Parses a `b3` header without IDs, which only contains the sampling
+//decision.
+fn parse_b3_sampling(headers: &Headers) -> Option<bool> {
+    match first_header(headers, "b3").as_ref().map(|value| &**value) {
+        Some("0") => Some(false),
+        Some("1") | Some("d") => Some(true),
+        _ => None
+    }
+}
+
+fn first_header(headers: &Headers, name: &str) -> Option<String> {
+    headers.get_raw(name)
+        .and_then(|lines| lines.first())
+        .and_then(|line| ::std::str::from_utf8(line).ok())
+        .map(|line| line.trim().to_owned())
+}
+
+fn is_hex(value: &str) -> bool {
+    value.bytes().all(|b| match b {
+        b'0'...b'9' | b'a'...b'f' | b'A'...b'F' => true,
+        _ => false
+    })
+}
+
+fn is_lower_hex(value: &str) -> bool {
+    value.bytes().all(|b| match b {
+        b'0'...b'9' | b'a'...b'f' => true,
+        _ => false
+    })
+}
+
+//Checks that an ID has the right length, and is not all zeros.
+fn is_id(id: &str, length: usize) -> bool {
+    id.len() == length && is_hex(id) && id.bytes().any(|b| b != b'0')
+}
+
+
+#[cfg(test)]
+mod test {
+    use header::Headers;
+    use super::TraceContext;
+
+    #[test]
+    fn parse_trace_headers() {
+        let mut headers = Headers::new();
+        headers.set_raw("traceparent", vec![b"00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".to_vec()]);
+        let trace = TraceContext::from_headers(&headers).expect("traceparent was not parsed");
+        assert_eq!(trace.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(trace.parent_id, Some("00f067aa0ba902b7".into()));
+        assert!(trace.sampled);
+        assert_eq!(trace.span_id.len(), 16);
+        assert_eq!(trace.traceparent(), format!("00-4bf92f3577b34da6a3ce929d0e0e4736-{}-01", trace.span_id));
+
+        let mut headers = Headers::new();
+        headers.set_raw("b3", vec![b"a3ce929d0e0e4736-00f067aa0ba902b7-0".to_vec()]);
+        let trace = TraceContext::from_headers(&headers).expect("b3 was not parsed");
+        assert_eq!(trace.trace_id, "0000000000000000a3ce929d0e0e4736");
+        assert!(!trace.sampled);
+
+        let mut headers = Headers::new();
+        headers.set_raw("X-B3-TraceId", vec![b"4bf92f3577b34da6a3ce929d0e0e4736".to_vec()]);
+        headers.set_raw("X-B3-SpanId", vec![b"00f067aa0ba902b7".to_vec()]);
+        let trace = TraceContext::from_headers(&headers).expect("X-B3 was not parsed");
+        assert!(trace.sampled);
+
+        let mut headers = Headers::new();
+        headers.set_raw("traceparent", vec![b"00-00000000000000000000000000000000-00f067aa0ba902b7-01".to_vec()]);
+        assert!(TraceContext::from_headers(&headers).is_none());
+
+        let mut headers = Headers::new();
+        headers.set_raw("traceparent", vec![b"00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01".to_vec()]);
+        assert!(TraceContext::from_headers(&headers).is_none());
+
+        let mut headers = Headers::new();
+        headers.set_raw("b3", vec![b"0".to_vec()]);
+        let trace = TraceContext::from_headers(&headers).expect("b3 was not parsed");
+        assert!(!trace.sampled);
+        assert_eq!(trace.parent_id, None);
+    }
+}
@@ -1,5 +1,11 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use time;
+
 use url::percent_encoding::percent_decode;
 use context::Parameters;
+use header::{Headers, ContentLength};
 
 pub fn parse_parameters(source: &[u8]) -> Parameters {
     let mut parameters = Parameters::new();
@@ -27,10 +33,351 @@ pub fn parse_parameters(source: &[u8]) -> Parameters {
     parameters
 }
 
+//Splits `s` at `separator`, unless it's within a quoted string or a
+//`<...>` enclosed URI. The parts are trimmed, but may be empty.
+pub fn split_outside(s: &str, separator: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut in_uri = false;
+
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+
+        match c {
+            '\\' if quoted => escaped = true,
+            '"' if !in_uri => quoted = !quoted,
+            '<' if !quoted => in_uri = true,
+            '>' if !quoted => in_uri = false,
+            c if c == separator && !quoted && !in_uri => {
+                parts.push(s[start..i].trim());
+                start = i + c.len_utf8();
+            },
+            _ => {}
+        }
+    }
+
+    parts.push(s[start..].trim());
+    parts
+}
+
+//Parses `name` or `name=value`, where the value may be a quoted string. The
+//name is converted to lower case.
+pub fn parse_parameter(s: &str) -> Option<(String, Option<String>)> {
+    let mut parts = s.splitn(2, '=');
+    let name = parts.next().map(|n| n.trim()).unwrap_or("");
+
+    if name.is_empty() {
+        None
+    } else {
+        Some((name.to_lowercase(), parts.next().map(|v| unquote(v.trim()))))
+    }
+}
+
+//Removes the surrounding quotes and escape characters from a quoted string.
+//Anything else is returned as it is.
+pub fn unquote(s: &str) -> String {
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        let mut unquoted = String::with_capacity(s.len() - 2);
+        let mut escaped = false;
+
+        for c in s[1..s.len() - 1].chars() {
+            if escaped {
+                unquoted.push(c);
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else {
+                unquoted.push(c);
+            }
+        }
+
+        unquoted
+    } else {
+        s.to_owned()
+    }
+}
+
+pub fn is_token_char(b: u8) -> bool {
+    match b {
+        b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'*' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => true,
+        b => (b >= b'0' && b <= b'9') || (b >= b'a' && b <= b'z') || (b >= b'A' && b <= b'Z')
+    }
+}
+
+//Percent encodes everything except unreserved characters.
+pub fn encode_path_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for &byte in segment.as_bytes() {
+        match byte {
+            b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            byte => encoded.push_str(&format!("%{:02X}", byte))
+        }
+    }
+    encoded
+}
+
+//Collects the comma separated values of the header `name`, from all of
+//its lines.
+pub fn header_list<'h>(headers: &'h Headers, name: &str) -> Vec<&'h str> {
+    headers.get_raw(name).map(|lines| {
+        lines.iter()
+            .filter_map(|line| ::std::str::from_utf8(line).ok())
+            .flat_map(|line| line.split(','))
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+            .collect()
+    }).unwrap_or_else(|| vec![])
+}
+
+//Checks if `s` is a valid token, as defined in RFC 7230.
+pub fn is_token(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(is_token_char)
+}
+
+//Writes `value` as it is if it's a token, or as a quoted string otherwise.
+pub fn write_value(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    if is_token(value) {
+        f.write_str(value)
+    } else {
+        try!(f.write_str("\""));
+        for c in value.chars() {
+            if c == '"' || c == '\\' {
+                try!(f.write_str("\\"));
+            }
+            try!(write!(f, "{}", c));
+        }
+        f.write_str("\"")
+    }
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2
+];
+
+//Calculates the SHA-256 digest of `data`, as defined in FIPS 180-4.
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19
+    ];
+
+    let bit_length = (data.len() as u64).wrapping_mul(8);
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    for i in 0..8 {
+        message.push((bit_length >> (56 - 8 * i)) as u8);
+    }
+
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for i in 0..16 {
+            w[i] = ((chunk[4 * i] as u32) << 24) | ((chunk[4 * i + 1] as u32) << 16) |
+                ((chunk[4 * i + 2] as u32) << 8) | chunk[4 * i + 3] as u32;
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+
+        let mut v = h;
+        for i in 0..64 {
+            let s1 = v[4].rotate_right(6) ^ v[4].rotate_right(11) ^ v[4].rotate_right(25);
+            let ch = (v[4] & v[5]) ^ (!v[4] & v[6]);
+            let t1 = v[7].wrapping_add(s1).wrapping_add(ch).wrapping_add(SHA256_K[i]).wrapping_add(w[i]);
+            let s0 = v[0].rotate_right(2) ^ v[0].rotate_right(13) ^ v[0].rotate_right(22);
+            let maj = (v[0] & v[1]) ^ (v[0] & v[2]) ^ (v[1] & v[2]);
+            let t2 = s0.wrapping_add(maj);
+
+            v = [t1.wrapping_add(t2), v[0], v[1], v[2], v[3].wrapping_add(t1), v[4], v[5], v[6]];
+        }
+
+        for i in 0..8 {
+            h[i] = h[i].wrapping_add(v[i]);
+        }
+    }
+
+    let mut digest = [0u8; 32];
+    for i in 0..8 {
+        digest[4 * i] = (h[i] >> 24) as u8;
+        digest[4 * i + 1] = (h[i] >> 16) as u8;
+        digest[4 * i + 2] = (h[i] >> 8) as u8;
+        digest[4 * i + 3] = h[i] as u8;
+    }
+
+    digest
+}
+
+//Calculates the HMAC-SHA256 of `message`, as defined in RFC 2104.
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    if key.len() > 64 {
+        block[..32].copy_from_slice(&sha256(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner: Vec<u8> = block.iter().map(|b| b ^ 0x36).collect();
+    inner.extend_from_slice(message);
+
+    let mut outer: Vec<u8> = block.iter().map(|b| b ^ 0x5c).collect();
+    outer.extend_from_slice(&sha256(&inner));
+
+    sha256(&outer)
+}
+
+//Compares `a` and `b` in a time that only depends on their lengths, so
+//signatures can't be guessed one byte at a time.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+//Calculates the 32 bit FNV-1a hash of `bytes`.
+pub fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c9dc5, |hash, &b| (hash ^ b as u32).wrapping_mul(0x01000193))
+}
+
+//Formats `bytes` as lowercase hexadecimal.
+pub fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        hex.push_str(&format!("{:02x}", byte));
+    }
+    hex
+}
+
+static ID_COUNTER: AtomicUsize = ATOMIC_USIZE_INIT;
+
+//Generates a unique, lowercase hexadecimal ID of `bytes` bytes, at most 32,
+//from the time and a counter.
+pub fn generate_id(bytes: usize) -> String {
+    let now = time::get_time();
+    let count = ID_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let seed = format!("{}.{}.{}", now.sec, now.nsec, count);
+    to_hex(&sha256(seed.as_bytes())[..bytes])
+}
+
+//Removes the `Content-Length` header, whether it's typed or raw, since
+//both forms may be present.
+pub fn remove_content_length(headers: &mut Headers) {
+    headers.remove::<ContentLength>();
+    headers.remove_raw("content-length");
+}
+
+//Adds `name` to the `Vary` header, unless it's already there, or the
+//header is `*`.
+pub fn add_vary(headers: &mut Headers, name: &str) {
+    let present = header_list(headers, "Vary").into_iter().any(|value| {
+        value == "*" || value.to_lowercase() == name.to_lowercase()
+    });
+    if present {
+        return;
+    }
+
+    let mut vary = headers.get_raw("Vary").map(|v| v.to_vec()).unwrap_or_else(Vec::new);
+    vary.push(name.as_bytes().to_vec());
+    headers.set_raw("Vary", vary);
+}
+
+//Replaces each `{{name}}` in `template` with the value from `lookup`.
+pub fn fill_template<F: Fn(&str) -> Option<String>>(template: &str, lookup: &F) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start + 2..].find("}}") {
+            Some(end) => start + 2 + end,
+            None => break
+        };
+
+        rendered.push_str(&rest[..start]);
+        if let Some(value) = lookup(rest[start + 2..end].trim()) {
+            rendered.push_str(&value);
+        }
+        rest = &rest[end + 2..];
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+const BASE64_CHARS: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+//Encodes `bytes` as standard base64, with padding.
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity((bytes.len() + 2) / 3 * 4);
+
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = ((b[0] as usize) << 16) | ((b[1] as usize) << 8) | b[2] as usize;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_CHARS[(n >> (18 - 6 * i)) & 0x3f] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+//Decodes standard base64. Padding is optional.
+pub fn base64_decode(encoded: &[u8]) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(encoded.len() / 4 * 3);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    let mut padding = false;
+
+    for &c in encoded {
+        let value = match c {
+            b'A'...b'Z' => c - b'A',
+            b'a'...b'z' => c - b'a' + 26,
+            b'0'...b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => {
+                padding = true;
+                continue;
+            },
+            _ => return None
+        };
+
+        if padding {
+            return None;
+        }
+
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+
+    Some(bytes)
+}
+
 #[cfg(test)]
 mod test {
     use std::borrow::ToOwned;
-    use super::parse_parameters;
+    use super::{parse_parameters, split_outside, parse_parameter, unquote, sha256, hmac_sha256, to_hex, fill_template, add_vary};
+    use header::{Headers, ContentLength};
 
     #[test]
     fn parsing_parameters() {
@@ -64,4 +411,57 @@ mod test {
         assert_eq!(parameters.get_raw(""), Some(&aa));
         assert_eq!(parameters.get_raw("ab"), Some(&ab));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn splitting_outside_quotes_and_uris() {
+        let parts = split_outside("<a,b>; rel=\"x, y\", <c>", ',');
+        assert_eq!(parts, vec!["<a,b>; rel=\"x, y\"", "<c>"]);
+    }
+
+    #[test]
+    fn parsing_header_parameters() {
+        assert_eq!(parse_parameter("Rel=next"), Some(("rel".to_owned(), Some("next".to_owned()))));
+        assert_eq!(parse_parameter("title=\"a \\\"b\\\"\""), Some(("title".to_owned(), Some("a \"b\"".to_owned()))));
+        assert_eq!(parse_parameter("flag"), Some(("flag".to_owned(), None)));
+        assert_eq!(parse_parameter("=oops"), None);
+    }
+
+    #[test]
+    fn unquoting_plain_values() {
+        assert_eq!(unquote("plain"), "plain");
+        assert_eq!(unquote("\"\""), "");
+    }
+
+    #[test]
+    fn sha256_digests() {
+        assert_eq!(to_hex(&sha256(b"")), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(to_hex(&sha256(b"abc")), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+
+    #[test]
+    fn hmac_sha256_signatures() {
+        let signature = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(to_hex(&signature), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[test]
+    fn filling_templates() {
+        let lookup = |name: &str| if name == "id" { Some("5".to_owned()) } else { None };
+        assert_eq!(fill_template("user {{id}}{{ id }}", &lookup), "user 55");
+        assert_eq!(fill_template("{{name}}!", &lookup), "!");
+        assert_eq!(fill_template("{{id", &lookup), "{{id");
+    }
+
+    #[test]
+    fn adding_vary() {
+        let mut headers = Headers::new();
+        add_vary(&mut headers, "Origin");
+        add_vary(&mut headers, "Accept-Encoding");
+        add_vary(&mut headers, "accept-encoding");
+        assert_eq!(headers.get_raw("Vary"), Some(&[b"Origin".to_vec(), b"Accept-Encoding".to_vec()][..]));
+
+        headers.set_raw("Vary", vec![b"*".to_vec()]);
+        add_vary(&mut headers, "Origin");
+        assert_eq!(headers.get_raw("Vary"), Some(&[b"*".to_vec()][..]));
+    }
+}
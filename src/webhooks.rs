@@ -0,0 +1,712 @@
+//!Outbound webhook delivery.
+//!
+//![`Webhooks`][webhooks] sends events to registered HTTP endpoints, as JSON
+//!`POST` requests. Each request carries the event name and a delivery ID in
+//!the `X-Webhook-Event` and `X-Webhook-Id` headers and, if the endpoint has a
+//!secret, an HMAC-SHA256 signature of the payload in `X-Webhook-Signature`:
+//!
+//!```text
+//!X-Webhook-Signature: sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843
+//!```
+//!
+//!Failed deliveries are retried with an exponential backoff, and deliveries
+//!that still fail after the last attempt, or that don't fit in the retry
+//!queue, are moved to a dead letter list and logged as errors. The
+//!deliveries and the retries are sent from one background thread, so
+//!`Webhooks` can be used directly as the server's event sink:
+//!
+//!```no_run
+//!#[macro_use]
+//!extern crate rustful;
+//!use rustful::{Server, TreeRouter, Context, Response};
+//!use rustful::events::Event;
+//!use rustful::webhooks::{Webhooks, Endpoint};
+//!
+//!fn create_order(context: Context, mut response: Response) {
+//!    //...create the order...
+//!    response.emit(Event::new("order.created", r#"{"id": 42}"#));
+//!    response.send("order 42 was created");
+//!}
+//!
+//!# fn main() {
+//!let webhooks = Webhooks::new();
+//!webhooks.register(Endpoint::new("http://example.com/hooks").secret("s3cret").event("order.created"));
+//!
+//!let router = insert_routes! {
+//!    TreeRouter::new() => {
+//!        "orders" => Post: create_order,
+//!        "admin/webhooks" => Get: webhooks.status_handler()
+//!    }
+//!};
+//!
+//!let server = Server {
+//!    event_sink: Some(Box::new(webhooks)),
+//!    ..Server::new(router)
+//!};
+//!# }
+//!```
+//!
+//!The connections to plain HTTP endpoints have connect, read and write
+//!timeouts, so a slow endpoint can't hold back the other deliveries for
+//!long. HTTPS endpoints are connected to using hyper's default connector,
+//!which doesn't have any timeouts.
+//!
+//![webhooks]: struct.Webhooks.html
+
+use std::cmp;
+use std::collections::{VecDeque, BinaryHeap};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration as StdDuration;
+
+use hyper;
+use hyper::Client;
+use hyper::net::{NetworkConnector, NetworkStream, ContextVerifier};
+use time::{Duration, SteadyTime};
+
+use context::Context;
+use events::{Event, Delivery, EventSink};
+use handler::Handler;
+use header::{Headers, ContentType};
+use log::{Log, StdOut};
+use mime::{Mime, TopLevel, SubLevel};
+use response::Response;
+use utils;
+
+///A registered webhook receiver.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Endpoint {
+    ///The URL where the events are posted.
+    pub url: String,
+
+    ///An optional secret for signing the payloads.
+    pub secret: Option<String>,
+
+    ///The names of the events that are sent to the endpoint. All events
+    ///are sent if it's empty.
+    pub events: Vec<String>
+}
+
+impl Endpoint {
+    ///Create an endpoint that receives all events, without signatures.
+    pub fn new<S: Into<String>>(url: S) -> Endpoint {
+        Endpoint {
+            url: url.into(),
+            secret: None,
+            events: vec![]
+        }
+    }
+
+    ///Sign the payloads with `secret`.
+    pub fn secret<S: Into<String>>(mut self, secret: S) -> Endpoint {
+        self.secret = Some(secret.into());
+        self
+    }
+
+    ///Subscribe to an event. The endpoint will only receive the events it's
+    ///subscribed to, or all events if it's not subscribed to any.
+    pub fn event<S: Into<String>>(mut self, name: S) -> Endpoint {
+        self.events.push(name.into());
+        self
+    }
+
+    ///Check if the endpoint should receive events with the name `name`.
+    pub fn accepts(&self, name: &str) -> bool {
+        self.events.is_empty() || self.events.iter().any(|event| event == name)
+    }
+
+    ///Create the `X-Webhook-Signature` value for `payload`, if the endpoint
+    ///has a secret.
+    pub fn sign(&self, payload: &str) -> Option<String> {
+        self.secret.as_ref().map(|secret| {
+            format!("sha256={}", utils::to_hex(&utils::hmac_sha256(secret.as_bytes(), payload.as_bytes())))
+        })
+    }
+}
+
+///The state of a delivery.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    ///The delivery is waiting for its first or next attempt.
+    Pending,
+
+    ///The endpoint accepted the event.
+    Delivered,
+
+    ///Every attempt failed and the delivery was moved to the dead letters.
+    Failed
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            State::Pending => f.write_str("pending"),
+            State::Delivered => f.write_str("delivered"),
+            State::Failed => f.write_str("failed")
+        }
+    }
+}
+
+///The status of an event delivery to an endpoint.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeliveryStatus {
+    ///The delivery ID, as sent in `X-Webhook-Id`.
+    pub id: usize,
+
+    ///The endpoint URL.
+    pub url: String,
+
+    ///The delivered event.
+    pub event: Event,
+
+    ///The current state of the delivery.
+    pub state: State,
+
+    ///The number of attempts that have been made.
+    pub attempts: u32,
+
+    ///A description of why the last attempt failed, if it did.
+    pub last_error: Option<String>
+}
+
+///Settings for `Webhooks`.
+pub struct Config {
+    ///The maximum number of attempts for each delivery. Default is 5.
+    pub max_attempts: u32,
+
+    ///The time to wait before the first retry. It's doubled for each retry
+    ///after that. Default is 1 second.
+    pub backoff: Duration,
+
+    ///The maximum number of deliveries that are waiting to be retried.
+    ///Deliveries that fail when it's full are moved to the dead letters.
+    ///Default is 1000.
+    pub max_retries: usize,
+
+    ///The time to wait for a connection to an endpoint. Default is 5
+    ///seconds.
+    pub connect_timeout: Duration,
+
+    ///The time to wait for each read and write, after the connection has
+    ///been made. Default is 10 seconds.
+    pub read_timeout: Duration,
+
+    ///The number of deliveries to remember, for the status handler. Default
+    ///is 100.
+    pub history_size: usize,
+
+    ///Where dead letters are reported. Default is `StdOut`.
+    pub log: Box<Log>
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            max_attempts: 5,
+            backoff: Duration::seconds(1),
+            max_retries: 1000,
+            connect_timeout: Duration::seconds(5),
+            read_timeout: Duration::seconds(10),
+            history_size: 100,
+            log: Box::new(StdOut)
+        }
+    }
+}
+
+///An outbound webhook dispatcher.
+///
+///Deliveries are queued and sent from a background thread, which stops
+///when the dispatcher is dropped. Deliveries that are waiting to be retried
+///at that point are dropped.
+pub struct Webhooks {
+    shared: Arc<Shared>,
+    sender: Mutex<Sender<Job>>
+}
+
+impl Webhooks {
+    ///Create a dispatcher with the default settings.
+    pub fn new() -> Webhooks {
+        Webhooks::with_config(Config::default())
+    }
+
+    ///Create a dispatcher with custom settings.
+    pub fn with_config(config: Config) -> Webhooks {
+        let shared = Arc::new(Shared::new(config));
+
+        let (sender, receiver) = channel();
+        let worker_shared = shared.clone();
+        thread::spawn(move || run_worker(worker_shared, receiver));
+
+        Webhooks {
+            shared: shared,
+            sender: Mutex::new(sender)
+        }
+    }
+
+    ///Register an endpoint.
+    pub fn register(&self, endpoint: Endpoint) {
+        if let Ok(mut endpoints) = self.shared.endpoints.write() {
+            endpoints.push(endpoint);
+        }
+    }
+
+    ///Remove every endpoint with the URL `url`. Deliveries that have already
+    ///been queued will still be sent.
+    pub fn unregister(&self, url: &str) {
+        if let Ok(mut endpoints) = self.shared.endpoints.write() {
+            endpoints.retain(|endpoint| endpoint.url != url);
+        }
+    }
+
+    ///Get the registered endpoints.
+    pub fn endpoints(&self) -> Vec<Endpoint> {
+        self.shared.endpoints.read().map(|endpoints| endpoints.clone()).unwrap_or_else(|_| vec![])
+    }
+
+    ///Queue `event` for delivery to every endpoint that accepts it. The IDs
+    ///of the deliveries are returned.
+    pub fn send(&self, event: Event) -> Vec<usize> {
+        let endpoints: Vec<Endpoint> = self.endpoints().into_iter().filter(|e| e.accepts(&event.name)).collect();
+        let mut ids = Vec::with_capacity(endpoints.len());
+
+        for endpoint in endpoints {
+            let id = self.shared.next_id.fetch_add(1, Ordering::SeqCst);
+            self.shared.record(DeliveryStatus {
+                id: id,
+                url: endpoint.url.clone(),
+                event: event.clone(),
+                state: State::Pending,
+                attempts: 0,
+                last_error: None
+            });
+
+            let job = Job {
+                id: id,
+                endpoint: endpoint,
+                event: event.clone()
+            };
+
+            if let Ok(sender) = self.sender.lock() {
+                //The worker only stops when the dispatcher is dropped.
+                let _ = sender.send(job);
+            }
+
+            ids.push(id);
+        }
+
+        ids
+    }
+
+    ///Get the status of a recent delivery.
+    pub fn status(&self, id: usize) -> Option<DeliveryStatus> {
+        self.shared.history.lock().ok().and_then(|history| history.iter().find(|d| d.id == id).cloned())
+    }
+
+    ///Get the status of the recent deliveries, oldest first.
+    pub fn deliveries(&self) -> Vec<DeliveryStatus> {
+        self.shared.history.lock().map(|history| history.iter().cloned().collect()).unwrap_or_else(|_| vec![])
+    }
+
+    ///Take the deliveries that failed every attempt, leaving the list empty.
+    pub fn take_dead_letters(&self) -> Vec<DeliveryStatus> {
+        self.shared.dead_letters.lock().map(|mut dead| dead.drain(..).collect()).unwrap_or_else(|_| vec![])
+    }
+
+    ///Create an admin handler that lists the recent deliveries and the dead
+    ///letters as plain text, one delivery per line. The list can be limited
+    ///to one state using the `state` query parameter, such as
+    ///`?state=failed`.
+    pub fn status_handler(&self) -> StatusHandler {
+        StatusHandler {
+            shared: self.shared.clone()
+        }
+    }
+}
+
+impl EventSink for Webhooks {
+    fn deliver(&self, delivery: Delivery) {
+        //Events from failed requests did never really happen.
+        if delivery.status.is_success() {
+            for event in delivery.events {
+                self.send(event);
+            }
+        }
+    }
+}
+
+///An admin handler that lists webhook deliveries. It's created using
+///`Webhooks::status_handler`.
+pub struct StatusHandler {
+    shared: Arc<Shared>
+}
+
+impl Handler for StatusHandler {
+    fn handle_request(&self, context: Context, mut response: Response) {
+        let state = context.query.get("state");
+        let mut body = String::new();
+
+        let history: Vec<DeliveryStatus> = self.shared.history.lock().map(|h| h.iter().cloned().collect()).unwrap_or_else(|_| vec![]);
+        let dead_letters = self.shared.dead_letters.lock().map(|d| d.clone()).unwrap_or_else(|_| vec![]);
+
+        let mut deliveries: Vec<DeliveryStatus> = dead_letters;
+        for delivery in history {
+            if !deliveries.iter().any(|d| d.id == delivery.id) {
+                deliveries.push(delivery);
+            }
+        }
+        deliveries.sort_by(|a, b| a.id.cmp(&b.id));
+
+        for delivery in deliveries {
+            if state.as_ref().map(|s| *s == delivery.state.to_string()).unwrap_or(true) {
+                body.push_str(&format!(
+                    "{} {} {} {} attempts={}",
+                    delivery.id,
+                    delivery.state,
+                    delivery.event.name,
+                    delivery.url,
+                    delivery.attempts
+                ));
+
+                if let Some(ref error) = delivery.last_error {
+                    body.push_str(&format!(" error={:?}", error));
+                }
+
+                body.push('\n');
+            }
+        }
+
+        response.headers_mut().set(ContentType(Mime(TopLevel::Text, SubLevel::Plain, vec![])));
+        response.send(body);
+    }
+}
+
+struct Shared {
+    config: Config,
+    endpoints: RwLock<Vec<Endpoint>>,
+    history: Mutex<VecDeque<DeliveryStatus>>,
+    dead_letters: Mutex<Vec<DeliveryStatus>>,
+    next_id: AtomicUsize
+}
+
+impl Shared {
+    fn new(config: Config) -> Shared {
+        Shared {
+            config: config,
+            endpoints: RwLock::new(vec![]),
+            history: Mutex::new(VecDeque::new()),
+            dead_letters: Mutex::new(vec![]),
+            next_id: AtomicUsize::new(1)
+        }
+    }
+
+    fn record(&self, status: DeliveryStatus) {
+        if status.state == State::Failed {
+            self.config.log.error(&format!(
+                "webhook delivery {} of {} to {} failed after {} attempts: {}",
+                status.id,
+                status.event.name,
+                status.url,
+                status.attempts,
+                status.last_error.as_ref().map(|e| &**e).unwrap_or("unknown error")
+            ));
+
+            if let Ok(mut dead_letters) = self.dead_letters.lock() {
+                dead_letters.push(status.clone());
+            }
+        }
+
+        if let Ok(mut history) = self.history.lock() {
+            if let Some(existing) = history.iter_mut().find(|d| d.id == status.id) {
+                *existing = status;
+                return;
+            }
+
+            history.push_back(status);
+            while history.len() > self.config.history_size {
+                history.pop_front();
+            }
+        }
+    }
+}
+
+struct Job {
+    id: usize,
+    endpoint: Endpoint,
+    event: Event
+}
+
+//A delivery that is waiting to be retried. The earliest retry is the
+//greatest, so it's first in a `BinaryHeap`.
+struct Retry {
+    due: SteadyTime,
+    attempt: u32,
+    job: Job
+}
+
+impl PartialEq for Retry {
+    fn eq(&self, other: &Retry) -> bool {
+        self.due == other.due && self.job.id == other.job.id
+    }
+}
+
+impl Eq for Retry {}
+
+impl PartialOrd for Retry {
+    fn partial_cmp(&self, other: &Retry) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Retry {
+    fn cmp(&self, other: &Retry) -> cmp::Ordering {
+        other.due.cmp(&self.due).then_with(|| other.job.id.cmp(&self.job.id))
+    }
+}
+
+fn run_worker(shared: Arc<Shared>, jobs: Receiver<Job>) {
+    let clients = Clients::new(&shared.config);
+    let mut retries = BinaryHeap::new();
+
+    loop {
+        while retries.peek().map(|retry: &Retry| retry.due <= SteadyTime::now()).unwrap_or(false) {
+            if let Some(retry) = retries.pop() {
+                attempt(&shared, &clients, &mut retries, retry.job, retry.attempt);
+            }
+        }
+
+        //Wait for a new delivery, or until the next retry is due.
+        let job = match retries.peek().map(|retry| retry.due - SteadyTime::now()) {
+            Some(wait) => match jobs.recv_timeout(wait.to_std().unwrap_or(StdDuration::from_millis(0))) {
+                Ok(job) => job,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return
+            },
+            None => match jobs.recv() {
+                Ok(job) => job,
+                Err(_) => return
+            }
+        };
+
+        attempt(&shared, &clients, &mut retries, job, 1);
+    }
+}
+
+//Makes an attempt to deliver `job`, and queues a retry if it fails and
+//there are attempts left.
+fn attempt(shared: &Shared, clients: &Clients, retries: &mut BinaryHeap<Retry>, job: Job, attempt: u32) {
+    let error = match clients.post(&job) {
+        Ok(()) => return shared.record(job.status(State::Delivered, attempt, None)),
+        Err(e) => e
+    };
+
+    if attempt >= shared.config.max_attempts {
+        shared.record(job.status(State::Failed, attempt, Some(error)));
+    } else if retries.len() >= shared.config.max_retries {
+        shared.record(job.status(State::Failed, attempt, Some(format!("{} (the retry queue is full)", error))));
+    } else {
+        shared.record(job.status(State::Pending, attempt, Some(error)));
+        let backoff = shared.config.backoff * (1 << cmp::min(attempt - 1, 16));
+        retries.push(Retry {
+            due: SteadyTime::now() + backoff,
+            attempt: attempt + 1,
+            job: job
+        });
+    }
+}
+
+//One client for plain HTTP endpoints, with timeouts, and one for the rest.
+struct Clients {
+    http: Client,
+    other: Client
+}
+
+impl Clients {
+    fn new(config: &Config) -> Clients {
+        let connector = TimeoutConnector {
+            connect_timeout: config.connect_timeout.to_std().unwrap_or(StdDuration::from_millis(0)),
+            read_timeout: config.read_timeout.to_std().unwrap_or(StdDuration::from_millis(0))
+        };
+
+        Clients {
+            http: Client::with_connector(connector),
+            other: Client::new()
+        }
+    }
+
+    fn post(&self, job: &Job) -> Result<(), String> {
+        let mut headers = Headers::new();
+        headers.set(ContentType(Mime(TopLevel::Application, SubLevel::Json, vec![])));
+        headers.set_raw("X-Webhook-Event", vec![job.event.name.clone().into_bytes()]);
+        headers.set_raw("X-Webhook-Id", vec![job.id.to_string().into_bytes()]);
+        if let Some(signature) = job.endpoint.sign(&job.event.payload) {
+            headers.set_raw("X-Webhook-Signature", vec![signature.into_bytes()]);
+        }
+
+        let client = if job.endpoint.url.starts_with("http://") { &self.http } else { &self.other };
+        let result = client.post(&job.endpoint.url[..])
+            .headers(headers)
+            .body(&job.event.payload[..])
+            .send();
+
+        match result {
+            Ok(ref response) if response.status.is_success() => Ok(()),
+            Ok(response) => Err(format!("the endpoint responded with {}", response.status)),
+            Err(e) => Err(e.to_string())
+        }
+    }
+}
+
+//Connects to plain HTTP endpoints, with timeouts. Zero means no timeout.
+struct TimeoutConnector {
+    connect_timeout: StdDuration,
+    read_timeout: StdDuration
+}
+
+impl NetworkConnector for TimeoutConnector {
+    type Stream = TimeoutStream;
+
+    fn connect(&self, host: &str, port: u16, scheme: &str) -> hyper::Result<TimeoutStream> {
+        if scheme != "http" {
+            return Err(hyper::Error::Io(io::Error::new(io::ErrorKind::InvalidInput, format!("unsupported scheme: {}", scheme))));
+        }
+
+        let zero = StdDuration::from_millis(0);
+        let mut error = io::Error::new(io::ErrorKind::NotFound, format!("could not resolve {}", host));
+        for address in try!((host, port).to_socket_addrs()) {
+            let connected = if self.connect_timeout == zero {
+                TcpStream::connect(address)
+            } else {
+                TcpStream::connect_timeout(&address, self.connect_timeout)
+            };
+
+            match connected {
+                Ok(stream) => {
+                    if self.read_timeout != zero {
+                        try!(stream.set_read_timeout(Some(self.read_timeout)));
+                        try!(stream.set_write_timeout(Some(self.read_timeout)));
+                    }
+                    return Ok(TimeoutStream(stream));
+                },
+                Err(e) => error = e
+            }
+        }
+
+        Err(hyper::Error::Io(error))
+    }
+
+    fn set_ssl_verifier(&mut self, _verifier: ContextVerifier) {}
+}
+
+struct TimeoutStream(TcpStream);
+
+impl Read for TimeoutStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for TimeoutStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl NetworkStream for TimeoutStream {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        self.0.peer_addr()
+    }
+}
+
+impl Job {
+    fn status(&self, state: State, attempts: u32, last_error: Option<String>) -> DeliveryStatus {
+        DeliveryStatus {
+            id: self.id,
+            url: self.endpoint.url.clone(),
+            event: self.event.clone(),
+            state: state,
+            attempts: attempts,
+            last_error: last_error
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BinaryHeap;
+    use time::{Duration, SteadyTime};
+    use events::Event;
+    use log::Quiet;
+    use super::{Endpoint, Config, Shared, Clients, Job, Retry, State, attempt};
+
+    fn job(id: usize) -> Job {
+        Job {
+            id: id,
+            //Nothing should be listening on port 1.
+            endpoint: Endpoint::new("http://127.0.0.1:1/"),
+            event: Event::new("a", "{}")
+        }
+    }
+
+    #[test]
+    fn accept_events() {
+        let all = Endpoint::new("http://example.com");
+        assert!(all.accepts("a"));
+
+        let some = Endpoint::new("http://example.com").event("a").event("b");
+        assert!(some.accepts("b"));
+        assert!(!some.accepts("c"));
+    }
+
+    #[test]
+    fn sign_payloads() {
+        let unsigned = Endpoint::new("http://example.com");
+        assert_eq!(unsigned.sign("what do ya want for nothing?"), None);
+
+        let signed = unsigned.secret("Jefe");
+        assert_eq!(
+            signed.sign("what do ya want for nothing?"),
+            Some("sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843".into())
+        );
+    }
+
+    #[test]
+    fn retry_in_order() {
+        let now = SteadyTime::now();
+        let mut retries = BinaryHeap::new();
+        retries.push(Retry { due: now + Duration::seconds(2), attempt: 2, job: job(1) });
+        retries.push(Retry { due: now + Duration::seconds(1), attempt: 2, job: job(2) });
+        retries.push(Retry { due: now + Duration::seconds(3), attempt: 2, job: job(3) });
+
+        let order: Vec<usize> = (0..3).filter_map(|_| retries.pop()).map(|retry| retry.job.id).collect();
+        assert_eq!(order, vec![2, 1, 3]);
+    }
+
+    #[test]
+    fn bound_the_retry_queue() {
+        let shared = Shared::new(Config {
+            max_retries: 1,
+            log: Box::new(Quiet),
+            ..Config::default()
+        });
+        let clients = Clients::new(&shared.config);
+        let mut retries = BinaryHeap::new();
+
+        attempt(&shared, &clients, &mut retries, job(1), 1);
+        attempt(&shared, &clients, &mut retries, job(2), 1);
+        assert_eq!(retries.len(), 1);
+
+        let history = shared.history.lock().unwrap();
+        assert_eq!(history[0].state, State::Pending);
+        assert_eq!(history[1].state, State::Failed);
+        assert_eq!(shared.dead_letters.lock().unwrap().len(), 1);
+    }
+}